@@ -8,7 +8,7 @@ use std::str;
 
 
 // External Dependencies ------------------------------------------------------
-use cobalt_entity::Entity;
+use cobalt_entity::{Entity, RegistryError};
 use cobalt::{
     BinaryRateLimiter, Config, ConnectionID, NoopPacketModifier, UdpSocket,
     Client, ClientEvent
@@ -34,7 +34,7 @@ impl ClientEntity for PlayerEntity {}
 
 impl Drop for PlayerEntity {
     fn drop(&mut self) {
-        self.dropped();
+        ClientEntity::dropped(self);
     }
 }
 
@@ -44,10 +44,10 @@ impl Drop for PlayerEntity {
 struct ClientRegistry;
 
 impl cobalt_entity::EntityRegistry<ClientEntity, cobalt::ConnectionID> for ClientRegistry {
-    fn entity_from_kind_and_bytes(&self, kind: u8, bytes: &[u8]) -> Option<Box<ClientEntity>> {
+    fn entity_from_kind_and_bytes(&self, kind: u16, bytes: &[u8]) -> Result<Box<ClientEntity>, RegistryError> {
         match kind {
-            1 => PlayerEntity::from_bytes(bytes).map(|e| Box::new(e) as Box<ClientEntity>),
-            _ => None
+            1 => PlayerEntity::from_bytes(bytes).map(|e| Box::new(e) as Box<ClientEntity>).ok_or(RegistryError::InvalidBytes(kind)),
+            _ => Err(RegistryError::UnknownKind(kind))
         }
     }
 }
@@ -119,7 +119,7 @@ fn main() {
 
         // Send a message to all connected clients
         if let Ok(conn) = client.connection() {
-            for packet in entity_client.send(512) {
+            for packet in entity_client.send(512).unwrap() {
                 conn.send(cobalt::MessageKind::Instant, packet);
             }
         }
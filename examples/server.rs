@@ -34,7 +34,7 @@ impl ServerEntity for PlayerEntity {}
 
 impl Drop for PlayerEntity {
     fn drop(&mut self) {
-        self.dropped();
+        ServerEntity::dropped(self);
     }
 }
 
@@ -45,10 +45,7 @@ impl Entity<ConnectionID> for PlayerEntity {
         None
     }
 
-    fn merge_bytes(&mut self, _: Option<&ConnectionToken<ConnectionID>>, _: &[u8]) {
-    }
-
-    fn kind(&self) -> u8 {
+    fn kind(&self) -> u16 {
         1
     }
 
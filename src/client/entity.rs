@@ -11,26 +11,43 @@ use std::fmt;
 
 
 // Internal Dependencies ------------------------------------------------------
-use ::shared::Config;
+use ::shared::{Config, serialize_entity_bytes, serialize_segments};
 use ::server::ConnectionToken;
-use ::traits::{Entity, EntitySerializer};
+use ::traits::{Entity, EntitySerializer, SerializeState};
 use super::{EntityToken, NetworkState};
 
 
 // Client Entity State --------------------------------------------------------
+/// The synchronisation state of a client side entity.
 #[derive(Debug, Eq, PartialEq, Hash, Copy, Clone)]
 pub enum LocalState {
+
+    /// The entity has not been created or was dropped again.
     Unknown,
+
+    /// The server has confirmed the creation of the entity and the client is
+    /// accepting updates for it.
     Accept,
+
+    /// The entity was created and the client is confirming its creation to
+    /// the server.
     Create,
-    Update
+
+    /// The entity is receiving updates from the server.
+    Update,
+
+    /// The entity was hidden by the server and is no longer receiving updates
+    /// until it gets revealed again.
+    Hidden
 }
 
 state_machine!(LocalState, {
     create: LocalState::Unknown => LocalState::Create,
     accept: LocalState::Create => LocalState::Accept,
     update: LocalState::Accept => LocalState::Update,
-    reset: LocalState::Create | LocalState::Accept | LocalState::Update => LocalState::Unknown,
+    hide: LocalState::Create | LocalState::Accept | LocalState::Update => LocalState::Hidden,
+    reveal: LocalState::Hidden => LocalState::Accept,
+    reset: LocalState::Create | LocalState::Accept | LocalState::Update | LocalState::Hidden => LocalState::Unknown,
 });
 
 
@@ -44,63 +61,66 @@ impl<E: Entity<U> + ?Sized, U: fmt::Debug> EntitySerializer<E, LocalState, Entit
         connection_slot: Option<&ConnectionToken<U>>,
         state: &LocalState,
         entity: Option<&mut Box<E>>,
-        update_tick: &mut u8
+        serialize_state: SerializeState
+
+    ) -> Result<Vec<u8>, ()> {
 
-    ) -> Vec<u8> {
+        let update_tick = serialize_state.update_tick;
 
         let index = token.index as u8;
         if let Some(entity) = entity {
             match *state {
 
                 LocalState::Create => {
-                    vec![NetworkState::ConfirmCreateToServer as u8, index]
+                    Ok(vec![NetworkState::ConfirmCreateToServer as u8, index])
                 },
 
                 LocalState::Accept => {
-                    vec![NetworkState::AcceptServerUpdate as u8, index]
+                    Ok(vec![NetworkState::AcceptServerUpdate as u8, index])
                 },
 
-                LocalState::Update => if let Some(update_bytes) = entity.part_bytes(connection_slot) {
+                // Segmented updates are send with their own state
+                LocalState::Update => if let Some((update_state, update_bytes)) = match serialize_segments(entity.part_segments(connection_slot))? {
+                    Some(segment_bytes) => Some((NetworkState::SendSegmentsToServer, segment_bytes)),
+                    None => entity.part_bytes(connection_slot).map(|update_bytes| (NetworkState::SendUpdateToServer, update_bytes))
 
-                    // TODO handle more than 255 bytes with bigger frames etc.
-                    if update_bytes.len() > 255 {
-                        panic!("More than 255 bytes in update!");
-                    }
+                } {
 
                     let mut bytes = vec![
-                        NetworkState::SendUpdateToServer as u8,
-                        index,
-                        update_bytes.len() as u8
+                        update_state as u8,
+                        index
                     ];
+                    serialize_entity_bytes(config, &update_bytes, &mut bytes)?;
                     bytes.extend_from_slice(&update_bytes);
-                    bytes
+                    Ok(bytes)
 
                 } else if let Some(tick_threshold) = config.minimum_update_interval {
 
                     *update_tick = update_tick.saturating_add(1);
 
-                    if *update_tick == tick_threshold {
+                    if *update_tick >= tick_threshold {
                         *update_tick = 0;
-                        vec![
+                        let mut bytes = vec![
                             NetworkState::SendUpdateToServer as u8,
-                            index,
-                            0
-                        ]
+                            index
+                        ];
+                        serialize_entity_bytes(config, &[], &mut bytes)?;
+                        Ok(bytes)
 
                     } else {
-                      vec![]
+                      Ok(vec![])
                     }
 
                 } else {
-                    vec![]
+                    Ok(vec![])
                 },
 
-                _ => vec![]
+                _ => Ok(vec![])
 
             }
 
         } else {
-            vec![NetworkState::ConfirmDestroyToServer as u8, index]
+            Ok(vec![NetworkState::ConfirmDestroyToServer as u8, index])
         }
 
     }
@@ -8,26 +8,35 @@
 
 // STD Dependencies -----------------------------------------------------------
 use std::fmt;
+use std::cmp::Reverse;
+use std::borrow::Cow;
+use std::collections::HashMap;
 use std::sync::atomic::{AtomicUsize, Ordering};
 
 
 // Internal Dependencies ------------------------------------------------------
+use ::{NETWORK_BYTE_OFFSET, PROTOCOL_VERSION};
 use ::traits::{Entity, EntityRegistry};
 use ::server::NetworkState as ServerNetworkState;
-use ::shared::{Config, EntityHandle, PacketList, deserialize_entity_bytes};
+use ::shared::{
+    Config, CustomPacketCallback, EntityHandle, FragmentBuffer, KindBandwidth,
+    PacketBuffer, PacketList, RegistryError, STREAM_PACKET_BYTES, decompress_packet, deserialize_entity_bytes, handshake_packet,
+    handshake_version, next_stream_packet, record_merge_bandwidth, record_part_bandwidth, stream_bytes, strip_packet_header,
+    update_tick, verify_packet_checksum
+};
 
 
 // Modules --------------------------------------------------------------------
 mod entity;
-use self::entity::{Serializer, LocalState};
+use self::entity::Serializer;
+pub use self::entity::LocalState;
 
 
 /// A unique token encapsulating access to a client side [`Entity`](trait.Entity.html).
 ///
-/// The entity behind the token can only be modified via a reference to the
-/// token and the only way to destroy the entity is by giving up ownership of
-/// its token.
-#[derive(Debug, Eq, PartialEq, Hash)]
+/// Since client side entities are only ever destroyed by the server, the token
+/// can be freely cloned and held onto in order to query the entity later on.
+#[derive(Debug, Eq, PartialEq, Hash, Clone)]
 pub struct EntityToken {
     index: usize,
     client_index: usize
@@ -43,6 +52,40 @@ impl EntityToken {
 }
 
 
+/// An entity lifecycle event which occurred during
+/// [`Client::receive_with_events`](struct.Client.html#method.receive_with_events).
+#[derive(Debug, Eq, PartialEq, Hash, Clone)]
+pub enum EntityEvent {
+
+    /// The entity was created from server data, this includes entities which
+    /// replaced an existing one.
+    Created(EntityToken),
+
+    /// The entity was destroyed by the server and
+    /// [`Entity::destroyed`](trait.Entity.html#method.destroyed) was invoked.
+    Destroyed(EntityToken),
+
+    /// The entity was forgotten due to the server side
+    /// [`Entity::filter`](trait.Entity.html#method.filter), its `destroyed()`
+    /// method is *not* invoked.
+    Forgotten(EntityToken),
+
+    /// The entity was hidden due to the server side
+    /// [`Entity::filter`](trait.Entity.html#method.filter) and a
+    /// `ForgetMode::Hide` [`Entity::forget_mode`](trait.Entity.html#method.forget_mode),
+    /// it is kept but no longer receives updates.
+    Hidden(EntityToken),
+
+    /// A previously hidden entity was revealed and receives updates again.
+    Revealed(EntityToken),
+
+    /// The creation of an entity was rejected by the
+    /// [`EntityRegistry`](trait.EntityRegistry.html) and ignored.
+    Rejected(RegistryError)
+
+}
+
+
 /// A enum of possible client side error values.
 #[derive(Debug, Eq, PartialEq, Hash, Clone)]
 pub enum Error {
@@ -57,7 +100,93 @@ pub enum Error {
     /// Returned by [`Client::receive`](struct.Client.html#method.receive) when
     /// the passed vector of bytes did contain additional data which is not part
     /// of the underlying client-server protocol.
-    RemainingPacketData(Vec<u8>)
+    RemainingPacketData(Vec<u8>),
+
+    /// Returned by [`Client::receive`](struct.Client.html#method.receive) when
+    /// [`Config::packet_checksum`](struct.Config.html#structfield.packet_checksum)
+    /// is enabled and the passed vector of bytes is missing its checksum or
+    /// was corrupted.
+    ///
+    /// The packet is discarded without being parsed.
+    ChecksumMismatch(Vec<u8>),
+
+    /// Returned by [`Client::receive`](struct.Client.html#method.receive) when
+    /// the length of an entity's creation or update bytes exceeds the
+    /// remainder of the packet.
+    ///
+    /// Contains the slot index of the entity, the remainder of the packet is
+    /// discarded.
+    TruncatedEntityData(usize),
+
+    /// Returned by [`Client::receive`](struct.Client.html#method.receive) when
+    /// [`Entity::validate_create_bytes`](trait.Entity.html#method.validate_create_bytes)
+    /// consumed a different number of bytes than advertised by the server.
+    ///
+    /// Contains the slot index of the entity, the creation is ignored and the
+    /// remainder of the packet is discarded.
+    CreatePayloadMismatch(usize),
+
+    /// Returned by [`Client::send`](struct.Client.html#method.send) when the
+    /// bytes of an entity cannot be fragmented into packets of the passed in
+    /// maximum size, which is contained in the error, or when they exceed the
+    /// maximum length of the configured
+    /// [`length_codec`](struct.Config.html#structfield.length_codec).
+    ///
+    /// Nothing is send in this case, see
+    /// [`ConfigBuilder::max_bytes_per_packet`](struct.ConfigBuilder.html#method.max_bytes_per_packet)
+    /// for validating the packet size up front.
+    PacketSizeTooSmall(usize),
+
+    /// Returned by [`Client::receive_stream`](struct.Client.html#method.receive_stream) when the length prefix of a packet is malformed
+    /// or exceeds the maximum stream packet size of `65535` bytes.
+    ///
+    /// The remainder of the stream cannot be recovered in this case.
+    InvalidStreamFrame,
+
+    /// Returned by [`Client::receive`](struct.Client.html#method.receive) when
+    /// the server announced a different protocol version, see
+    /// [`Config::announce_version`](struct.Config.html#structfield.announce_version).
+    ProtocolVersionMismatch {
+        /// The protocol version of the client.
+        expected: u8,
+        /// The protocol version announced by the server.
+        got: u8
+    }
+
+}
+
+
+/// Statistics accumulated by a [`Client`](struct.Client.html) across calls to
+/// its send and receive methods.
+///
+/// The counters keep increasing until they are explicitly cleared via
+/// [`Client::reset_stats`](struct.Client.html#method.reset_stats), resetting
+/// them once per tick yields per-tick statistics.
+#[derive(Debug, Default, Eq, PartialEq, Clone)]
+pub struct Stats {
+
+    /// Number of entities created from `SendCreateToClient` packets, including
+    /// entities which replaced an existing one.
+    pub entities_created: usize,
+
+    /// Number of entities destroyed by the server.
+    pub entities_destroyed: usize,
+
+    /// Number of entities forgotten due to the server side
+    /// [`Entity::filter`](trait.Entity.html#method.filter).
+    pub entities_forgotten: usize,
+
+    /// Total number of bytes passed into
+    /// [`Client::receive`](struct.Client.html#method.receive).
+    pub bytes_received: usize,
+
+    /// Total number of bytes returned from
+    /// [`Client::send`](struct.Client.html#method.send).
+    pub bytes_sent: usize,
+
+    /// Number of server updates which were merged into entities via
+    /// [`Entity::merge_bytes`](trait.Entity.html#method.merge_bytes).
+    pub updates_merged: usize
 
 }
 
@@ -68,7 +197,9 @@ pub enum NetworkState {
     ConfirmCreateToServer = 1,
     AcceptServerUpdate = 2,
     SendUpdateToServer = 3,
-    ConfirmDestroyToServer = 4
+    ConfirmDestroyToServer = 4,
+    AcknowledgeServerUpdate = 5,
+    SendSegmentsToServer = 6
 }
 
 impl NetworkState {
@@ -79,12 +210,14 @@ impl NetworkState {
             2 => Some(NetworkState::AcceptServerUpdate),
             3 => Some(NetworkState::SendUpdateToServer),
             4 => Some(NetworkState::ConfirmDestroyToServer),
+            5 => Some(NetworkState::AcknowledgeServerUpdate),
+            6 => Some(NetworkState::SendSegmentsToServer),
             _ => None
         }
     }
 
     pub fn is_potential_packet(first_byte: u8) -> bool {
-        first_byte >= 1 && first_byte <= 4
+        first_byte >= 1 && first_byte <= 6
     }
 
 }
@@ -95,41 +228,168 @@ lazy_static! {
     static ref CLIENT_INDEX: AtomicUsize = AtomicUsize::new(0);
 }
 
+const MAX_RECENT_ERRORS: usize = 16;
+
 type ClientEntityHandle<E, U> = Vec<
     Option<EntityHandle<E, Serializer, LocalState, EntityToken, U>>
 >;
 
+type ReceiveInspector = Box<dyn FnMut(&[u8]) + Send + Sync>;
+
 /// Client side abstraction for entity synchronisation.
 ///
 /// Each client can manage up to 256 entities at once.
+///
+/// Just like on the server, the entities of a client are always iterated in
+/// ascending order of their slot index.
+///
+/// Just like the [`Server`](struct.Server.html), a client is `Send` and
+/// `Sync` as long as its entity type `E`, the user data `U` and its
+/// registry `R` are.
 pub struct Client<E: Entity<U> + ?Sized, U: fmt::Debug, R: EntityRegistry<E, U>> {
     index: usize,
     handles: ClientEntityHandle<E, U>,
     active_handles: Vec<(EntityToken, Option<usize>, bool)>,
     local_states: [LocalState; 256],
+    acknowledgements: [Option<u8>; 256],
+    hide_confirms: [bool; 256],
+    update_cooldowns: [usize; 256],
+    fragments: FragmentBuffer,
     config: Config,
-    registry: R
+    registry: R,
+    stats: Stats,
+    kind_bandwidth: HashMap<u16, KindBandwidth>,
+    recent_errors: Vec<Error>,
+    receive_inspector: Option<ReceiveInspector>,
+    updates_paused: bool
 }
 
 impl<E: Entity<U> + ?Sized, U: fmt::Debug, R: EntityRegistry<E, U>> Client<E, U, R> {
 
     /// Creates a new entity client.
     pub fn new(config: Config, registry: R) -> Client<E, U, R> {
+        Client::with_id(config, registry, CLIENT_INDEX.fetch_add(1, Ordering::SeqCst))
+    }
+
+    /// Creates a new entity client with a caller supplied instance index
+    /// instead of one taken from the process wide counter.
+    ///
+    /// > Note: Clients which share an instance index will accept each others
+    /// > tokens, it is up to the caller to keep the supplied ids unique.
+    pub fn with_id(config: Config, registry: R, id: usize) -> Client<E, U, R> {
         Client {
-            index: CLIENT_INDEX.fetch_add(1, Ordering::SeqCst),
+            index: id,
             handles: vec_with_default![None; 256],
             local_states: [LocalState::Unknown; 256],
+            acknowledgements: [None; 256],
+            hide_confirms: [false; 256],
+            update_cooldowns: [0; 256],
+            fragments: FragmentBuffer::default(),
             active_handles: Vec::new(),
             config: config,
-            registry: registry
+            registry: registry,
+            stats: Stats::default(),
+            kind_bandwidth: HashMap::new(),
+            recent_errors: Vec::new(),
+            receive_inspector: None,
+            updates_paused: false
         }
     }
 
+    /// Returns the unique instance index of the client which is embedded into
+    /// all of its tokens.
+    pub fn instance_index(&self) -> usize {
+        self.index
+    }
+
     /// Overrides server's current configuration with the one provided.
     pub fn set_config(&mut self, config: Config) {
         self.config = config;
     }
 
+    /// Pauses or resumes the serialization of entity updates to the server.
+    ///
+    /// While paused, creation and destruction confirmations as well as
+    /// update acknowledgements are still send as usual.
+    pub fn set_updates_paused(&mut self, paused: bool) {
+        self.updates_paused = paused;
+    }
+
+    /// Returns whether entity updates are currently paused.
+    pub fn updates_paused(&self) -> bool {
+        self.updates_paused
+    }
+
+    /// Returns the statistics accumulated since the client was created or
+    /// [`Client::reset_stats`](struct.Client.html#method.reset_stats) was last
+    /// called.
+    pub fn stats(&self) -> &Stats {
+        &self.stats
+    }
+
+    /// Resets all accumulated statistics back to zero.
+    pub fn reset_stats(&mut self) {
+        self.stats = Stats::default();
+        self.kind_bandwidth.clear();
+    }
+
+    /// Returns the update bandwidth accumulated per entity kind since the
+    /// client was created or
+    /// [`Client::reset_stats`](struct.Client.html#method.reset_stats) was last
+    /// called.
+    pub fn kind_bandwidth(&self) -> &HashMap<u16, KindBandwidth> {
+        &self.kind_bandwidth
+    }
+
+    /// Returns the most recent errors returned from
+    /// [`Client::receive`](struct.Client.html#method.receive) and
+    /// [`Client::receive_with`](struct.Client.html#method.receive_with),
+    /// ordered from oldest to newest.
+    ///
+    /// Only the last 16 errors are kept around, older ones are discarded.
+    pub fn recent_errors(&self) -> &[Error] {
+        &self.recent_errors
+    }
+
+    /// Returns the number of entity handles which currently occupy a slot,
+    /// including those whose entity has already been destroyed.
+    pub fn entity_count(&self) -> usize {
+        self.active_handles.len()
+    }
+
+    /// Returns the number of entities which are still alive.
+    ///
+    /// Unlike [`Client::entity_count`](struct.Client.html#method.entity_count)
+    /// destroyed entities are no longer counted, even while their handle has
+    /// not been reclaimed yet.
+    pub fn live_entity_count(&self) -> usize {
+        self.active_handles.iter().filter(|&&(ref entity_token, _, _)| {
+            match self.handles[entity_token.index] {
+                Some(ref handle) => handle.is_alive(),
+                None => false
+            }
+
+        }).count()
+    }
+
+    /// Returns the number of handles whose entity has been destroyed but
+    /// whose slot has not been reclaimed yet.
+    ///
+    /// Destroyed handles are kept around for
+    /// [`Config::handle_timeout_ticks`](struct.Config.html#structfield.handle_timeout_ticks)
+    /// calls to
+    /// [`Client::update_entities_with`](struct.Client.html#method.update_entities_with)
+    /// before their slot is released.
+    pub fn pending_handle_count(&self) -> usize {
+        self.active_handles.iter().filter(|&&(ref entity_token, _, _)| {
+            match self.handles[entity_token.index] {
+                Some(ref handle) => !handle.is_alive(),
+                None => false
+            }
+
+        }).count()
+    }
+
     /// Takes a closure and iterates over all active entities of the client,
     /// calling that closure on each entity while collecting the return value
     /// into a vector.
@@ -159,6 +419,22 @@ impl<E: Entity<U> + ?Sized, U: fmt::Debug, R: EntityRegistry<E, U>> Client<E, U,
         }
     }
 
+    /// Takes a closure and iterates over all active entities of the client
+    /// whose [`Entity::kind`](trait.Entity.html#method.kind) matches `kind`,
+    /// calling that closure on each matching entity.
+    pub fn with_entities_of_kind<F: FnMut(&EntityToken, &mut Box<E>)>(&mut self, kind: u16, mut callback: F) {
+        for &(ref entity_token, _, _) in &self.active_handles {
+            let handle = &mut self.handles[entity_token.index];
+            if handle.is_some()  {
+                if let Some(entity) = handle.as_mut().unwrap().get_entity_mut() {
+                    if entity.kind() == kind {
+                        callback(entity_token, entity);
+                    }
+                }
+            }
+        }
+    }
+
     /// Takes a closure and iterates over all active entities of the client,
     /// updating their state and calling that closure on each entity.
     ///
@@ -179,7 +455,7 @@ impl<E: Entity<U> + ?Sized, U: fmt::Debug, R: EntityRegistry<E, U>> Client<E, U,
                 // a buffer for the destruction confirmation getting through
                 // from us to the server in the first place
                 } else if timeout.is_none() {
-                    *timeout = Some(self.config.handle_timeout_ticks);
+                    *timeout = Some(handle.as_ref().unwrap().timeout_ticks(&self.config));
                 }
 
                 // Safely reduce timeout until we hit 0
@@ -195,6 +471,9 @@ impl<E: Entity<U> + ?Sized, U: fmt::Debug, R: EntityRegistry<E, U>> Client<E, U,
             // Drop the handle once it is no longer connected with the server
             if !*connected {
                 self.local_states[entity_token.index].reset();
+                self.acknowledgements[entity_token.index] = None;
+                self.hide_confirms[entity_token.index] = false;
+                self.update_cooldowns[entity_token.index] = 0;
                 *handle = None
             }
 
@@ -203,71 +482,611 @@ impl<E: Entity<U> + ?Sized, U: fmt::Debug, R: EntityRegistry<E, U>> Client<E, U,
         // Remove disconnected handles
         self.active_handles.retain(|&(_, _, connected)| connected );
 
+        debug_assert_eq!(self.check_invariants(), Ok(()));
+
+    }
+
+    /// Returns whether the entity referenced by the `EntityToken` belongs to
+    /// the client and has not yet been destroyed.
+    pub fn entity_is_alive(&self, entity_token: &EntityToken) -> bool {
+        entity_token.client_index == self.index && match self.handles[entity_token.index] {
+            Some(ref handle) => handle.is_alive(),
+            None => false
+        }
+    }
+
+    /// Returns an immutable reference to the boxed entity referenced by the
+    /// `EntityToken`.
+    ///
+    /// Returns `None` in case the token does not belong to the client or the
+    /// entity has already been destroyed.
+    pub fn entity_get(&self, entity_token: &EntityToken) -> Option<&Box<E>> {
+        if entity_token.client_index != self.index {
+            None
+
+        } else if let Some(ref handle) = self.handles[entity_token.index] {
+            handle.get_entity()
+
+        } else {
+            None
+        }
+    }
+
+    /// Returns a mutable reference to the boxed entity referenced by the
+    /// `EntityToken`.
+    ///
+    /// Returns `None` in case the token does not belong to the client or the
+    /// entity has already been destroyed.
+    pub fn entity_get_mut(&mut self, entity_token: &EntityToken) -> Option<&mut Box<E>> {
+        if entity_token.client_index != self.index {
+            None
+
+        } else if let Some(ref mut handle) = self.handles[entity_token.index] {
+            handle.get_entity_mut()
+
+        } else {
+            None
+        }
+    }
+
+    /// Returns the synchronisation state of the entity referenced by the
+    /// `EntityToken`.
+    ///
+    /// This is mostly useful for debugging de-synchronisations between the
+    /// client and the server.
+    ///
+    /// Returns `None` in case the token does not belong to the client or the
+    /// entity's handle has already been dropped.
+    pub fn entity_local_state(&self, entity_token: &EntityToken) -> Option<LocalState> {
+        if entity_token.client_index != self.index || self.handles[entity_token.index].is_none() {
+            None
+
+        } else {
+            Some(self.local_states[entity_token.index])
+        }
+    }
+
+    /// Verifies the consistency of the client's internal state, returning a
+    /// description of the first violated invariant.
+    ///
+    /// This is used by debug assertions and the test suite to detect
+    /// corrupted bookkeeping early on.
+    pub fn check_invariants(&self) -> Result<(), String> {
+
+        // Active handles are iterated in slot order
+        if self.active_handles.windows(2).any(|pair| pair[0].0.index > pair[1].0.index) {
+            return Err("Active handles are not sorted by slot index.".to_string());
+        }
+
+        // Active handles must reference unique, occupied slots
+        let mut seen_handles = [false; 256];
+        for &(ref entity_token, _, _) in &self.active_handles {
+
+            let index = entity_token.index;
+            if seen_handles[index] {
+                return Err(format!("Duplicate active handle for slot {}.", index));
+
+            } else if self.handles[index].is_none() {
+                return Err(format!("Active handle for empty slot {}.", index));
+            }
+            seen_handles[index] = true;
+
+        }
+
+        for (index, handle) in self.handles.iter().enumerate() {
+            if handle.is_some() {
+                if !seen_handles[index] {
+                    return Err(format!("Occupied slot {} has no active handle.", index));
+                }
+
+            // Empty slots must be ready for a new entity
+            } else if self.local_states[index] != LocalState::Unknown {
+                return Err(format!(
+                    "Empty slot {} is in state {:?} instead of Unknown.",
+                    index, self.local_states[index]
+                ));
+
+            } else if self.acknowledgements[index].is_some() {
+                return Err(format!("Empty slot {} has a pending acknowledgement.", index));
+
+            } else if self.hide_confirms[index] {
+                return Err(format!("Empty slot {} has a pending hide confirmation.", index));
+            }
+        }
+
+        Ok(())
+
+    }
+
+    /// Destroys all alive entities of the client at once, calling their
+    /// [`Entity::destroyed`](trait.Entity.html#method.destroyed) method.
+    ///
+    /// Unlike [`Client::reset`](struct.Client.html#method.reset) the handles
+    /// are kept around and confirm the destruction to the server before they
+    /// time out, just like they would for destructions initiated by the
+    /// server.
+    ///
+    /// This is intended for tearing down the client side state, e.g. at the
+    /// end of a match.
+    pub fn destroy_all(&mut self) {
+        for &(ref entity_token, _, _) in &self.active_handles {
+            if let Some(handle) = self.handles[entity_token.index].as_mut() {
+                if handle.is_alive() {
+                    handle.destroy();
+                    self.stats.entities_destroyed += 1;
+                }
+            }
+        }
+    }
+
+    /// Returns a final batch of packets which confirm the destruction of all
+    /// entities whose handle has not been reclaimed yet.
+    ///
+    /// This should be send to the server right before disconnecting and
+    /// calling [`Client::reset`](struct.Client.html#method.reset), allowing
+    /// the server to reclaim the slots of the entities immediately instead of
+    /// waiting for
+    /// [`Config::handle_timeout_ticks`](struct.Config.html#structfield.handle_timeout_ticks)
+    /// to run out.
+    pub fn disconnect_flush(&mut self, max_bytes_per_packet: usize) -> Result<Vec<Vec<u8>>, Error> {
+
+        let mut packets = PacketList::with_overhead(
+            max_bytes_per_packet,
+            self.config.packet_overhead,
+            self.config.max_entities_per_packet,
+            self.config.packet_header.as_ref(),
+            self.config.compression.as_ref(),
+            self.config.packet_checksum
+        );
+
+        for &(ref entity_token, _, _) in &self.active_handles {
+            let index = entity_token.index;
+            let is_destroyed = match self.handles[index] {
+                Some(ref handle) => !handle.is_alive(),
+                None => false
+            };
+            if is_destroyed || self.hide_confirms[index] {
+                self.hide_confirms[index] = false;
+                packets.append_bytes(vec![NetworkState::ConfirmDestroyToServer as u8, index as u8]);
+            }
+        }
+
+        if packets.is_oversized() {
+            return Err(Error::PacketSizeTooSmall(max_bytes_per_packet));
+        }
+
+        let packets = packets.into_vec();
+        self.stats.bytes_sent += packets.iter().map(|p| p.len()).sum::<usize>();
+        Ok(packets)
+
     }
 
     /// Fully resets the internal state of the client, dropping all entities
     /// and handles.
     ///
     /// This should only be called after having cleanly disconnected from a server.
+    ///
+    /// Pending destroy confirmations are not send, see
+    /// [`Client::disconnect_flush`](struct.Client.html#method.disconnect_flush).
     pub fn reset(&mut self) {
 
         for &mut (ref entity_token, _, _) in &mut self.active_handles {
             self.local_states[entity_token.index].reset();
+            self.acknowledgements[entity_token.index] = None;
+            self.hide_confirms[entity_token.index] = false;
+            self.update_cooldowns[entity_token.index] = 0;
             self.handles[entity_token.index] = None;
         }
 
         self.active_handles.clear();
+        self.fragments.reset();
+
+    }
 
+    /// Returns a packet containing the protocol magic and version of the
+    /// library, which should be send as the very first packet to a server and
+    /// fed into
+    /// [`Server::accept_handshake`](struct.Server.html#method.accept_handshake).
+    pub fn handshake_packet(&self) -> Vec<u8> {
+        handshake_packet(&self.config)
+    }
+
+    /// Sets an inspector which is invoked with every packet passed into
+    /// [`Client::receive`](struct.Client.html#method.receive) and its
+    /// variants, e.g. for logging or recording a replay of the stream.
+    ///
+    /// The inspector receives the bytes of the packet as they were passed
+    /// in, before they are parsed in any way.
+    pub fn set_receive_inspector<F: FnMut(&[u8]) + Send + Sync + 'static>(&mut self, inspector: F) {
+        self.receive_inspector = Some(Box::new(inspector));
     }
 
     /// Returns a list of one or more protocol packets that can be fed into
     /// [`Server::connection_receive`](struct.Server.html#method.connection_receive)
     /// in order to synchronise entities between the client and a server.
-    pub fn send(&mut self, max_bytes_per_packet: usize) -> Vec<Vec<u8>> {
-
-        let mut packets = PacketList::new(max_bytes_per_packet);
-        for &mut(ref entity_token, _, _) in &mut self.active_handles {
-            packets.append_bytes(self.handles[entity_token.index].as_mut().unwrap().as_bytes(
-                &self.config,
-                None,
-                &self.local_states[entity_token.index]
-            ));
+    pub fn send(&mut self, max_bytes_per_packet: usize) -> Result<Vec<Vec<u8>>, Error> {
+        let mut buffer = PacketBuffer::new();
+        match self.send_packets(max_bytes_per_packet, None, &mut buffer) {
+            Ok(_) => Ok(buffer.into_packets()),
+            Err(err) => Err(err)
         }
+    }
 
-        packets.into_vec()
+    /// Works like [`Client::send`](struct.Client.html#method.send) but writes
+    /// the packets into the provided
+    /// [`PacketBuffer`](struct.PacketBuffer.html) instead of allocating new
+    /// ones.
+    ///
+    /// The returned packets borrow the buffer and are replaced by the next
+    /// call which uses the same buffer.
+    pub fn send_into<'b>(
+        &mut self,
+        max_bytes_per_packet: usize,
+        buffer: &'b mut PacketBuffer
+
+    ) -> Result<&'b [Vec<u8>], Error> {
+        match self.send_packets(max_bytes_per_packet, None, buffer) {
+            Ok(_) => Ok(buffer.packets()),
+            Err(err) => Err(err)
+        }
+    }
 
+    /// Works like [`Client::send`](struct.Client.html#method.send) but returns
+    /// all packets as a single buffer in which each packet is prefixed with
+    /// its length.
+    ///
+    /// This is intended for stream oriented transports which perform their
+    /// own framing, the buffer is consumed again by
+    /// [`Server::connection_receive_stream`](struct.Server.html#method.connection_receive_stream).
+    pub fn send_stream(&mut self) -> Result<Vec<u8>, Error> {
+        let mut buffer = PacketBuffer::new();
+        match self.send_packets(STREAM_PACKET_BYTES, None, &mut buffer) {
+            Ok(_) => Ok(stream_bytes(buffer.packets())),
+            Err(err) => Err(err)
+        }
+    }
+
+    /// Works like [`Client::send`](struct.Client.html#method.send) but stops
+    /// serializing entity updates once `max_bytes_per_tick` bytes of entity
+    /// data have been written.
+    ///
+    /// Entities are serialized in the order of their
+    /// [`Entity::priority`](trait.Entity.html#method.priority), the updates
+    /// of the remaining entities are deferred to a later call and their
+    /// [`Entity::part_bytes`](trait.Entity.html#method.part_bytes) method is
+    /// not invoked. Since the budget is checked in front of each update, the
+    /// last serialized update may exceed it.
+    ///
+    /// Creation and destruction confirmations as well as update
+    /// acknowledgements are never deferred.
+    pub fn send_with_budget(
+        &mut self,
+        max_bytes_per_packet: usize,
+        max_bytes_per_tick: usize
+
+    ) -> Result<Vec<Vec<u8>>, Error> {
+        let mut buffer = PacketBuffer::new();
+        match self.send_packets(max_bytes_per_packet, Some(max_bytes_per_tick), &mut buffer) {
+            Ok(_) => Ok(buffer.into_packets()),
+            Err(err) => Err(err)
+        }
     }
 
     /// Consumes a protocol packet that was generated by
     /// [`Server::connection_send`](struct.Server.html#method.connection_send)
     /// in order to synchronise entities between a server and the client.
     pub fn receive(&mut self, bytes: Vec<u8>) -> Result<(), Error> {
+        let result = self.receive_packet(Cow::Owned(bytes), None, None);
+        self.record_error(result)
+    }
+
+    /// Consumes a protocol packet just like
+    /// [`Client::receive`](struct.Client.html#method.receive) but borrows the
+    /// bytes instead of taking ownership of them.
+    ///
+    /// The bytes are only copied in case an error needs to return them or
+    /// when they have to be decompressed or reassembled from fragments.
+    pub fn receive_slice(&mut self, bytes: &[u8]) -> Result<(), Error> {
+        let result = self.receive_packet(Cow::Borrowed(bytes), None, None);
+        self.record_error(result)
+    }
+
+    /// Consumes the length prefixed packets of a buffer generated by
+    /// [`Server::connection_send_stream`](struct.Server.html#method.connection_send_stream).
+    ///
+    /// Returns the number of bytes which were consumed, a packet which is
+    /// incomplete at the end of the buffer is left untouched so it can be
+    /// passed in again once the rest of it has been received.
+    ///
+    /// Processing stops at the first packet which fails, returning the number
+    /// of bytes consumed in front of it along with the error.
+    pub fn receive_stream(&mut self, bytes: &[u8]) -> Result<usize, (usize, Error)> {
+        let mut consumed = 0;
+        loop {
+            match next_stream_packet(&bytes[consumed..]) {
+                Ok(Some((packet, length))) => match self.receive_slice(packet) {
+                    Ok(_) => consumed += length,
+                    Err(err) => return Err((consumed, err))
+                },
+                Ok(None) => return Ok(consumed),
+                Err(_) => return Err((consumed, Error::InvalidStreamFrame))
+            }
+        }
+    }
+
+    /// Consumes a protocol packet just like
+    /// [`Client::receive`](struct.Client.html#method.receive) but also returns
+    /// the entity lifecycle events which were caused by it.
+    ///
+    /// Forgotten and destroyed entities are reported separately since only
+    /// the latter had their `destroyed()` method invoked.
+    pub fn receive_with_events(&mut self, bytes: Vec<u8>) -> Result<Vec<EntityEvent>, Error> {
+        let mut events = Vec::new();
+        let result = self.receive_packet(Cow::Owned(bytes), None, Some(&mut events));
+        self.record_error(result).map(|_| events)
+    }
+
+    /// Consumes a protocol packet just like
+    /// [`Client::receive`](struct.Client.html#method.receive) but forwards the
+    /// bytes of any embedded custom protocol packet to the provided closure
+    /// instead of returning them as an error.
+    ///
+    /// Custom protocol data starts at the first byte whose value is higher
+    /// than [`NETWORK_BYTE_OFFSET`](constant.NETWORK_BYTE_OFFSET.html) and
+    /// extends until the end of the packet, any entity data in front of it is
+    /// still processed.
+    ///
+    /// Errors are only returned for data which is neither part of the
+    /// client-server protocol nor of a custom one.
+    pub fn receive_with<F: FnMut(&[u8])>(&mut self, bytes: Vec<u8>, mut callback: F) -> Result<(), Error> {
+        let result = self.receive_packet(Cow::Owned(bytes), Some(&mut callback), None);
+        self.record_error(result)
+    }
+
+    // Internal
+    fn send_packets(
+        &mut self,
+        max_bytes_per_packet: usize,
+        max_bytes_per_tick: Option<usize>,
+        buffer: &mut PacketBuffer
+
+    ) -> Result<(), Error> {
+
+        let mut packets = PacketList::with_buffer(
+            max_bytes_per_packet,
+            self.config.packet_overhead,
+            self.config.max_entities_per_packet,
+            self.config.packet_header.as_ref(),
+            self.config.compression.as_ref(),
+            self.config.packet_checksum,
+            buffer
+        );
+
+        // Serialize higher priority entities first
+        let handles = &self.handles;
+        let mut ordered: Vec<(u8, usize)> = self.active_handles.iter().filter_map(|&(ref entity_token, _, _)| {
+            handles[entity_token.index].as_ref().map(|handle| {
+                let priority = handle.get_entity().map_or(0, |entity| entity.priority());
+                (priority, entity_token.index)
+            })
+
+        }).collect();
+        ordered.sort_by_key(|&(priority, _)| Reverse(priority));
+
+        let mut bytes_used = 0;
+        for (_, index) in ordered {
+
+            let handle = self.handles[index].as_mut().unwrap();
+            let is_deferred = match max_bytes_per_tick {
+                Some(max) => bytes_used >= max
+                          && self.local_states[index] == LocalState::Update
+                          && handle.is_alive(),
+                None => false
+            };
+
+            // Rate capped entities wait for the cooldown of their last update
+            let is_updating = self.local_states[index] == LocalState::Update && handle.is_alive();
+            let is_capped = if is_updating && self.update_cooldowns[index] > 0 {
+                self.update_cooldowns[index] -= 1;
+                true
+
+            } else {
+                if !is_updating {
+                    self.update_cooldowns[index] = 0;
+                }
+                false
+            };
+
+            let is_paused = is_updating && self.updates_paused;
+            let bytes = if is_deferred || is_capped || is_paused {
+                Ok(Vec::new())
+
+            } else {
+                handle.as_bytes(
+                    &self.config,
+                    None,
+                    &self.local_states[index],
+                    None
+                )
+            };
+
+            // Entity bytes which exceed the length codec cannot be send
+            let mut bytes = match bytes {
+                Ok(bytes) => bytes,
+                Err(()) => {
+                    packets.set_oversized();
+                    continue;
+                }
+            };
+
+            if bytes.first() == Some(&(NetworkState::SendUpdateToServer as u8))
+                || bytes.first() == Some(&(NetworkState::SendSegmentsToServer as u8)) {
+                if let Some(entity) = handle.get_entity() {
+                    record_part_bandwidth(&self.config, &mut self.kind_bandwidth, entity.kind(), &bytes[2..]);
+                }
+                if let Some(rate) = self.config.maximum_update_rate {
+                    self.update_cooldowns[index] = rate.saturating_sub(1);
+                }
+            }
+
+            // Acknowledge the last received server update
+            if let Some(sequence) = self.acknowledgements[index].take() {
+                bytes.extend_from_slice(&[
+                    NetworkState::AcknowledgeServerUpdate as u8,
+                    index as u8,
+                    sequence
+                ]);
+            }
+
+            // Confirm hidden entities once per received hide
+            if self.hide_confirms[index] {
+                self.hide_confirms[index] = false;
+                bytes.extend_from_slice(&[NetworkState::ConfirmDestroyToServer as u8, index as u8]);
+            }
+
+            bytes_used += bytes.len();
+            packets.append_bytes(bytes);
+
+        }
+
+        if packets.is_oversized() {
+            buffer.clear();
+            return Err(Error::PacketSizeTooSmall(max_bytes_per_packet));
+        }
+
+        packets.into_buffer(buffer);
+        self.stats.bytes_sent += buffer.packets().iter().map(|p| p.len()).sum::<usize>();
+
+        Ok(())
+
+    }
+
+    fn record_error(&mut self, result: Result<(), Error>) -> Result<(), Error> {
+        if let Err(ref err) = result {
+            if self.recent_errors.len() == MAX_RECENT_ERRORS {
+                self.recent_errors.remove(0);
+            }
+            self.recent_errors.push(err.clone());
+        }
+        result
+    }
+
+    fn receive_packet(
+        &mut self,
+        bytes: Cow<[u8]>,
+        mut custom: Option<CustomPacketCallback>,
+        mut events: Option<&mut Vec<EntityEvent>>
+
+    ) -> Result<(), Error> {
+
+        self.stats.bytes_received += bytes.len();
+
+        if let Some(ref mut inspector) = self.receive_inspector {
+            inspector(&bytes);
+        }
+
+        let bytes = match strip_packet_header(&self.config, bytes) {
+            Ok(bytes) => bytes,
+            Err(bytes) => return Err(Error::InvalidPacketData(bytes.into_owned()))
+        };
+
+        let bytes = match verify_packet_checksum(&self.config, bytes) {
+            Ok(bytes) => bytes,
+            Err(bytes) => return Err(Error::ChecksumMismatch(bytes.into_owned()))
+        };
+
+        let bytes = match decompress_packet(&self.config, bytes) {
+            Ok(bytes) => bytes,
+            Err(bytes) => return Err(Error::InvalidPacketData(bytes.into_owned()))
+        };
+
+        // Reassemble fragmented entity bytes
+        let bytes = if FragmentBuffer::is_fragment(&bytes) {
+            match self.fragments.receive(&self.config, &bytes) {
+                Some(bytes) => Cow::Owned(bytes),
+                None => return Ok(())
+            }
+
+        } else {
+            bytes
+        };
+
+        // Verify the protocol version announced by the server
+        if let Some(version) = handshake_version(&bytes) {
+            return if version == PROTOCOL_VERSION {
+                Ok(())
+
+            } else {
+                Err(Error::ProtocolVersionMismatch {
+                    expected: PROTOCOL_VERSION,
+                    got: version
+                })
+            };
+        }
 
         let (mut i, len) = (0, bytes.len());
+
         if len == 0 {
             return Ok(());
 
         } else if !ServerNetworkState::is_potential_packet(bytes[0]) {
-            return Err(Error::InvalidPacketData(bytes));
+            return match custom {
+                Some(custom) if bytes[0] > NETWORK_BYTE_OFFSET => {
+                    custom(&bytes);
+                    Ok(())
+                },
+                _ => Err(Error::InvalidPacketData(bytes.into_owned()))
+            };
         }
 
-        while i + 1 < len {
+        while i < len {
+
+            // Forward trailing custom protocol data
+            if let Some(ref mut custom) = custom {
+                if bytes[i] > NETWORK_BYTE_OFFSET {
+                    custom(&bytes[i..]);
+                    return Ok(());
+                }
+            }
+
+            if i + 1 == len {
+                break;
+            }
 
             let (state, index) = (bytes[i], bytes[i + 1] as usize);
             let local_state = &mut self.local_states[index];
             i += 2;
 
             match ServerNetworkState::from_u8(state) {
-                Some(ServerNetworkState::SendCreateToClient) => if let Some((entity_bytes, length)) = deserialize_entity_bytes(&bytes[i..], 2) {
+                Some(ServerNetworkState::SendCreateToClient) => if let Some((entity_bytes, length)) = deserialize_entity_bytes(&self.config, &bytes[i..], 2) {
 
+                    let kind = ((entity_bytes[0] as u16) << 8) | entity_bytes[1] as u16;
                     if self.handles[index].is_none() {
 
-                        if let Some(entity) = self.registry.entity_from_kind_and_bytes(entity_bytes[0], &entity_bytes[1..]) {
-                            local_state.create();
-                            self.handles[index] = Some(EntityHandle::new(EntityToken::new(index, self.index), entity));
-                            self.active_handles.push(
-                                (EntityToken::new(index, self.index), None, true)
-                            );
+                        match self.registry.entity_from_kind_and_bytes(kind, &entity_bytes[2..]) {
+                            Ok(ref entity) if !is_valid_create(&**entity, &entity_bytes[2..]) => {
+                                return Err(Error::CreatePayloadMismatch(index));
+                            },
+                            Ok(entity) => {
+                                local_state.create();
+                                self.stats.entities_created += 1;
+                                self.handles[index] = Some(EntityHandle::new(EntityToken::new(index, self.index), entity));
+                                let position = self.active_handles.iter().position(|&(ref token, _, _)| {
+                                    token.index > index
+
+                                }).unwrap_or(self.active_handles.len());
+
+                                self.active_handles.insert(
+                                    position,
+                                    (EntityToken::new(index, self.index), None, true)
+                                );
+                                if let Some(ref mut events) = events {
+                                    events.push(EntityEvent::Created(EntityToken::new(index, self.index)));
+                                }
+                            },
+                            Err(err) => if let Some(ref mut events) = events {
+                                events.push(EntityEvent::Rejected(err));
+                            }
                         }
 
                     // Replace handles in case the server sends new data and this handle is already
@@ -282,8 +1101,11 @@ impl<E: Entity<U> + ?Sized, U: fmt::Debug, R: EntityRegistry<E, U>> Client<E, U,
                     // SendCreateToClient and ConfirmClientCreate packets.
                     //
                     // To work around these issues with establish the following rules:
-                    // a. If the entity has a different kind replace it directly
-                    // b. If the entity has the same kind as the existing one, replace it
+                    // a. If the existing entity was already destroyed or forgotten
+                    //    replace it directly, this also covers slots which are
+                    //    re-used within the same packet
+                    // b. If the entity has a different kind replace it directly
+                    // c. If the entity has the same kind as the existing one, replace it
                     //    only when NOT in the create state.
                     //
                     //    If it is in the create state we're already sending
@@ -295,43 +1117,116 @@ impl<E: Entity<U> + ?Sized, U: fmt::Debug, R: EntityRegistry<E, U>> Client<E, U,
                     //
                     // In all other cases we'll do nothing.
                     } else {
-                        let existing_kind = self.handles[index].as_mut().unwrap().get_entity_mut().map_or(entity_bytes[0], |entity| {
+                        let existing_kind = self.handles[index].as_mut().unwrap().get_entity_mut().map_or(kind, |entity| {
                             entity.kind()
                         });
 
-                        if entity_bytes[0] != existing_kind || *local_state != LocalState::Create {
-                            if let Some(entity) = self.registry.entity_from_kind_and_bytes(entity_bytes[0], &entity_bytes[1..]) {
-                                self.handles[index].as_mut().unwrap().replace_entity(entity);
-                                local_state.reset();
-                                local_state.create();
+                        let is_alive = self.handles[index].as_ref().unwrap().is_alive();
+                        if !is_alive || kind != existing_kind || *local_state != LocalState::Create {
+                            match self.registry.entity_from_kind_and_bytes(kind, &entity_bytes[2..]) {
+                                Ok(ref entity) if !is_valid_create(&**entity, &entity_bytes[2..]) => {
+                                    return Err(Error::CreatePayloadMismatch(index));
+                                },
+                                Ok(entity) => {
+                                    self.handles[index].as_mut().unwrap().replace_entity(entity);
+                                    local_state.reset();
+                                    local_state.create();
+
+                                    // Per slot state of the previous entity does not carry over
+                                    self.acknowledgements[index] = None;
+                                    self.hide_confirms[index] = false;
+                                    self.update_cooldowns[index] = 0;
+                                    self.stats.entities_created += 1;
+                                    if let Some(ref mut events) = events {
+                                        if is_alive {
+                                            events.push(EntityEvent::Forgotten(EntityToken::new(index, self.index)));
+                                        }
+                                        events.push(EntityEvent::Created(EntityToken::new(index, self.index)));
+                                    }
+                                },
+                                Err(err) => if let Some(ref mut events) = events {
+                                    events.push(EntityEvent::Rejected(err));
+                                }
                             }
                         }
                     }
 
                     i += length;
 
+                } else {
+                    return Err(Error::TruncatedEntityData(index));
                 },
-                Some(ServerNetworkState::ConfirmClientCreate) => if self.handles[index].is_some() && local_state.accept() {
-                    self.handles[index].as_mut().unwrap().create();
+                Some(ServerNetworkState::ConfirmClientCreate) => if self.handles[index].is_some() {
+                    if local_state.accept() {
+                        self.handles[index].as_mut().unwrap().create();
+
+                    // Hidden entities are revealed by a repeated confirmation
+                    } else if local_state.reveal() {
+                        if let Some(ref mut events) = events {
+                            events.push(EntityEvent::Revealed(EntityToken::new(index, self.index)));
+                        }
+                    }
                 },
-                Some(ServerNetworkState::SendUpdateToClient) => if let Some((entity_bytes, length)) = deserialize_entity_bytes(&bytes[i..], 1) {
-
-                    if self.handles[index].is_some() {
+                Some(ServerNetworkState::SendHideToClient) => if self.handles[index].is_some() {
+                    // Hidden entities are kept around, but no longer accept
+                    // any updates until they get revealed again
+                    if self.handles[index].as_ref().unwrap().is_alive() {
+                        if local_state.hide() {
+                            if let Some(ref mut events) = events {
+                                events.push(EntityEvent::Hidden(EntityToken::new(index, self.index)));
+                            }
+                        }
+                        self.hide_confirms[index] = *local_state == LocalState::Hidden;
+                    }
+                },
+                Some(ServerNetworkState::SendUpdateToClient) | Some(ServerNetworkState::SendSegmentsToClient) => {
 
-                        local_state.update();
+                    // Acknowledged updates are prefixed with their sequence,
+                    // followed by the server tick if enabled
+                    let sequence_offset = if self.config.acknowledge_updates { 1 } else { 0 };
+                    let tick = if self.config.send_update_ticks {
+                        bytes.get(i + sequence_offset..).and_then(update_tick)
 
-                        if *local_state == LocalState::Update {
-                            if !entity_bytes.is_empty() {
-                                self.handles[index].as_mut().unwrap().merge_bytes(
-                                    None,
-                                    entity_bytes
-                                );
+                    } else {
+                        None
+                    };
+
+                    let offset = sequence_offset + if self.config.send_update_ticks { 4 } else { 0 };
+                    let config = &self.config;
+                    if let Some((entity_bytes, length)) = bytes.get(i + offset..).and_then(|b| deserialize_entity_bytes(config, b, 0)) {
+
+                        if self.handles[index].is_some() {
+
+                            local_state.update();
+
+                            if *local_state == LocalState::Update {
+                                // Rejected updates are neither counted nor acknowledged
+                                let handle = self.handles[index].as_mut().unwrap();
+                                let is_segmented = state == ServerNetworkState::SendSegmentsToClient as u8;
+                                if !entity_bytes.is_empty() && match tick {
+                                    _ if is_segmented => handle.merge_segments(None, entity_bytes),
+                                    Some(tick) => handle.merge_bytes_at(None, tick, entity_bytes),
+                                    None => handle.merge_bytes(None, entity_bytes)
+
+                                }.is_ok() {
+                                    if let Some(entity) = handle.get_entity() {
+                                        record_merge_bandwidth(&mut self.kind_bandwidth, entity.kind(), entity_bytes);
+                                    }
+                                    self.stats.updates_merged += 1;
+
+                                    if sequence_offset > 0 {
+                                        self.acknowledgements[index] = Some(bytes[i]);
+                                    }
+                                }
                             }
+
                         }
 
-                    }
+                        i += offset + length;
 
-                    i += length;
+                    } else {
+                        return Err(Error::TruncatedEntityData(index));
+                    }
 
                 },
                 Some(ServerNetworkState::SendDestroyToClient) => if self.handles[index].is_some() {
@@ -343,6 +1238,12 @@ impl<E: Entity<U> + ?Sized, U: fmt::Debug, R: EntityRegistry<E, U>> Client<E, U,
                     // Not however that we do not rely on full in-order receival of
                     // packets since we specifically support the case were create
                     // packets are received for not-yet destroyed entities.
+                    if self.handles[index].as_ref().unwrap().is_alive() {
+                        self.stats.entities_destroyed += 1;
+                        if let Some(ref mut events) = events {
+                            events.push(EntityEvent::Destroyed(EntityToken::new(index, self.index)));
+                        }
+                    }
                     self.handles[index].as_mut().unwrap().destroy();
                 },
                 Some(ServerNetworkState::SendForgetToClient) => if self.handles[index].is_some() {
@@ -354,6 +1255,12 @@ impl<E: Entity<U> + ?Sized, U: fmt::Debug, R: EntityRegistry<E, U>> Client<E, U,
                     // Not however that we do not rely on full in-order receival of
                     // packets since we specifically support the case were create
                     // packets are received for not-yet destroyed entities.
+                    if self.handles[index].as_ref().unwrap().is_alive() {
+                        self.stats.entities_forgotten += 1;
+                        if let Some(ref mut events) = events {
+                            events.push(EntityEvent::Forgotten(EntityToken::new(index, self.index)));
+                        }
+                    }
                     self.handles[index].as_mut().unwrap().forget();
                 },
                 None => return Err(Error::RemainingPacketData((&bytes[i..]).to_vec()))
@@ -368,6 +1275,15 @@ impl<E: Entity<U> + ?Sized, U: fmt::Debug, R: EntityRegistry<E, U>> Client<E, U,
 }
 
 
+// Helpers --------------------------------------------------------------------
+fn is_valid_create<E: Entity<U> + ?Sized, U: fmt::Debug>(entity: &E, bytes: &[u8]) -> bool {
+    match entity.validate_create_bytes(bytes) {
+        Some(consumed) => consumed == bytes.len(),
+        None => true
+    }
+}
+
+
 // Traits ---------------------------------------------------------------------
 impl<E: Entity<U> + ?Sized, R: EntityRegistry<E, U>, U: fmt::Debug> fmt::Debug for Client<E, U, R> {
     fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
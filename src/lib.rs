@@ -32,7 +32,7 @@ macro_rules! state_machine {
         }
     ) => (
         impl $name {$(
-            pub fn $method_name(&mut self) -> bool {
+            pub(crate) fn $method_name(&mut self) -> bool {
                 match *self {
                     $(
                         $x => {
@@ -79,6 +79,11 @@ mod client;
 /// and [`Server::connection_receive`](struct.Server.html#method.receive) to
 /// forward the packets of the custom protocol.
 ///
+/// Alternatively [`Client::receive_with`](struct.Client.html#method.receive_with)
+/// and [`Server::connection_receive_with`](struct.Server.html#method.connection_receive_with)
+/// forward custom protocol data to a closure, keeping it apart from actual
+/// protocol errors.
+///
 /// # Example
 ///
 /// ```norun
@@ -90,9 +95,18 @@ mod client;
 /// ```
 pub const NETWORK_BYTE_OFFSET: u8 = 8;
 
+/// The version of the client-server protocol, which is increased whenever
+/// the layout of its packets changes.
+///
+/// The version is part of the protocol handshake, see
+/// [`Client::handshake_packet`](struct.Client.html#method.handshake_packet)
+/// and
+/// [`Config::announce_version`](struct.Config.html#structfield.announce_version).
+pub const PROTOCOL_VERSION: u8 = 1;
+
 // Re-Exports -----------------------------------------------------------------
-pub use self::traits::{Entity, EntityRegistry};
-pub use server::{Server, ConnectionToken, EntityToken as ServerEntityToken, Error as ServerError};
-pub use client::{Client, EntityToken as ClientEntityToken, Error as ClientError};
-pub use shared::Config;
+pub use self::traits::{Entity, EntityRegistry, LengthCodec};
+pub use server::{Server, ConnectionToken, ConnectionSendEvent, EntityObserver, EntityRef, EntityToken as ServerEntityToken, Error as ServerError, Stats as ServerStats, UpdateStats as ServerUpdateStats, RemoteState};
+pub use client::{Client, EntityEvent as ClientEntityEvent, EntityToken as ClientEntityToken, Error as ClientError, Stats as ClientStats, LocalState};
+pub use shared::{Compressor, Config, ConfigBuilder, ConfigError, DropReason, ForgetMode, KindBandwidth, MergeError, PacketBuffer, PacketHeader, RegistryError, Relevance, SingleByte, SlotStrategy, TwoByte, Varint};
 
@@ -7,25 +7,58 @@
 // except according to those terms.
 
 // STD Dependencies -----------------------------------------------------------
+use std::cmp;
 use std::fmt;
+use std::collections::VecDeque;
+use std::ops::{Index, IndexMut};
 
 
 // Internal Dependencies ------------------------------------------------------
-use ::shared::Config;
-use ::traits::{Entity, EntitySerializer};
+use ::shared::{Config, serialize_entity_bytes, serialize_segments};
+use ::traits::{Entity, EntitySerializer, SerializeState};
 use super::{ConnectionToken, EntityToken, NetworkState};
 
 
 // Server Entity State --------------------------------------------------------
+/// The synchronisation state of a server side entity for a single client
+/// connection.
 #[derive(Debug, Eq, PartialEq, Ord, PartialOrd, Hash, Copy, Clone)]
 pub enum RemoteState {
+
+    /// The client has not yet confirmed the creation of the entity.
     Unknown,
+
+    /// The entity already existed when the connection was added and has not
+    /// yet been send to the client.
     Accept,
+
+    /// The client has confirmed the creation of the entity.
     Create,
+
+    /// The client has accepted the creation confirmation of the server and
+    /// the entity is being updated.
     Update,
+
+    /// The client has confirmed the destruction of the entity.
     Destroy,
+
+    /// The entity is filtered for the connection and the client is being told
+    /// to forget about it.
     Forget,
-    Forgotten
+
+    /// The client has confirmed that it forgot about the entity.
+    Forgotten,
+
+    /// The entity is filtered for the connection and the client is being told
+    /// to hide it.
+    Hide,
+
+    /// The client has confirmed that it hid the entity.
+    Hidden,
+
+    /// The entity is no longer filtered for the connection and the client is
+    /// being told to resume its updates.
+    Reveal
 }
 
 state_machine!(RemoteState, {
@@ -34,13 +67,245 @@ state_machine!(RemoteState, {
     reset_destroyed: RemoteState::Destroy => RemoteState::Unknown,
     reset_forgotten: RemoteState::Forgotten => RemoteState::Unknown,
     create: RemoteState::Unknown => RemoteState::Create,
-    update: RemoteState::Create => RemoteState::Update,
-    destroy: RemoteState::Accept | RemoteState::Create | RemoteState::Update => RemoteState::Destroy,
+    update: RemoteState::Create | RemoteState::Reveal => RemoteState::Update,
+    destroy: RemoteState::Accept | RemoteState::Create | RemoteState::Update
+           | RemoteState::Hide | RemoteState::Hidden | RemoteState::Reveal => RemoteState::Destroy,
     forget: RemoteState::Accept | RemoteState::Create | RemoteState::Update => RemoteState::Forget,
     forgotten: RemoteState::Forget => RemoteState::Forgotten,
+    hide: RemoteState::Accept | RemoteState::Create | RemoteState::Update | RemoteState::Reveal => RemoteState::Hide,
+    hidden: RemoteState::Hide => RemoteState::Hidden,
+    reveal: RemoteState::Hidden => RemoteState::Reveal,
 });
 
 
+// Server Entity State Map ----------------------------------------------------
+const UNKNOWN_STATE: RemoteState = RemoteState::Unknown;
+
+// Per connection synchronisation data of a single entity slot
+#[derive(Debug, Clone)]
+pub struct RemoteSlot {
+    pub index: usize,
+    pub state: RemoteState,
+    pub create_sent: bool,
+    pub create_resend: usize,
+    pub baseline: Baseline
+}
+
+impl RemoteSlot {
+
+    fn new(index: usize) -> RemoteSlot {
+        RemoteSlot {
+            index: index,
+            state: RemoteState::Unknown,
+            create_sent: false,
+            create_resend: 0,
+            baseline: Baseline::default()
+        }
+    }
+
+    fn is_tracked(&self) -> bool {
+        self.state != RemoteState::Unknown || self.create_sent || self.create_resend > 0
+    }
+
+}
+
+// Sparse per connection states which only track slots in a state other than
+// Unknown or with a pending creation, kept sorted by slot index
+#[derive(Debug, Default, Clone)]
+pub struct RemoteStates {
+    slots: Vec<RemoteSlot>
+}
+
+impl RemoteStates {
+
+    pub fn len(&self) -> usize {
+        self.slots.len()
+    }
+
+    pub fn iter<'a>(&'a self) -> impl Iterator<Item = &'a RemoteSlot> + 'a {
+        self.slots.iter()
+    }
+
+    pub fn get(&self, index: usize) -> Option<&RemoteSlot> {
+        match self.slots.binary_search_by_key(&index, |slot| slot.index) {
+            Ok(position) => Some(&self.slots[position]),
+            Err(_) => None
+        }
+    }
+
+    pub fn find_mut(&mut self, index: usize) -> Option<&mut RemoteSlot> {
+        match self.slots.binary_search_by_key(&index, |slot| slot.index) {
+            Ok(position) => Some(&mut self.slots[position]),
+            Err(_) => None
+        }
+    }
+
+    pub fn get_mut(&mut self, index: usize) -> &mut RemoteSlot {
+        // Untracked slots are inserted as Unknown and removed again once
+        // the states are compacted
+        let position = match self.slots.binary_search_by_key(&index, |slot| slot.index) {
+            Ok(position) => position,
+            Err(position) => {
+                self.slots.insert(position, RemoteSlot::new(index));
+                position
+            }
+        };
+        &mut self.slots[position]
+    }
+
+    pub fn create_sent(&self, index: usize) -> bool {
+        match self.get(index) {
+            Some(slot) => slot.create_sent,
+            None => false
+        }
+    }
+
+    pub fn remove(&mut self, index: usize) {
+        if let Ok(position) = self.slots.binary_search_by_key(&index, |slot| slot.index) {
+            self.slots.remove(position);
+        }
+    }
+
+    pub fn retain<F: FnMut(usize) -> bool>(&mut self, mut f: F) {
+        self.slots.retain(|slot| slot.is_tracked() && f(slot.index));
+    }
+
+    pub fn compact(&mut self) {
+        self.slots.retain(|slot| slot.is_tracked());
+    }
+
+    pub fn evict_oldest_baselines(&mut self, max: usize) {
+        let mut baselines: Vec<&mut Baseline> = self.slots.iter_mut().map(|slot| &mut slot.baseline).collect();
+        Baseline::evict_oldest(&mut baselines, max);
+    }
+
+}
+
+impl Index<usize> for RemoteStates {
+    type Output = RemoteState;
+    fn index(&self, index: usize) -> &RemoteState {
+        match self.get(index) {
+            Some(slot) => &slot.state,
+            None => &UNKNOWN_STATE
+        }
+    }
+}
+
+impl IndexMut<usize> for RemoteStates {
+    fn index_mut(&mut self, index: usize) -> &mut RemoteState {
+        &mut self.get_mut(index).state
+    }
+}
+
+
+// Server Entity Baseline -----------------------------------------------------
+const MAX_PENDING_BASELINES: usize = 128;
+
+#[derive(Debug, Default, Clone)]
+pub struct Baseline {
+    sequence: u8,
+    tick: usize,
+    acknowledged: Option<(usize, Vec<u8>)>,
+    pending: VecDeque<(u8, usize, Vec<u8>)>,
+    skipped_updates: usize,
+    update_cooldown: usize
+}
+
+impl Baseline {
+
+    pub fn acknowledged(&self) -> Option<&[u8]> {
+        self.acknowledged.as_ref().map(|acknowledged| &acknowledged.1[..])
+    }
+
+    pub fn acknowledged_tick(&self) -> Option<usize> {
+        self.acknowledged.as_ref().map(|acknowledged| acknowledged.0)
+    }
+
+    pub fn last_tick(&self) -> Option<usize> {
+        match self.pending.back() {
+            Some(&(_, tick, _)) => Some(tick),
+            None => self.acknowledged_tick()
+        }
+    }
+
+    pub fn set_tick(&mut self, tick: usize) {
+        self.tick = tick;
+    }
+
+    pub fn sequence(&self) -> u8 {
+        self.sequence
+    }
+
+    pub fn send(&mut self, bytes: &[u8]) -> u8 {
+
+        // Sequence numbers wrap around so we can only keep a limited number
+        // of unacknowledged updates around
+        if self.pending.len() == MAX_PENDING_BASELINES {
+            self.pending.pop_front();
+        }
+
+        self.sequence = self.sequence.wrapping_add(1);
+        self.pending.push_back((self.sequence, self.tick, bytes.to_vec()));
+        self.sequence
+
+    }
+
+    pub fn acknowledge(&mut self, sequence: u8) {
+        if let Some(index) = self.pending.iter().position(|&(s, _, _)| s == sequence) {
+            // Drop all updates older than the acknowledged one
+            self.acknowledged = self.pending.drain(0..index + 1).next_back().map(|(_, tick, bytes)| (tick, bytes));
+        }
+    }
+
+    pub fn skip_update(&mut self, interval: usize) -> bool {
+        // Only every n-th update is send, starting with the first one
+        let skip = self.skipped_updates > 0;
+        self.skipped_updates = (self.skipped_updates + 1) % cmp::max(interval, 1);
+        skip
+    }
+
+    pub fn cool_down(&mut self) -> bool {
+        // Updates are capped until the cooldown of the last one ran out
+        if self.update_cooldown > 0 {
+            self.update_cooldown -= 1;
+            true
+
+        } else {
+            false
+        }
+    }
+
+    pub fn start_cooldown(&mut self, rate: usize) {
+        self.update_cooldown = rate.saturating_sub(1);
+    }
+
+    pub fn evict_oldest(baselines: &mut [&mut Baseline], max: usize) {
+
+        let mut retained: Vec<(usize, usize)> = baselines.iter().enumerate().filter_map(|(index, baseline)| {
+            baseline.last_tick().map(|tick| (tick, index))
+
+        }).collect();
+
+        // Evict the least recently updated baselines first
+        if retained.len() > max {
+            retained.sort();
+            for &(_, index) in &retained[..retained.len() - max] {
+                baselines[index].reset();
+            }
+        }
+
+    }
+
+    pub fn reset(&mut self) {
+        self.acknowledged = None;
+        self.pending.clear();
+        self.skipped_updates = 0;
+        self.update_cooldown = 0;
+    }
+
+}
+
+
 // Server Entity Handle -------------------------------------------------------
 pub struct Serializer;
 impl<E: Entity<U> + ?Sized, U: fmt::Debug> EntitySerializer<E, RemoteState, EntityToken, U> for Serializer {
@@ -51,81 +316,124 @@ impl<E: Entity<U> + ?Sized, U: fmt::Debug> EntitySerializer<E, RemoteState, Enti
         connection_slot: Option<&ConnectionToken<U>>,
         state: &RemoteState,
         entity: Option<&mut Box<E>>,
-        update_tick: &mut u8
+        serialize_state: SerializeState
+
+    ) -> Result<Vec<u8>, ()> {
 
-    ) -> Vec<u8> {
+        let SerializeState { update_tick, dirty, baseline } = serialize_state;
 
         let index = token.index as u8;
         if let Some(entity) = entity {
             match *state {
 
                 RemoteState::Unknown => {
-                    let create_bytes = entity.to_bytes(connection_slot.unwrap());
-
-                    // TODO handle more than 255 bytes with bigger frames etc.
-                    if create_bytes.len() > 255 {
-                        panic!("More than 255 bytes in update!");
-                    }
-
+                    let create_bytes = entity.snapshot_bytes(connection_slot.unwrap());
                     let mut bytes = vec![
                         NetworkState::SendCreateToClient as u8,
-                        index,
-                        create_bytes.len() as u8,
-                        entity.kind()
+                        index
                     ];
+                    serialize_entity_bytes(config, &create_bytes, &mut bytes)?;
+                    let kind = entity.kind();
+                    bytes.push((kind >> 8) as u8);
+                    bytes.push(kind as u8);
                     bytes.extend_from_slice(&create_bytes);
-                    bytes
+                    Ok(bytes)
                 },
 
                 RemoteState::Create => {
-                    vec![NetworkState::ConfirmClientCreate as u8, index]
+                    Ok(vec![NetworkState::ConfirmClientCreate as u8, index])
                 },
 
-                RemoteState::Update => if let Some(update_bytes) = entity.part_bytes(connection_slot) {
+                RemoteState::Update => {
 
-                    // TODO handle more than 255 bytes with bigger frames etc.
-                    if update_bytes.len() > 255 {
-                        panic!("More than 255 bytes in update!");
-                    }
+                    // Entities without meaningful changes skip their serialization,
+                    // segmented updates are send with their own state
+                    let update = if dirty && entity.should_send_update() {
+                        match serialize_segments(entity.part_segments(connection_slot))? {
+                            Some(segment_bytes) => Some((NetworkState::SendSegmentsToClient, segment_bytes)),
+                            None => entity.part_bytes_delta(
+                                connection_slot,
+                                baseline.as_ref().and_then(|baseline| baseline.acknowledged())
 
-                    let mut bytes = vec![
-                        NetworkState::SendUpdateToClient as u8,
-                        index,
-                        update_bytes.len() as u8
-                    ];
-                    bytes.extend_from_slice(&update_bytes);
-                    bytes
+                            ).map(|update_bytes| (NetworkState::SendUpdateToClient, update_bytes))
+                        }
+
+                    } else {
+                        None
+                    };
+
+                    if let Some((update_state, update_bytes)) = update {
+
+                        // Encode the length up front so that updates which
+                        // cannot be send do not advance the baseline
+                        let mut length_bytes = Vec::new();
+                        serialize_entity_bytes(config, &update_bytes, &mut length_bytes)?;
+
+                        let mut bytes = vec![
+                            update_state as u8,
+                            index
+                        ];
+
+                        // Prefix the update with its sequence for acknowledgement
+                        if let Some(baseline) = baseline {
+                            if update_bytes.is_empty() {
+                                bytes.push(baseline.sequence());
 
-                } else if let Some(tick_threshold) = config.minimum_update_interval {
+                            } else {
+                                bytes.push(baseline.send(&update_bytes));
+                            }
+                        }
 
-                    *update_tick = update_tick.saturating_add(1);
+                        bytes.extend_from_slice(&length_bytes);
+                        bytes.extend_from_slice(&update_bytes);
+                        Ok(bytes)
 
-                    if *update_tick == tick_threshold {
-                        *update_tick = 0;
-                        vec![
-                            NetworkState::SendUpdateToClient as u8,
-                            index,
-                            0
-                        ]
+                    } else if let Some(tick_threshold) = config.minimum_update_interval {
+
+                        *update_tick = update_tick.saturating_add(1);
+
+                        if *update_tick >= tick_threshold {
+                            *update_tick = 0;
+                            let mut bytes = vec![
+                                NetworkState::SendUpdateToClient as u8,
+                                index
+                            ];
+                            if let Some(baseline) = baseline {
+                                bytes.push(baseline.sequence());
+                            }
+                            serialize_entity_bytes(config, &[], &mut bytes)?;
+                            Ok(bytes)
+
+                        } else {
+                          Ok(vec![])
+                        }
 
                     } else {
-                      vec![]
+                        Ok(vec![])
                     }
 
-                } else {
-                    vec![]
                 },
 
                 RemoteState::Forget => {
-                    vec![NetworkState::SendForgetToClient as u8, index]
+                    Ok(vec![NetworkState::SendForgetToClient as u8, index])
+                },
+
+                RemoteState::Hide => {
+                    Ok(vec![NetworkState::SendHideToClient as u8, index])
+                },
+
+                // Revealed entities repeat the creation confirmation so the
+                // client accepts updates for them again
+                RemoteState::Reveal => {
+                    Ok(vec![NetworkState::ConfirmClientCreate as u8, index])
                 },
 
-                _ => vec![]
+                _ => Ok(vec![])
 
             }
 
         } else {
-            vec![NetworkState::SendDestroyToClient as u8, index]
+            Ok(vec![NetworkState::SendDestroyToClient as u8, index])
         }
 
     }
@@ -8,18 +8,28 @@
 
 // STD Dependencies -----------------------------------------------------------
 use std::fmt;
+use std::borrow::Cow;
+use std::ops::Range;
+use std::collections::HashMap;
 use std::sync::atomic::{AtomicUsize, Ordering};
 
 
 // Internal Dependencies ------------------------------------------------------
+use ::NETWORK_BYTE_OFFSET;
 use ::traits::Entity;
 use ::client::NetworkState as ClientNetworkState;
-use ::shared::{Config, EntityHandle, PacketList, deserialize_entity_bytes};
+use ::shared::{
+    Config, CustomPacketCallback, DropReason, EntityHandle, ForgetMode, FragmentBuffer, KindBandwidth,
+    PacketBuffer, PacketList, Relevance, STREAM_PACKET_BYTES, SlotStrategy, decompress_packet,
+    deserialize_entity_bytes, next_stream_packet, record_merge_bandwidth, record_part_bandwidth,
+    serialize_entity_bytes, stream_bytes, strip_packet_header, update_tick_bytes, verify_handshake, verify_packet_checksum, version_bytes
+};
 
 
 // Modules --------------------------------------------------------------------
 mod entity;
-use self::entity::{Serializer, RemoteState};
+use self::entity::{RemoteStates, Serializer};
+pub use self::entity::{Baseline, RemoteState};
 
 
 /// A unique token that grants access to a client connection on a entity
@@ -38,6 +48,16 @@ pub struct ConnectionToken<U: fmt::Debug> {
     server_index: usize
 }
 
+impl<U: fmt::Debug> ConnectionToken<U> {
+
+    /// Returns the slot index of the connection on its server, as listed by
+    /// [`Server::active_connection_indices`](struct.Server.html#method.active_connection_indices).
+    pub fn index(&self) -> usize {
+        self.index
+    }
+
+}
+
 
 /// A unique token that grantsencapsulating access to a server side
 /// [`Entity`](trait.Entity.html).
@@ -48,16 +68,68 @@ pub struct ConnectionToken<U: fmt::Debug> {
 #[derive(Debug, Eq, PartialEq, Hash)]
 pub struct EntityToken {
     index: usize,
-    server_index: usize
+    server_index: usize,
+    generation: usize
 }
 
 impl EntityToken {
-    fn new(index: usize, server_index: usize) -> EntityToken {
+
+    fn new(index: usize, server_index: usize, generation: usize) -> EntityToken {
         EntityToken {
             index: index,
-            server_index: server_index
+            server_index: server_index,
+            generation: generation
+        }
+    }
+
+    /// Returns a copyable [`EntityRef`](struct.EntityRef.html) to the entity
+    /// referenced by the token.
+    pub fn as_ref(&self) -> EntityRef {
+        EntityRef {
+            index: self.index,
+            server_index: self.server_index,
+            generation: self.generation
         }
     }
+
+}
+
+/// A lightweight, copyable reference to a server side entity which is
+/// obtained via [`EntityToken::as_ref`](struct.EntityToken.html#method.as_ref).
+///
+/// References can be used to access the entity via
+/// [`Server::entity_get`](struct.Server.html#method.entity_get) and
+/// [`Server::entity_get_mut`](struct.Server.html#method.entity_get_mut), but
+/// destroying the entity still requires its owned
+/// [`EntityToken`](struct.EntityToken.html). Once the entity has been
+/// destroyed its references no longer resolve, even when its slot gets
+/// re-used by a new entity.
+#[derive(Debug, Eq, PartialEq, Hash, Copy, Clone)]
+pub struct EntityRef {
+    index: usize,
+    server_index: usize,
+    generation: usize
+}
+
+impl<'a> From<&'a EntityToken> for EntityRef {
+    fn from(entity_token: &'a EntityToken) -> EntityRef {
+        entity_token.as_ref()
+    }
+}
+
+/// A non-owning, read-only reference to a server side entity.
+///
+/// Observers are obtained via
+/// [`Server::entity_observer`](struct.Server.html#method.entity_observer)
+/// and can be cloned freely. Unlike an
+/// [`EntityToken`](struct.EntityToken.html) they cannot be used to modify or
+/// destroy the entity and they stop resolving once the entity has been
+/// destroyed, even when its slot gets re-used by a new entity.
+#[derive(Debug, Eq, PartialEq, Hash, Clone)]
+pub struct EntityObserver {
+    index: usize,
+    server_index: usize,
+    generation: usize
 }
 
 
@@ -69,6 +141,11 @@ pub enum Error {
     /// further entities can be created.
     AllEntityTokensInUse,
 
+    /// Returned by [`Server::entity_create_at`](struct.Server.html#method.entity_create_at)
+    /// when the requested slot is still occupied by an alive entity or by a
+    /// destroyed one awaiting confirmation.
+    EntitySlotInUse(u8),
+
     /// Returned when all connection tokens of a server are already in use and
     /// no further connections can be added.
     AllConnectionTokensInUse,
@@ -93,7 +170,130 @@ pub enum Error {
     /// Returned by [`Server::connection_receive`](struct.Server.html#method.receive)
     /// when the passed vector of bytes did contain additional data which is not part
     /// of the underlying client-server protocol.
-    RemainingPacketData(Vec<u8>)
+    RemainingPacketData(Vec<u8>),
+
+    /// Returned by [`Server::connection_receive`](struct.Server.html#method.receive)
+    /// when [`Config::packet_checksum`](struct.Config.html#structfield.packet_checksum)
+    /// is enabled and the passed vector of bytes is missing its checksum or
+    /// was corrupted.
+    ///
+    /// The packet is discarded without being parsed.
+    ChecksumMismatch(Vec<u8>),
+
+    /// Returned by [`Server::accept_handshake`](struct.Server.html#method.accept_handshake)
+    /// when the handshake packet does not match the protocol magic or version
+    /// of the library.
+    ProtocolMismatch,
+
+    /// Returned by [`Server::connection_receive`](struct.Server.html#method.receive)
+    /// when [`Entity::merge_bytes`](trait.Entity.html#method.merge_bytes)
+    /// rejected the update bytes send by the client.
+    ///
+    /// Contains the slot index of the connection and of the entity, the
+    /// remainder of the packet is discarded.
+    RejectedEntityUpdate(usize, usize),
+
+    /// Returned by [`Server::connection_receive`](struct.Server.html#method.receive)
+    /// when the length of an entity's update bytes exceeds the remainder of
+    /// the packet or an acknowledgement is missing its update sequence.
+    ///
+    /// Contains the slot index of the entity, the remainder of the packet is
+    /// discarded. [`Server::connection_receive_partial`](struct.Server.html#method.connection_receive_partial)
+    /// instead stops in front of such an update so it can be completed later.
+    TruncatedEntityData(usize),
+
+    /// Returned by [`Server::connection_send`](struct.Server.html#method.connection_send)
+    /// when the bytes of an entity cannot be fragmented into packets of the
+    /// passed in maximum size, which is contained in the error, or when they
+    /// exceed the maximum length of the configured
+    /// [`length_codec`](struct.Config.html#structfield.length_codec).
+    ///
+    /// Nothing is send in this case, see
+    /// [`ConfigBuilder::max_bytes_per_packet`](struct.ConfigBuilder.html#method.max_bytes_per_packet)
+    /// for validating the packet size up front.
+    PacketSizeTooSmall(usize),
+
+    /// Returned by [`Server::connection_receive_stream`](struct.Server.html#method.connection_receive_stream) when the length prefix of a packet is malformed
+    /// or exceeds the maximum stream packet size of `65535` bytes.
+    ///
+    /// The remainder of the stream cannot be recovered in this case.
+    InvalidStreamFrame,
+
+    /// Returned when sending to, receiving from or resynchronising a
+    /// connection which was suspended via
+    /// [`Server::connection_suspend`](struct.Server.html#method.connection_suspend).
+    ConnectionSuspended
+
+}
+
+
+/// Statistics accumulated by a [`Server`](struct.Server.html) across calls to
+/// its entity and connection methods.
+///
+/// The counters keep increasing until they are explicitly cleared via
+/// [`Server::reset_stats`](struct.Server.html#method.reset_stats), resetting
+/// them once per tick yields per-tick statistics.
+#[derive(Debug, Default, Eq, PartialEq, Clone)]
+pub struct Stats {
+
+    /// Number of entities created via
+    /// [`Server::entity_create_with`](struct.Server.html#method.entity_create_with).
+    pub entities_created: usize,
+
+    /// Number of entities destroyed via
+    /// [`Server::entity_destroy`](struct.Server.html#method.entity_destroy).
+    pub entities_destroyed: usize,
+
+    /// Total number of bytes returned from
+    /// [`Server::connection_send`](struct.Server.html#method.connection_send)
+    /// across all connections.
+    pub bytes_sent: usize,
+
+    /// Number of client updates which were merged into entities via
+    /// [`Entity::merge_bytes`](trait.Entity.html#method.merge_bytes).
+    pub updates_merged: usize
+
+}
+
+
+/// An entity lifecycle event which is part of the packets returned from
+/// [`Server::connection_send_with_events`](struct.Server.html#method.connection_send_with_events).
+#[derive(Debug, Eq, PartialEq, Hash, Clone)]
+pub enum ConnectionSendEvent {
+
+    /// The creation of the entity was send to the client.
+    Created(EntityObserver),
+
+    /// The destruction of the entity was send to the client.
+    Destroyed(EntityObserver),
+
+    /// The client was told to forget about the entity.
+    Forgotten(EntityObserver),
+
+    /// The client was told to hide the entity.
+    Hidden(EntityObserver)
+
+}
+
+/// A summary of a single call to
+/// [`Server::update_entities_with`](struct.Server.html#method.update_entities_with).
+#[derive(Debug, Default, Eq, PartialEq, Clone)]
+pub struct UpdateStats {
+
+    /// Number of handles of destroyed entities which were dropped, including
+    /// the ones which timed out.
+    pub handles_dropped: usize,
+
+    /// Number of handles of destroyed entities which were dropped because not
+    /// all connections confirmed the destruction in time.
+    pub handles_timed_out: usize,
+
+    /// Number of alive entities the closure was called on.
+    pub alive: usize,
+
+    /// Number of alive entities the closure was skipped for since they were
+    /// able to sleep while not being observed by any connection.
+    pub sleeping: usize
 
 }
 
@@ -103,9 +303,11 @@ pub enum Error {
 pub enum NetworkState {
     SendCreateToClient = 0,
     ConfirmClientCreate = 1,
+    SendHideToClient = 2,
     SendUpdateToClient = 3,
     SendDestroyToClient = 4,
-    SendForgetToClient = 5
+    SendForgetToClient = 5,
+    SendSegmentsToClient = 6
 }
 
 impl NetworkState {
@@ -114,15 +316,17 @@ impl NetworkState {
         match state {
             0 => Some(NetworkState::SendCreateToClient),
             1 => Some(NetworkState::ConfirmClientCreate),
+            2 => Some(NetworkState::SendHideToClient),
             3 => Some(NetworkState::SendUpdateToClient),
             4 => Some(NetworkState::SendDestroyToClient),
             5 => Some(NetworkState::SendForgetToClient),
+            6 => Some(NetworkState::SendSegmentsToClient),
             _ => None
         }
     }
 
     pub fn is_potential_packet(first_byte: u8) -> bool {
-        first_byte <= 5
+        first_byte <= 6
     }
 
 }
@@ -137,37 +341,166 @@ type ServerEntityHandle<E, U> = Vec<
     Option<EntityHandle<E, Serializer, RemoteState, EntityToken, U>>
 >;
 
+type HiddenCallback<U> = Box<dyn FnMut(&ConnectionToken<U>) + Send + Sync>;
+type SendInspector<U> = Box<dyn FnMut(&ConnectionToken<U>, &[u8]) + Send + Sync>;
+
 /// Server side abstraction for entity synchronisation.
 ///
 /// A server can manage up to 256 entities at once.
+///
+/// All methods which iterate over the entities of the server, including the
+/// serialization within
+/// [`Server::connection_send`](struct.Server.html#method.connection_send),
+/// visit them in ascending order of their slot index. This order does not
+/// depend on when the entities were created or destroyed and is therefore
+/// deterministic for lockstep simulations.
+///
+/// A server is `Send` and `Sync` as long as its entity type `E` and the
+/// connection user data `U` are, this also applies to the `ConnectionToken`,
+/// `EntityToken`, `EntityRef` and `EntityObserver` types. Entities are
+/// usually boxed trait objects, in which case `E` should be declared as
+/// `Entity<U> + Send` in order to move the server across threads and as
+/// `Entity<U> + Send + Sync` in order to share it.
 pub struct Server<E: Entity<U> + ?Sized, U: fmt::Debug> {
     index: usize,
     handles: ServerEntityHandle<E, U>,
     active_handles: Vec<(EntityToken, Option<usize>, usize, bool)>,
     active_connections: Vec<usize>,
-    connections: Vec<Option<[RemoteState; 256]>>,
-    config: Config
+    connections: Vec<Option<RemoteStates>>,
+    fragments: Vec<FragmentBuffer>,
+    pending_destroys: Vec<Vec<(u8, usize)>>,
+    handshakes: Vec<bool>,
+    keepalives: Vec<bool>,
+    visible: Vec<bool>,
+    versions_sent: Vec<bool>,
+    suspended: Vec<Option<Vec<(usize, usize)>>>,
+    hidden_callback: Option<HiddenCallback<U>>,
+    send_inspector: Option<SendInspector<U>>,
+    tick: usize,
+    config: Config,
+    stats: Stats,
+    kind_bandwidth: HashMap<u16, KindBandwidth>,
+    slot_random: u64,
+    slot_release_ticks: Vec<usize>,
+    slot_release_tick: usize,
+    slot_generations: Vec<usize>,
+    slot_reserved: Vec<bool>,
+    updates_paused: bool
 }
 
 impl<E: Entity<U> + ?Sized, U: fmt::Debug> Server<E, U> {
 
     /// Creates a new entity server.
     pub fn new(config: Config) -> Server<E, U> {
+        Server::with_id(config, SERVER_INDEX.fetch_add(1, Ordering::SeqCst))
+    }
+
+    /// Creates a new entity server with a caller supplied instance index
+    /// instead of one taken from the process wide counter.
+    ///
+    /// This allows for reproducible server identities, e.g. when replaying a
+    /// deterministic simulation.
+    ///
+    /// > Note: Servers which share an instance index will accept each others
+    /// > tokens, it is up to the caller to keep the supplied ids unique.
+    pub fn with_id(config: Config, id: usize) -> Server<E, U> {
         Server {
-            index: SERVER_INDEX.fetch_add(1, Ordering::SeqCst),
+            index: id,
             handles: vec_with_default![None; 256],
             active_handles: Vec::new(),
             active_connections: Vec::new(),
             connections: vec_with_default![None; 256],
-            config: config
+            fragments: vec_with_default![FragmentBuffer::default(); 256],
+            pending_destroys: vec_with_default![Vec::new(); 256],
+            handshakes: vec![false; 256],
+            keepalives: vec![false; 256],
+            visible: vec![false; 256],
+            versions_sent: vec![false; 256],
+            suspended: vec_with_default![None; 256],
+            hidden_callback: None,
+            send_inspector: None,
+            tick: 0,
+            slot_random: slot_random_seed(&config),
+            config: config,
+            stats: Stats::default(),
+            kind_bandwidth: HashMap::new(),
+            slot_release_ticks: vec![0; 256],
+            slot_release_tick: 0,
+            slot_generations: vec![0; 256],
+            slot_reserved: vec![false; 256],
+            updates_paused: false
         }
     }
 
+    /// Returns the unique instance index of the server which is embedded into
+    /// all of its tokens.
+    pub fn instance_index(&self) -> usize {
+        self.index
+    }
+
+    /// Overrides the instance index of the server.
+    ///
+    /// This is only intended for tests which require reproducible indices,
+    /// tokens which were created before calling this method will no longer
+    /// be accepted by the server.
+    ///
+    /// > Note: Servers which share an instance index will accept each others
+    /// > tokens.
+    #[cfg(feature = "testing")]
+    pub fn set_instance_index(&mut self, index: usize) {
+        self.index = index;
+    }
+
     /// Overrides server's current configuration with the one provided.
+    ///
+    /// In case the new configuration uses `SlotStrategy::Random` the slot
+    /// allocation is re-seeded.
     pub fn set_config(&mut self, config: Config) {
+        self.slot_random = slot_random_seed(&config);
         self.config = config;
     }
 
+    /// Pauses or resumes the serialization of entity updates for all
+    /// connections.
+    ///
+    /// While paused, creations, destructions and their confirmations still
+    /// progress as usual, only the update parts of alive entities are no
+    /// longer send.
+    ///
+    /// > Note: Forced keepalives are still send while updates are paused.
+    pub fn set_updates_paused(&mut self, paused: bool) {
+        self.updates_paused = paused;
+    }
+
+    /// Returns whether entity updates are currently paused.
+    pub fn updates_paused(&self) -> bool {
+        self.updates_paused
+    }
+
+    /// Returns the statistics accumulated since the server was created or
+    /// [`Server::reset_stats`](struct.Server.html#method.reset_stats) was last
+    /// called.
+    pub fn stats(&self) -> &Stats {
+        &self.stats
+    }
+
+    /// Resets all accumulated statistics back to zero.
+    pub fn reset_stats(&mut self) {
+        self.stats = Stats::default();
+        self.kind_bandwidth.clear();
+    }
+
+    /// Returns the update bandwidth accumulated per entity kind since the
+    /// server was created or
+    /// [`Server::reset_stats`](struct.Server.html#method.reset_stats) was last
+    /// called.
+    ///
+    /// This covers the updates send to all connections as well as the ones
+    /// received from them.
+    pub fn kind_bandwidth(&self) -> &HashMap<u16, KindBandwidth> {
+        &self.kind_bandwidth
+    }
+
     /// Creates a new entity via the specified closure and returns a `EntityToken`
     /// granting access to it.
     ///
@@ -183,38 +516,226 @@ impl<E: Entity<U> + ?Sized, U: fmt::Debug> Server<E, U> {
     ) -> Result<EntityToken, Error> {
 
         if let Some(index) = self.find_free_entity_slot_index() {
+            Ok(self.create_entity_in_slot(index, callback()))
 
-            // Create entity handle which encapsulates the actual entity
-            let mut handle = EntityHandle::new(
-                EntityToken::new(index, self.index),
-                callback()
-            );
+        } else {
+            Err(Error::AllEntityTokensInUse)
+        }
 
-            handle.create();
+    }
 
-            self.handles[index] = Some(handle);
+    /// Creates a new entity via the specified closure in the slot at `index`
+    /// and returns a `EntityToken` granting access to it.
+    ///
+    /// This allows entities to keep stable slot indices across sessions,
+    /// regardless of the configured
+    /// [`SlotStrategy`](enum.SlotStrategy.html). Entities created via
+    /// [`Server::entity_create_with`](struct.Server.html#method.entity_create_with)
+    /// will simply skip over slots which are taken explicitly.
+    ///
+    /// The closure used for entity construction will only be executed in case
+    /// the slot is actually free.
+    ///
+    /// Otherwise `Error::EntitySlotInUse` will be returned.
+    pub fn entity_create_at<F: FnOnce() -> Box<E>>(
+        &mut self,
+        index: u8,
+        callback: F
 
-            // Add to list of active slots
-            self.active_handles.push((
-                EntityToken::new(index, self.index),
-                None,
-                self.active_connections.len(),
-                true
-            ));
+    ) -> Result<EntityToken, Error> {
 
-            // Return a unique handle which cannot be copied
-            Ok(EntityToken::new(index, self.index))
+        if self.handles[index as usize].is_none() {
+            Ok(self.create_entity_in_slot(index as usize, callback()))
 
         } else {
-            Err(Error::AllEntityTokensInUse)
+            Err(Error::EntitySlotInUse(index))
+        }
+
+    }
+
+    /// Excludes the slots in `range` from the automatic slot allocation of
+    /// [`Server::entity_create_with`](struct.Server.html#method.entity_create_with)
+    /// and [`Server::entity_create_batch`](struct.Server.html#method.entity_create_batch).
+    ///
+    /// Reserved slots can only be filled explicitly via
+    /// [`Server::entity_create_at`](struct.Server.html#method.entity_create_at),
+    /// which allows singleton entities to keep well known slot indices.
+    /// Reservations accumulate across calls and slots past `255` are
+    /// ignored.
+    pub fn reserve_slots(&mut self, range: Range<usize>) {
+        for reserved in self.slot_reserved.iter_mut().skip(range.start).take(range.end.saturating_sub(range.start)) {
+            *reserved = true;
+        }
+    }
+
+    /// Creates `count` new entities via the specified closure and returns the
+    /// `EntityToken`s granting access to them.
+    ///
+    /// The closure is called with the index of each entity within the batch
+    /// and will only be executed in case enough free `EntityToken`s are
+    /// available for the whole batch.
+    ///
+    /// Otherwise no entities are created and `Error::AllEntityTokensInUse`
+    /// will be returned.
+    pub fn entity_create_batch<F: FnMut(usize) -> Box<E>>(
+        &mut self,
+        count: usize,
+        mut callback: F
+
+    ) -> Result<Vec<EntityToken>, Error> {
+
+        let free: Vec<usize> = (0..256).filter(|i| self.is_free_entity_slot(*i)).collect();
+        if count > free.len() {
+            return Err(Error::AllEntityTokensInUse);
         }
 
+        self.active_handles.reserve(count);
+
+        let mut free = free.into_iter();
+        let mut tokens = Vec::with_capacity(count);
+        for i in 0..count {
+            // Lowest slots are taken in order without re-scanning
+            let index = match self.config.slot_strategy {
+                SlotStrategy::Lowest => free.next(),
+                _ => self.find_free_entity_slot_index()
+
+            }.expect("Free slots were counted in advance.");
+            tokens.push(self.create_entity_in_slot(index, callback(i)));
+        }
+
+        Ok(tokens)
+
+    }
+
+    /// Returns whether the entity referenced by the `EntityToken` belongs to
+    /// the server and has not yet been destroyed.
+    pub fn entity_is_alive(&self, entity_token: &EntityToken) -> bool {
+        self.is_current_ref(&entity_token.as_ref()) && match self.handles[entity_token.index] {
+            Some(ref handle) => handle.is_alive(),
+            None => false
+        }
     }
 
     /// Returns an immutable reference to the boxed entity referenced by the
-    /// `EntityToken`.
-    pub fn entity_get(&self, entity_token: &EntityToken) -> Option<&Box<E>> {
-        if let Some(ref handle) = self.handles[entity_token.index] {
+    /// `EntityToken` or [`EntityRef`](struct.EntityRef.html).
+    pub fn entity_get<R: Into<EntityRef>>(&self, entity_ref: R) -> Option<&Box<E>> {
+        let entity_ref = entity_ref.into();
+        if !self.is_current_ref(&entity_ref) {
+            None
+
+        } else if let Some(ref handle) = self.handles[entity_ref.index] {
+            if handle.is_alive() {
+                handle.get_entity()
+
+            } else {
+                None
+            }
+
+        } else {
+            None
+        }
+    }
+
+    /// Marks the entity referenced by the `EntityToken` as changed, so that
+    /// it is serialized by the next calls to
+    /// [`Server::connection_send`](struct.Server.html#method.connection_send).
+    ///
+    /// This allows gameplay code which mutates entities outside of
+    /// [`Server::update_entities_with_dirty`](struct.Server.html#method.update_entities_with_dirty)
+    /// to flag them for serialization. The mark lasts until the next update
+    /// replaces it with the result of its closure.
+    ///
+    /// Tokens which do not belong to the server are ignored.
+    pub fn entity_mark_dirty(&mut self, entity_token: &EntityToken) {
+        if self.is_current_ref(&entity_token.as_ref()) {
+            if let Some(handle) = self.handles[entity_token.index].as_mut() {
+                handle.set_dirty(true);
+            }
+        }
+    }
+
+    /// Sets the connection which owns the entity referenced by the
+    /// `EntityToken`, passing `None` removes the current owner.
+    ///
+    /// The entity is notified via
+    /// [`Entity::owner_changed`](trait.Entity.html#method.owner_changed) and
+    /// marked as dirty so that the change is serialized by the next calls to
+    /// [`Server::connection_send`](struct.Server.html#method.connection_send).
+    ///
+    /// Tokens which do not belong to the server or refer to a connection
+    /// which is no longer connected are ignored.
+    pub fn entity_set_owner(&mut self, entity_token: &EntityToken, owner: Option<&ConnectionToken<U>>) {
+        let is_invalid_owner = match owner {
+            Some(owner) => !self.is_live_connection(owner),
+            None => false
+        };
+        if self.is_current_ref(&entity_token.as_ref()) && !is_invalid_owner {
+            if let Some(handle) = self.handles[entity_token.index].as_mut() {
+                handle.set_owner(owner.map(|owner| owner.index));
+            }
+        }
+    }
+
+    /// Returns the connection index of the owner of the entity referenced by
+    /// the `EntityToken`, see
+    /// [`Server::entity_set_owner`](struct.Server.html#method.entity_set_owner).
+    pub fn entity_owner(&self, entity_token: &EntityToken) -> Option<usize> {
+        if self.is_current_ref(&entity_token.as_ref()) {
+            self.handles[entity_token.index].as_ref().and_then(|handle| handle.owner())
+
+        } else {
+            None
+        }
+    }
+
+    /// Returns the tokens of all alive entities which are owned by one of the
+    /// passed in connections whose `user_data` matches the predicate, see
+    /// [`Server::entity_set_owner`](struct.Server.html#method.entity_set_owner).
+    ///
+    /// Since the `user_data` is stored on the `ConnectionToken`s themselves,
+    /// the connections to consider have to be passed in, tokens which do
+    /// not belong to the server or refer to a connection which is no longer
+    /// connected are ignored.
+    pub fn entities_owned_where<'a, I, P>(&self, connections: I, predicate: P) -> Vec<&EntityToken>
+        where I: IntoIterator<Item = &'a ConnectionToken<U>>, P: Fn(&U) -> bool, U: 'a {
+
+        let mut owners = [false; 256];
+        for connection_token in connections {
+            if self.is_live_connection(connection_token) && predicate(&connection_token.user_data) {
+                owners[connection_token.index] = true;
+            }
+        }
+
+        self.active_handles.iter().filter_map(|&(ref entity_token, _, _, _)| {
+            match self.handles[entity_token.index] {
+                Some(ref handle) if handle.is_alive() => match handle.owner() {
+                    Some(owner) if owners[owner] => Some(entity_token),
+                    _ => None
+                },
+                _ => None
+            }
+
+        }).collect()
+
+    }
+
+    /// Returns a cloneable, read-only observer for the entity referenced by
+    /// the `EntityToken`.
+    pub fn entity_observer(&self, entity_token: &EntityToken) -> EntityObserver {
+        EntityObserver {
+            index: entity_token.index,
+            server_index: entity_token.server_index,
+            generation: entity_token.generation
+        }
+    }
+
+    /// Returns a reference to the boxed entity referenced by the
+    /// `EntityObserver` or `None` in case the entity has been destroyed.
+    pub fn entity_observe(&self, observer: &EntityObserver) -> Option<&Box<E>> {
+        if observer.server_index != self.index || observer.generation != self.slot_generations[observer.index] {
+            None
+
+        } else if let Some(ref handle) = self.handles[observer.index] {
             if handle.is_alive() {
                 handle.get_entity()
 
@@ -228,9 +749,13 @@ impl<E: Entity<U> + ?Sized, U: fmt::Debug> Server<E, U> {
     }
 
     /// Returns a mutable reference to the boxed entity referenced by the
-    /// `EntityToken`.
-    pub fn entity_get_mut(&mut self, entity_token: &EntityToken) -> Option<&mut Box<E>> {
-        if let Some(ref mut handle) = self.handles[entity_token.index] {
+    /// `EntityToken` or [`EntityRef`](struct.EntityRef.html).
+    pub fn entity_get_mut<R: Into<EntityRef>>(&mut self, entity_ref: R) -> Option<&mut Box<E>> {
+        let entity_ref = entity_ref.into();
+        if !self.is_current_ref(&entity_ref) {
+            None
+
+        } else if let Some(ref mut handle) = self.handles[entity_ref.index] {
             if handle.is_alive() {
                 handle.get_entity_mut()
 
@@ -244,18 +769,37 @@ impl<E: Entity<U> + ?Sized, U: fmt::Debug> Server<E, U> {
     }
 
     /// Destroys the entity referenced by the `EntityToken`.
+    ///
+    /// Returns the token in case it does not belong to the server or its
+    /// slot has already been re-used by a new entity.
     pub fn entity_destroy(
         &mut self,
         entity_token: EntityToken
 
     ) -> Result<(), EntityToken> {
 
-        if entity_token.server_index != self.index {
+        if !self.is_current_ref(&entity_token.as_ref()) {
             Err(entity_token)
 
         } else if let Some(handle) = self.handles[entity_token.index].as_mut() {
             if handle.is_alive() {
-                handle.destroy();
+                let is_transient = handle.is_transient();
+
+                // Only retain the entity while connections still need to
+                // confirm its destruction
+                let is_counted = self.active_handles.iter().any(|&(ref token, _, connection_count, _)| {
+                    token.index == entity_token.index && connection_count > 0
+                });
+
+                handle.retire();
+                if !is_counted {
+                    handle.dropped(DropReason::Confirmed);
+                }
+
+                self.stats.entities_destroyed += 1;
+                if is_transient {
+                    self.release_handle(entity_token.index);
+                }
                 Ok(())
 
             } else {
@@ -268,6 +812,109 @@ impl<E: Entity<U> + ?Sized, U: fmt::Debug> Server<E, U> {
 
     }
 
+    /// Destroys the entity referenced by the `EntityToken` and hands the
+    /// ownership of its box back to the caller.
+    ///
+    /// The entity's [`Entity::destroyed`](trait.Entity.html#method.destroyed)
+    /// method is called and connected clients are told to destroy the entity
+    /// just like with
+    /// [`Server::entity_destroy`](struct.Server.html#method.entity_destroy).
+    /// The entity's handle is removed right away and its slot is free for
+    /// re-use, so just like for
+    /// [`Entity::transient`](trait.Entity.html#method.transient) entities
+    /// the destruction is only send once and never re-send.
+    ///
+    /// Returns the token in case it does not belong to the server, the
+    /// entity has already been destroyed or its slot has been re-used.
+    pub fn entity_take(
+        &mut self,
+        entity_token: EntityToken
+
+    ) -> Result<Box<E>, EntityToken> {
+
+        if !self.is_current_ref(&entity_token.as_ref()) {
+            Err(entity_token)
+
+        } else if let Some(handle) = self.handles[entity_token.index].as_mut() {
+            if let Some(entity) = handle.take() {
+                self.stats.entities_destroyed += 1;
+                self.release_handle(entity_token.index);
+                Ok(entity)
+
+            } else {
+                Err(entity_token)
+            }
+
+        } else {
+            Err(entity_token)
+        }
+
+    }
+
+    /// Destroys all alive entities of the server at once.
+    ///
+    /// This behaves exactly like calling
+    /// [`Server::entity_destroy`](struct.Server.html#method.entity_destroy)
+    /// for every entity, connected clients will be told to destroy their
+    /// entities and the handles are dropped once all destructions have been
+    /// confirmed or timed out.
+    pub fn entity_destroy_all(&mut self) {
+        let mut transient_indices = Vec::new();
+        for &(ref entity_token, _, _, _) in &self.active_handles {
+            if let Some(handle) = self.handles[entity_token.index].as_mut() {
+                if handle.is_alive() {
+                    if handle.is_transient() {
+                        transient_indices.push(entity_token.index);
+                    }
+                    handle.retire();
+                    self.stats.entities_destroyed += 1;
+                }
+            }
+        }
+        for index in transient_indices {
+            self.release_handle(index);
+        }
+    }
+
+    /// Returns the number of entity handles which currently occupy a slot,
+    /// including those whose entity has already been destroyed.
+    pub fn entity_count(&self) -> usize {
+        self.active_handles.len()
+    }
+
+    /// Returns the number of entities which are still alive.
+    ///
+    /// Unlike [`Server::entity_count`](struct.Server.html#method.entity_count)
+    /// destroyed entities are no longer counted, even while their handle has
+    /// not been reclaimed yet.
+    pub fn live_entity_count(&self) -> usize {
+        self.active_handles.iter().filter(|&&(ref entity_token, _, _, _)| {
+            match self.handles[entity_token.index] {
+                Some(ref handle) => handle.is_alive(),
+                None => false
+            }
+
+        }).count()
+    }
+
+    /// Returns the number of handles whose entity has been destroyed but
+    /// whose slot has not been reclaimed yet.
+    ///
+    /// Destroyed handles are kept around until all connections confirmed the
+    /// destruction or until
+    /// [`Config::handle_timeout_ticks`](struct.Config.html#structfield.handle_timeout_ticks)
+    /// runs out, so a steadily growing count usually indicates connections
+    /// which never respond.
+    pub fn pending_handle_count(&self) -> usize {
+        self.active_handles.iter().filter(|&&(ref entity_token, _, _, _)| {
+            match self.handles[entity_token.index] {
+                Some(ref handle) => !handle.is_alive(),
+                None => false
+            }
+
+        }).count()
+    }
+
     /// Takes a closure and iterates over all active entities of the server,
     /// calling that closure on each entity while collecting the return value
     /// into a vector.
@@ -305,70 +952,174 @@ impl<E: Entity<U> + ?Sized, U: fmt::Debug> Server<E, U> {
         }
     }
 
-    /// Takes a closure and iterates over all active entities of the client,
-    /// updating their state and calling that closure on each entity.
-    ///
-    /// This is the main update function of the server and should be called
-    /// exactly once per time step.
-    pub fn update_entities_with<F: FnMut(&EntityToken, &mut Box<E>)>(
+    /// Takes a closure and iterates over all active entities of the server
+    /// whose [`Entity::category`](trait.Entity.html#method.category) shares at
+    /// least one bit with the `category` mask, calling that closure on each
+    /// matching entity.
+    pub fn with_entities_in_category<F: FnMut(&EntityToken, &mut Box<E>)>(
         &mut self,
+        category: u32,
         mut callback: F
 
     ) {
-        for &mut (
-            ref entity_token,
-            ref mut timeout,
-            ref mut connection_count,
-            ref mut connected
-
-        ) in &mut self.active_handles {
-
+        for &(ref entity_token, _, _, _) in &self.active_handles {
             let handle = &mut self.handles[entity_token.index];
-            let is_alive = handle.is_some()
-                        && handle.as_ref().unwrap().is_alive();
-
-            if is_alive {
-                callback(entity_token, handle.as_mut().unwrap().get_entity_mut().unwrap())
-
-            } else if *connection_count > 0 {
-
-                // If the entity is destroyed we timeout all open connections
-                // that don't respond with a ConfirmDestroyToServer packet
-                // within the given number of update calls.
-                if timeout.is_none() {
-                    *timeout = Some(self.config.handle_timeout_ticks);
+            if handle.is_some()  {
+                if let Some(entity) = handle.as_mut().unwrap().get_entity_mut() {
+                    if entity.category() & category != 0 {
+                        callback(entity_token, entity);
+                    }
                 }
+            }
+        }
+    }
 
-                // Safely reduce timeout until we hit 0
-                if timeout.is_some() {
-                    *timeout = Some(timeout.unwrap().saturating_sub(1));
-                    if timeout.unwrap() == 0 {
-                        *connection_count = 0;
+    /// Takes a closure and iterates over all active entities of the server
+    /// whose [`Entity::kind`](trait.Entity.html#method.kind) matches `kind`,
+    /// calling that closure on each matching entity.
+    pub fn with_entities_of_kind<F: FnMut(&EntityToken, &mut Box<E>)>(
+        &mut self,
+        kind: u16,
+        mut callback: F
+
+    ) {
+        for &(ref entity_token, _, _, _) in &self.active_handles {
+            let handle = &mut self.handles[entity_token.index];
+            if handle.is_some()  {
+                if let Some(entity) = handle.as_mut().unwrap().get_entity_mut() {
+                    if entity.kind() == kind {
+                        callback(entity_token, entity);
                     }
                 }
             }
+        }
+    }
 
-            // Drop handlers of destroyed entities in case there are no
-            // more connected client
-            if !is_alive && *connection_count == 0 {
-
-                // Reset entity state for all open client connections
-                self.connections.iter_mut().filter(|r| r.is_some()).map(|r| r.unwrap()).all(|mut remote_states| {
-                    remote_states[entity_token.index].destroy();
-                    remote_states[entity_token.index].reset_destroyed();
-                    true
-                });
-
-                *connected = false;
-                *handle = None;
+    /// Takes a closure and iterates over all active entities of the server
+    /// which are relevant to the connection referenced by the
+    /// `ConnectionToken`, calling that closure on each relevant entity.
+    ///
+    /// An entity is relevant unless its
+    /// [`Entity::relevance`](trait.Entity.html#method.relevance) for the
+    /// connection is `Relevance::Forgotten`, which by default is the case
+    /// when [`Entity::filter`](trait.Entity.html#method.filter) returns
+    /// `false`.
+    ///
+    /// The closure is not called at all in case the `ConnectionToken` does
+    /// not belong to the server.
+    pub fn with_entities_for_connection<F: FnMut(&EntityToken, &mut Box<E>)>(
+        &mut self,
+        connection_token: &ConnectionToken<U>,
+        mut callback: F
 
+    ) {
+        if connection_token.server_index != self.index || self.connections[connection_token.index].is_none() {
+            return;
+        }
+        for &(ref entity_token, _, _, _) in &self.active_handles {
+            let handle = &mut self.handles[entity_token.index];
+            if handle.is_some()  {
+                if let Some(entity) = handle.as_mut().unwrap().get_entity_mut() {
+                    if entity.relevance(connection_token) != Relevance::Forgotten {
+                        callback(entity_token, entity);
+                    }
+                }
             }
+        }
+    }
 
+    /// Returns the tokens of all alive entities which are currently not
+    /// visible to any of the server's connections.
+    ///
+    /// An entity is considered unobserved when it is forgotten or hidden for
+    /// every connection (or when there are no connections at all), which
+    /// makes it a candidate for being put to sleep or despawned.
+    ///
+    /// The returned tokens are references to the server's own tokens and can
+    /// be compared against or used to access the entities via
+    /// [`Server::entity_get`](struct.Server.html#method.entity_get).
+    pub fn unobserved_entities(&self) -> Vec<&EntityToken> {
+        let mut tokens: Vec<&EntityToken> = Vec::new();
+        'handles: for &(ref entity_token, _, _, _) in &self.active_handles {
+            if let Some(ref handle) = self.handles[entity_token.index] {
+                if !handle.is_alive() {
+                    continue;
+                }
+                for connection_index in &self.active_connections {
+                    if let Some(ref remote_states) = self.connections[*connection_index] {
+                        match remote_states[entity_token.index] {
+                            RemoteState::Forget | RemoteState::Forgotten
+                            | RemoteState::Hide | RemoteState::Hidden => {},
+                            _ => continue 'handles
+                        }
+                    }
+                }
+                tokens.push(entity_token);
+            }
         }
+        tokens
+    }
 
-        // Remove destroy handles without any connected clients
-        self.active_handles.retain(|&(_, _, _, connected)| connected);
+    /// Takes a closure and iterates over all active entities of the client,
+    /// updating their state and calling that closure on each entity.
+    ///
+    /// This is the main update function of the server and should be called
+    /// exactly once per time step.
+    ///
+    /// Entities which are not observed by any connection are skipped in case
+    /// [`Entity::can_sleep`](trait.Entity.html#method.can_sleep) returns
+    /// `true` for them, they are kept alive nonetheless.
+    ///
+    /// Returns a summary of the entity handles which were processed.
+    pub fn update_entities_with<F: FnMut(&EntityToken, &mut Box<E>)>(
+        &mut self,
+        mut callback: F
+
+    ) -> UpdateStats {
+        self.update_entities_with_dirty(|entity_token, entity| {
+            callback(entity_token, entity);
+            true
+        })
+    }
+
+    /// Works like
+    /// [`Server::update_entities_with`](struct.Server.html#method.update_entities_with)
+    /// but the closure returns whether the entity changed during the update.
+    ///
+    /// Entities for which the closure returned `false` are not serialized as
+    /// part of
+    /// [`Server::connection_send`](struct.Server.html#method.connection_send)
+    /// until they are marked as changed again by a later update or via
+    /// [`Server::entity_mark_dirty`](struct.Server.html#method.entity_mark_dirty).
+    /// Their creation, destruction and periodic empty updates are not
+    /// affected.
+    pub fn update_entities_with_dirty<F: FnMut(&EntityToken, &mut Box<E>) -> bool>(
+        &mut self,
+        callback: F
+
+    ) -> UpdateStats {
+        self.update_entities(callback, |_| {})
+    }
+
+    /// Works like
+    /// [`Server::update_entities_with`](struct.Server.html#method.update_entities_with)
+    /// but additionally calls `on_drop` with the token of each destroyed
+    /// entity whose handle was finally dropped during this update.
+    ///
+    /// Handles are dropped once all connections have confirmed the
+    /// destruction of their entity or timed out, after which the entity's
+    /// slot is free to be re-used.
+    pub fn update_entities_with_drops<F: FnMut(&EntityToken, &mut Box<E>), G: FnMut(&EntityToken)>(
+        &mut self,
+        mut update: F,
+        on_drop: G
 
+    ) -> UpdateStats {
+        self.update_entities(|entity_token, entity| {
+            update(entity_token, entity);
+            true
+
+        }, on_drop)
     }
 
     /// Registers a new connection with the server, returning its token when
@@ -392,12 +1143,19 @@ impl<E: Entity<U> + ?Sized, U: fmt::Debug> Server<E, U> {
         if let Some(index) = self.find_free_connection_slot_index() {
 
             // Put active handles into the accept state for the new connection
-            let mut remote_states = [RemoteState::Unknown; 256];
+            let mut remote_states = RemoteStates::default();
             for &(ref entity_token, _, _, _) in &self.active_handles {
                 remote_states[entity_token.index].accept();
             }
 
             self.connections[index] = Some(remote_states);
+            self.fragments[index].reset();
+            self.pending_destroys[index].clear();
+            self.handshakes[index] = false;
+            self.keepalives[index] = false;
+            self.visible[index] = false;
+            self.versions_sent[index] = false;
+            self.suspended[index] = None;
             self.active_connections.push(index);
 
             // Return a unique handle which cannot be copied
@@ -427,15 +1185,37 @@ impl<E: Entity<U> + ?Sized, U: fmt::Debug> Server<E, U> {
         } else if let Some(remote_states) = self.connections[connection_token.index].take() {
 
             // Decrease connection counts for all active handles this connection had
-            // state for
-            for &mut(_, _, ref mut connection_count, _) in &mut self.active_handles {
-                if remote_states[connection_token.index] > RemoteState::Accept {
-                    *connection_count -= 1;
+            // state for, suspended connections are no longer counted
+            if self.suspended[connection_token.index].take().is_none() {
+                for &mut(ref entity_token, _, ref mut connection_count, _) in &mut self.active_handles {
+                    let handle = &self.handles[entity_token.index];
+                    let is_alive = handle.is_some()
+                                && handle.as_ref().unwrap().is_alive();
+
+                    let is_counted = is_connection_counted(
+                        remote_states[entity_token.index],
+                        is_alive,
+                        remote_states.create_sent(entity_token.index)
+                    );
+
+                    if is_counted {
+                        *connection_count = connection_count.saturating_sub(1);
+                    }
+                }
+            }
+
+            // Entities can no longer be owned by the connection
+            for &(ref entity_token, _, _, _) in &self.active_handles {
+                if let Some(handle) = self.handles[entity_token.index].as_mut() {
+                    if handle.owner() == Some(connection_token.index) {
+                        handle.set_owner(None);
+                    }
                 }
             }
 
             // Remove internal connection
             self.connections[connection_token.index] = None;
+            self.pending_destroys[connection_token.index].clear();
             self.active_connections.retain(|index| *index != connection_token.index);
 
             // Return connection user data
@@ -447,163 +1227,1479 @@ impl<E: Entity<U> + ?Sized, U: fmt::Debug> Server<E, U> {
 
     }
 
-    /// Returns a list of one or more protocol packets that can be fed into
-    /// [`Client::receive`](struct.Client.html#method.receive)
-    /// in order to synchronise entities between the server and a client.
-    pub fn connection_send(
+    /// Removes a already registered connection, returning its previously owned
+    /// `user_data` together with a list of final protocol packets which tell
+    /// the client to destroy all entities it might know about.
+    ///
+    /// The packets should be flushed to the client before closing the
+    /// underlying transport. Since the connection is gone, their delivery is
+    /// not confirmed or retried.
+    ///
+    /// The token is given back without removing the connection in case the
+    /// packets do not fit into `max_bytes_per_packet`.
+    pub fn connection_remove_graceful(
         &mut self,
-        connection_token: &ConnectionToken<U>,
+        connection_token: ConnectionToken<U>,
         max_bytes_per_packet: usize
 
-    ) -> Result<Vec<Vec<u8>>, Error> {
+    ) -> Result<(U, Vec<Vec<u8>>), ConnectionToken<U>> {
+
+        if connection_token.server_index != self.index {
+            Err(connection_token)
+
+        } else if let Some(remote_states) = self.connections[connection_token.index].as_ref() {
+
+            let mut packets = PacketList::with_overhead(
+                max_bytes_per_packet,
+                self.config.packet_overhead,
+                self.config.max_entities_per_packet,
+                self.config.packet_header.as_ref(),
+                self.config.compression.as_ref(),
+                self.config.packet_checksum
+            );
+
+            for &(index, _) in &self.pending_destroys[connection_token.index] {
+                packets.append_bytes(vec![NetworkState::SendDestroyToClient as u8, index]);
+            }
+
+            // Entities which were never send or have been forgotten do not
+            // exist on the client
+            for &(ref token, _, _, _) in &self.active_handles {
+                let is_known = match remote_states[token.index] {
+                    RemoteState::Unknown => remote_states.create_sent(token.index),
+                    RemoteState::Create | RemoteState::Update | RemoteState::Destroy
+                    | RemoteState::Hide | RemoteState::Hidden | RemoteState::Reveal => true,
+                    _ => false
+                };
+                if is_known {
+                    packets.append_bytes(vec![
+                        NetworkState::SendDestroyToClient as u8,
+                        token.index as u8
+                    ]);
+                }
+            }
+
+            if packets.is_oversized() {
+                return Err(connection_token);
+            }
+
+            let packets = packets.into_vec();
+            self.stats.bytes_sent += packets.iter().map(|p| p.len()).sum::<usize>();
+
+            self.connection_remove(connection_token).map(|user_data| (user_data, packets))
+
+        } else {
+            Err(connection_token)
+        }
+
+    }
+
+    /// Resets the synchronisation state of all alive entities for an already
+    /// registered connection, so that the next call to
+    /// [`Server::connection_send`](struct.Server.html#method.connection_send)
+    /// creates them from scratch.
+    ///
+    /// Unlike removing and re-adding the connection this keeps the
+    /// connection's `user_data` and slot index. Destroyed entities still
+    /// await the confirmation of their destruction.
+    pub fn connection_resync(&mut self, connection_token: &ConnectionToken<U>) -> Result<(), Error> {
 
         if connection_token.server_index != self.index {
             Err(Error::UnknownSenderToken)
 
+        } else if self.suspended[connection_token.index].is_some() {
+            Err(Error::ConnectionSuspended)
+
         } else if let Some(remote_states) = self.connections[connection_token.index].as_mut() {
 
-            let mut packets = PacketList::new(max_bytes_per_packet);
             for &mut(ref token, _, ref mut connection_count, _) in &mut self.active_handles {
+                if self.handles[token.index].as_ref().unwrap().is_alive() {
 
-                let handle = &mut self.handles[token.index];
-                let remote_state = &mut remote_states[token.index];
+                    // Newly accepted entities are counted again on the next send
+                    let slot = remote_states.get_mut(token.index);
+                    if slot.state != RemoteState::Accept && *connection_count > 0 {
+                        *connection_count -= 1;
+                    }
 
-                if handle.as_ref().unwrap().is_alive() {
+                    slot.state = RemoteState::Accept;
+                    slot.baseline.reset();
 
-                    // Increase the entities connection count for newly established connections
-                    if remote_state.reset_accepted() {
-                        *connection_count += 1;
-                    }
+                }
+            }
 
-                    // Check if the entity should no longer be send to the connection.
-                    // The client should simply forget about the entity and drop it
-                    // without running its destroyed() method.
-                    if !handle.as_ref().unwrap().filter(connection_token) {
-                        if *remote_state < RemoteState::Forget {
-                            remote_state.forget();
-                        }
+            self.fragments[connection_token.index].reset();
 
-                    // If the entity should be send to the client again,
-                    // reset its state so we tell the client to create it again
-                    } else {
-                        remote_state.reset_forgotten();
-                    }
+            Ok(())
 
-                // Reduce the entities connection count if a client has confirmed destruction
-                } else if *connection_count > 0 && remote_state.reset_destroyed() {
-                    *connection_count -= 1;
-                }
+        } else {
+            Err(Error::UnknownSenderToken)
+        }
 
-                // Only serialize entities which have open client connections
-                if *connection_count > 0 {
-                    packets.append_bytes(handle.as_mut().unwrap().as_bytes(
-                        &self.config,
-                        Some(connection_token),
-                        remote_state
-                    ));
-                }
+    }
 
-            }
+    /// Makes the next call to
+    /// [`Server::connection_send`](struct.Server.html#method.connection_send)
+    /// for an already registered connection return at least one packet, e.g.
+    /// in order to probe the liveness of the connection.
+    ///
+    /// In case nothing else is send, the keepalive consists of an empty update
+    /// for one of the entities the client has confirmed or of an empty packet
+    /// if there are none.
+    pub fn connection_force_keepalive(&mut self, connection_token: &ConnectionToken<U>) -> Result<(), Error> {
+        if connection_token.server_index != self.index {
+            Err(Error::UnknownSenderToken)
 
-            Ok(packets.into_vec())
+        } else if self.connections[connection_token.index].is_some() {
+            self.keepalives[connection_token.index] = true;
+            Ok(())
 
         } else {
             Err(Error::UnknownSenderToken)
         }
+    }
+
+    /// Returns whether the client of an already registered connection
+    /// currently has any alive entities which are being synchronised to it.
+    ///
+    /// Entities which have been destroyed or which the client was told to
+    /// forget about are not visible.
+    pub fn connection_has_visible_entities(&self, connection_token: &ConnectionToken<U>) -> bool {
+        if connection_token.server_index != self.index {
+            false
+
+        } else if let Some(remote_states) = self.connections[connection_token.index].as_ref() {
+            self.active_handles.iter().any(|&(ref token, _, _, _)| {
+                self.handles[token.index].as_ref().unwrap().is_alive() && matches!(
+                    remote_states[token.index],
+                    RemoteState::Create | RemoteState::Update
+                )
+            })
 
+        } else {
+            false
+        }
     }
 
-    /// Consumes a protocol packet for a specific client connection that was
-    /// generated by [`Client::send`](struct.Client.html#method.send)
-    /// in order to synchronise entities between a client and the server.
-    pub fn connection_receive(
+    /// Sets a callback which is invoked once a connection no longer has any
+    /// visible entities, see
+    /// [`Server::connection_has_visible_entities`](struct.Server.html#method.connection_has_visible_entities).
+    ///
+    /// The transition is detected at the end of
+    /// [`Server::connection_send`](struct.Server.html#method.connection_send)
+    /// and
+    /// [`Server::connection_receive`](struct.Server.html#method.connection_receive),
+    /// the callback is not invoked for connections which never had any
+    /// visible entities.
+    pub fn set_connection_hidden_callback<F: FnMut(&ConnectionToken<U>) + Send + Sync + 'static>(
         &mut self,
-        connection_token: &ConnectionToken<U>,
-        bytes: Vec<u8>
+        callback: F
+    ) {
+        self.hidden_callback = Some(Box::new(callback));
+    }
 
-    ) -> Result<(), Error> {
+    /// Sets an inspector which is invoked with every packet returned from
+    /// [`Server::connection_send`](struct.Server.html#method.connection_send)
+    /// and its variants, e.g. for logging or recording a replay of the
+    /// stream.
+    ///
+    /// The inspector receives the connection along with the final bytes of
+    /// the packet, including any packet header and compression.
+    pub fn set_send_inspector<F: FnMut(&ConnectionToken<U>, &[u8]) + Send + Sync + 'static>(
+        &mut self,
+        inspector: F
+    ) {
+        self.send_inspector = Some(Box::new(inspector));
+    }
+
+    /// Suspends an already registered connection, e.g. after its transport
+    /// was lost abruptly, while keeping the synchronisation state of its
+    /// entities around for a later
+    /// [`Server::connection_resume`](struct.Server.html#method.connection_resume).
+    ///
+    /// Suspended connections are no longer counted for their entities, so
+    /// destroyed entities are dropped without waiting for their client.
+    /// Sending to or receiving from a suspended connection returns
+    /// `Error::ConnectionSuspended` and it is not part of
+    /// [`Server::active_connection_indices`](struct.Server.html#method.active_connection_indices).
+    pub fn connection_suspend(&mut self, connection_token: &ConnectionToken<U>) -> Result<(), Error> {
 
         if connection_token.server_index != self.index {
-            Err(Error::UnknownReceiverToken(bytes))
+            Err(Error::UnknownSenderToken)
 
-        } else if let Some(remote_states) = self.connections[connection_token.index].as_mut() {
+        } else if self.suspended[connection_token.index].is_some() {
+            Ok(())
 
-            let (mut i, len) = (0, bytes.len());
-            if len == 0 {
-                return Ok(());
+        } else if let Some(remote_states) = self.connections[connection_token.index].as_ref() {
 
-            } else if !ClientNetworkState::is_potential_packet(bytes[0]) {
-                return Err(Error::InvalidPacketData(bytes));
+            // Remember which entities the connection was counted for
+            let mut counted = Vec::new();
+            for &mut(ref entity_token, _, ref mut connection_count, _) in &mut self.active_handles {
+                let is_counted = is_connection_counted(
+                    remote_states[entity_token.index],
+                    self.handles[entity_token.index].as_ref().unwrap().is_alive(),
+                    remote_states.create_sent(entity_token.index)
+                );
+
+                if is_counted && *connection_count > 0 {
+                    *connection_count -= 1;
+                    counted.push((entity_token.index, entity_token.generation));
+                }
             }
 
-            while i + 1 < len {
+            self.suspended[connection_token.index] = Some(counted);
+            self.active_connections.retain(|index| *index != connection_token.index);
 
-                let (state, index) = (bytes[i], bytes[i + 1] as usize);
-                let remote_state = &mut remote_states[index];
-                i += 2;
+            Ok(())
 
-                match ClientNetworkState::from_u8(state) {
-                    Some(ClientNetworkState::ConfirmCreateToServer) => if self.handles[index].is_some() {
-                        remote_state.create();
-                    },
-                    Some(ClientNetworkState::AcceptServerUpdate) => if self.handles[index].is_some() {
-                        remote_state.update();
-                    },
-                    Some(ClientNetworkState::SendUpdateToServer) => if let Some((entity_bytes, length)) = deserialize_entity_bytes(&bytes[i..], 1) {
+        } else {
+            Err(Error::UnknownSenderToken)
+        }
 
-                        if self.handles[index].is_some() && *remote_state == RemoteState::Update {
-                            if !entity_bytes.is_empty() {
-                                self.handles[index].as_mut().unwrap().merge_bytes(
-                                    Some(connection_token),
-                                    entity_bytes
-                                );
-                            }
-                        }
+    }
 
-                        i += length;
+    /// Resumes a connection which was suspended via
+    /// [`Server::connection_suspend`](struct.Server.html#method.connection_suspend).
+    ///
+    /// Entities which the client already knew about are not created again,
+    /// the ones that were created in the meantime are send with the next
+    /// call to
+    /// [`Server::connection_send`](struct.Server.html#method.connection_send)
+    /// and the ones which were dropped in the meantime are destroyed.
+    pub fn connection_resume(&mut self, connection_token: &ConnectionToken<U>) -> Result<(), Error> {
 
-                    },
-                    Some(ClientNetworkState::ConfirmDestroyToServer) => if self.handles[index].is_some() {
-                        if !self.handles[index].as_ref().unwrap().is_alive() {
-                            remote_state.destroy();
+        if connection_token.server_index != self.index {
+            Err(Error::UnknownSenderToken)
 
-                        } else {
-                            remote_state.forgotten();
-                        }
-                    },
-                    None => return Err(Error::RemainingPacketData((&bytes[i..]).to_vec()))
+        } else if let Some(remote_states) = self.connections[connection_token.index].as_mut() {
+
+            if let Some(counted) = self.suspended[connection_token.index].take() {
+
+                for &mut(ref entity_token, _, ref mut connection_count, _) in &mut self.active_handles {
+                    if counted.contains(&(entity_token.index, entity_token.generation)) {
+                        *connection_count += 1;
+
+                    // Entities created during the suspension are accepted
+                    // just like for newly added connections
+                    } else if self.handles[entity_token.index].as_ref().unwrap().is_alive() {
+                        remote_states[entity_token.index].accept();
+                    }
                 }
 
+                self.active_connections.push(connection_token.index);
+
             }
 
             Ok(())
 
         } else {
-            Err(Error::UnknownReceiverToken(bytes))
+            Err(Error::UnknownSenderToken)
         }
 
     }
 
-    // Internal
+    /// Returns the number of connections currently registered with the
+    /// server, including suspended ones.
+    pub fn connection_tokens_len(&self) -> usize {
+        self.connections.iter().filter(|connection| connection.is_some()).count()
+    }
 
-    fn find_free_entity_slot_index(&self) -> Option<usize> {
-        for i in 0..256 {
-            if self.handles[i].is_none() {
-                return Some(i);
+    /// Returns an iterator over the slot indices of all connections currently
+    /// registered with the server, in the order they were added.
+    ///
+    /// The indices can be matched against
+    /// [`ConnectionToken::index`](struct.ConnectionToken.html#method.index).
+    pub fn active_connection_indices<'a>(&'a self) -> impl Iterator<Item = usize> + 'a {
+        self.active_connections.iter().cloned()
+    }
+
+    /// Verifies the protocol handshake packet of a connection which was
+    /// created via
+    /// [`Client::handshake_packet`](struct.Client.html#method.handshake_packet).
+    ///
+    /// Returns `Error::ProtocolMismatch` in case the client uses a different
+    /// protocol version. When
+    /// [`Config::require_handshake`](struct.Config.html#structfield.require_handshake)
+    /// is enabled, entities are only synchronised with connections whose
+    /// handshake has been accepted.
+    pub fn accept_handshake(
+        &mut self,
+        connection_token: &ConnectionToken<U>,
+        bytes: &[u8]
+
+    ) -> Result<(), Error> {
+
+        if connection_token.server_index != self.index || self.connections[connection_token.index].is_none() {
+            Err(Error::UnknownReceiverToken(bytes.to_vec()))
+
+        } else {
+            match strip_packet_header(&self.config, Cow::Borrowed(bytes)) {
+                Ok(ref bytes) if verify_handshake(bytes) => {
+                    self.handshakes[connection_token.index] = true;
+                    Ok(())
+                },
+                _ => Err(Error::ProtocolMismatch)
             }
         }
-        None
+
     }
 
-    fn find_free_connection_slot_index(&self) -> Option<usize> {
-        for i in 0..256 {
-            if self.connections[i].is_none() {
-                return Some(i);
-            }
+    /// Returns a list of one or more protocol packets that can be fed into
+    /// [`Client::receive`](struct.Client.html#method.receive)
+    /// in order to synchronise entities between the server and a client.
+    ///
+    /// Each entity contributes at most one frame per call, so state changes
+    /// which happen within the same tick are coalesced:
+    ///
+    /// - **Create + Update**: Until the client has confirmed the creation,
+    ///   the create frame is re-send with the entity's current
+    ///   [`Entity::snapshot_bytes`](trait.Entity.html#method.snapshot_bytes) and
+    ///   [`Entity::part_bytes`](trait.Entity.html#method.part_bytes) is not
+    ///   consulted, so any updates are folded into the create frame.
+    ///
+    /// Updates and destructions of an already created entity are never
+    /// coalesced, since the client must first confirm the creation.
+    pub fn connection_send(
+        &mut self,
+        connection_token: &ConnectionToken<U>,
+        max_bytes_per_packet: usize
+
+    ) -> Result<Vec<Vec<u8>>, Error> {
+        self.connection_send_with_events(connection_token, max_bytes_per_packet).map(|(packets, _)| packets)
+    }
+
+    /// Works like
+    /// [`Server::connection_send`](struct.Server.html#method.connection_send)
+    /// but additionally returns a list of the entity creations, destructions
+    /// and forgets which are contained in the returned packets.
+    ///
+    /// Until the client confirms them these are re-send on subsequent calls,
+    /// in which case they are also reported again.
+    pub fn connection_send_with_events(
+        &mut self,
+        connection_token: &ConnectionToken<U>,
+        max_bytes_per_packet: usize
+
+    ) -> Result<(Vec<Vec<u8>>, Vec<ConnectionSendEvent>), Error> {
+        let mut buffer = PacketBuffer::new();
+        self.send_packets(connection_token, max_bytes_per_packet, &mut buffer).map(|events| {
+            (buffer.into_packets(), events)
+        })
+    }
+
+    /// Works like
+    /// [`Server::connection_send`](struct.Server.html#method.connection_send)
+    /// but writes the packets into the provided
+    /// [`PacketBuffer`](struct.PacketBuffer.html) instead of allocating new
+    /// ones.
+    ///
+    /// The returned packets borrow the buffer and are replaced by the next
+    /// call which uses the same buffer, re-using a buffer per connection
+    /// avoids most of the per-tick allocations of the send path.
+    pub fn connection_send_into<'b>(
+        &mut self,
+        connection_token: &ConnectionToken<U>,
+        max_bytes_per_packet: usize,
+        buffer: &'b mut PacketBuffer
+
+    ) -> Result<&'b [Vec<u8>], Error> {
+        match self.send_packets(connection_token, max_bytes_per_packet, buffer) {
+            Ok(_) => Ok(buffer.packets()),
+            Err(err) => Err(err)
+        }
+    }
+
+    /// Works like
+    /// [`Server::connection_send`](struct.Server.html#method.connection_send)
+    /// but returns all packets as a single buffer in which each packet is
+    /// prefixed with its length.
+    ///
+    /// This is intended for stream oriented transports which perform their
+    /// own framing, the buffer is consumed again by
+    /// [`Client::receive_stream`](struct.Client.html#method.receive_stream).
+    pub fn connection_send_stream(&mut self, connection_token: &ConnectionToken<U>) -> Result<Vec<u8>, Error> {
+        let mut buffer = PacketBuffer::new();
+        match self.send_packets(connection_token, STREAM_PACKET_BYTES, &mut buffer) {
+            Ok(_) => Ok(stream_bytes(buffer.packets())),
+            Err(err) => Err(err)
         }
-        None
     }
 
+    /// Consumes a protocol packet for a specific client connection that was
+    /// generated by [`Client::send`](struct.Client.html#method.send)
+    /// in order to synchronise entities between a client and the server.
+    pub fn connection_receive(
+        &mut self,
+        connection_token: &ConnectionToken<U>,
+        bytes: Vec<u8>
+
+    ) -> Result<(), Error> {
+        self.receive_packet(connection_token, Cow::Owned(bytes), None)
+    }
+
+    /// Consumes a protocol packet for a specific client connection just like
+    /// [`Server::connection_receive`](struct.Server.html#method.connection_receive)
+    /// but borrows the bytes instead of taking ownership of them.
+    ///
+    /// The bytes are only copied in case an error needs to return them or
+    /// when they have to be decompressed or reassembled from fragments.
+    pub fn connection_receive_slice(
+        &mut self,
+        connection_token: &ConnectionToken<U>,
+        bytes: &[u8]
+
+    ) -> Result<(), Error> {
+        self.receive_packet(connection_token, Cow::Borrowed(bytes), None)
+    }
+
+    /// Consumes the length prefixed packets of a buffer generated by
+    /// [`Client::send_stream`](struct.Client.html#method.send_stream) for a
+    /// specific client connection.
+    ///
+    /// Returns the number of bytes which were consumed, a packet which is
+    /// incomplete at the end of the buffer is left untouched so it can be
+    /// passed in again once the rest of it has been received.
+    ///
+    /// Processing stops at the first packet which fails, returning the number
+    /// of bytes consumed in front of it along with the error.
+    pub fn connection_receive_stream(
+        &mut self,
+        connection_token: &ConnectionToken<U>,
+        bytes: &[u8]
+
+    ) -> Result<usize, (usize, Error)> {
+        let mut consumed = 0;
+        loop {
+            match next_stream_packet(&bytes[consumed..]) {
+                Ok(Some((packet, length))) => match self.receive_packet(connection_token, Cow::Borrowed(packet), None) {
+                    Ok(_) => consumed += length,
+                    Err(err) => return Err((consumed, err))
+                },
+                Ok(None) => return Ok(consumed),
+                Err(_) => return Err((consumed, Error::InvalidStreamFrame))
+            }
+        }
+    }
+
+    /// Consumes a batch of protocol packets for a specific client connection
+    /// in order, validating the `ConnectionToken` only once for the whole
+    /// batch.
+    ///
+    /// Processing stops at the first packet which fails, returning its index
+    /// within the batch along with the error. All packets in front of it
+    /// have already been applied at that point.
+    ///
+    /// Errors caused by the token itself are reported for index `0`.
+    pub fn connection_receive_many(
+        &mut self,
+        connection_token: &ConnectionToken<U>,
+        packets: Vec<Vec<u8>>
+
+    ) -> Result<(), (usize, Error)> {
+
+        let mut packets = packets.into_iter();
+        if connection_token.server_index != self.index {
+            return Err((0, Error::UnknownReceiverToken(packets.next().unwrap_or_default())));
+
+        } else if self.suspended[connection_token.index].is_some() {
+            return Err((0, Error::ConnectionSuspended));
+
+        } else if self.connections[connection_token.index].is_none() {
+            return Err((0, Error::UnknownReceiverToken(packets.next().unwrap_or_default())));
+        }
+
+        for (index, bytes) in packets.enumerate() {
+            if let Err(err) = self.receive_connection_packet(connection_token, Cow::Owned(bytes), None) {
+                return Err((index, err));
+            }
+        }
+
+        Ok(())
+
+    }
+
+    /// Consumes a protocol packet for a specific client connection just like
+    /// [`Server::connection_receive`](struct.Server.html#method.connection_receive)
+    /// but forwards the bytes of any embedded custom protocol packet to the
+    /// provided closure instead of returning them as an error.
+    ///
+    /// Custom protocol data starts at the first byte whose value is higher
+    /// than [`NETWORK_BYTE_OFFSET`](constant.NETWORK_BYTE_OFFSET.html) and
+    /// extends until the end of the packet, any entity data in front of it is
+    /// still processed.
+    ///
+    /// Errors are only returned for data which is neither part of the
+    /// client-server protocol nor of a custom one.
+    pub fn connection_receive_with<F: FnMut(&[u8])>(
+        &mut self,
+        connection_token: &ConnectionToken<U>,
+        bytes: Vec<u8>,
+        mut callback: F
+
+    ) -> Result<(), Error> {
+        self.receive_packet(connection_token, Cow::Owned(bytes), Some(&mut callback))
+    }
+
+    /// Consumes the client-server protocol data at the start of `bytes` for
+    /// a specific client connection, returning the number of bytes which
+    /// were consumed.
+    ///
+    /// This is intended for streaming transports which accumulate the data
+    /// generated by [`Client::send`](struct.Client.html#method.send) and might
+    /// hold an incomplete trailing entity state. Parsing stops cleanly in front
+    /// of such a state, so the remaining bytes can be retained and passed in
+    /// again once more data has arrived.
+    ///
+    /// > Note: The bytes are not treated as a complete packet, packet
+    /// > headers, compression and fragments are not supported.
+    pub fn connection_receive_partial(
+        &mut self,
+        connection_token: &ConnectionToken<U>,
+        bytes: &[u8]
+
+    ) -> Result<usize, Error> {
+
+        if connection_token.server_index != self.index || self.connections[connection_token.index].is_none() {
+            Err(Error::UnknownReceiverToken(bytes.to_vec()))
+
+        } else if self.suspended[connection_token.index].is_some() {
+            Err(Error::ConnectionSuspended)
+
+        } else if !bytes.is_empty() && !ClientNetworkState::is_potential_packet(bytes[0]) {
+            Err(Error::InvalidPacketData(bytes.to_vec()))
+
+        } else {
+            let consumed = self.receive_entity_states(connection_token, bytes, None, true);
+            self.update_visibility(connection_token);
+            consumed
+        }
+
+    }
+
+    /// Returns the synchronisation state of the entity referenced by the
+    /// `EntityToken` for the connection referenced by the `ConnectionToken`.
+    ///
+    /// This is mostly useful for debugging de-synchronisations between the
+    /// server and one of its clients.
+    ///
+    /// Returns `None` in case either of the tokens does not belong to the
+    /// server or the entity's handle has already been dropped.
+    pub fn connection_entity_state(
+        &self,
+        connection_token: &ConnectionToken<U>,
+        entity_token: &EntityToken
+
+    ) -> Option<RemoteState> {
+
+        if connection_token.server_index != self.index
+            || entity_token.server_index != self.index
+            || self.handles[entity_token.index].is_none() {
+            None
+
+        } else {
+            self.connections[connection_token.index].as_ref().map(|remote_states| {
+                remote_states[entity_token.index]
+            })
+        }
+
+    }
+
+    /// Returns the number of entity slots for which the connection referenced
+    /// by the `ConnectionToken` currently tracks a synchronisation state.
+    ///
+    /// States are only tracked for entities which are known to, or pending
+    /// for, the connection's client, so the memory used by a connection
+    /// scales with the number of its entities.
+    ///
+    /// Returns `None` in case the token does not belong to the server.
+    pub fn connection_tracked_entities(&self, connection_token: &ConnectionToken<U>) -> Option<usize> {
+        if connection_token.server_index != self.index {
+            None
+
+        } else {
+            self.connections[connection_token.index].as_ref().map(|remote_states| {
+                remote_states.len()
+            })
+        }
+    }
+
+    /// Returns the server tick of the last update of the entity referenced
+    /// by the `EntityToken` which the client of the connection referenced by
+    /// the `ConnectionToken` has acknowledged.
+    ///
+    /// Ticks are counted by the calls to
+    /// [`Server::update_entities_with`](struct.Server.html#method.update_entities_with)
+    /// and recorded whenever an update is send to a connection.
+    ///
+    /// Returns `None` in case
+    /// [`Config::acknowledge_updates`](struct.Config.html#structfield.acknowledge_updates)
+    /// is disabled, either of the tokens does not belong to the server, the
+    /// entity's slot has been re-used or the client has not yet acknowledged
+    /// any update of the entity.
+    pub fn connection_entity_acked_tick(
+        &self,
+        connection_token: &ConnectionToken<U>,
+        entity_token: &EntityToken
+
+    ) -> Option<usize> {
+        if connection_token.server_index != self.index || !self.is_current_ref(&entity_token.as_ref()) {
+            None
+
+        } else {
+            self.connections[connection_token.index].as_ref().and_then(|remote_states| {
+                remote_states.get(entity_token.index)
+
+            }).and_then(|slot| slot.baseline.acknowledged_tick())
+        }
+    }
+
+    /// Verifies the consistency of the server's internal state, returning a
+    /// description of the first violated invariant.
+    ///
+    /// This is used by debug assertions and the test suite to detect
+    /// corrupted bookkeeping early on.
+    pub fn check_invariants(&self) -> Result<(), String> {
+
+        // Active handles are iterated in slot order
+        if self.active_handles.windows(2).any(|pair| pair[0].0.index > pair[1].0.index) {
+            return Err("Active handles are not sorted by slot index.".to_string());
+        }
+
+        // Count the connections which have yet to accept each slot
+        let mut connected = 0;
+        let mut accepting = [0; 256];
+        for connection_index in &self.active_connections {
+            if let Some(ref remote_states) = self.connections[*connection_index] {
+                for slot in remote_states.iter().filter(|slot| slot.state == RemoteState::Accept) {
+                    accepting[slot.index] += 1;
+                }
+                connected += 1;
+            }
+        }
+
+        // Active handles must reference unique, occupied slots
+        let mut seen_handles = [false; 256];
+        for &(ref entity_token, _, connection_count, _) in &self.active_handles {
+
+            let index = entity_token.index;
+            if seen_handles[index] {
+                return Err(format!("Duplicate active handle for slot {}.", index));
+            }
+            seen_handles[index] = true;
+
+            let handle = match self.handles[index] {
+                Some(ref handle) => handle,
+                None => return Err(format!("Active handle for empty slot {}.", index))
+            };
+
+            // Alive entities are counted for every connection except those
+            // which have yet to accept them
+            if handle.is_alive() {
+                let expected = connected - accepting[index];
+
+                if connection_count != expected {
+                    return Err(format!(
+                        "Connection count of slot {} is {} instead of {}.",
+                        index, connection_count, expected
+                    ));
+                }
+            }
+
+        }
+
+        for (index, handle) in self.handles.iter().enumerate() {
+            if handle.is_some() && !seen_handles[index] {
+                return Err(format!("Occupied slot {} has no active handle.", index));
+            }
+        }
+
+        // Active connections must reference unique, occupied slots
+        let mut seen_connections = [false; 256];
+        for index in &self.active_connections {
+            if seen_connections[*index] {
+                return Err(format!("Duplicate active connection for slot {}.", index));
+
+            } else if self.connections[*index].is_none() {
+                return Err(format!("Active connection for empty slot {}.", index));
+            }
+            seen_connections[*index] = true;
+        }
+
+        for (index, connection) in self.connections.iter().enumerate() {
+            if self.suspended[index].is_some() {
+                if seen_connections[index] {
+                    return Err(format!("Suspended connection slot {} is active.", index));
+
+                } else if connection.is_none() {
+                    return Err(format!("Suspended connection slot {} is empty.", index));
+                }
+
+            } else if connection.is_some() && !seen_connections[index] {
+                return Err(format!("Occupied connection slot {} is not active.", index));
+            }
+        }
+
+        Ok(())
+
+    }
+
+    // Internal
+    fn receive_packet(
+        &mut self,
+        connection_token: &ConnectionToken<U>,
+        bytes: Cow<[u8]>,
+        custom: Option<CustomPacketCallback>
+
+    ) -> Result<(), Error> {
+
+        if connection_token.server_index != self.index {
+            Err(Error::UnknownReceiverToken(bytes.into_owned()))
+
+        } else if self.suspended[connection_token.index].is_some() {
+            Err(Error::ConnectionSuspended)
+
+        } else if self.connections[connection_token.index].is_some() {
+            self.receive_connection_packet(connection_token, bytes, custom)
+
+        } else {
+            Err(Error::UnknownReceiverToken(bytes.into_owned()))
+        }
+
+    }
+
+    fn receive_connection_packet(
+        &mut self,
+        connection_token: &ConnectionToken<U>,
+        bytes: Cow<[u8]>,
+        custom: Option<CustomPacketCallback>
+
+    ) -> Result<(), Error> {
+
+        let bytes = match strip_packet_header(&self.config, bytes) {
+            Ok(bytes) => bytes,
+            Err(bytes) => return Err(Error::InvalidPacketData(bytes.into_owned()))
+        };
+
+        let bytes = match verify_packet_checksum(&self.config, bytes) {
+            Ok(bytes) => bytes,
+            Err(bytes) => return Err(Error::ChecksumMismatch(bytes.into_owned()))
+        };
+
+        let bytes = match decompress_packet(&self.config, bytes) {
+            Ok(bytes) => bytes,
+            Err(bytes) => return Err(Error::InvalidPacketData(bytes.into_owned()))
+        };
+
+        // Reassemble fragmented entity bytes
+        let bytes = if FragmentBuffer::is_fragment(&bytes) {
+            match self.fragments[connection_token.index].receive(&self.config, &bytes) {
+                Some(bytes) => Cow::Owned(bytes),
+                None => return Ok(())
+            }
+
+        } else {
+            bytes
+        };
+
+        if bytes.is_empty() {
+            return Ok(());
+
+        } else if !ClientNetworkState::is_potential_packet(bytes[0]) {
+            return match custom {
+                Some(custom) if bytes[0] > NETWORK_BYTE_OFFSET => {
+                    custom(&bytes);
+                    Ok(())
+                },
+                _ => Err(Error::InvalidPacketData(bytes.into_owned()))
+            };
+        }
+
+        self.receive_entity_states(connection_token, &bytes, custom, false)?;
+
+        self.update_visibility(connection_token);
+
+        Ok(())
+
+    }
+
+    fn receive_entity_states(
+        &mut self,
+        connection_token: &ConnectionToken<U>,
+        bytes: &[u8],
+        mut custom: Option<CustomPacketCallback>,
+        stop_at_incomplete: bool
+
+    ) -> Result<usize, Error> {
+
+        let remote_states = self.connections[connection_token.index].as_mut().unwrap();
+        let (mut i, len) = (0, bytes.len());
+        while i < len {
+
+            // Forward trailing custom protocol data
+            if let Some(ref mut custom) = custom {
+                if bytes[i] > NETWORK_BYTE_OFFSET {
+                    custom(&bytes[i..]);
+                    return Ok(len);
+                }
+            }
+
+            if i + 1 == len {
+                break;
+            }
+
+            let start = i;
+            let (state, index) = (bytes[i], bytes[i + 1] as usize);
+            i += 2;
+
+            // Only slots which are tracked for the connection can be affected,
+            // states for any other slots are parsed but ignored without
+            // allocating a slot for them
+            let slot = if self.handles[index].is_some() {
+                remote_states.find_mut(index)
+
+            } else {
+                None
+            };
+
+            match ClientNetworkState::from_u8(state) {
+                Some(ClientNetworkState::ConfirmCreateToServer) => if let Some(slot) = slot {
+                    slot.state.create();
+                },
+                Some(ClientNetworkState::AcceptServerUpdate) => if let Some(slot) = slot {
+                    // Revealed entities were already confirmed before
+                    let revealed = slot.state == RemoteState::Reveal;
+                    if slot.state.update() && !revealed {
+                        self.handles[index].as_mut().unwrap().confirmed_by(connection_token);
+                    }
+                },
+                Some(ClientNetworkState::SendUpdateToServer) | Some(ClientNetworkState::SendSegmentsToServer) => if let Some((entity_bytes, length)) = deserialize_entity_bytes(&self.config, &bytes[i..], 0) {
+
+                    let is_updating = match slot {
+                        Some(slot) => slot.state == RemoteState::Update,
+                        None => false
+                    };
+
+                    if is_updating && !entity_bytes.is_empty() {
+                        let handle = self.handles[index].as_mut().unwrap();
+                        let merged = if state == ClientNetworkState::SendSegmentsToServer as u8 {
+                            handle.merge_segments(Some(connection_token), entity_bytes)
+
+                        } else {
+                            handle.merge_bytes(Some(connection_token), entity_bytes)
+                        };
+
+                        if merged.is_err() {
+                            return Err(Error::RejectedEntityUpdate(connection_token.index, index));
+                        }
+                        if let Some(entity) = handle.get_entity() {
+                            record_merge_bandwidth(&mut self.kind_bandwidth, entity.kind(), entity_bytes);
+                        }
+                        self.stats.updates_merged += 1;
+                    }
+
+                    i += length;
+
+                } else if stop_at_incomplete {
+                    return Ok(start);
+
+                } else {
+                    return Err(Error::TruncatedEntityData(index));
+                },
+                Some(ClientNetworkState::AcknowledgeServerUpdate) => if i < len {
+                    if let Some(slot) = slot {
+                        if slot.state == RemoteState::Update {
+                            slot.baseline.acknowledge(bytes[i]);
+                        }
+                    }
+                    i += 1;
+
+                } else if stop_at_incomplete {
+                    return Ok(start);
+
+                } else {
+                    return Err(Error::TruncatedEntityData(index));
+                },
+                Some(ClientNetworkState::ConfirmDestroyToServer) => if let Some(slot) = slot {
+                    if !self.handles[index].as_ref().unwrap().is_alive() {
+                        slot.state.destroy();
+
+                    } else if !slot.state.forgotten() {
+                        slot.state.hidden();
+                    }
+                },
+                None => return Err(Error::RemainingPacketData((&bytes[i..]).to_vec()))
+            }
+
+        }
+
+        Ok(i)
+
+    }
+
+    fn send_packets(
+        &mut self,
+        connection_token: &ConnectionToken<U>,
+        max_bytes_per_packet: usize,
+        buffer: &mut PacketBuffer
+
+    ) -> Result<Vec<ConnectionSendEvent>, Error> {
+
+        if connection_token.server_index != self.index {
+            Err(Error::UnknownSenderToken)
+
+        } else if self.suspended[connection_token.index].is_some() {
+            Err(Error::ConnectionSuspended)
+
+        } else if self.config.require_handshake && !self.handshakes[connection_token.index] {
+            if self.connections[connection_token.index].is_some() {
+                buffer.clear();
+                Ok(Vec::new())
+
+            } else {
+                Err(Error::UnknownSenderToken)
+            }
+
+        } else if let Some(remote_states) = self.connections[connection_token.index].as_mut() {
+
+            let mut packets = PacketList::with_buffer(
+                max_bytes_per_packet,
+                self.config.packet_overhead,
+                self.config.max_entities_per_packet,
+                self.config.packet_header.as_ref(),
+                self.config.compression.as_ref(),
+                self.config.packet_checksum,
+                buffer
+            );
+
+            let mut events = Vec::new();
+            let mut keepalive_index = None;
+
+            // Announce the protocol version ahead of all other packets
+            if self.config.announce_version && !self.versions_sent[connection_token.index] {
+                packets.append_packet(version_bytes());
+                self.versions_sent[connection_token.index] = true;
+            }
+
+            // Best-effort destroys of dropped transient entities
+            for (index, generation) in self.pending_destroys[connection_token.index].drain(0..) {
+                packets.append_bytes(vec![NetworkState::SendDestroyToClient as u8, index]);
+                events.push(ConnectionSendEvent::Destroyed(EntityObserver {
+                    index: index as usize,
+                    server_index: self.index,
+                    generation: generation
+                }));
+            }
+
+            for &mut(ref token, _, ref mut connection_count, _) in &mut self.active_handles {
+
+                let handle = &mut self.handles[token.index];
+                let slot = remote_states.get_mut(token.index);
+                let remote_state = &mut slot.state;
+                let mut relevance = Relevance::Full;
+
+                if handle.as_ref().unwrap().is_alive() {
+
+                    // Increase the entities connection count for newly established connections
+                    if remote_state.reset_accepted() {
+                        *connection_count += 1;
+                    }
+
+                    // Check if the entity should no longer be send to the connection.
+                    // The client should simply forget about the entity and drop it
+                    // without running its destroyed() method.
+                    relevance = handle.as_ref().unwrap().relevance(connection_token);
+                    if relevance == Relevance::Forgotten {
+                        match handle.as_ref().unwrap().forget_mode() {
+                            ForgetMode::Destroy => if *remote_state < RemoteState::Forget {
+                                remote_state.forget();
+                            },
+                            // Hidden entities are kept on the client without
+                            // receiving any further updates
+                            ForgetMode::Hide => {
+                                remote_state.hide();
+                            }
+                        }
+
+                    // If the entity should be send to the client again,
+                    // reset its state so we tell the client to create it again.
+                    //
+                    // Hidden entities are revealed instead once the client
+                    // has confirmed hiding them.
+                    } else if !remote_state.reset_forgotten() {
+                        remote_state.reveal();
+                    }
+
+                // Reduce the entities connection count if a client has confirmed destruction
+                } else if *connection_count > 0 && remote_state.reset_destroyed() {
+                    *connection_count -= 1;
+
+                // Entities which were destroyed before their creation was ever
+                // send are not announced to the client at all
+                } else if *remote_state == RemoteState::Unknown && !slot.create_sent {
+                    if *connection_count > 0 {
+                        *connection_count -= 1;
+                    }
+                    remote_state.accept();
+                }
+
+                // Baselines only apply to the entity's current update phase
+                let baseline = &mut slot.baseline;
+                if *remote_state != RemoteState::Update {
+                    baseline.reset();
+                }
+
+                baseline.set_tick(self.tick);
+
+                if keepalive_index.is_none() && *remote_state == RemoteState::Update && handle.as_ref().unwrap().is_alive() {
+                    keepalive_index = Some(token.index);
+                }
+
+                // Low frequency entities only serialize every n-th update
+                let skip_update = *remote_state == RemoteState::Update && match relevance {
+                    Relevance::LowFrequency(interval) => baseline.skip_update(interval),
+                    _ => false
+                };
+
+                // Rate capped entities wait for the cooldown of their last update
+                let skip_update = skip_update || (
+                    *remote_state == RemoteState::Update
+                    && handle.as_ref().unwrap().is_alive()
+                    && self.config.maximum_update_rate.is_some()
+                    && baseline.cool_down()
+                );
+
+                // Paused updates are skipped without affecting any other state
+                let skip_update = skip_update || (
+                    self.updates_paused
+                    && *remote_state == RemoteState::Update
+                    && handle.as_ref().unwrap().is_alive()
+                );
+
+                // Unconfirmed creations are only re-send every n-th update
+                let create_resend = &mut slot.create_resend;
+                let skip_create = if *remote_state != RemoteState::Unknown {
+                    *create_resend = 0;
+                    false
+
+                } else if *create_resend > 0 && handle.as_ref().unwrap().is_alive() {
+                    *create_resend -= 1;
+                    true
+
+                } else {
+                    false
+                };
+
+                // Only serialize entities which have open client connections,
+                // destructions are only send to clients which know the entity
+                let is_announced = handle.as_ref().unwrap().is_alive() || *remote_state != RemoteState::Accept;
+                if *connection_count > 0 && !skip_update && !skip_create && is_announced {
+
+                    let bytes = handle.as_mut().unwrap().as_bytes(
+                        &self.config,
+                        Some(connection_token),
+                        remote_state,
+                        if self.config.acknowledge_updates {
+                            Some(baseline)
+
+                        } else {
+                            None
+                        }
+                    );
+
+                    // Entity bytes which exceed the length codec cannot be send
+                    let mut bytes = match bytes {
+                        Ok(bytes) => bytes,
+                        Err(()) => {
+                            packets.set_oversized();
+                            continue;
+                        }
+                    };
+
+                    let observer = EntityObserver {
+                        index: token.index,
+                        server_index: self.index,
+                        generation: self.slot_generations[token.index]
+                    };
+
+                    match bytes.first().and_then(|state| NetworkState::from_u8(*state)) {
+                        Some(NetworkState::SendCreateToClient) => {
+                            slot.create_sent = true;
+                            if let Some(interval) = self.config.create_resend_interval {
+                                *create_resend = interval.saturating_sub(1);
+                            }
+                            events.push(ConnectionSendEvent::Created(observer));
+                        },
+                        Some(NetworkState::SendDestroyToClient) => events.push(ConnectionSendEvent::Destroyed(observer)),
+                        Some(NetworkState::SendForgetToClient) => events.push(ConnectionSendEvent::Forgotten(observer)),
+                        Some(NetworkState::SendHideToClient) => events.push(ConnectionSendEvent::Hidden(observer)),
+                        Some(NetworkState::SendUpdateToClient) | Some(NetworkState::SendSegmentsToClient) => if let Some(entity) = handle.as_ref().unwrap().get_entity() {
+                            // Skip the update sequence if present
+                            let offset = if self.config.acknowledge_updates { 3 } else { 2 };
+                            record_part_bandwidth(&self.config, &mut self.kind_bandwidth, entity.kind(), &bytes[offset..]);
+                            if let Some(rate) = self.config.maximum_update_rate {
+                                baseline.start_cooldown(rate);
+                            }
+
+                            // Prefix the update with the server tick
+                            if self.config.send_update_ticks {
+                                bytes.splice(offset..offset, update_tick_bytes(self.tick).iter().cloned());
+                            }
+                        },
+                        _ => {}
+                    }
+
+                    packets.append_bytes(bytes);
+
+                }
+
+            }
+
+            // Stop tracking states which were reset during serialization
+            remote_states.compact();
+
+            // Forced keepalives are only needed when nothing else is send
+            if self.keepalives[connection_token.index] {
+                self.keepalives[connection_token.index] = false;
+                if packets.is_empty() {
+                    match keepalive_index {
+                        Some(index) => {
+                            let mut bytes = vec![NetworkState::SendUpdateToClient as u8, index as u8];
+                            if self.config.acknowledge_updates {
+                                bytes.push(remote_states.get(index).map_or(0, |slot| slot.baseline.sequence()));
+                            }
+                            if self.config.send_update_ticks {
+                                bytes.extend_from_slice(&update_tick_bytes(self.tick));
+                            }
+                            if serialize_entity_bytes(&self.config, &[], &mut bytes).is_ok() {
+                                packets.append_bytes(bytes);
+
+                            } else {
+                                packets.set_oversized();
+                            }
+                        },
+                        None => packets.append_empty()
+                    }
+                }
+            }
+
+            if let Some(max) = self.config.max_baselines_per_connection {
+                remote_states.evict_oldest_baselines(max);
+            }
+
+            if packets.is_oversized() {
+                buffer.clear();
+                return Err(Error::PacketSizeTooSmall(max_bytes_per_packet));
+            }
+
+            packets.into_buffer(buffer);
+            self.stats.bytes_sent += buffer.packets().iter().map(|p| p.len()).sum::<usize>();
+
+            if let Some(ref mut inspector) = self.send_inspector {
+                for packet in buffer.packets() {
+                    inspector(connection_token, packet);
+                }
+            }
+            self.update_visibility(connection_token);
+
+            Ok(events)
+
+        } else {
+            Err(Error::UnknownSenderToken)
+        }
+
+    }
+
+    fn update_visibility(&mut self, connection_token: &ConnectionToken<U>) {
+        let visible = self.connection_has_visible_entities(connection_token);
+        if self.visible[connection_token.index] && !visible {
+            if let Some(ref mut callback) = self.hidden_callback {
+                callback(connection_token);
+            }
+        }
+        self.visible[connection_token.index] = visible;
+    }
+
+    fn update_entities<F: FnMut(&EntityToken, &mut Box<E>) -> bool, G: FnMut(&EntityToken)>(
+        &mut self,
+        mut callback: F,
+        mut on_drop: G
+
+    ) -> UpdateStats {
+
+        let mut update_stats = UpdateStats::default();
+        self.tick = self.tick.wrapping_add(1);
+
+        for &mut (
+            ref entity_token,
+            ref mut timeout,
+            ref mut connection_count,
+            ref mut connected
+
+        ) in &mut self.active_handles {
+
+            let handle = &mut self.handles[entity_token.index];
+            let is_alive = handle.is_some()
+                        && handle.as_ref().unwrap().is_alive();
+
+            let mut drop_reason = DropReason::Confirmed;
+            if is_alive {
+
+                // Unobserved entities may sleep until a connection observes them
+                let entity = handle.as_mut().unwrap().get_entity_mut().unwrap();
+                if *connection_count == 0 && entity.can_sleep() {
+                    update_stats.sleeping += 1;
+
+                } else {
+                    let dirty = callback(entity_token, entity);
+                    handle.as_mut().unwrap().set_dirty(dirty);
+                    update_stats.alive += 1;
+                }
+
+            } else if *connection_count > 0 {
+
+                // If the entity is destroyed we timeout all open connections
+                // that don't respond with a ConfirmDestroyToServer packet
+                // within the given number of update calls.
+                if timeout.is_none() {
+                    *timeout = Some(match *handle {
+                        Some(ref handle) => handle.timeout_ticks(&self.config),
+                        None => self.config.handle_timeout_ticks
+                    });
+                }
+
+                // Safely reduce timeout until we hit 0
+                if timeout.is_some() {
+                    *timeout = Some(timeout.unwrap().saturating_sub(1));
+
+                    // Allow the destroyed entity to extend its own timeout
+                    if let Some(remaining) = handle.as_ref().and_then(|handle| handle.extend_timeout(timeout.unwrap())) {
+                        *timeout = Some(remaining);
+                    }
+
+                    if timeout.unwrap() == 0 {
+                        *connection_count = 0;
+                        update_stats.handles_timed_out += 1;
+                        drop_reason = DropReason::TimedOut;
+                    }
+                }
+            }
+
+            // Drop handlers of destroyed entities in case there are no
+            // more connected client
+            if !is_alive && *connection_count == 0 {
+
+                // Reset entity state for all open client connections, suspended
+                // ones might still know about the entity
+                let generation = self.slot_generations[entity_token.index];
+                for (connection_index, remote_states) in self.connections.iter_mut().enumerate() {
+                    if let Some(remote_states) = remote_states.as_mut() {
+                        if self.suspended[connection_index].is_some() {
+                            queue_pending_destroy(
+                                remote_states[entity_token.index],
+                                remote_states.create_sent(entity_token.index),
+                                &mut self.pending_destroys[connection_index],
+                                entity_token.index,
+                                generation
+                            );
+                        }
+                        remote_states[entity_token.index].destroy();
+                        remote_states[entity_token.index].reset_destroyed();
+                    }
+                }
+
+                if let Some(handle) = handle.as_mut() {
+                    handle.dropped(drop_reason);
+                }
+
+                *connected = false;
+                *handle = None;
+                update_stats.handles_dropped += 1;
+                on_drop(entity_token);
+
+                // Remember when the slot was released for LRU allocation
+                self.slot_release_tick += 1;
+                self.slot_release_ticks[entity_token.index] = self.slot_release_tick;
+
+            }
+
+        }
+
+        // Remove destroy handles without any connected clients
+        self.active_handles.retain(|&(_, _, _, connected)| connected);
+
+        // Stop tracking the states of dropped handles
+        if update_stats.handles_dropped > 0 {
+            let handles = &self.handles;
+            for remote_states in self.connections.iter_mut() {
+                if let Some(remote_states) = remote_states.as_mut() {
+                    remote_states.retain(|index| handles[index].is_some());
+                }
+            }
+        }
+
+        debug_assert_eq!(self.check_invariants(), Ok(()));
+
+        update_stats
+
+    }
+
+    fn is_current_ref(&self, entity_ref: &EntityRef) -> bool {
+        entity_ref.server_index == self.index && entity_ref.generation == self.slot_generations[entity_ref.index]
+    }
+
+    fn is_live_connection(&self, connection_token: &ConnectionToken<U>) -> bool {
+        connection_token.server_index == self.index && self.connections[connection_token.index].is_some()
+    }
+
+    fn release_handle(&mut self, index: usize) {
+
+        // Queue a single destroy for all connections which might know about
+        // the entity and reset their state for the slot
+        let generation = self.slot_generations[index];
+        for (connection_index, remote_states) in self.connections.iter_mut().enumerate() {
+            if let Some(remote_states) = remote_states.as_mut() {
+                queue_pending_destroy(
+                    remote_states[index],
+                    remote_states.create_sent(index),
+                    &mut self.pending_destroys[connection_index],
+                    index,
+                    generation
+                );
+                remote_states.remove(index);
+            }
+        }
+
+        self.handles[index] = None;
+        self.active_handles.retain(|&(ref entity_token, _, _, _)| entity_token.index != index);
+
+        // Remember when the slot was released for LRU allocation
+        self.slot_release_tick += 1;
+        self.slot_release_ticks[index] = self.slot_release_tick;
+
+    }
+
+    fn create_entity_in_slot(&mut self, index: usize, entity: Box<E>) -> EntityToken {
+
+        // Create entity handle which encapsulates the actual entity
+        let generation = self.slot_generations[index].wrapping_add(1);
+        let mut handle = EntityHandle::new(
+            EntityToken::new(index, self.index, generation),
+            entity
+        );
+
+        handle.create();
+
+        self.handles[index] = Some(handle);
+        self.slot_generations[index] = generation;
+
+        // Clear any state left over from the slot's previous entity
+        for remote_states in self.connections.iter_mut() {
+            if let Some(remote_states) = remote_states.as_mut() {
+                remote_states.remove(index);
+            }
+        }
+
+        // Add to list of active slots, which is kept sorted by slot index
+        let position = self.active_handles.iter().position(|&(ref token, _, _, _)| {
+            token.index > index
+
+        }).unwrap_or(self.active_handles.len());
+
+        self.active_handles.insert(position, (
+            EntityToken::new(index, self.index, generation),
+            None,
+            self.active_connections.len(),
+            true
+        ));
+
+        self.stats.entities_created += 1;
+
+        // Return a unique handle which cannot be copied
+        EntityToken::new(index, self.index, generation)
+
+    }
+
+    fn is_free_entity_slot(&self, index: usize) -> bool {
+        self.handles[index].is_none() && !self.slot_reserved[index]
+    }
+
+    fn find_free_entity_slot_index(&mut self) -> Option<usize> {
+        match self.config.slot_strategy {
+            SlotStrategy::Lowest => {
+                (0..256).find(|i| self.is_free_entity_slot(*i))
+            },
+            SlotStrategy::Random(_) => {
+                let free: Vec<usize> = (0..256).filter(|i| self.is_free_entity_slot(*i)).collect();
+                if free.is_empty() {
+                    None
+
+                } else {
+                    // Xorshift
+                    self.slot_random ^= self.slot_random << 13;
+                    self.slot_random ^= self.slot_random >> 7;
+                    self.slot_random ^= self.slot_random << 17;
+                    Some(free[(self.slot_random % free.len() as u64) as usize])
+                }
+            },
+            SlotStrategy::Lru => {
+                (0..256).filter(|i| self.is_free_entity_slot(*i)).min_by_key(|i| {
+                    self.slot_release_ticks[*i]
+                })
+            }
+        }
+    }
+
+    fn find_free_connection_slot_index(&self) -> Option<usize> {
+        for i in 0..256 {
+            if self.connections[i].is_none() {
+                return Some(i);
+            }
+        }
+        None
+    }
+
+}
+
+
+// Helpers --------------------------------------------------------------------
+// Destroyed entities are no longer counted once the client has confirmed
+// their destruction, unless it never learned about them in the first place
+fn is_connection_counted(remote_state: RemoteState, is_alive: bool, create_sent: bool) -> bool {
+    match remote_state {
+        RemoteState::Accept => false,
+        RemoteState::Unknown => is_alive || !create_sent,
+        _ => true
+    }
+}
+
+fn queue_pending_destroy(
+    remote_state: RemoteState,
+    create_sent: bool,
+    pending_destroys: &mut Vec<(u8, usize)>,
+    index: usize,
+    generation: usize
+) {
+    match remote_state {
+        // Connections which were never send the creation do not know the entity
+        RemoteState::Unknown if !create_sent => {},
+        RemoteState::Unknown | RemoteState::Create | RemoteState::Update
+        | RemoteState::Hide | RemoteState::Hidden | RemoteState::Reveal => {
+            pending_destroys.push((index as u8, generation));
+        },
+        _ => {}
+    }
+}
+
+fn slot_random_seed(config: &Config) -> u64 {
+    match config.slot_strategy {
+        // Xorshift gets stuck on a zero state
+        SlotStrategy::Random(seed) => if seed == 0 {
+            0x9E37_79B9_7F4A_7C15
+
+        } else {
+            seed
+        },
+        _ => 0
+    }
 }
 
 
@@ -12,18 +12,23 @@ use std::marker::PhantomData;
 
 
 // Internal Dependencies ------------------------------------------------------
-use ::shared::Config;
-use ::traits::{Entity, EntitySerializer};
-use ::server::ConnectionToken;
+use ::shared::{Config, DropReason, ForgetMode, MergeError, Relevance};
+use ::traits::{Entity, EntitySerializer, SerializeState};
+use ::server::{Baseline, ConnectionToken};
 
 
 // Entity Handle --------------------------------------------------------------
 pub struct EntityHandle<E: Entity<U> + ?Sized, R: EntitySerializer<E, S, O, U>, S, O, U: fmt::Debug> {
     token: O,
     entity: Option<Box<E>>,
+    retained: Option<Box<E>>,
     handler: PhantomData<R>,
     state: PhantomData<S>,
-    update_tick: u8,
+    update_tick: usize,
+    timeout_ticks: Option<usize>,
+    observed: bool,
+    dirty: bool,
+    owner: Option<usize>,
     connection_id: PhantomData<U>
 }
 
@@ -33,9 +38,14 @@ impl<E: Entity<U> + ?Sized, R: EntitySerializer<E, S, O, U>, S, O, U: fmt::Debug
         EntityHandle {
             token: token,
             entity: Some(entity),
+            retained: None,
             handler: PhantomData,
             state: PhantomData,
             update_tick: 0,
+            observed: false,
+            dirty: true,
+            owner: None,
+            timeout_ticks: None,
             connection_id: PhantomData
         }
     }
@@ -52,13 +62,84 @@ impl<E: Entity<U> + ?Sized, R: EntitySerializer<E, S, O, U>, S, O, U: fmt::Debug
         self.entity.as_mut()
     }
 
-    pub fn filter(&self, connection_slot: &ConnectionToken<U>) -> bool {
-        self.entity.as_ref().unwrap().filter(connection_slot)
+    pub fn timeout_ticks(&self, config: &Config) -> usize {
+        self.timeout_ticks.unwrap_or(config.handle_timeout_ticks)
     }
 
-    pub fn merge_bytes(&mut self, connection_slot: Option<&ConnectionToken<U>>, bytes: &[u8]) {
+    pub fn dropped(&mut self, reason: DropReason) {
+        if let Some(mut entity) = self.retained.take() {
+            entity.dropped(reason);
+        }
+    }
+
+    pub fn extend_timeout(&self, remaining: usize) -> Option<usize> {
+        self.retained.as_ref().and_then(|entity| {
+            entity.extend_destroy_timeout(remaining)
+        })
+    }
+
+    pub fn set_dirty(&mut self, dirty: bool) {
+        self.dirty = dirty;
+    }
+
+    pub fn owner(&self) -> Option<usize> {
+        self.owner
+    }
+
+    pub fn set_owner(&mut self, owner: Option<usize>) {
+        if owner != self.owner {
+            self.owner = owner;
+            if let Some(ref mut entity) = self.entity {
+                entity.owner_changed(owner);
+            }
+
+            // Owners usually serialize differently
+            self.dirty = true;
+        }
+    }
+
+    pub fn relevance(&self, connection_slot: &ConnectionToken<U>) -> Relevance {
+        self.entity.as_ref().unwrap().relevance(connection_slot)
+    }
+
+    pub fn forget_mode(&self) -> ForgetMode {
+        self.entity.as_ref().unwrap().forget_mode()
+    }
+
+    pub fn merge_bytes(&mut self, connection_slot: Option<&ConnectionToken<U>>, bytes: &[u8]) -> Result<(), MergeError> {
         if let Some(ref mut entity) = self.entity {
-            entity.merge_bytes(connection_slot, bytes);
+            entity.merge_bytes(connection_slot, bytes)
+
+        } else {
+            Ok(())
+        }
+    }
+
+    pub fn merge_segments(&mut self, connection_slot: Option<&ConnectionToken<U>>, bytes: &[u8]) -> Result<(), MergeError> {
+        if let Some(ref mut entity) = self.entity {
+            entity.merge_segments(connection_slot, bytes)
+
+        } else {
+            Ok(())
+        }
+    }
+
+    pub fn merge_bytes_at(&mut self, connection_slot: Option<&ConnectionToken<U>>, tick: u32, bytes: &[u8]) -> Result<(), MergeError> {
+        if let Some(ref mut entity) = self.entity {
+            entity.merge_bytes_at(connection_slot, tick, bytes)
+
+        } else {
+            Ok(())
+        }
+    }
+
+    pub fn confirmed_by(&mut self, connection_slot: &ConnectionToken<U>) {
+        if let Some(ref mut entity) = self.entity {
+            if !self.observed {
+                self.observed = true;
+                entity.observed(connection_slot);
+            }
+            entity.confirmed_by(connection_slot);
         }
     }
 
@@ -71,16 +152,21 @@ impl<E: Entity<U> + ?Sized, R: EntitySerializer<E, S, O, U>, S, O, U: fmt::Debug
         &mut self,
         config: &Config,
         connection_slot: Option<&ConnectionToken<U>>,
-        state: &S
+        state: &S,
+        baseline: Option<&mut Baseline>
 
-    ) -> Vec<u8> {
+    ) -> Result<Vec<u8>, ()> {
         R::as_bytes(
             config,
             &self.token,
             connection_slot,
             state,
             self.entity.as_mut(),
-            &mut self.update_tick
+            SerializeState {
+                update_tick: &mut self.update_tick,
+                dirty: self.dirty,
+                baseline: baseline
+            }
         )
     }
 
@@ -93,11 +179,34 @@ impl<E: Entity<U> + ?Sized, R: EntitySerializer<E, S, O, U>, S, O, U: fmt::Debug
     pub fn destroy(&mut self) {
         if let Some(mut entity) = self.entity.take() {
             entity.destroyed();
+            self.timeout_ticks = entity.handle_timeout_ticks();
         }
     }
 
+    pub fn retire(&mut self) {
+        if let Some(mut entity) = self.entity.take() {
+            entity.destroyed();
+            self.timeout_ticks = entity.handle_timeout_ticks();
+            self.retained = Some(entity);
+        }
+    }
+
+    pub fn is_transient(&self) -> bool {
+        match self.entity.as_ref().or(self.retained.as_ref()) {
+            Some(entity) => entity.transient(),
+            None => false
+        }
+    }
+
+    pub fn take(&mut self) -> Option<Box<E>> {
+        self.retire();
+        self.retained.take()
+    }
+
     pub fn forget(&mut self) {
-        self.entity.take();
+        if let Some(entity) = self.entity.take() {
+            self.timeout_ticks = entity.handle_timeout_ticks();
+        }
     }
 
 }
@@ -0,0 +1,97 @@
+// Copyright (c) 2015-2017 Ivo Wetzel
+
+// Licensed under the Apache License, Version 2.0 <LICENSE-APACHE or
+// http://www.apache.org/licenses/LICENSE-2.0> or the MIT license
+// <LICENSE-MIT or http://opensource.org/licenses/MIT>, at your
+// option. This file may not be copied, modified, or distributed
+// except according to those terms.
+
+// Internal Dependencies ------------------------------------------------------
+use ::traits::LengthCodec;
+
+
+/// A length codec which encodes lengths of up to `255` bytes as a single byte.
+///
+/// This is the default codec.
+#[derive(Debug, Default, Copy, Clone)]
+pub struct SingleByte;
+
+impl LengthCodec for SingleByte {
+
+    fn max_length(&self) -> usize {
+        255
+    }
+
+    fn encode(&self, length: usize, bytes: &mut Vec<u8>) {
+        bytes.push(length as u8);
+    }
+
+    fn decode(&self, bytes: &[u8]) -> Option<(usize, usize)> {
+        bytes.first().map(|length| (*length as usize, 1))
+    }
+
+}
+
+/// A length codec which encodes lengths of up to `65535` bytes as two bytes
+/// in big endian order.
+#[derive(Debug, Default, Copy, Clone)]
+pub struct TwoByte;
+
+impl LengthCodec for TwoByte {
+
+    fn max_length(&self) -> usize {
+        65535
+    }
+
+    fn encode(&self, length: usize, bytes: &mut Vec<u8>) {
+        bytes.push((length >> 8) as u8);
+        bytes.push(length as u8);
+    }
+
+    fn decode(&self, bytes: &[u8]) -> Option<(usize, usize)> {
+        if bytes.len() < 2 {
+            None
+
+        } else {
+            Some((((bytes[0] as usize) << 8) | bytes[1] as usize, 2))
+        }
+    }
+
+}
+
+/// A length codec which encodes lengths as a variable number of bytes, using
+/// 7 bits per byte and the highest bit to mark continuation.
+///
+/// Lengths below `128` occupy a single byte and lengths of up to `2^28 - 1`
+/// bytes can be encoded.
+#[derive(Debug, Default, Copy, Clone)]
+pub struct Varint;
+
+const VARINT_MAX_BYTES: usize = 4;
+
+impl LengthCodec for Varint {
+
+    fn max_length(&self) -> usize {
+        (1 << (7 * VARINT_MAX_BYTES)) - 1
+    }
+
+    fn encode(&self, mut length: usize, bytes: &mut Vec<u8>) {
+        while length >= 0x80 {
+            bytes.push((length & 0x7F) as u8 | 0x80);
+            length >>= 7;
+        }
+        bytes.push(length as u8);
+    }
+
+    fn decode(&self, bytes: &[u8]) -> Option<(usize, usize)> {
+        let mut length = 0;
+        for (i, byte) in bytes.iter().take(VARINT_MAX_BYTES).enumerate() {
+            length |= ((byte & 0x7F) as usize) << (7 * i);
+            if byte & 0x80 == 0 {
+                return Some((length, i + 1));
+            }
+        }
+        None
+    }
+
+}
@@ -8,12 +8,29 @@
 
 // Modules --------------------------------------------------------------------
 mod entity_handle;
+mod length_codec;
+
+
+// STD Dependencies -----------------------------------------------------------
+use std::{cmp, fmt, mem};
+use std::borrow::Cow;
+use std::collections::HashMap;
+
+
+// Internal Dependencies ------------------------------------------------------
+use ::{NETWORK_BYTE_OFFSET, PROTOCOL_VERSION};
+use ::traits::LengthCodec;
 
 
 // Re-Exports -----------------------------------------------------------------
 pub use self::entity_handle::EntityHandle;
+pub use self::length_codec::{SingleByte, TwoByte, Varint};
 
 /// Client and server related configuration options.
+///
+/// The fields can be set directly, but
+/// [`Config::builder`](struct.Config.html#method.builder) should be preferred
+/// since it rejects invalid combinations of values.
 #[derive(Debug)]
 pub struct Config  {
 
@@ -29,13 +46,246 @@ pub struct Config  {
     /// which prevent the confirmation of the entity's destruction this timeout
     /// will prevent the entity slot from becoming permanently blocked.
     ///
+    /// The value can be overridden on a per entity basis via
+    /// [`Entity::handle_timeout_ticks`](trait.Entity.html#method.handle_timeout_ticks).
+    ///
+    /// A value of `0` drops the handles of destroyed entities on the very next
+    /// tick, without waiting for any confirmations.
+    ///
     /// The default value is `30` ticks.
     pub handle_timeout_ticks: usize,
 
-    /// TODO Specifies the minimum update interval between...
-    /// part_bytes...
-    /// merge_bytes...
-    pub minimum_update_interval: Option<u8>
+    /// Specifies the maximum number of ticks after which an entity which did
+    /// not produce any update bytes via
+    /// [`Entity::part_bytes`](trait.Entity.html#method.part_bytes) will send
+    /// an empty update packet to its remote.
+    ///
+    /// The tick counter is kept per entity and advanced each time the entity
+    /// is serialized in its update state without having any update bytes,
+    /// once the counter reaches the interval an empty update is sent and the
+    /// counter starts again from zero. On the server the counter is advanced
+    /// once for each connection the entity is serialized for.
+    ///
+    /// Empty updates are never passed into
+    /// [`Entity::merge_bytes`](trait.Entity.html#method.merge_bytes).
+    ///
+    /// **Important:** A value of `Some(0)` sends an empty update on every
+    /// single tick and is rejected by
+    /// [`ConfigBuilder::build`](struct.ConfigBuilder.html#method.build).
+    ///
+    /// The default value is `None` which disables empty updates.
+    pub minimum_update_interval: Option<usize>,
+
+    /// Caps how often updates of a single entity are send to its remote.
+    ///
+    /// With a value of `Some(n)` an entity sends at most one update every n
+    /// ticks, during the ticks in between its
+    /// [`Entity::part_bytes`](trait.Entity.html#method.part_bytes) method is
+    /// not invoked so its changes accumulate until the next update. A tick is
+    /// a call to [`Client::send`](struct.Client.html#method.send) on the
+    /// client and a call to
+    /// [`Server::connection_send`](struct.Server.html#method.connection_send)
+    /// for the respective connection on the server.
+    ///
+    /// Creations, destructions and acknowledgements are never capped.
+    ///
+    /// **Important:** A value of `Some(0)` is rejected by
+    /// [`ConfigBuilder::build`](struct.ConfigBuilder.html#method.build).
+    ///
+    /// The default value is `None` which sends updates on every tick.
+    pub maximum_update_rate: Option<usize>,
+
+    /// Throttles how often the [`Server`](struct.Server.html) re-sends the
+    /// creation of an entity which has not yet been confirmed by the client
+    /// of a connection.
+    ///
+    /// With a value of `Some(n)` an unconfirmed creation is only re-send on
+    /// every n-th call to
+    /// [`Server::connection_send`](struct.Server.html#method.connection_send)
+    /// for that connection, saving bandwidth on lossy links with slow
+    /// confirmations.
+    ///
+    /// **Important:** A value of `Some(0)` is rejected by
+    /// [`ConfigBuilder::build`](struct.ConfigBuilder.html#method.build).
+    ///
+    /// The default value is `None` which re-sends creations on every call.
+    pub create_resend_interval: Option<usize>,
+
+    /// Determines how the [`Server`](struct.Server.html) picks the slot index
+    /// for newly created entities.
+    ///
+    /// Client side slots are always dictated by the server.
+    ///
+    /// The default value is `SlotStrategy::Lowest`.
+    pub slot_strategy: SlotStrategy,
+
+    /// An optional header which is prepended to every packet returned from
+    /// [`Server::connection_send`](struct.Server.html#method.connection_send)
+    /// and [`Client::send`](struct.Client.html#method.send).
+    ///
+    /// The header's length counts against the maximum number of bytes per
+    /// packet and it is stripped again from every packet passed into
+    /// [`Server::connection_receive`](struct.Server.html#method.connection_receive)
+    /// and [`Client::receive`](struct.Client.html#method.receive), so both
+    /// sides must be configured with a header of the same length.
+    ///
+    /// The default value is `None`.
+    pub packet_header: Option<PacketHeader>,
+
+    /// The number of bytes per packet which are reserved for the framing of
+    /// the underlying transport, e.g. a per-message header.
+    ///
+    /// The overhead is subtracted from the maximum number of bytes per packet
+    /// passed into
+    /// [`Server::connection_send`](struct.Server.html#method.connection_send)
+    /// and [`Client::send`](struct.Client.html#method.send).
+    ///
+    /// The default value is `0`.
+    pub packet_overhead: usize,
+
+    /// The maximum number of entities which are serialized into a single
+    /// packet, a new packet is started once the limit is reached even if
+    /// further entities would still fit in byte-wise.
+    ///
+    /// This spreads the processing of many small entities across multiple
+    /// packets on the receiving side.
+    ///
+    /// The default value is `None` which only limits packets by their size.
+    pub max_entities_per_packet: Option<usize>,
+
+    /// Whether clients acknowledge each server update they have received.
+    ///
+    /// When enabled, update packets carry an additional sequence byte which
+    /// the client echoes back, the last acknowledged update bytes are then
+    /// passed to
+    /// [`Entity::part_bytes_delta`](trait.Entity.html#method.part_bytes_delta)
+    /// on the server so that updates can be delta encoded.
+    ///
+    /// The server tick of the last acknowledged update is available via
+    /// [`Server::connection_entity_acked_tick`](struct.Server.html#method.connection_entity_acked_tick).
+    ///
+    /// Since this changes the wire format both the server and its clients
+    /// must use the same setting.
+    ///
+    /// The default value is `false`.
+    pub acknowledge_updates: bool,
+
+    /// Whether server updates carry the server tick at which they were
+    /// serialized.
+    ///
+    /// When enabled, update packets send to clients carry four additional
+    /// bytes with the server tick, which advances on each call to
+    /// [`Server::update_entities_with`](struct.Server.html#method.update_entities_with).
+    /// Clients then pass the tick into
+    /// [`Entity::merge_bytes_at`](trait.Entity.html#method.merge_bytes_at)
+    /// so predicted state can be reconciled against it.
+    ///
+    /// Since this changes the wire format both the server and its clients
+    /// must use the same setting.
+    ///
+    /// The default value is `false`.
+    pub send_update_ticks: bool,
+
+    /// An optional compressor which is applied to every packet returned from
+    /// [`Server::connection_send`](struct.Server.html#method.connection_send)
+    /// and [`Client::send`](struct.Client.html#method.send).
+    ///
+    /// Packets are only send compressed when this actually reduces their size,
+    /// compressed packets are marked with a leading byte of
+    /// [`NETWORK_BYTE_OFFSET`](constant.NETWORK_BYTE_OFFSET.html) and are
+    /// decompressed again before being parsed by
+    /// [`Server::connection_receive`](struct.Server.html#method.connection_receive)
+    /// and [`Client::receive`](struct.Client.html#method.receive), so both
+    /// sides must be configured with the same compressor.
+    ///
+    /// The default value is `None`.
+    pub compression: Option<Compressor>,
+
+    /// Whether every packet returned from
+    /// [`Server::connection_send`](struct.Server.html#method.connection_send)
+    /// and [`Client::send`](struct.Client.html#method.send) carries a
+    /// checksum to detect corruptions which were not caught by the transport.
+    ///
+    /// Checksummed packets are marked with a leading byte and followed by a
+    /// two byte Fletcher-16 checksum, which count against the maximum number
+    /// of bytes per packet. Packets with a missing or mismatching checksum are
+    /// rejected with `ChecksumMismatch` before being parsed by
+    /// [`Server::connection_receive`](struct.Server.html#method.connection_receive)
+    /// and [`Client::receive`](struct.Client.html#method.receive), so both
+    /// sides must use the same setting.
+    ///
+    /// Custom protocol packets whose first byte is higher than
+    /// [`NETWORK_BYTE_OFFSET`](constant.NETWORK_BYTE_OFFSET.html) are not
+    /// checksummed and passed on without being verified.
+    ///
+    /// The default value is `false`.
+    pub packet_checksum: bool,
+
+    /// The codec which is used to encode the length prefix of the serialized
+    /// bytes of entity creations and updates.
+    ///
+    /// The codec limits the number of bytes which can be returned from
+    /// [`Entity::snapshot_bytes`](trait.Entity.html#method.snapshot_bytes) and
+    /// [`Entity::part_bytes`](trait.Entity.html#method.part_bytes).
+    ///
+    /// Since this changes the wire format both the server and its clients
+    /// must use the same codec.
+    ///
+    /// The default value is [`SingleByte`](struct.SingleByte.html).
+    pub length_codec: Box<dyn LengthCodec>,
+
+    /// Whether the server waits for a protocol handshake from each connection
+    /// before synchronising any entities with it.
+    ///
+    /// The handshake is created via
+    /// [`Client::handshake_packet`](struct.Client.html#method.handshake_packet)
+    /// and verified by
+    /// [`Server::accept_handshake`](struct.Server.html#method.accept_handshake),
+    /// until then
+    /// [`Server::connection_send`](struct.Server.html#method.connection_send)
+    /// returns no packets for the connection.
+    ///
+    /// The default value is `false`.
+    pub require_handshake: bool,
+
+    /// Whether the server announces its
+    /// [`PROTOCOL_VERSION`](constant.PROTOCOL_VERSION.html) to each
+    /// connection.
+    ///
+    /// The announcement is sent as a separate packet in front of all others
+    /// by the first call to
+    /// [`Server::connection_send`](struct.Server.html#method.connection_send)
+    /// for a connection, a client using a different version returns
+    /// `Error::ProtocolVersionMismatch` from
+    /// [`Client::receive`](struct.Client.html#method.receive).
+    ///
+    /// The default value is `false`.
+    pub announce_version: bool,
+
+    /// The maximum number of entity baselines which are retained per
+    /// connection when
+    /// [`Config::acknowledge_updates`](struct.Config.html#structfield.acknowledge_updates)
+    /// is enabled.
+    ///
+    /// Once exceeded, the baselines of the least recently updated entities
+    /// are evicted and their next update is send without a baseline,
+    /// resulting in a full resend of the entity's state.
+    ///
+    /// The default value is `None` which retains all baselines.
+    pub max_baselines_per_connection: Option<usize>
+
+}
+
+impl Config {
+
+    /// Returns a builder for a validated `Config`, starting out with the
+    /// default values.
+    pub fn builder() -> ConfigBuilder {
+        ConfigBuilder {
+            config: Config::default(),
+            max_bytes_per_packet: None
+        }
+    }
 
 }
 
@@ -43,77 +293,995 @@ impl Default for Config {
     fn default() -> Config {
         Config {
             handle_timeout_ticks: 30,
-            minimum_update_interval: None
+            minimum_update_interval: None,
+            maximum_update_rate: None,
+            create_resend_interval: None,
+            slot_strategy: SlotStrategy::Lowest,
+            packet_header: None,
+            packet_overhead: 0,
+            max_entities_per_packet: None,
+            acknowledge_updates: false,
+            send_update_ticks: false,
+            compression: None,
+            packet_checksum: false,
+            length_codec: Box::new(SingleByte),
+            require_handshake: false,
+            announce_version: false,
+            max_baselines_per_connection: None
         }
     }
 }
 
+/// A enum of possible configuration error values.
+#[derive(Debug, Eq, PartialEq, Hash)]
+pub enum ConfigError {
+
+    /// Returned when the
+    /// [`minimum_update_interval`](struct.Config.html#structfield.minimum_update_interval)
+    /// is set to `Some(0)`.
+    ZeroUpdateInterval,
+
+    /// Returned when the
+    /// [`max_entities_per_packet`](struct.Config.html#structfield.max_entities_per_packet)
+    /// is set to `Some(0)`.
+    ZeroEntitiesPerPacket,
+
+    /// Returned when the
+    /// [`create_resend_interval`](struct.Config.html#structfield.create_resend_interval)
+    /// is set to `Some(0)`.
+    ZeroCreateResendInterval,
+
+    /// Returned when the
+    /// [`maximum_update_rate`](struct.Config.html#structfield.maximum_update_rate)
+    /// is set to `Some(0)`.
+    ZeroUpdateRate,
+
+    /// Returned when the packet size passed to
+    /// [`ConfigBuilder::max_bytes_per_packet`](struct.ConfigBuilder.html#method.max_bytes_per_packet)
+    /// leaves no room for entity bytes after subtracting the
+    /// [`packet_overhead`](struct.Config.html#structfield.packet_overhead),
+    /// the [`packet_header`](struct.Config.html#structfield.packet_header),
+    /// the [`packet_checksum`](struct.Config.html#structfield.packet_checksum)
+    /// and the header of a fragment.
+    PacketSizeTooSmall(usize)
+
+}
+
+/// A builder for [`Config`](struct.Config.html) values which validates the
+/// configuration before it is used.
+#[derive(Debug)]
+pub struct ConfigBuilder {
+    config: Config,
+    max_bytes_per_packet: Option<usize>
+}
+
+impl ConfigBuilder {
+
+    /// Sets [`Config::handle_timeout_ticks`](struct.Config.html#structfield.handle_timeout_ticks).
+    pub fn handle_timeout_ticks(mut self, ticks: usize) -> ConfigBuilder {
+        self.config.handle_timeout_ticks = ticks;
+        self
+    }
+
+    /// Sets [`Config::minimum_update_interval`](struct.Config.html#structfield.minimum_update_interval).
+    pub fn minimum_update_interval(mut self, interval: Option<usize>) -> ConfigBuilder {
+        self.config.minimum_update_interval = interval;
+        self
+    }
+
+    /// Sets [`Config::maximum_update_rate`](struct.Config.html#structfield.maximum_update_rate).
+    pub fn maximum_update_rate(mut self, rate: Option<usize>) -> ConfigBuilder {
+        self.config.maximum_update_rate = rate;
+        self
+    }
+
+    /// Sets [`Config::create_resend_interval`](struct.Config.html#structfield.create_resend_interval).
+    pub fn create_resend_interval(mut self, interval: Option<usize>) -> ConfigBuilder {
+        self.config.create_resend_interval = interval;
+        self
+    }
+
+    /// Sets [`Config::slot_strategy`](struct.Config.html#structfield.slot_strategy).
+    pub fn slot_strategy(mut self, strategy: SlotStrategy) -> ConfigBuilder {
+        self.config.slot_strategy = strategy;
+        self
+    }
+
+    /// Sets [`Config::packet_header`](struct.Config.html#structfield.packet_header).
+    pub fn packet_header(mut self, header: Option<PacketHeader>) -> ConfigBuilder {
+        self.config.packet_header = header;
+        self
+    }
+
+    /// Sets [`Config::packet_overhead`](struct.Config.html#structfield.packet_overhead).
+    pub fn packet_overhead(mut self, overhead: usize) -> ConfigBuilder {
+        self.config.packet_overhead = overhead;
+        self
+    }
+
+    /// Sets [`Config::max_entities_per_packet`](struct.Config.html#structfield.max_entities_per_packet).
+    pub fn max_entities_per_packet(mut self, max: Option<usize>) -> ConfigBuilder {
+        self.config.max_entities_per_packet = max;
+        self
+    }
+
+    /// Sets [`Config::acknowledge_updates`](struct.Config.html#structfield.acknowledge_updates).
+    pub fn acknowledge_updates(mut self, acknowledge: bool) -> ConfigBuilder {
+        self.config.acknowledge_updates = acknowledge;
+        self
+    }
+
+    /// Sets [`Config::send_update_ticks`](struct.Config.html#structfield.send_update_ticks).
+    pub fn send_update_ticks(mut self, send: bool) -> ConfigBuilder {
+        self.config.send_update_ticks = send;
+        self
+    }
+
+    /// Sets [`Config::compression`](struct.Config.html#structfield.compression).
+    pub fn compression(mut self, compressor: Option<Compressor>) -> ConfigBuilder {
+        self.config.compression = compressor;
+        self
+    }
+
+    /// Sets [`Config::packet_checksum`](struct.Config.html#structfield.packet_checksum).
+    pub fn packet_checksum(mut self, checksum: bool) -> ConfigBuilder {
+        self.config.packet_checksum = checksum;
+        self
+    }
+
+    /// Sets [`Config::length_codec`](struct.Config.html#structfield.length_codec).
+    pub fn length_codec<C: LengthCodec + 'static>(mut self, codec: C) -> ConfigBuilder {
+        self.config.length_codec = Box::new(codec);
+        self
+    }
+
+    /// Sets [`Config::require_handshake`](struct.Config.html#structfield.require_handshake).
+    pub fn require_handshake(mut self, require: bool) -> ConfigBuilder {
+        self.config.require_handshake = require;
+        self
+    }
+
+    /// Sets [`Config::announce_version`](struct.Config.html#structfield.announce_version).
+    pub fn announce_version(mut self, announce: bool) -> ConfigBuilder {
+        self.config.announce_version = announce;
+        self
+    }
+
+    /// Sets [`Config::max_baselines_per_connection`](struct.Config.html#structfield.max_baselines_per_connection).
+    pub fn max_baselines_per_connection(mut self, max: Option<usize>) -> ConfigBuilder {
+        self.config.max_baselines_per_connection = max;
+        self
+    }
+
+    /// Validates that packets of `max` bytes, as passed to
+    /// [`Server::connection_send`](struct.Server.html#method.connection_send)
+    /// and [`Client::send`](struct.Client.html#method.send), can hold the
+    /// configured packet framing.
+    ///
+    /// The packet size itself is not part of the configuration.
+    pub fn max_bytes_per_packet(mut self, max: usize) -> ConfigBuilder {
+        self.max_bytes_per_packet = Some(max);
+        self
+    }
+
+    /// Validates and returns the configuration.
+    pub fn build(self) -> Result<Config, ConfigError> {
+        if self.config.minimum_update_interval == Some(0) {
+            Err(ConfigError::ZeroUpdateInterval)
+
+        } else if self.config.max_entities_per_packet == Some(0) {
+            Err(ConfigError::ZeroEntitiesPerPacket)
+
+        } else if self.config.create_resend_interval == Some(0) {
+            Err(ConfigError::ZeroCreateResendInterval)
+
+        } else if self.config.maximum_update_rate == Some(0) {
+            Err(ConfigError::ZeroUpdateRate)
+
+        } else if let Some(max) = self.max_bytes_per_packet.filter(|max| *max < min_bytes_per_packet(&self.config)) {
+            Err(ConfigError::PacketSizeTooSmall(max))
+
+        } else {
+            Ok(self.config)
+        }
+    }
+
+}
+
+/// Returned from [`Entity::merge_bytes`](trait.Entity.html#method.merge_bytes)
+/// to reject malformed update bytes.
+#[derive(Debug, Eq, PartialEq, Hash, Copy, Clone)]
+pub struct MergeError;
+
+/// Returned from
+/// [`EntityRegistry::entity_from_kind_and_bytes`](trait.EntityRegistry.html#method.entity_from_kind_and_bytes)
+/// to report why an entity could not be created.
+#[derive(Debug, Eq, PartialEq, Hash, Copy, Clone)]
+pub enum RegistryError {
+
+    /// The registry has no entity type for the kind.
+    UnknownKind(u16),
+
+    /// The creation bytes for an entity of the kind could not be
+    /// de-serialized.
+    InvalidBytes(u16)
+
+}
+
+/// Update bandwidth accumulated for all entities of a single
+/// [`Entity::kind`](trait.Entity.html#method.kind), as returned from
+/// [`Server::kind_bandwidth`](struct.Server.html#method.kind_bandwidth) and
+/// [`Client::kind_bandwidth`](struct.Client.html#method.kind_bandwidth).
+#[derive(Debug, Default, Eq, PartialEq, Clone)]
+pub struct KindBandwidth {
+
+    /// Number of non-empty updates which were serialized via
+    /// [`Entity::part_bytes`](trait.Entity.html#method.part_bytes) and send.
+    pub part_calls: usize,
+
+    /// Total number of bytes of the send updates, excluding their framing.
+    pub part_bytes: usize,
+
+    /// Number of updates which were merged via
+    /// [`Entity::merge_bytes`](trait.Entity.html#method.merge_bytes).
+    pub merge_calls: usize,
+
+    /// Total number of bytes of the merged updates.
+    pub merge_bytes: usize
+
+}
+
+/// Strategies for allocating entity slot indices on a
+/// [`Server`](struct.Server.html).
+#[derive(Debug, Eq, PartialEq, Hash, Copy, Clone)]
+pub enum SlotStrategy {
+
+    /// Always picks the free slot with the lowest index.
+    Lowest,
+
+    /// Picks a random free slot, making slot indices hard to predict.
+    ///
+    /// The contained value seeds the pseudo random number generator, the same
+    /// seed will always produce the same sequence of allocations.
+    Random(u64),
+
+    /// Picks the free slot which has been released the longest time ago,
+    /// maximizing the time until a slot index gets re-used.
+    Lru
+
+}
+
+
+/// The relevance of a server side entity for a specific connection as
+/// returned by [`Entity::relevance`](trait.Entity.html#method.relevance).
+#[derive(Debug, Eq, PartialEq, Hash, Copy, Clone)]
+pub enum Relevance {
+
+    /// The entity is send to the connection and updated on every call to
+    /// [`Server::connection_send`](struct.Server.html#method.connection_send).
+    Full,
+
+    /// The entity is send to the connection but its updates are only
+    /// serialized on every n-th call to
+    /// [`Server::connection_send`](struct.Server.html#method.connection_send).
+    ///
+    /// Creation and destruction of the entity are not affected.
+    LowFrequency(usize),
+
+    /// The entity is not send to the connection and the client is told to
+    /// forget about it.
+    Forgotten
+
+}
+
+
+/// Why the handle of a destroyed server side entity was dropped as passed to
+/// [`Entity::dropped`](trait.Entity.html#method.dropped).
+#[derive(Debug, Eq, PartialEq, Hash, Copy, Clone)]
+pub enum DropReason {
+
+    /// All connections confirmed the destruction of the entity or none of
+    /// them knew about it.
+    Confirmed,
+
+    /// At least one connection did not confirm the destruction of the entity
+    /// within its handle timeout.
+    TimedOut
+
+}
+
+
+/// How a client stops observing an entity which is no longer relevant for its
+/// connection as returned by
+/// [`Entity::forget_mode`](trait.Entity.html#method.forget_mode).
+#[derive(Debug, Eq, PartialEq, Hash, Copy, Clone)]
+pub enum ForgetMode {
+
+    /// The client drops the entity and it is created from scratch once it
+    /// becomes relevant again.
+    Destroy,
+
+    /// The client keeps the entity but no longer receives updates for it
+    /// until it becomes relevant again.
+    Hide
+
+}
+
+
+/// An application defined header which is prepended to each entity packet,
+/// e.g. to multiplex the entity stream with other data via a channel id.
+pub struct PacketHeader {
+    length: usize,
+    callback: Box<dyn Fn(&mut [u8]) + Send + Sync>
+}
+
+impl PacketHeader {
+
+    /// Creates a new packet header of a fixed `length` whose bytes are
+    /// produced by calling the provided closure once per packet.
+    ///
+    /// The closure is passed a zeroed slice of exactly `length` bytes to
+    /// write the header into.
+    pub fn new<F: Fn(&mut [u8]) + Send + Sync + 'static>(
+        length: usize,
+        callback: F
+
+    ) -> PacketHeader {
+        PacketHeader {
+            length: length,
+            callback: Box::new(callback)
+        }
+    }
+
+    /// Returns the length of the header in bytes.
+    pub fn length(&self) -> usize {
+        self.length
+    }
+
+    fn to_bytes(&self) -> Vec<u8> {
+        let mut bytes = vec![0; self.length];
+        (self.callback)(&mut bytes);
+        bytes
+    }
+
+}
+
+impl fmt::Debug for PacketHeader {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "PacketHeader ({} byte(s))", self.length)
+    }
+}
+
+
+type CompressCallback = Box<dyn Fn(&[u8]) -> Vec<u8> + Send + Sync>;
+type DecompressCallback = Box<dyn Fn(&[u8]) -> Option<Vec<u8>> + Send + Sync>;
+
+/// An application defined pair of closures which compress and decompress
+/// whole packets, e.g. via deflate.
+pub struct Compressor {
+    compress: CompressCallback,
+    decompress: DecompressCallback
+}
+
+impl Compressor {
+
+    /// Creates a new compressor from a `compress` closure and its inverse
+    /// `decompress` closure.
+    ///
+    /// `decompress` should return `None` for bytes which cannot be
+    /// decompressed, the packet is then rejected as invalid.
+    pub fn new<C, D>(compress: C, decompress: D) -> Compressor
+        where C: Fn(&[u8]) -> Vec<u8> + Send + Sync + 'static,
+              D: Fn(&[u8]) -> Option<Vec<u8>> + Send + Sync + 'static {
+
+        Compressor {
+            compress: Box::new(compress),
+            decompress: Box::new(decompress)
+        }
+
+    }
+
+}
+
+impl fmt::Debug for Compressor {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "Compressor")
+    }
+}
+
+
+// Packet Compression ---------------------------------------------------------
+const COMPRESSION_MARKER: u8 = NETWORK_BYTE_OFFSET;
+
+fn compress_packet(compressor: &Compressor, packet: Vec<u8>) -> Vec<u8> {
+    let compressed = (compressor.compress)(&packet);
+    if compressed.len() + 1 < packet.len() {
+        let mut bytes = Vec::with_capacity(compressed.len() + 1);
+        bytes.push(COMPRESSION_MARKER);
+        bytes.extend_from_slice(&compressed);
+        bytes
+
+    } else {
+        packet
+    }
+}
+
+
+// Packet Checksums -----------------------------------------------------------
+// Client handshakes are never checksummed and are told apart by their magic
+// bytes, the server's version announcement is send as a regular packet and is
+// therefore checksummed (and compressed) like any other
+const CHECKSUM_MARKER: u8 = 6;
+const CHECKSUM_OVERHEAD: usize = 3;
+
+fn fletcher16(bytes: &[u8]) -> [u8; 2] {
+    let (mut a, mut b) = (0u16, 0u16);
+    for byte in bytes {
+        a = (a + *byte as u16) % 255;
+        b = (b + a) % 255;
+    }
+    [b as u8, a as u8]
+}
+
+fn checksum_packet(mut packet: Vec<u8>) -> Vec<u8> {
+    let checksum = fletcher16(&packet);
+    packet.insert(0, CHECKSUM_MARKER);
+    packet.extend_from_slice(&checksum);
+    packet
+}
+
+
+// Protocol Handshake ---------------------------------------------------------
+const HANDSHAKE_MAGIC: [u8; 3] = [6, 0x43, 0x45];
+
+pub fn handshake_packet(config: &Config) -> Vec<u8> {
+    let mut bytes = match config.packet_header {
+        Some(ref header) => header.to_bytes(),
+        None => Vec::new()
+    };
+    bytes.append(&mut version_bytes());
+    bytes
+}
+
+pub fn version_bytes() -> Vec<u8> {
+    let mut bytes = HANDSHAKE_MAGIC.to_vec();
+    bytes.push(PROTOCOL_VERSION);
+    bytes
+}
+
+pub fn update_tick_bytes(tick: usize) -> [u8; 4] {
+    let tick = tick as u32;
+    [(tick >> 24) as u8, (tick >> 16) as u8, (tick >> 8) as u8, tick as u8]
+}
+
+pub fn update_tick(bytes: &[u8]) -> Option<u32> {
+    if bytes.len() >= 4 {
+        Some(
+            (bytes[0] as u32) << 24 | (bytes[1] as u32) << 16
+            | (bytes[2] as u32) << 8 | bytes[3] as u32
+        )
+
+    } else {
+        None
+    }
+}
+
+pub fn handshake_version(bytes: &[u8]) -> Option<u8> {
+    if bytes.len() == HANDSHAKE_MAGIC.len() + 1 && bytes[..HANDSHAKE_MAGIC.len()] == HANDSHAKE_MAGIC {
+        Some(bytes[HANDSHAKE_MAGIC.len()])
+
+    } else {
+        None
+    }
+}
+
+pub fn verify_handshake(bytes: &[u8]) -> bool {
+    handshake_version(bytes) == Some(PROTOCOL_VERSION)
+}
+
+
+// Packet Fragments -----------------------------------------------------------
+const FRAGMENT_MARKER: u8 = 7;
+const FRAGMENT_OVERHEAD: usize = 3;
+
+// State, slot, sequence, tick, length prefix and kind around the entity bytes
+const FRAGMENT_FRAMING_BYTES: usize = 16;
+
+#[derive(Debug, Default)]
+pub struct FragmentBuffer {
+    index: u8,
+    count: u8,
+    bytes: Vec<u8>
+}
+
+impl FragmentBuffer {
+
+    pub fn is_fragment(bytes: &[u8]) -> bool {
+        !bytes.is_empty() && bytes[0] == FRAGMENT_MARKER
+    }
+
+    pub fn receive(&mut self, config: &Config, bytes: &[u8]) -> Option<Vec<u8>> {
+
+        if bytes.len() < FRAGMENT_OVERHEAD {
+            return None;
+        }
+
+        let (index, count) = (bytes[1], bytes[2]);
+
+        // Reject fragments which cannot be part of any chunk
+        if count == 0 || index >= count {
+            self.reset();
+            return None;
+        }
+
+        // Start over with the first fragment of a new chunk
+        if index == 0 {
+            self.reset();
+            self.count = count;
+        }
+
+        // Drop the whole chunk in case any of its fragments went missing or
+        // it grows larger than any entity state could be
+        let max_bytes = config.length_codec.max_length() + FRAGMENT_FRAMING_BYTES;
+        if index != self.index || count != self.count || self.bytes.len() + bytes.len() - FRAGMENT_OVERHEAD > max_bytes {
+            self.reset();
+            None
+
+        } else {
+            self.bytes.extend_from_slice(&bytes[FRAGMENT_OVERHEAD..]);
+            self.index += 1;
+            if self.index == self.count {
+                self.index = 0;
+                self.count = 0;
+                Some(self.bytes.drain(0..).collect())
+
+            } else {
+                None
+            }
+        }
+
+    }
+
+    pub fn reset(&mut self) {
+        self.index = 0;
+        self.count = 0;
+        self.bytes.clear();
+    }
+
+}
+
+
+// Smallest packet size which can still carry a single fragment byte
+fn min_bytes_per_packet(config: &Config) -> usize {
+    config.packet_overhead
+        + config.packet_header.as_ref().map_or(0, |header| header.length)
+        + if config.packet_checksum { CHECKSUM_OVERHEAD } else { 0 }
+        + FRAGMENT_OVERHEAD + 1
+}
+
 
 // Chunked Packet List --------------------------------------------------------
-pub struct PacketList {
+/// A reusable buffer for the packets returned from
+/// [`Server::connection_send_into`](struct.Server.html#method.connection_send_into).
+///
+/// The buffer keeps the allocations of its packets around between calls so
+/// that sending to a connection every tick does not need to allocate new
+/// vectors once the buffer has grown to its working size.
+#[derive(Debug, Default)]
+pub struct PacketBuffer {
+    packets: Vec<Vec<u8>>,
+    spare: Vec<Vec<u8>>
+}
+
+impl PacketBuffer {
+
+    /// Creates a new, empty packet buffer.
+    pub fn new() -> PacketBuffer {
+        PacketBuffer::default()
+    }
+
+    /// Returns the packets which were written into the buffer by the last
+    /// send.
+    pub fn packets(&self) -> &[Vec<u8>] {
+        &self.packets
+    }
+
+    /// Consumes the buffer and returns its packets.
+    pub fn into_packets(self) -> Vec<Vec<u8>> {
+        self.packets
+    }
+
+    /// Removes all packets from the buffer while keeping their allocations
+    /// around for re-use.
+    pub fn clear(&mut self) {
+        for mut packet in self.packets.drain(0..) {
+            packet.clear();
+            self.spare.push(packet);
+        }
+    }
+
+}
+
+pub struct PacketList<'a> {
     max_bytes_per_packet: usize,
+    max_entities_per_packet: Option<usize>,
+    header: Option<&'a PacketHeader>,
+    compressor: Option<&'a Compressor>,
+    checksum: bool,
     packet_bytes: Vec<u8>,
-    packets: Vec<Vec<u8>>
+    packet_entities: usize,
+    oversized: bool,
+    packets: Vec<Vec<u8>>,
+    spare: Vec<Vec<u8>>
 }
 
-impl PacketList {
+impl<'a> PacketList<'a> {
+
+    pub fn with_overhead(
+        max_bytes_per_packet: usize,
+        reserved_overhead: usize,
+        max_entities_per_packet: Option<usize>,
+        header: Option<&'a PacketHeader>,
+        compressor: Option<&'a Compressor>,
+        checksum: bool
+
+    ) -> PacketList<'a> {
+        PacketList::with_buffer(
+            max_bytes_per_packet,
+            reserved_overhead,
+            max_entities_per_packet,
+            header,
+            compressor,
+            checksum,
+            &mut PacketBuffer::default()
+        )
+    }
+
+    pub fn with_buffer(
+        max_bytes_per_packet: usize,
+        reserved_overhead: usize,
+        max_entities_per_packet: Option<usize>,
+        header: Option<&'a PacketHeader>,
+        compressor: Option<&'a Compressor>,
+        checksum: bool,
+        buffer: &mut PacketBuffer
+
+    ) -> PacketList<'a> {
+
+        // Transport overhead, headers and checksums count against the packet size
+        let max_bytes_per_packet = max_bytes_per_packet.saturating_sub(
+            reserved_overhead
+            + header.map_or(0, |header| header.length)
+            + if checksum { CHECKSUM_OVERHEAD } else { 0 }
+        );
+
+        // Take over the allocations of previously send packets
+        buffer.clear();
+
+        let mut spare = mem::take(&mut buffer.spare);
+        let packet_bytes = spare.pop().unwrap_or_else(|| {
+            Vec::with_capacity(max_bytes_per_packet)
+        });
 
-    pub fn new(max_bytes_per_packet: usize) -> PacketList {
         PacketList {
             max_bytes_per_packet: max_bytes_per_packet,
-            packet_bytes: Vec::with_capacity(max_bytes_per_packet),
-            packets: Vec::new()
+            max_entities_per_packet: max_entities_per_packet,
+            header: header,
+            compressor: compressor,
+            checksum: checksum,
+            packet_bytes: packet_bytes,
+            packet_entities: 0,
+            oversized: false,
+            packets: mem::take(&mut buffer.packets),
+            spare: spare
         }
+
     }
 
     pub fn append_bytes(&mut self, mut bytes: Vec<u8>) {
 
+        // Empty bytes do not count as an entity
+        if bytes.is_empty() {
+            return;
+        }
+
+        let is_full = match self.max_entities_per_packet {
+            Some(max) => self.packet_entities >= max,
+            None => false
+        };
+
         // Append the bytes to the current packet if they won't overflow...
-        if self.packet_bytes.len() + bytes.len() <= self.max_bytes_per_packet {
+        if !is_full && self.packet_bytes.len() + bytes.len() <= self.max_bytes_per_packet {
             self.packet_bytes.append(&mut bytes);
+            self.packet_entities += 1;
+
+        // ...fragment them in case they would not fit into a packet of their own...
+        } else if bytes.len() > self.max_bytes_per_packet {
+
+            // Bytes which cannot be split into at most 255 fragments are
+            // dropped and reported via is_oversized()
+            if self.max_bytes_per_packet <= FRAGMENT_OVERHEAD {
+                self.oversized = true;
+                return;
+            }
+
+            let fragment_size = self.max_bytes_per_packet - FRAGMENT_OVERHEAD;
+            let count = bytes.chunks(fragment_size).len();
+            if count > 255 {
+                self.oversized = true;
+                return;
+            }
+
+            self.push_packet_bytes();
+
+            for (index, chunk) in bytes.chunks(fragment_size).enumerate() {
+                let mut fragment = self.spare.pop().unwrap_or_default();
+                fragment.extend_from_slice(&[FRAGMENT_MARKER, index as u8, count as u8]);
+                fragment.extend_from_slice(chunk);
+                self.packets.push(fragment);
+            }
 
         // ...otherwise use them to start the next packet
         } else {
 
             // Push the next packet with the previous packet bytes
-            if !self.packet_bytes.is_empty() {
-                self.packets.push(self.packet_bytes.drain(0..).collect());
-            }
+            self.push_packet_bytes();
 
             // Start a new packet containing the overflowing entity bytes
             self.packet_bytes.append(&mut bytes);
+            self.packet_entities = 1;
 
         }
 
     }
 
-    pub fn into_vec(mut self) -> Vec<Vec<u8>> {
+    pub fn append_empty(&mut self) {
+        let packet = self.spare.pop().unwrap_or_default();
+        self.append_packet(packet);
+    }
+
+    pub fn append_packet(&mut self, packet: Vec<u8>) {
+        self.push_packet_bytes();
+        self.packets.push(packet);
+    }
 
+    pub fn is_empty(&self) -> bool {
+        self.packets.is_empty() && self.packet_bytes.is_empty()
+    }
+
+    pub fn set_oversized(&mut self) {
+        self.oversized = true;
+    }
+
+    pub fn is_oversized(&self) -> bool {
+        self.oversized
+    }
+
+    pub fn into_vec(self) -> Vec<Vec<u8>> {
+        let mut buffer = PacketBuffer::default();
+        self.into_buffer(&mut buffer);
+        buffer.packets
+    }
+
+    pub fn into_buffer(mut self, buffer: &mut PacketBuffer) {
+
+        self.push_packet_bytes();
+
+        if let Some(compressor) = self.compressor {
+            self.packets = self.packets.into_iter().map(|packet| {
+                compress_packet(compressor, packet)
+
+            }).collect();
+        }
+
+        // Empty packets are send as is
+        if self.checksum {
+            self.packets = self.packets.into_iter().map(|packet| {
+                if packet.is_empty() {
+                    packet
+
+                } else {
+                    checksum_packet(packet)
+                }
+
+            }).collect();
+        }
+
+        if let Some(header) = self.header {
+            for packet in &mut self.packets {
+                packet.splice(0..0, header.to_bytes());
+            }
+        }
+
+        self.spare.push(self.packet_bytes);
+        buffer.packets = self.packets;
+        buffer.spare = self.spare;
+
+    }
+
+    fn push_packet_bytes(&mut self) {
+        self.packet_entities = 0;
         if !self.packet_bytes.is_empty() {
-            self.packets.push(self.packet_bytes);
+            let next = self.spare.pop().unwrap_or_else(|| {
+                Vec::with_capacity(self.max_bytes_per_packet)
+            });
+            self.packets.push(mem::replace(&mut self.packet_bytes, next));
         }
+    }
+
+}
 
-        self.packets
 
+// Stream Framing -------------------------------------------------------------
+pub const STREAM_PACKET_BYTES: usize = 65535;
+
+pub fn stream_bytes(packets: &[Vec<u8>]) -> Vec<u8> {
+    let mut bytes = Vec::with_capacity(packets.iter().map(|p| p.len() + 4).sum());
+    for packet in packets {
+        Varint.encode(packet.len(), &mut bytes);
+        bytes.extend_from_slice(packet);
     }
+    bytes
+}
+
+// Length prefixes of valid stream packets never exceed three bytes
+const STREAM_PREFIX_BYTES: usize = 3;
 
+pub fn next_stream_packet(bytes: &[u8]) -> Result<Option<(&[u8], usize)>, ()> {
+    match Varint.decode(&bytes[..cmp::min(bytes.len(), STREAM_PREFIX_BYTES)]) {
+        Some((length, _)) if length > STREAM_PACKET_BYTES => Err(()),
+        Some((length, offset)) => if bytes.len() >= offset + length {
+            Ok(Some((&bytes[offset..offset + length], offset + length)))
+
+        } else {
+            Ok(None)
+        },
+        None => if bytes.len() >= STREAM_PREFIX_BYTES {
+            Err(())
+
+        } else {
+            Ok(None)
+        }
+    }
 }
 
 
 // Generic Helpers ------------------------------------------------------------
-pub fn deserialize_entity_bytes(bytes: &[u8], overhead: usize) -> Option<(&[u8], usize)> {
-    let bytes_length = bytes.len();
-    if bytes_length < overhead {
-        None
+pub type CustomPacketCallback<'a> = &'a mut dyn FnMut(&[u8]);
+
+pub fn strip_packet_header<'a>(config: &Config, bytes: Cow<'a, [u8]>) -> Result<Cow<'a, [u8]>, Cow<'a, [u8]>> {
+    if let Some(ref header) = config.packet_header {
+        if bytes.is_empty() {
+            Ok(bytes)
+
+        } else if bytes.len() < header.length {
+            Err(bytes)
+
+        } else {
+            Ok(match bytes {
+                Cow::Borrowed(bytes) => Cow::Borrowed(&bytes[header.length..]),
+                Cow::Owned(mut bytes) => Cow::Owned(bytes.split_off(header.length))
+            })
+        }
+
+    } else {
+        Ok(bytes)
+    }
+}
+
+pub fn decompress_packet<'a>(config: &Config, bytes: Cow<'a, [u8]>) -> Result<Cow<'a, [u8]>, Cow<'a, [u8]>> {
+    match config.compression {
+        Some(ref compressor) if !bytes.is_empty() && bytes[0] == COMPRESSION_MARKER => {
+            match (compressor.decompress)(&bytes[1..]) {
+                Some(bytes) => Ok(Cow::Owned(bytes)),
+                None => Err(bytes)
+            }
+        },
+        _ => Ok(bytes)
+    }
+}
+
+pub fn verify_packet_checksum<'a>(config: &Config, bytes: Cow<'a, [u8]>) -> Result<Cow<'a, [u8]>, Cow<'a, [u8]>> {
+    let len = bytes.len();
+    // Custom protocol packets are passed through unchecked
+    if !config.packet_checksum || len == 0 || bytes[0] > NETWORK_BYTE_OFFSET {
+        Ok(bytes)
+
+    } else if len < CHECKSUM_OVERHEAD || bytes[0] != CHECKSUM_MARKER || fletcher16(&bytes[1..len - 2]) != bytes[len - 2..] {
+        Err(bytes)
+
+    } else {
+        Ok(match bytes {
+            Cow::Borrowed(bytes) => Cow::Borrowed(&bytes[1..len - 2]),
+            Cow::Owned(mut bytes) => {
+                bytes.truncate(len - 2);
+                bytes.remove(0);
+                Cow::Owned(bytes)
+            }
+        })
+    }
+}
+
+pub fn serialize_entity_bytes(config: &Config, entity_bytes: &[u8], bytes: &mut Vec<u8>) -> Result<(), ()> {
+    let codec = &config.length_codec;
+    if entity_bytes.len() > codec.max_length() {
+        Err(())
+
+    } else {
+        codec.encode(entity_bytes.len(), bytes);
+        Ok(())
+    }
+}
+
+pub fn serialize_segments(segments: Vec<(u8, Vec<u8>)>) -> Result<Option<Vec<u8>>, ()> {
+    if segments.is_empty() {
+        Ok(None)
 
     } else {
-        let entity_length = bytes[0] as usize;
-        if bytes_length < entity_length + overhead {
+        let mut bytes = Vec::new();
+        for (id, segment_bytes) in segments {
+            if segment_bytes.len() > Varint.max_length() {
+                return Err(());
+            }
+            bytes.push(id);
+            Varint.encode(segment_bytes.len(), &mut bytes);
+            bytes.extend_from_slice(&segment_bytes);
+        }
+        Ok(Some(bytes))
+    }
+}
+
+pub fn deserialize_segments(bytes: &[u8]) -> Option<Vec<(u8, &[u8])>> {
+    let (mut segments, mut i) = (Vec::new(), 0);
+    while i < bytes.len() {
+        if let Some((length, offset)) = Varint.decode(&bytes[i + 1..]) {
+            let start = i + 1 + offset;
+            if bytes.len() < start + length {
+                return None;
+            }
+            segments.push((bytes[i], &bytes[start..start + length]));
+            i = start + length;
+
+        } else {
+            return None;
+        }
+    }
+    Some(segments)
+}
+
+pub fn record_part_bandwidth(
+    config: &Config,
+    bandwidth: &mut HashMap<u16, KindBandwidth>,
+    kind: u16,
+    update_bytes: &[u8]
+) {
+    if let Some((entity_bytes, _)) = deserialize_entity_bytes(config, update_bytes, 0) {
+        if !entity_bytes.is_empty() {
+            let kind_bandwidth = bandwidth.entry(kind).or_default();
+            kind_bandwidth.part_calls += 1;
+            kind_bandwidth.part_bytes += entity_bytes.len();
+        }
+    }
+}
+
+pub fn record_merge_bandwidth(bandwidth: &mut HashMap<u16, KindBandwidth>, kind: u16, entity_bytes: &[u8]) {
+    let kind_bandwidth = bandwidth.entry(kind).or_default();
+    kind_bandwidth.merge_calls += 1;
+    kind_bandwidth.merge_bytes += entity_bytes.len();
+}
+
+pub fn deserialize_entity_bytes<'a>(config: &Config, bytes: &'a [u8], overhead: usize) -> Option<(&'a [u8], usize)> {
+    if let Some((entity_length, offset)) = config.length_codec.decode(bytes) {
+        let end = offset + overhead + entity_length;
+        if bytes.len() < end {
             None
 
         } else {
-            Some((&bytes[1..entity_length + overhead], entity_length + overhead))
+            Some((&bytes[offset..end], end))
         }
+
+    } else {
+        None
     }
 }
 
@@ -12,6 +12,7 @@ use std::fmt;
 
 // Internal Dependencies ------------------------------------------------------
 use ::server::ConnectionToken;
+use ::shared::{DropReason, ForgetMode, MergeError, Relevance, deserialize_segments};
 
 
 /// A trait that describes a serializable entity which is synchronized across
@@ -25,7 +26,19 @@ pub trait Entity<U: fmt::Debug>: fmt::Debug {
     /// [`EntityRegistry::entity_from_kind_and_bytes`](trait.EntityRegistry.html#method.entity_from_kind_and_bytes)
     /// in order to choose the concrete type on which to invoke
     /// [`Entity::from_bytes`](trait.Entity.html#method.from_bytes).
-    fn kind(&self) -> u8;
+    ///
+    /// The kind is send as two bytes as part of each entity creation.
+    fn kind(&self) -> u16;
+
+    /// Returns a bit set of application defined categories the entity
+    /// belongs to, e.g. to group entities broader than their
+    /// [`Entity::kind`](trait.Entity.html#method.kind) for bulk operations via
+    /// [`Server::with_entities_in_category`](struct.Server.html#method.with_entities_in_category).
+    ///
+    /// By default this returns `0`.
+    fn category(&self) -> u32 {
+        0
+    }
 
     /// Serializes a potential sub-set of the entities state into a vector of bytes.
     ///
@@ -47,9 +60,144 @@ pub trait Entity<U: fmt::Debug>: fmt::Debug {
     /// The serialization is performed on a per-connection basis and may return
     /// different data for each connection.
     ///
+    /// Malformed bytes can be rejected by returning a `MergeError`, rejected
+    /// updates from a client are then reported by
+    /// [`Server::connection_receive`](struct.Server.html#method.connection_receive)
+    /// as `ServerError::RejectedEntityUpdate`. The client ignores rejected
+    /// updates from the server.
+    ///
     /// > Note: This method is only called when the vector produced by `part_bytes`
     /// > has a length greater than zero.
-    fn merge_bytes(&mut self, Option<&ConnectionToken<U>>, &[u8]);
+    ///
+    /// Updates serialized via [`part_segments`](#method.part_segments) are
+    /// passed to [`merge_segments`](#method.merge_segments) instead.
+    ///
+    /// By default this ignores the bytes and returns `Ok(())`.
+    fn merge_bytes(&mut self, Option<&ConnectionToken<U>>, &[u8]) -> Result<(), MergeError> {
+        Ok(())
+    }
+
+    /// Decodes the segments produced by
+    /// [`part_segments`](#method.part_segments) and passes each of them to
+    /// [`merge_segment`](#method.merge_segment).
+    ///
+    /// This is called for every received update which was serialized via
+    /// `part_segments` in place of [`merge_bytes`](#method.merge_bytes) and
+    /// [`merge_bytes_at`](#method.merge_bytes_at). Bytes which are not made up
+    /// of segments are rejected.
+    fn merge_segments(&mut self, connection_slot: Option<&ConnectionToken<U>>, bytes: &[u8]) -> Result<(), MergeError> {
+        match deserialize_segments(bytes) {
+            Some(segments) => {
+                for (id, segment_bytes) in segments {
+                    self.merge_segment(connection_slot, id, segment_bytes)?;
+                }
+                Ok(())
+            },
+            None => Err(MergeError)
+        }
+    }
+
+    /// Updates the entities state just like
+    /// [`merge_bytes`](#method.merge_bytes) but also receives the server
+    /// tick at which the bytes were serialized.
+    ///
+    /// This is only called on the client and only when
+    /// [`Config::send_update_ticks`](struct.Config.html#structfield.send_update_ticks)
+    /// is enabled, allowing predicted state to be reconciled against the
+    /// authoritative state of the server for that tick.
+    ///
+    /// By default the tick is ignored and the bytes are passed to
+    /// `merge_bytes`.
+    fn merge_bytes_at(&mut self, connection_slot: Option<&ConnectionToken<U>>, _: u32, bytes: &[u8]) -> Result<(), MergeError> {
+        self.merge_bytes(connection_slot, bytes)
+    }
+
+    /// Serializes independently updated parts of the entity's state as a
+    /// list of `(segment_id, bytes)` pairs, which is an alternative to packing
+    /// them into a single [`part_bytes`](#tymethod.part_bytes) vector.
+    ///
+    /// Each segment is send with its ID and a length prefix and is passed to
+    /// the remote's [`merge_segment`](#method.merge_segment) by
+    /// [`merge_segments`](#method.merge_segments), only changed segments
+    /// need to be returned.
+    ///
+    /// When the list is empty `part_bytes` is used instead, so entities which
+    /// only make use of segments should return `None` from it.
+    ///
+    /// By default this returns an empty list.
+    fn part_segments(&mut self, Option<&ConnectionToken<U>>) -> Vec<(u8, Vec<u8>)> {
+        Vec::new()
+    }
+
+    /// Updates the entity's state from a single segment which was produced
+    /// by the remote entity's [`part_segments`](#method.part_segments).
+    ///
+    /// Returning a `MergeError` rejects the whole update just like
+    /// [`merge_bytes`](#method.merge_bytes) does.
+    ///
+    /// By default this ignores the segment and returns `Ok(())`.
+    fn merge_segment(&mut self, Option<&ConnectionToken<U>>, u8, &[u8]) -> Result<(), MergeError> {
+        Ok(())
+    }
+
+    /// Serializes the entity like [`part_bytes`](#tymethod.part_bytes) but also
+    /// receives the bytes of the last update which the connection has
+    /// acknowledged, allowing implementors to only send what has changed
+    /// since then.
+    ///
+    /// Baselines are only tracked on the server when
+    /// [`Config::acknowledge_updates`](struct.Config.html#structfield.acknowledge_updates)
+    /// is enabled, otherwise `None` is passed.
+    ///
+    /// > Note: The client has no knowledge of which baseline an update was
+    /// > encoded against, so the update bytes need to carry enough information
+    /// > for `merge_bytes` to decode them.
+    ///
+    /// By default this ignores the baseline and calls `part_bytes`.
+    fn part_bytes_delta(&mut self, connection_slot: Option<&ConnectionToken<U>>, _: Option<&[u8]>) -> Option<Vec<u8>> {
+        self.part_bytes(connection_slot)
+    }
+
+    /// Determines whether a server side entity currently has any changes
+    /// worth sending to its clients.
+    ///
+    /// When this returns `false` the server does not call
+    /// [`part_bytes`](#tymethod.part_bytes) for the entity, empty updates
+    /// configured via
+    /// [`Config::minimum_update_interval`](struct.Config.html#structfield.minimum_update_interval)
+    /// are still sent.
+    ///
+    /// By default this returns `true`.
+    fn should_send_update(&self) -> bool {
+        true
+    }
+
+    /// Determines whether a server side entity can skip its updates while it
+    /// is not observed by any connection.
+    ///
+    /// Sleeping entities are not passed to the closure of
+    /// [`Server::update_entities_with`](struct.Server.html#method.update_entities_with)
+    /// but are kept alive and resume their updates once a connection
+    /// observes them again.
+    ///
+    /// By default this returns `false`.
+    fn can_sleep(&self) -> bool {
+        false
+    }
+
+    /// Determines the order in which a client side entity's updates are
+    /// serialized by
+    /// [`Client::send`](struct.Client.html#method.send), entities with a
+    /// higher priority are serialized first.
+    ///
+    /// In combination with
+    /// [`Client::send_with_budget`](struct.Client.html#method.send_with_budget)
+    /// the updates of low priority entities are deferred first.
+    ///
+    /// By default this returns `0`.
+    fn priority(&self) -> u8 {
+        0
+    }
 
     /// Called exactly once after the entity has been constructed.
     ///
@@ -57,6 +205,36 @@ pub trait Entity<U: fmt::Debug>: fmt::Debug {
     /// in a `::new()` method.
     fn created(&mut self) {}
 
+    /// Called on a server side entity the first time the client of any
+    /// connection has confirmed its creation, right before the corresponding
+    /// [`confirmed_by`](#method.confirmed_by) call.
+    ///
+    /// Unlike [`created`](#method.created) this is only invoked once the
+    /// entity is actually observed by a client.
+    fn observed(&mut self, &ConnectionToken<U>) {}
+
+    /// Called on a server side entity once the client of the connection has
+    /// confirmed its creation and the entity started receiving updates.
+    ///
+    /// This is invoked at most once per connection, unless the entity gets
+    /// forgotten and later re-created on that connection.
+    fn confirmed_by(&mut self, &ConnectionToken<U>) {}
+
+    /// Called on a server side entity whenever its owning connection changes
+    /// via
+    /// [`Server::entity_set_owner`](struct.Server.html#method.entity_set_owner),
+    /// receiving the index of the new owner's `ConnectionToken`.
+    ///
+    /// The owner is reset to `None` once its connection gets removed from
+    /// the server.
+    ///
+    /// This is the only way the entity learns about its owner, in order for
+    /// [`Entity::to_bytes`](trait.Entity.html#method.to_bytes) and
+    /// [`Entity::part_bytes`](trait.Entity.html#method.part_bytes) to mark
+    /// the entity as locally controlled for its owner, implementations store
+    /// the passed index and compare it against `ConnectionToken::index`.
+    fn owner_changed(&mut self, Option<usize>) {}
+
     /// Determines whether a entity should be at all serialized for a specific
     /// connection.
     ///
@@ -65,6 +243,34 @@ pub trait Entity<U: fmt::Debug>: fmt::Debug {
         true
     }
 
+    /// Determines how relevant the entity is for a specific connection,
+    /// allowing for graded interest management instead of the binary
+    /// [`Entity::filter`](trait.Entity.html#method.filter).
+    ///
+    /// By default this returns `Relevance::Full` when `filter` returns `true`
+    /// and `Relevance::Forgotten` otherwise.
+    fn relevance(&self, connection_slot: &ConnectionToken<U>) -> Relevance {
+        if self.filter(connection_slot) {
+            Relevance::Full
+
+        } else {
+            Relevance::Forgotten
+        }
+    }
+
+    /// Determines how the client of a connection stops observing the entity
+    /// once its [`Entity::relevance`](trait.Entity.html#method.relevance)
+    /// turns into `Relevance::Forgotten`.
+    ///
+    /// With `ForgetMode::Hide` the client entity stays around and resumes its
+    /// updates once the entity becomes relevant again, preserving any client
+    /// side state in between.
+    ///
+    /// By default this returns `ForgetMode::Destroy`.
+    fn forget_mode(&self) -> ForgetMode {
+        ForgetMode::Destroy
+    }
+
     /// Called exactly once when the entity is **cleanly** destroyed.
     ///
     /// This always happens for server entities. However, for client entities
@@ -73,8 +279,55 @@ pub trait Entity<U: fmt::Debug>: fmt::Debug {
     /// **Important:** If you need to clean up any external data which is not
     /// dropped along with the entity itself, this must be done by implementing
     /// a custom `Drop` trait on the entity struct.
+    ///
+    /// > Note: Server entities which still have to be confirmed as destroyed
+    /// > by any connection are kept around and only dropped once their
+    /// > handle is dropped.
     fn destroyed(&mut self) {}
 
+    /// Overrides [`Config::handle_timeout_ticks`](struct.Config.html#structfield.handle_timeout_ticks)
+    /// for the handle of this entity once it has been destroyed or forgotten.
+    ///
+    /// The value is read right before the entity is dropped from its handle.
+    ///
+    /// By default this returns `None` and the configured value is used.
+    fn handle_timeout_ticks(&self) -> Option<usize> {
+        None
+    }
+
+    /// Whether the entity is transient and does not require its destruction
+    /// to be confirmed by the clients, e.g. for purely cosmetic entities.
+    ///
+    /// The handle of a transient server entity is dropped as soon as the
+    /// entity is destroyed and each connection is send a single best-effort
+    /// destroy packet, which is not retried in case it gets lost.
+    ///
+    /// By default this returns `false`.
+    fn transient(&self) -> bool {
+        false
+    }
+
+    /// Called once per tick on a destroyed server entity while its handle is
+    /// waiting for all connections to confirm the destruction.
+    ///
+    /// Receives the number of ticks left until the handle times out, returning
+    /// `Some` replaces the remaining ticks with the returned value.
+    ///
+    /// By default this returns `None` and the timeout is left untouched.
+    fn extend_destroy_timeout(&self, usize) -> Option<usize> {
+        None
+    }
+
+    /// Called on a destroyed server entity right before its handle is
+    /// dropped by
+    /// [`Server::update_entities_with`](struct.Server.html#method.update_entities_with),
+    /// with the reason for dropping it.
+    ///
+    /// This is not called for transient entities or entities whose box was
+    /// handed back via
+    /// [`Server::entity_take`](struct.Server.html#method.entity_take).
+    fn dropped(&mut self, DropReason) {}
+
     /// Serializes the entity that implements the trait into a vector of bytes.
     ///
     /// The serialization is performed on a per-connection basis and may return
@@ -83,6 +336,21 @@ pub trait Entity<U: fmt::Debug>: fmt::Debug {
         vec![]
     }
 
+    /// Serializes a complete, authoritative snapshot of the entity for a
+    /// specific connection.
+    ///
+    /// The snapshot is used as the payload of the entity's creation on the
+    /// client, including re-creations after
+    /// [`Server::connection_resync`](struct.Server.html#method.connection_resync),
+    /// and is independent of the incremental
+    /// [`Entity::part_bytes`](trait.Entity.html#method.part_bytes).
+    ///
+    /// By default this returns the result of
+    /// [`Entity::to_bytes`](trait.Entity.html#method.to_bytes).
+    fn snapshot_bytes(&self, connection_slot: &ConnectionToken<U>) -> Vec<u8> {
+        self.to_bytes(connection_slot)
+    }
+
     /// Constructs a new entity by de-serializing it from the passed in slice.
     ///
     /// May return `None` in case the bytes cannot be de-serialized into the
@@ -91,5 +359,17 @@ pub trait Entity<U: fmt::Debug>: fmt::Debug {
         None
     }
 
+    /// Returns the number of creation bytes which were consumed when the
+    /// entity was constructed via
+    /// [`EntityRegistry::entity_from_kind_and_bytes`](trait.EntityRegistry.html#method.entity_from_kind_and_bytes).
+    ///
+    /// The client rejects the creation with `CreatePayloadMismatch` in case
+    /// the number differs from the length of the passed in creation bytes.
+    ///
+    /// By default this returns `None` which accepts any length.
+    fn validate_create_bytes(&self, &[u8]) -> Option<usize> {
+        None
+    }
+
 }
 
@@ -11,6 +11,7 @@ use std::fmt;
 
 
 // Internal Dependencies ------------------------------------------------------
+use ::shared::RegistryError;
 use ::traits::Entity;
 
 
@@ -23,7 +24,10 @@ pub trait EntityRegistry<E: Entity<U> + ?Sized, U: fmt::Debug>: fmt::Debug {
     /// The entity instance should be created by calling the
     /// [`Entity::from_bytes`](trait.Entity.html#method.from_bytes)
     /// implementation of the concrete entity type specified by `kind`.
-    fn entity_from_kind_and_bytes(&self, kind: u8, bytes: &[u8]) -> Option<Box<E>>;
+    ///
+    /// Rejected creations are ignored by the client and reported via
+    /// [`Client::receive_with_events`](struct.Client.html#method.receive_with_events).
+    fn entity_from_kind_and_bytes(&self, kind: u16, bytes: &[u8]) -> Result<Box<E>, RegistryError>;
 
 }
 
@@ -13,10 +13,16 @@ use std::fmt;
 // Internal Dependencies ------------------------------------------------------
 use ::shared::Config;
 use ::traits::Entity;
-use ::server::ConnectionToken;
+use ::server::{Baseline, ConnectionToken};
 
 
 // Entity Serializer ----------------------------------------------------------
+pub struct SerializeState<'a> {
+    pub update_tick: &'a mut usize,
+    pub dirty: bool,
+    pub baseline: Option<&'a mut Baseline>
+}
+
 pub trait EntitySerializer<E: Entity<U> + ?Sized, S, O, U: fmt::Debug> {
     fn as_bytes(
         &Config,
@@ -24,8 +30,7 @@ pub trait EntitySerializer<E: Entity<U> + ?Sized, S, O, U: fmt::Debug> {
         Option<&ConnectionToken<U>>,
         &S,
         Option<&mut Box<E>>,
-        &mut u8
+        SerializeState
 
-    ) -> Vec<u8>;
+    ) -> Result<Vec<u8>, ()>;
 }
-
@@ -0,0 +1,38 @@
+// Copyright (c) 2015-2017 Ivo Wetzel
+
+// Licensed under the Apache License, Version 2.0 <LICENSE-APACHE or
+// http://www.apache.org/licenses/LICENSE-2.0> or the MIT license
+// <LICENSE-MIT or http://opensource.org/licenses/MIT>, at your
+// option. This file may not be copied, modified, or distributed
+// except according to those terms.
+
+// STD Dependencies -----------------------------------------------------------
+use std::fmt;
+
+
+/// A trait that describes how the length prefix of serialized entity bytes
+/// is encoded on the wire.
+///
+/// Since this changes the wire format both the server and its clients must be
+/// configured with the same codec via
+/// [`Config::length_codec`](struct.Config.html#structfield.length_codec).
+pub trait LengthCodec: fmt::Debug + Send + Sync {
+
+    /// Returns the largest length which can be encoded by the codec.
+    ///
+    /// Entity bytes which exceed this length cannot be send and make
+    /// [`Server::connection_send`](struct.Server.html#method.connection_send)
+    /// and [`Client::send`](struct.Client.html#method.send) return a
+    /// `PacketSizeTooSmall` error.
+    fn max_length(&self) -> usize;
+
+    /// Appends the encoded `length` to the passed in vector.
+    fn encode(&self, length: usize, &mut Vec<u8>);
+
+    /// Decodes a length from the start of the passed in slice.
+    ///
+    /// Returns the decoded length and the number of bytes it occupied or
+    /// `None` in case the slice does not start with a complete length.
+    fn decode(&self, &[u8]) -> Option<(usize, usize)>;
+
+}
@@ -10,10 +10,12 @@
 mod entity;
 mod entity_registry;
 mod entity_serializer;
+mod length_codec;
 
 
 // Re-Exports -----------------------------------------------------------------
 pub use self::entity::Entity;
 pub use self::entity_registry::EntityRegistry;
-pub use self::entity_serializer::EntitySerializer;
+pub use self::entity_serializer::{EntitySerializer, SerializeState};
+pub use self::length_codec::LengthCodec;
 
@@ -1,15 +1,17 @@
 // STD Dependencies -----------------------------------------------------------
 use std::rc::Rc;
-use std::sync::Mutex;
+use std::sync::{Arc, Mutex};
+use std::thread;
 
 
 // Library Dependencies -------------------------------------------------------
 extern crate cobalt_entity;
 use cobalt_entity::{
-    Entity, EntityRegistry,
-    Client, ClientError,
-    Server, ServerError,
-    Config
+    Entity, EntityRegistry, LengthCodec,
+    Client, ClientEntityEvent, ClientError, ClientStats, LocalState,
+    Server, ServerError, ServerStats, ServerUpdateStats, RemoteState, ConnectionSendEvent,
+    Compressor, Config, ConfigError, DropReason, KindBandwidth, PacketBuffer, PacketHeader, RegistryError, Relevance, SlotStrategy,
+    SingleByte, TwoByte, Varint, ForgetMode, PROTOCOL_VERSION
 };
 
 
@@ -17,7 +19,7 @@ use cobalt_entity::{
 #[macro_use]
 mod mock;
 use mock::{
-    TestStat, TestUserData, TestEntity, TestEntityTwo,
+    TestStat, TestUserData, TestEntity, TestEntityTwo, TestSegmentEntity, TestSendEntity, TestRandom,
     ClientEntity, ServerEntity
 };
 
@@ -45,10 +47,36 @@ macro_rules! assert_stats_clone {
 fn config(ticks: usize) -> Config {
     Config {
         handle_timeout_ticks: ticks,
-        minimum_update_interval: None
+        ..Config::default()
     }
 }
 
+fn rle_compress(bytes: &[u8]) -> Vec<u8> {
+    let mut compressed = Vec::new();
+    for byte in bytes {
+        let len = compressed.len();
+        if len > 0 && compressed[len - 1] == *byte && compressed[len - 2] < 255 {
+            compressed[len - 2] += 1;
+
+        } else {
+            compressed.push(1);
+            compressed.push(*byte);
+        }
+    }
+    compressed
+}
+
+fn rle_decompress(bytes: &[u8]) -> Option<Vec<u8>> {
+    let mut decompressed = Vec::new();
+    for pair in bytes.chunks(2) {
+        if pair.len() != 2 {
+            return None;
+        }
+        decompressed.extend(vec![pair[1]; pair[0] as usize]);
+    }
+    Some(decompressed)
+}
+
 
 #[test]
 fn test_server_create() {
@@ -93,6 +121,278 @@ fn test_server_with_entities() {
 
 }
 
+#[test]
+fn test_server_with_entities_in_category() {
+
+    let mut server = Server::<ServerEntity, TestUserData>::new(config(3));
+    let stats_one: Rc<Mutex<TestStat>> = Rc::new(Mutex::new(TestStat::default()));
+    let stats_two: Rc<Mutex<TestStat>> = Rc::new(Mutex::new(TestStat::default()));
+    let stats_three: Rc<Mutex<TestStat>> = Rc::new(Mutex::new(TestStat::default()));
+    stats_one.lock().unwrap().category = 0b001;
+    stats_two.lock().unwrap().category = 0b011;
+    stats_three.lock().unwrap().category = 0b100;
+
+    let entity_one = server.entity_create_with(|| Box::new(TestEntity::new(true, stats_one.clone()))).unwrap();
+    let entity_two = server.entity_create_with(|| Box::new(TestEntity::new(true, stats_two.clone()))).unwrap();
+    let entity_three = server.entity_create_with(|| Box::new(TestEntity::new(true, stats_three.clone()))).unwrap();
+
+    let mut tokens = Vec::new();
+    server.with_entities_in_category(0b001, |token, _| tokens.push(token == &entity_one || token == &entity_two));
+    assert_eq!(tokens, vec![true, true]);
+
+    let mut count = 0;
+    server.with_entities_in_category(0b010, |token, _| { assert_eq!(token, &entity_two); count += 1; });
+    assert_eq!(count, 1);
+
+    let mut count = 0;
+    server.with_entities_in_category(0b110, |token, _| { assert!(token != &entity_one); count += 1; });
+    assert_eq!(count, 2);
+
+    let mut count = 0;
+    server.with_entities_in_category(0, |_, _| { count += 1; });
+    assert_eq!(count, 0);
+
+    // Destroyed entities are skipped
+    server.entity_destroy(entity_three).ok();
+
+    let mut count = 0;
+    server.with_entities_in_category(0b100, |_, _| { count += 1; });
+    assert_eq!(count, 0);
+
+}
+
+#[test]
+fn test_server_with_entities_of_kind() {
+
+    let mut server = Server::<ServerEntity, TestUserData>::new(config(3));
+    let stats_one: Rc<Mutex<TestStat>> = Rc::new(Mutex::new(TestStat::default()));
+    let stats_two: Rc<Mutex<TestStat>> = Rc::new(Mutex::new(TestStat::default()));
+    stats_two.lock().unwrap().kind = Some(2);
+
+    let entity_one = server.entity_create_with(|| Box::new(TestEntity::new(true, stats_one.clone()))).unwrap();
+    let entity_two = server.entity_create_with(|| Box::new(TestEntity::new(true, stats_two.clone()))).unwrap();
+    let entity_three = server.entity_create_with(|| Box::new(TestEntity::new(true, stats_one.clone()))).unwrap();
+
+    let mut tokens = Vec::new();
+    server.with_entities_of_kind(1, |token, _| tokens.push(token == &entity_one || token == &entity_three));
+    assert_eq!(tokens, vec![true, true]);
+
+    let mut count = 0;
+    server.with_entities_of_kind(2, |token, _| { assert_eq!(token, &entity_two); count += 1; });
+    assert_eq!(count, 1);
+
+    let mut count = 0;
+    server.with_entities_of_kind(3, |_, _| { count += 1; });
+    assert_eq!(count, 0);
+
+    // Destroyed entities are skipped
+    server.entity_destroy(entity_one).ok();
+
+    let mut count = 0;
+    server.with_entities_of_kind(1, |token, _| { assert_eq!(token, &entity_three); count += 1; });
+    assert_eq!(count, 1);
+
+}
+
+#[test]
+fn test_server_with_entities_for_connection() {
+
+    let mut server = Server::<ServerEntity, TestUserData>::new(config(3));
+    let stats_one: Rc<Mutex<TestStat>> = Rc::new(Mutex::new(TestStat::default()));
+    let stats_two: Rc<Mutex<TestStat>> = Rc::new(Mutex::new(TestStat::default()));
+    stats_two.lock().unwrap().filter_user_data = Some(1);
+
+    let connection_one = server.connection_add_with(||TestUserData::new(1)).unwrap();
+    let connection_two = server.connection_add_with(||TestUserData::new(2)).unwrap();
+    let entity_one = server.entity_create_with(|| Box::new(TestEntity::new(true, stats_one.clone()))).unwrap();
+    let entity_two = server.entity_create_with(|| Box::new(TestEntity::new(true, stats_two.clone()))).unwrap();
+
+    // Filtered entities are skipped for the first connection only
+    let mut tokens = Vec::new();
+    server.with_entities_for_connection(&connection_one, |token, _| tokens.push(token == &entity_one));
+    assert_eq!(tokens, vec![true]);
+
+    let mut tokens = Vec::new();
+    server.with_entities_for_connection(&connection_two, |token, _| tokens.push(token == &entity_one || token == &entity_two));
+    assert_eq!(tokens, vec![true, true]);
+
+    // Foreign connections see no entities
+    let mut server_two = Server::<ServerEntity, TestUserData>::new(config(3));
+    let connection_three = server_two.connection_add_with(||TestUserData::new(3)).unwrap();
+    let mut count = 0;
+    server.with_entities_for_connection(&connection_three, |_, _| { count += 1; });
+    assert_eq!(count, 0);
+
+}
+
+#[test]
+fn test_server_entity_create_at() {
+
+    let mut server = Server::<ServerEntity, TestUserData>::new(config(2));
+    let stats: Rc<Mutex<TestStat>> = Rc::new(Mutex::new(TestStat::default()));
+
+    // Explicit slots
+    let entity_one = server.entity_create_at(2, || Box::new(TestEntity::new(true, stats.clone()))).unwrap();
+    let entity_two = server.entity_create_at(0, || Box::new(TestEntity::new(true, stats.clone()))).unwrap();
+    assert!(server.entity_is_alive(&entity_one));
+    assert!(server.entity_is_alive(&entity_two));
+
+    // Occupied slots are rejected without invoking the closure
+    assert_eq!(server.entity_create_at(2, || panic!("Closure must not be called.")), Err(ServerError::EntitySlotInUse(2)));
+
+    // Automatic allocation skips explicitly taken slots
+    let _ = server.entity_create_with(|| Box::new(TestEntity::new(true, stats.clone()))).unwrap();
+    let _ = server.entity_create_with(|| Box::new(TestEntity::new(true, stats.clone()))).unwrap();
+    assert_eq!(server.entity_create_at(1, || Box::new(TestEntity::new(true, stats.clone()))), Err(ServerError::EntitySlotInUse(1)));
+    assert_eq!(server.entity_create_at(3, || Box::new(TestEntity::new(true, stats.clone()))), Err(ServerError::EntitySlotInUse(3)));
+
+    // Destroyed slots stay occupied until their handle is dropped
+    server.entity_destroy(entity_one).unwrap();
+    assert_eq!(server.entity_create_at(2, || Box::new(TestEntity::new(true, stats.clone()))), Err(ServerError::EntitySlotInUse(2)));
+    server.update_entities_with(|_, _| {});
+
+    let entity_three = server.entity_create_at(2, || Box::new(TestEntity::new(true, stats.clone()))).unwrap();
+    assert!(server.entity_is_alive(&entity_three));
+
+    let connection = server.connection_add_with(||TestUserData::new(255)).unwrap();
+    assert_server_send!(server, connection, vec![], vec![
+        0, 0, 3, 0, 1, 255, 128, 255,
+        0, 1, 3, 0, 1, 255, 128, 255,
+        0, 2, 3, 0, 1, 255, 128, 255,
+        0, 3, 3, 0, 1, 255, 128, 255
+
+    ]).expect("Server sents SendCreateToClient packets to Client.");
+
+}
+
+#[test]
+fn test_server_reserve_slots() {
+
+    let mut server = Server::<ServerEntity, TestUserData>::new(config(2));
+    let stats: Rc<Mutex<TestStat>> = Rc::new(Mutex::new(TestStat::default()));
+    server.reserve_slots(0..2);
+    server.reserve_slots(3..4);
+
+    // Automatic allocation skips reserved slots
+    let _ = server.entity_create_with(|| Box::new(TestEntity::new(true, stats.clone()))).unwrap();
+    let _ = server.entity_create_batch(2, |_| Box::new(TestEntity::new(true, stats.clone()))).unwrap();
+
+    // Reserved slots are filled explicitly
+    let _ = server.entity_create_at(1, || Box::new(TestEntity::new(true, stats.clone()))).unwrap();
+
+    let connection = server.connection_add_with(||TestUserData::new(255)).unwrap();
+    assert_server_send!(server, connection, vec![], vec![
+        0, 1, 3, 0, 1, 255, 128, 255,
+        0, 2, 3, 0, 1, 255, 128, 255,
+        0, 4, 3, 0, 1, 255, 128, 255,
+        0, 5, 3, 0, 1, 255, 128, 255
+
+    ]).expect("Server sents SendCreateToClient packets to Client.");
+
+    // Reserved slots are never handed out, even when all others are taken
+    let mut server = Server::<ServerEntity, TestUserData>::new(Config {
+        slot_strategy: SlotStrategy::Random(42),
+        ..Config::default()
+    });
+    server.reserve_slots(0..250);
+    server.reserve_slots(255..300);
+    let _ = server.entity_create_batch(5, |_| Box::new(TestEntity::new(true, stats.clone()))).unwrap();
+    assert_eq!(server.entity_create_with(|| Box::new(TestEntity::new(true, stats.clone()))), Err(ServerError::AllEntityTokensInUse));
+    assert!(server.entity_create_at(0, || Box::new(TestEntity::new(true, stats.clone()))).is_ok());
+    assert!(server.entity_create_at(255, || Box::new(TestEntity::new(true, stats.clone()))).is_ok());
+
+}
+
+#[test]
+fn test_server_entity_is_alive() {
+
+    let mut server = Server::<ServerEntity, TestUserData>::new(config(3));
+    let mut server_two = Server::<ServerEntity, TestUserData>::new(config(3));
+    let stats: Rc<Mutex<TestStat>> = Rc::new(Mutex::new(TestStat::default()));
+
+    let _ = server.connection_add_with(||TestUserData::new(0)).unwrap();
+    let entity = server.entity_create_with(|| Box::new(TestEntity::new(true, stats.clone()))).unwrap();
+    let foreign = server_two.entity_create_with(|| Box::new(TestEntity::new(true, stats.clone()))).unwrap();
+    assert!(server.entity_is_alive(&entity));
+    assert!(server_two.entity_is_alive(&foreign));
+
+    // Foreign tokens
+    assert!(!server.entity_is_alive(&foreign));
+    assert!(!server_two.entity_is_alive(&entity));
+
+    // Destroyed entities whose handle is still waiting for confirmation
+    server.entity_destroy_all();
+    assert!(!server.entity_is_alive(&entity));
+
+    // Dropped handles
+    for _ in 0..4 {
+        server.update_entities_with(|_, _| {});
+    }
+    assert_eq!(format!("{:?}", server), "EntityServer (1 connection(s), 0 entity(s))");
+    assert!(!server.entity_is_alive(&entity));
+
+}
+
+#[test]
+fn test_server_entity_observer() {
+
+    let mut server = Server::<ServerEntity, TestUserData>::new(config(0));
+    let stats: Rc<Mutex<TestStat>> = Rc::new(Mutex::new(TestStat::default()));
+
+    let entity = server.entity_create_with(|| Box::new(TestEntity::new(true, stats.clone()))).unwrap();
+    let observer = server.entity_observer(&entity);
+    let observer_clone = observer.clone();
+    assert_eq!(observer, observer_clone);
+    assert!(server.entity_observe(&observer).is_some());
+    assert!(server.entity_observe(&observer_clone).is_some());
+
+    server.entity_destroy(entity).unwrap();
+    assert!(server.entity_observe(&observer).is_none());
+    assert!(server.entity_observe(&observer_clone).is_none());
+
+    // Observers do not resolve to new entities which re-use the slot
+    server.update_entities_with(|_, _| {});
+    let entity_two = server.entity_create_with(|| Box::new(TestEntity::new(true, stats.clone()))).unwrap();
+    assert!(server.entity_observe(&server.entity_observer(&entity_two)).is_some());
+    assert!(server.entity_observe(&observer).is_none());
+    assert!(server.entity_observe(&observer_clone).is_none());
+
+}
+
+#[test]
+fn test_server_entity_ref() {
+
+    let mut server = Server::<ServerEntity, TestUserData>::new(config(0));
+    let mut server_two = Server::<ServerEntity, TestUserData>::new(config(0));
+    let stats: Rc<Mutex<TestStat>> = Rc::new(Mutex::new(TestStat::default()));
+
+    let entity = server.entity_create_with(|| Box::new(TestEntity::new(true, stats.clone()))).unwrap();
+    let entity_ref = entity.as_ref();
+    let entity_ref_copy = entity_ref;
+    assert_eq!(entity_ref, entity_ref_copy);
+
+    // References give access just like the token
+    assert!(server.entity_get(entity_ref).is_some());
+    assert!(server.entity_get_mut(entity_ref_copy).is_some());
+    assert!(server.entity_get(&entity).is_some());
+
+    // References of other servers do not resolve
+    let foreign = server_two.entity_create_with(|| Box::new(TestEntity::new(true, stats.clone()))).unwrap();
+    assert!(server.entity_get(foreign.as_ref()).is_none());
+
+    // Destruction requires the owned token
+    server.entity_destroy(entity).unwrap();
+    assert!(server.entity_get(entity_ref).is_none());
+    assert!(server.entity_get_mut(entity_ref_copy).is_none());
+
+    // References do not resolve to new entities which re-use the slot
+    server.update_entities_with(|_, _| {});
+    let entity_two = server.entity_create_with(|| Box::new(TestEntity::new(true, stats.clone()))).unwrap();
+    assert!(server.entity_get(&entity_two).is_some());
+    assert!(server.entity_get(entity_ref).is_none());
+
+}
+
 #[test]
 fn test_server_map_entities() {
 
@@ -117,6 +417,79 @@ fn test_server_map_entities() {
 
 }
 
+#[test]
+fn test_server_entity_create_batch() {
+
+    let mut server = Server::<ServerEntity, TestUserData>::new(config(3));
+    let stats: Rc<Mutex<TestStat>> = Rc::new(Mutex::new(TestStat::default()));
+
+    let tokens = server.entity_create_batch(250, |_| Box::new(TestEntity::new(true, stats.clone()))).unwrap();
+    assert_eq!(tokens.len(), 250);
+    assert!(tokens.iter().all(|token| server.entity_get(token).is_some()));
+    assert_stats!(stats, new, 250);
+
+    // Batches which do not fit are rejected without creating any entities
+    let mut calls = 0;
+    assert_eq!(server.entity_create_batch(7, |_| {
+        calls += 1;
+        Box::new(TestEntity::new(true, stats.clone()))
+
+    }).map(|tokens| tokens.len()), Err(ServerError::AllEntityTokensInUse));
+    assert_eq!(calls, 0);
+    assert_stats!(stats, new, 250);
+    assert!(server.entity_create_with(|| Box::new(TestEntity::new(true, stats.clone()))).is_ok());
+
+    // The remaining slots can be filled exactly
+    let tokens = server.entity_create_batch(5, |i| Box::new(TestEntity::new(i % 2 == 0, stats.clone()))).unwrap();
+    assert_eq!(tokens.len(), 5);
+    assert!(tokens.iter().all(|token| server.entity_get(token).is_some()));
+    assert_stats!(stats, new, 256);
+    assert_eq!(server.entity_create_with(|| Box::new(TestEntity::new(true, stats.clone()))), Err(ServerError::AllEntityTokensInUse));
+
+    assert_eq!(server.entity_create_batch(0, |_| Box::new(TestEntity::new(true, stats.clone()))).map(|tokens| tokens.len()), Ok(0));
+    assert_eq!(server.entity_create_batch(1, |_| Box::new(TestEntity::new(true, stats.clone()))).map(|tokens| tokens.len()), Err(ServerError::AllEntityTokensInUse));
+
+}
+
+#[test]
+fn test_server_entity_iteration_order() {
+
+    let mut server = Server::<ServerEntity, TestUserData>::new(config(3));
+    let create = |server: &mut Server<ServerEntity, TestUserData>, kind: u16| {
+        let stats: Rc<Mutex<TestStat>> = Rc::new(Mutex::new(TestStat::default()));
+        stats.lock().unwrap().kind = Some(kind);
+        server.entity_create_with(|| Box::new(TestEntity::new(true, stats))).unwrap()
+    };
+
+    let _ = create(&mut server, 10);
+    let one = create(&mut server, 11);
+    let _ = create(&mut server, 12);
+    let three = create(&mut server, 13);
+    let _ = create(&mut server, 14);
+
+    // Free slots 3 and 1, in that order
+    server.entity_destroy(three).unwrap();
+    server.entity_destroy(one).unwrap();
+    server.update_entities_with(|_, _| {});
+    assert_eq!(server.map_entities::<u16, _>(|_, entity| entity.kind()), [10, 12, 14]);
+
+    // Re-created entities are iterated by their slot, not their creation
+    let _ = create(&mut server, 21);
+    let _ = create(&mut server, 23);
+    assert_eq!(server.map_entities::<u16, _>(|_, entity| entity.kind()), [10, 21, 12, 23, 14]);
+
+    let mut kinds = Vec::new();
+    server.with_entities(|_, entity| kinds.push(entity.kind()));
+    assert_eq!(kinds, [10, 21, 12, 23, 14]);
+
+    // Serialization follows the same order
+    let connection = server.connection_add_with(||TestUserData::new(0)).unwrap();
+    let packets = server.connection_send(&connection, 4096).unwrap();
+    let indices: Vec<u8> = packets[0].chunks(8).map(|create| create[1]).collect();
+    assert_eq!(indices, [0, 1, 2, 3, 4]);
+
+}
+
 #[test]
 fn test_server_exhaustive_create_entity() {
 
@@ -211,6 +584,110 @@ fn test_server_exhaustive_entity_slot_unique() {
 
 }
 
+#[test]
+fn test_server_entity_slot_strategy_random() {
+
+    let stats: Rc<Mutex<TestStat>> = Rc::new(Mutex::new(TestStat::default()));
+    let mut server = Server::<ServerEntity, TestUserData>::new(Config {
+        slot_strategy: SlotStrategy::Random(42),
+        ..Config::default()
+    });
+
+    let connection_one = server.connection_add_with(||TestUserData::new(32)).unwrap();
+
+    // Extract the slot index of each new entity from the SendCreateToClient
+    // packets, which are always ordered by slot index
+    let mut indices: Vec<u8> = Vec::new();
+    for _ in 0..16 {
+        server.entity_create_with(|| Box::new(TestEntity::new(true, stats.clone()))).unwrap();
+        let created: Vec<u8> = server.connection_send(&connection_one, 8).unwrap().into_iter().map(|p| p[1]).filter(|index| {
+            !indices.contains(index)
+
+        }).collect();
+        indices.extend(created);
+    }
+    assert_eq!(indices.len(), 16);
+    assert!(indices.windows(2).any(|w| w[0] > w[1]), "Slot indices should not be monotonically increasing.");
+
+    let mut unique = indices.clone();
+    unique.sort();
+    unique.dedup();
+    assert_eq!(unique.len(), 16);
+
+}
+
+#[test]
+fn test_server_entity_slot_strategy_lru() {
+
+    let stats: Rc<Mutex<TestStat>> = Rc::new(Mutex::new(TestStat::default()));
+    let mut server = Server::<ServerEntity, TestUserData>::new(Config {
+        slot_strategy: SlotStrategy::Lru,
+        ..Config::default()
+    });
+
+    let entity = server.entity_create_with(|| Box::new(TestEntity::new(true, stats.clone()))).unwrap();
+    server.entity_destroy(entity).unwrap();
+    server.update_entities_with(|_, _| {});
+
+    // The released slot is re-used last
+    let connection_one = server.connection_add_with(||TestUserData::new(32)).unwrap();
+    server.entity_create_with(|| Box::new(TestEntity::new(true, stats.clone()))).unwrap();
+    assert_server_send!(server, connection_one, vec![], vec![0, 1, 3, 0, 1, 255, 128, 32]).expect("Server allocates the least recently used slot.");
+
+}
+
+#[test]
+#[cfg(feature = "testing")]
+fn test_server_instance_index() {
+
+    let stats: Rc<Mutex<TestStat>> = Rc::new(Mutex::new(TestStat::default()));
+    let mut server_one = Server::<ServerEntity, TestUserData>::new(config(5));
+    let mut server_two = Server::<ServerEntity, TestUserData>::new(config(5));
+
+    server_one.set_instance_index(1000);
+    server_two.set_instance_index(1001);
+    assert_eq!(server_one.instance_index(), 1000);
+    assert_eq!(server_two.instance_index(), 1001);
+
+    let connection_one = server_one.connection_add_with(||TestUserData::new(0)).unwrap();
+    let entity_one = server_one.entity_create_with(|| Box::new(TestEntity::new(true, stats.clone()))).unwrap();
+    server_two.connection_add_with(||TestUserData::new(0)).unwrap();
+
+    assert_eq!(server_two.connection_send(&connection_one, 4096), Err(ServerError::UnknownSenderToken));
+    assert!(server_two.entity_destroy(entity_one).is_err());
+
+    // Servers with the same index accept each others tokens
+    server_two.set_instance_index(1000);
+    assert_eq!(server_two.connection_send(&connection_one, 4096), Ok(vec![]));
+
+}
+
+#[test]
+fn test_server_with_id() {
+
+    let stats: Rc<Mutex<TestStat>> = Rc::new(Mutex::new(TestStat::default()));
+    let mut server_one = Server::<ServerEntity, TestUserData>::with_id(config(5), 2000);
+    let mut server_two = Server::<ServerEntity, TestUserData>::with_id(config(5), 2001);
+    let mut server_three = Server::<ServerEntity, TestUserData>::with_id(config(5), 2000);
+    assert_eq!(server_one.instance_index(), 2000);
+    assert_eq!(server_two.instance_index(), 2001);
+    assert_eq!(server_three.instance_index(), 2000);
+
+    let connection_one = server_one.connection_add_with(||TestUserData::new(0)).unwrap();
+    let entity_one = server_one.entity_create_with(|| Box::new(TestEntity::new(true, stats.clone()))).unwrap();
+    server_two.connection_add_with(||TestUserData::new(0)).unwrap();
+    server_three.connection_add_with(||TestUserData::new(0)).unwrap();
+
+    // Servers with different ids reject each others tokens
+    assert_eq!(server_two.connection_send(&connection_one, 4096), Err(ServerError::UnknownSenderToken));
+    assert!(server_two.entity_get(&entity_one).is_none());
+    assert!(server_two.entity_destroy(entity_one).is_err());
+
+    // Servers with the same id accept each others tokens
+    assert_eq!(server_three.connection_send(&connection_one, 4096), Ok(vec![]));
+
+}
+
 #[test]
 fn test_server_unkown_connection_tokens() {
 
@@ -240,1200 +717,5073 @@ fn test_server_connection_send_packet_split() {
 
     let connection_one = server.connection_add_with(||TestUserData::new(32)).unwrap();
 
-    assert_server_send_packets!(server, connection_one, 16, vec![], vec![
-        vec![0, 0, 3, 1, 255, 128, 32, 0, 1, 3, 1, 255, 128, 32],
-        vec![0, 2, 3, 1, 255, 128, 32]
+    assert_server_send_packets!(server, connection_one, 18, vec![], vec![
+        vec![0, 0, 3, 0, 1, 255, 128, 32, 0, 1, 3, 0, 1, 255, 128, 32],
+        vec![0, 2, 3, 0, 1, 255, 128, 32]
 
     ]).expect("Server does split packets across entity state boundaries.");
 
-    assert_server_send_packets!(server, connection_one, 8, vec![], vec![
-        vec![0, 0, 3, 1, 255, 128, 32],
-        vec![0, 1, 3, 1, 255, 128, 32],
-        vec![0, 2, 3, 1, 255, 128, 32]
+    assert_server_send_packets!(server, connection_one, 9, vec![], vec![
+        vec![0, 0, 3, 0, 1, 255, 128, 32],
+        vec![0, 1, 3, 0, 1, 255, 128, 32],
+        vec![0, 2, 3, 0, 1, 255, 128, 32]
 
     ]).expect("Server does split packets across entity state boundaries.");
 
-    assert_server_send_packets!(server, connection_one, 7, vec![], vec![
-        vec![0, 0, 3, 1, 255, 128, 32],
-        vec![0, 1, 3, 1, 255, 128, 32],
-        vec![0, 2, 3, 1, 255, 128, 32]
+    assert_server_send_packets!(server, connection_one, 8, vec![], vec![
+        vec![0, 0, 3, 0, 1, 255, 128, 32],
+        vec![0, 1, 3, 0, 1, 255, 128, 32],
+        vec![0, 2, 3, 0, 1, 255, 128, 32]
 
     ]).expect("Server does split packets across entity state boundaries.");
 
-    assert_server_send_packets!(server, connection_one, 4, vec![], vec![
-        vec![0, 0, 3, 1, 255, 128, 32],
-        vec![0, 1, 3, 1, 255, 128, 32],
-        vec![0, 2, 3, 1, 255, 128, 32]
+    assert_server_send_packets!(server, connection_one, 6, vec![], vec![
+        vec![7, 0, 3, 0, 0, 3],
+        vec![7, 1, 3, 0, 1, 255],
+        vec![7, 2, 3, 128, 32],
+        vec![7, 0, 3, 0, 1, 3],
+        vec![7, 1, 3, 0, 1, 255],
+        vec![7, 2, 3, 128, 32],
+        vec![7, 0, 3, 0, 2, 3],
+        vec![7, 1, 3, 0, 1, 255],
+        vec![7, 2, 3, 128, 32]
 
-    ]).expect("Server does split packets across entity state boundaries.");
+    ]).expect("Server does fragment entity states which exceed the packet size.");
+
+    // Packets which cannot hold any fragment data are rejected
+    assert_eq!(server.connection_send(&connection_one, 3), Err(ServerError::PacketSizeTooSmall(3)));
 
 }
 
 #[test]
-fn test_server_disconnected_entity() {
+fn test_server_connection_send_packet_overhead() {
 
-    let mut server = Server::<ServerEntity, TestUserData>::new(config(5));
+    let mut server = Server::<ServerEntity, TestUserData>::new(Config {
+        packet_overhead: 3,
+        ..Config::default()
+    });
 
     let stats: Rc<Mutex<TestStat>> = Rc::new(Mutex::new(TestStat::default()));
+    for _ in 0..5 {
+        server.entity_create_with(|| Box::new(TestEntity::new(true, stats.clone()))).unwrap();
+    }
 
-    let entity_one = server.entity_create_with(|| Box::new(TestEntity::new(true, stats.clone())));
-    assert!(entity_one.is_ok());
+    let connection_one = server.connection_add_with(||TestUserData::new(32)).unwrap();
+    for max_bytes_per_packet in 7..32 {
+        for packet in server.connection_send(&connection_one, max_bytes_per_packet).unwrap() {
+            assert!(packet.len() <= max_bytes_per_packet - 3);
+        }
+    }
 
-    let entity_two = server.entity_create_with(|| Box::new(TestEntity::new(true, stats.clone())));
-    assert!(entity_two.is_ok());
+    assert_server_send_packets!(server, connection_one, 19, vec![], vec![
+        vec![0, 0, 3, 0, 1, 255, 128, 32, 0, 1, 3, 0, 1, 255, 128, 32],
+        vec![0, 2, 3, 0, 1, 255, 128, 32, 0, 3, 3, 0, 1, 255, 128, 32],
+        vec![0, 4, 3, 0, 1, 255, 128, 32]
 
-    // Test entity updates
-    let mut count = 0;
-    server.update_entities_with(|_, entity| {
-        entity.server_update(&mut count);
-    });
+    ]).expect("Server leaves room for the packet overhead.");
 
-    assert_eq!(count, 2);
-    assert_stats!(stats, merge_calls, 0);
-    assert_stats!(stats, part_calls, 0);
-    assert_stats!(stats, drop_calls, 0);
+}
 
-    // Test that alive entities are not dropped if there are no connections
-    let mut count = 0;
-    server.update_entities_with(|_, entity| {
-        entity.server_update(&mut count);
+#[test]
+fn test_server_connection_send_compression() {
+
+    let mut server = Server::<ServerEntity, TestUserData>::new(Config {
+        compression: Some(Compressor::new(rle_compress, rle_decompress)),
+        ..Config::default()
     });
 
-    assert_eq!(count, 2);
-    assert_stats!(stats, drop_calls, 0);
+    let stats: Rc<Mutex<TestStat>> = Rc::new(Mutex::new(TestStat::default()));
+    let connection_one = server.connection_add_with(||TestUserData::new(96)).unwrap();
+    server.entity_create_with(|| Box::new(TestEntity::new(true, stats.clone()))).unwrap();
 
-    // Test that destroyed entities are dropped if there are no connections
-    assert!(server.entity_destroy(entity_one.unwrap()).is_ok());
+    // Packets which do not shrink are send uncompressed
+    assert_server_send!(server, connection_one, vec![], vec![0, 0, 3, 0, 1, 255, 128, 96]).expect("Server sents uncompressed SendCreateToClient packet to Client.");
 
-    // Entity should be dropped right away
-    assert_stats!(stats, drop_calls, 1);
+    // Compressed packets are decompressed on receive
+    let mut confirm = vec![8];
+    confirm.extend(rle_compress(&[1, 0]));
+    assert_server_send!(server, connection_one, confirm, vec![1, 0]).expect("Server accepts compressed ConfirmCreateToServer from Client.");
+    assert_server_send_empty!(server, connection_one, vec![2, 0]).expect("Server accepts AcceptServerUpdate from Client.");
 
-    let mut count = 0;
-    server.update_entities_with(|_, entity| {
-        entity.server_update(&mut count);
-    });
+    // Packets which shrink are send compressed
+    stats.lock().unwrap().part_bytes_value = Some(vec![0; 32]);
+    assert_eq!(server.connection_send(&connection_one, 256).unwrap(), vec![vec![8, 1, 3, 1, 0, 1, 32, 32, 0]]);
 
-    assert_eq!(count, 1);
+    // Invalid compressed data is rejected
+    assert_eq!(server.connection_receive(&connection_one, vec![8, 1]), Err(ServerError::InvalidPacketData(vec![8, 1])));
 
-    // Test that destroyed entities are dropped if there are no connections
-    assert!(server.entity_destroy(entity_two.unwrap()).is_ok());
+}
 
-    // Entity should be dropped right away
-    assert_stats!(stats, drop_calls, 2);
+#[test]
+fn test_server_connection_packet_checksum() {
 
-    let mut count = 0;
-    server.update_entities_with(|_, entity| {
-        entity.server_update(&mut count);
+    let mut server = Server::<ServerEntity, TestUserData>::new(Config {
+        packet_checksum: true,
+        ..Config::default()
     });
 
-    assert_eq!(count, 0);
+    let stats: Rc<Mutex<TestStat>> = Rc::new(Mutex::new(TestStat::default()));
+    let connection_one = server.connection_add_with(||TestUserData::new(96)).unwrap();
+    let entity_one = server.entity_create_with(|| Box::new(TestEntity::new(true, stats.clone()))).unwrap();
 
-}
+    // Packets are marked and followed by their checksum
+    assert_eq!(server.connection_send(&connection_one, 256).unwrap(), vec![vec![6, 0, 0, 3, 0, 1, 255, 128, 96, 119, 228]]);
 
-#[test]
-fn test_server_connection_add_existing_entity() {
+    // The checksum counts against the packet size
+    assert_eq!(server.connection_send(&connection_one, 10).unwrap(), vec![vec![6, 7, 0, 2, 0, 0, 3, 0, 65, 12], vec![6, 7, 1, 2, 1, 255, 128, 96, 166, 235]]);
 
-    let mut server = Server::<ServerEntity, TestUserData>::new(config(5));
-    let stats: Rc<Mutex<TestStat>> = Rc::new(Mutex::new(TestStat::default()));
-
-    let _ = server.entity_create_with(|| Box::new(TestEntity::new(true, stats.clone())));
+    // Corrupted packets are rejected before being parsed
+    assert_eq!(server.connection_receive(&connection_one, vec![6, 1, 1, 2, 1]), Err(ServerError::ChecksumMismatch(vec![6, 1, 1, 2, 1])));
+    assert_eq!(server.connection_entity_state(&connection_one, &entity_one), Some(RemoteState::Unknown));
 
-    let connection_one = server.connection_add_with(||TestUserData::new(128)).unwrap();
-    let connection_two = server.connection_add_with(||TestUserData::new(255)).unwrap();
+    // Packets without a checksum are rejected
+    assert_eq!(server.connection_receive(&connection_one, vec![1, 0]), Err(ServerError::ChecksumMismatch(vec![1, 0])));
 
-    assert_server_send!(server, connection_one, vec![], vec![0, 0, 3, 1, 255, 128, 128]).expect("Server sents SendCreateToClient packet to Client directly before next update call.");
-    assert_server_send!(server, connection_two, vec![], vec![0, 0, 3, 1, 255, 128, 255]).expect("Server sents SendCreateToClient packet to Client directly before next update call.");
-
-    let mut count = 0;
-    server.update_entities_with(|_, entity| {
-        entity.server_update(&mut count);
-    });
+    // Valid packets are accepted
+    server.connection_receive(&connection_one, vec![6, 1, 0, 2, 1]).expect("Server accepts checksummed ConfirmCreateToServer from Client.");
+    assert_eq!(server.connection_send(&connection_one, 256).unwrap(), vec![vec![6, 1, 0, 2, 1]]);
 
-    assert_eq!(count, 1);
+    // Custom packets are passed through without a checksum
+    let mut custom = Vec::new();
+    assert!(server.connection_receive_with(&connection_one, vec![255, 2], |bytes| custom.push(bytes.to_vec())).is_ok());
+    assert_eq!(custom, vec![vec![255, 2]]);
+    assert_eq!(server.connection_receive_with(&connection_one, vec![8, 2], |_| unreachable!()), Err(ServerError::ChecksumMismatch(vec![8, 2])));
 
-    assert_server_send!(server, connection_one, vec![], vec![0, 0, 3, 1, 255, 128, 128]).expect("Server sents SendCreateToClient packet to Client after next update call.");
-    assert_server_send!(server, connection_two, vec![], vec![0, 0, 3, 1, 255, 128, 255]).expect("Server sents SendCreateToClient packet to Client after next update call.");
+    // Peers without checksums do not accept checksummed packets
+    server.set_config(Config::default());
+    assert_eq!(server.connection_receive(&connection_one, vec![6, 1, 200, 202, 201]), Err(ServerError::TruncatedEntityData(1)));
 
 }
 
 #[test]
-fn test_server_connection_create_entity() {
-
-    let mut server = Server::<ServerEntity, TestUserData>::new(config(5));
-    let stats: Rc<Mutex<TestStat>> = Rc::new(Mutex::new(TestStat::default()));
+fn test_server_connection_send_with_events() {
 
-    let connection_one = server.connection_add_with(||TestUserData::new(128)).unwrap();
-    let connection_two = server.connection_add_with(||TestUserData::new(255)).unwrap();
+    let mut server = Server::<ServerEntity, TestUserData>::new(config(3));
+    let stats_one: Rc<Mutex<TestStat>> = Rc::new(Mutex::new(TestStat::default()));
+    let stats_two: Rc<Mutex<TestStat>> = Rc::new(Mutex::new(TestStat::default()));
 
-    assert_server_send_empty!(server, connection_one, vec![]).expect("Server sents no packet to Client before entity is created.");
-    assert_server_send_empty!(server, connection_two, vec![]).expect("Server sents no packet to Client before entity is created.");
+    let connection_one = server.connection_add_with(||TestUserData::new(255)).unwrap();
+    let entity_one = server.entity_create_with(|| Box::new(TestEntity::new(true, stats_one.clone()))).unwrap();
+    let entity_two = server.entity_create_with(|| Box::new(TestEntity::new(true, stats_two.clone()))).unwrap();
+    let observer_one = server.entity_observer(&entity_one);
+    let observer_two = server.entity_observer(&entity_two);
 
-    let _ = server.entity_create_with(|| Box::new(TestEntity::new(true, stats.clone())));
+    assert_eq!(server.connection_send_with_events(&connection_one, 4096), Ok((vec![vec![
+        0, 0, 3, 0, 1, 255, 128, 255,
+        0, 1, 3, 0, 1, 255, 128, 255
 
-    assert_server_send!(server, connection_one, vec![], vec![0, 0, 3, 1, 255, 128, 128]).expect("Server sents SendCreateToClient packet to Client after entity creation directly before next update call.");
-    assert_server_send!(server, connection_two, vec![], vec![0, 0, 3, 1, 255, 128, 255]).expect("Server sents SendCreateToClient packet to Client after entity creation directly before next update call.");
+    ]], vec![
+        ConnectionSendEvent::Created(observer_one.clone()),
+        ConnectionSendEvent::Created(observer_two.clone())
+    ])));
 
-    let mut count = 0;
-    server.update_entities_with(|_, entity| {
-        entity.server_update(&mut count);
-    });
+    assert_server_send!(server, connection_one, vec![1, 0, 1, 1], vec![1, 0, 1, 1]).expect("Server accepts ConfirmCreateToServer from Client.");
+    assert_server_send_empty!(server, connection_one, vec![2, 0, 2, 1]).expect("Server accepts AcceptServerUpdate from Client.");
 
-    assert_eq!(count, 1);
-    assert_stats!(stats, drop_calls, 0);
+    // Updates do not produce any events
+    stats_one.lock().unwrap().part_bytes_value = Some(vec![1]);
+    assert_eq!(server.connection_send_with_events(&connection_one, 4096), Ok((vec![vec![3, 0, 1, 1]], vec![])));
 
-    assert_server_send!(server, connection_one, vec![], vec![0, 0, 3, 1, 255, 128, 128]).expect("Server sents SendCreateToClient packet to Client after entity creation after next update call.");
-    assert_server_send!(server, connection_two, vec![], vec![0, 0, 3, 1, 255, 128, 255]).expect("Server sents SendCreateToClient packet to Client after entity creation after next update call.");
+    server.entity_destroy(entity_one).unwrap();
+    stats_two.lock().unwrap().relevance = Some(Relevance::Forgotten);
+    assert_eq!(server.connection_send_with_events(&connection_one, 4096), Ok((vec![vec![4, 0, 5, 1]], vec![
+        ConnectionSendEvent::Destroyed(observer_one),
+        ConnectionSendEvent::Forgotten(observer_two)
+    ])));
 
 }
 
 #[test]
-fn test_server_connection_remove_existing_alive_entity() {
+fn test_server_send_across_threads() {
 
-    let mut server = Server::<ServerEntity, TestUserData>::new(config(5));
-    let stats: Rc<Mutex<TestStat>> = Rc::new(Mutex::new(TestStat::default()));
+    fn assert_sync<T: Sync>(_: &T) {}
 
-    let _ = server.entity_create_with(|| Box::new(TestEntity::new(true, stats.clone())));
+    let stats: Arc<Mutex<TestStat>> = Arc::new(Mutex::new(TestStat::default()));
+    let mut server = Server::<Entity<TestUserData> + Send + Sync, TestUserData>::new(config(3));
+    server.set_connection_hidden_callback(|_| {});
+    assert_sync(&server);
 
-    let connection_one = server.connection_add_with(||TestUserData::new(0)).unwrap();
-    let connection_two = server.connection_add_with(||TestUserData::new(0)).unwrap();
+    let connection_one = server.connection_add_with(||TestUserData::new(255)).unwrap();
+    let entity = server.entity_create_with(|| Box::new(TestSendEntity::new(stats.clone()))).unwrap();
 
-    let mut count = 0;
-    server.update_entities_with(|_, entity| { entity.server_update(&mut count); });
-    assert_eq!(count, 1);
+    // The server and its tokens can be moved into another thread
+    let (mut server, connection_one, entity, packets) = thread::spawn(move || {
+        let packets = server.connection_send(&connection_one, 256).unwrap();
+        (server, connection_one, entity, packets)
 
-    server.connection_remove(connection_one).unwrap();
-    server.connection_remove(connection_two).unwrap();
+    }).join().unwrap();
 
-    let mut count = 0;
-    server.update_entities_with(|_, entity| { entity.server_update(&mut count); });
-    assert_eq!(count, 1);
+    assert_eq!(packets, vec![vec![0, 0, 0, 0, 5]]);
+    server.connection_receive(&connection_one, vec![1, 0, 2, 0]).unwrap();
+    assert_eq!(server.connection_entity_state(&connection_one, &entity), Some(RemoteState::Update));
 
-    assert_stats!(stats, drop_calls, 0);
+    stats.lock().unwrap().part_bytes_value = Some(vec![1]);
+    assert_eq!(server.connection_send(&connection_one, 256).unwrap(), vec![vec![3, 0, 1, 1]]);
+    assert_stats!(stats, part_calls, 1);
 
 }
 
 #[test]
-fn test_server_connect_remove_drop_destroy_entity() {
-
-    let mut server = Server::<ServerEntity, TestUserData>::new(config(5));
-    let stats: Rc<Mutex<TestStat>> = Rc::new(Mutex::new(TestStat::default()));
-
-    let entity = server.entity_create_with(|| Box::new(TestEntity::new(true, stats.clone()))).unwrap();
-
-    let connection_one = server.connection_add_with(||TestUserData::new(128)).unwrap();
-    let connection_two = server.connection_add_with(||TestUserData::new(255)).unwrap();
-
-    assert_server_send!(server, connection_one, vec![], vec![0, 0, 3, 1, 255, 128, 128]).expect("Server sents SendCreateToClient packet to Client.");
-    assert_server_send!(server, connection_two, vec![], vec![0, 0, 3, 1, 255, 128, 255]).expect("Server sents SendCreateToClient packet to Client.");
-
-    let mut count = 0;
-    server.update_entities_with(|_, entity| { entity.server_update(&mut count); });
-    assert_eq!(count, 1);
-
-    server.entity_destroy(entity).unwrap();
-    assert_stats!(stats, drop_calls, 1);
+fn test_server_connection_send_into() {
 
-    assert_server_send!(server, connection_one, vec![], vec![4, 0]).expect("Server sents SendDestroyToClient packet to Client for destroyed entity.");
-    assert_server_send!(server, connection_two, vec![], vec![4, 0]).expect("Server sents SendDestroyToClient packet to Client for destroyed entity.");
-
-    let mut count = 0;
-    server.update_entities_with(|_, entity| { entity.server_update(&mut count); });
-    assert_eq!(count, 0);
+    let server_config = || Config {
+        packet_header: Some(PacketHeader::new(1, |bytes| bytes[0] = 7)),
+        ..Config::default()
+    };
 
-    assert_server_send!(server, connection_one, vec![], vec![4, 0]).expect("Server still sends SendDestroyToClient to Client after next update call.");
-    assert_server_send!(server, connection_two, vec![], vec![4, 0]).expect("Server still sends SendDestroyToClient to Client after next update call.");
+    let mut server = Server::<ServerEntity, TestUserData>::new(server_config());
+    let mut server_into = Server::<ServerEntity, TestUserData>::new(server_config());
+    let stats: Rc<Mutex<TestStat>> = Rc::new(Mutex::new(TestStat::default()));
+    let stats_into: Rc<Mutex<TestStat>> = Rc::new(Mutex::new(TestStat::default()));
 
-    assert_server_send!(server, connection_one, vec![4, 0], vec![4, 0]).expect("Server ignores ConfirmDestroyToServer and still send SendDestroyToClient to the client.");
-    assert_server_send!(server, connection_two, vec![], vec![4, 0]).expect("Server still sends SendDestroyToClient for other clients.");
+    let connection_one = server.connection_add_with(||TestUserData::new(255)).unwrap();
+    let connection_into = server_into.connection_add_with(||TestUserData::new(255)).unwrap();
+    for _ in 0..2 {
+        server.entity_create_with(|| Box::new(TestEntity::new(true, stats.clone()))).unwrap();
+        server_into.entity_create_with(|| Box::new(TestEntity::new(true, stats_into.clone()))).unwrap();
+    }
 
-    // ConfirmDestroyToServer should have been ignored until now
-    assert_server_send!(server, connection_one, vec![4, 0], vec![4, 0]).expect("Server ignores ConfirmDestroyToServer until client sends ConfirmCreateToServer.");
-    assert_server_send!(server, connection_two, vec![4, 0], vec![4, 0]).expect("Server ignores ConfirmDestroyToServer until client sends ConfirmCreateToServer.");
+    let mut buffer = PacketBuffer::new();
+    let mut send = |bytes: Vec<u8>, part_bytes: Option<Vec<u8>>| {
+        server.connection_receive(&connection_one, bytes.clone()).unwrap();
+        server_into.connection_receive(&connection_into, bytes).unwrap();
+        stats.lock().unwrap().part_bytes_value = part_bytes.clone();
+        stats_into.lock().unwrap().part_bytes_value = part_bytes;
+        let packets = server.connection_send(&connection_one, 9).unwrap();
+        assert_eq!(server_into.connection_send_into(&connection_into, 9, &mut buffer).unwrap(), &packets[..]);
+        packets
+    };
 
-    // Send ConfirmCreateToServer
-    assert_server_send!(server, connection_one, vec![1, 0], vec![4, 0]).expect("Server accepts ConfirmCreateToServer for destroyed entity.");
-    assert_server_send!(server, connection_two, vec![1, 0], vec![4, 0]).expect("Server accepts ConfirmCreateToServer for destroyed entity.");
+    // Creates are split across multiple packets
+    assert_eq!(send(vec![], None), vec![
+        vec![7, 0, 0, 3, 0, 1, 255, 128, 255],
+        vec![7, 0, 1, 3, 0, 1, 255, 128, 255]
+    ]);
 
-    // ConfirmDestroyToServer should not be accepted
-    assert_server_send!(server, connection_one, vec![4, 0], vec![4, 0]).expect("Server accept ConfirmDestroyToServer and still send SendDestroyToClient to the client.");
-    assert_server_send!(server, connection_two, vec![], vec![4, 0]).expect("Server still sends SendDestroyToClient for other clients.");
-    assert_server_send_empty!(server, connection_two, vec![4, 0]).expect("Server accepts ConfirmDestroyToServer and sends so further SendDestroyToClient to the client.");
+    // Previously send packets are replaced
+    assert_eq!(send(vec![7, 1, 0, 1, 1], None), vec![vec![7, 1, 0, 1, 1]]);
+    assert_eq!(send(vec![7, 2, 0, 2, 1], None), Vec::<Vec<u8>>::new());
 
-    assert_server_send_empty!(server, connection_one, vec![]).expect("Server sends no further packets after handle was dropped.");
-    assert_server_send_empty!(server, connection_two, vec![]).expect("Server sends no further packets after handle was dropped.");
+    // Fragmented updates
+    assert_eq!(send(vec![], Some(vec![1, 2, 3, 4, 5, 6, 7, 8])), vec![
+        vec![7, 7, 0, 3, 3, 0, 8, 1, 2],
+        vec![7, 7, 1, 3, 3, 4, 5, 6, 7],
+        vec![7, 7, 2, 3, 8]
+    ]);
 
 }
 
 #[test]
-fn test_server_timeout_destroyed_entities() {
+fn test_server_connection_send_max_entities() {
 
-    let mut server = Server::<ServerEntity, TestUserData>::new(config(5));
+    let mut server = Server::<ServerEntity, TestUserData>::new(Config {
+        max_entities_per_packet: Some(2),
+        ..Config::default()
+    });
     let stats: Rc<Mutex<TestStat>> = Rc::new(Mutex::new(TestStat::default()));
 
-    let entity = server.entity_create_with(|| Box::new(TestEntity::new(true, stats.clone()))).unwrap();
+    let connection_one = server.connection_add_with(||TestUserData::new(255)).unwrap();
+    for _ in 0..5 {
+        server.entity_create_with(|| Box::new(TestEntity::new(true, stats.clone()))).unwrap();
+    }
 
-    let connection_one = server.connection_add_with(||TestUserData::new(128)).unwrap();
-    let connection_two = server.connection_add_with(||TestUserData::new(255)).unwrap();
+    // Packets are split by entity count even though they would fit byte-wise
+    assert_eq!(server.connection_send(&connection_one, 256).unwrap(), vec![
+        vec![0, 0, 3, 0, 1, 255, 128, 255, 0, 1, 3, 0, 1, 255, 128, 255],
+        vec![0, 2, 3, 0, 1, 255, 128, 255, 0, 3, 3, 0, 1, 255, 128, 255],
+        vec![0, 4, 3, 0, 1, 255, 128, 255]
+    ]);
+
+    // The byte limit still applies below the entity count
+    assert_eq!(server.connection_send(&connection_one, 12).unwrap(), vec![
+        vec![0, 0, 3, 0, 1, 255, 128, 255],
+        vec![0, 1, 3, 0, 1, 255, 128, 255],
+        vec![0, 2, 3, 0, 1, 255, 128, 255],
+        vec![0, 3, 3, 0, 1, 255, 128, 255],
+        vec![0, 4, 3, 0, 1, 255, 128, 255]
+    ]);
 
-    assert_server_send!(server, connection_one, vec![], vec![0, 0, 3, 1, 255, 128, 128]).expect("Server sents SendCreateToClient packet to Client.");
-    assert_server_send!(server, connection_two, vec![], vec![0, 0, 3, 1, 255, 128, 255]).expect("Server sents SendCreateToClient packet to Client.");
+}
 
-    let mut count = 0;
-    server.update_entities_with(|_, entity| { entity.server_update(&mut count); });
-    assert_eq!(count, 1);
+#[test]
+fn test_server_connection_send_fragments() {
 
-    server.entity_destroy(entity).unwrap();
-    assert_stats!(stats, drop_calls, 1);
+    let mut server = Server::<ServerEntity, TestUserData>::new(config(3));
+    let stats: Rc<Mutex<TestStat>> = Rc::new(Mutex::new(TestStat::default()));
 
-    assert_server_send!(server, connection_one, vec![], vec![4, 0]).expect("Server sents SendDestroyToClient packet to Client for destroyed entity.");
-    assert_server_send!(server, connection_two, vec![], vec![4, 0]).expect("Server sents SendDestroyToClient packet to Client for destroyed entity.");
+    let connection_one = server.connection_add_with(||TestUserData::new(255)).unwrap();
+    server.entity_create_with(|| Box::new(TestEntity::new(true, stats.clone()))).unwrap();
+    server.entity_create_with(|| Box::new(TestEntity::new(true, stats.clone()))).unwrap();
 
-    let mut count = 0;
-    server.update_entities_with(|_, entity| { entity.server_update(&mut count); });
-    assert_eq!(count, 0);
+    // Fragmented entity states are reassembled by the client
+    let (mut client, _) = create_client(3);
+    for packet in server.connection_send(&connection_one, 5).unwrap() {
+        assert!(packet.len() <= 5);
+        client.receive(packet).expect("Client accepts fragmented SendCreateToClient packet.");
+    }
 
-    assert_server_send!(server, connection_one, vec![], vec![4, 0]).expect("Server still sends SendCreateToClient to Client after next update call.");
-    assert_server_send!(server, connection_two, vec![], vec![4, 0]).expect("Server still sends SendCreateToClient to Client after next update call.");
+    assert_eq!(client.send(4096).unwrap(), vec![vec![1, 0, 1, 1]]);
 
-    server.update_entities_with(|_, entity| { entity.server_update(&mut count); });
-    server.update_entities_with(|_, entity| { entity.server_update(&mut count); });
-    server.update_entities_with(|_, entity| { entity.server_update(&mut count); });
+    // Missing fragments drop the whole entity state
+    let packets = server.connection_send(&connection_one, 5).unwrap();
+    let (mut client, _) = create_client(3);
+    for (i, packet) in packets.into_iter().enumerate() {
+        if i != 1 {
+            client.receive(packet).expect("Client accepts fragmented SendCreateToClient packet.");
+        }
+    }
 
-    assert_server_send!(server, connection_one, vec![], vec![4, 0]).expect("Server still sends SendCreateToClient to Client after next 4th update call.");
-    assert_server_send!(server, connection_two, vec![], vec![4, 0]).expect("Server still sends SendCreateToClient to Client after next 4th update call.");
+    assert_eq!(client.send(4096).unwrap(), vec![vec![1, 1]]);
 
-    server.update_entities_with(|_, entity| { entity.server_update(&mut count); });
+    // Fragmented client updates are reassembled by the server
+    assert_server_send!(server, connection_one, vec![1, 0, 1, 1], vec![1, 0, 1, 1]).expect("Server accepts ConfirmCreateToServer from Client.");
+    assert_server_send_empty!(server, connection_one, vec![2, 0, 2, 1]).expect("Server accepts AcceptServerUpdate from Client.");
 
-    assert_server_send_empty!(server, connection_one, vec![]).expect("Server sends no further packets after handle was dropped after 5th update call.");
-    assert_server_send_empty!(server, connection_two, vec![]).expect("Server sends no further packets after handle was dropped after 5th update call.");
+    stats.lock().unwrap().merge_bytes_value = vec![1, 2, 3, 4];
+    assert_server_send_empty!(server, connection_one, vec![7, 0, 3, 3, 0]).expect("Server buffers first fragment.");
+    assert_server_send_empty!(server, connection_one, vec![7, 1, 3, 4, 1, 2]).expect("Server buffers second fragment.");
+    assert_stats!(stats, merge_calls, 0);
+    assert_server_send_empty!(server, connection_one, vec![7, 2, 3, 3, 4]).expect("Server reassembles last fragment.");
+    assert_stats!(stats, merge_calls, 1);
 
 }
 
 #[test]
-fn test_server_entity_flow() {
+fn test_server_connection_send_fragments_large_entity() {
 
-    let mut server = Server::<ServerEntity, TestUserData>::new(config(5));
+    let mut server = Server::<ServerEntity, TestUserData>::new(Config {
+        length_codec: Box::new(TwoByte),
+        ..config(3)
+    });
     let stats: Rc<Mutex<TestStat>> = Rc::new(Mutex::new(TestStat::default()));
 
     let connection_one = server.connection_add_with(||TestUserData::new(255)).unwrap();
-    let entity = server.entity_create_with(|| Box::new(TestEntity::new(true, stats.clone()))).unwrap();
-    assert_stats!(stats, created_calls, 1);
-    assert_stats!(stats, destroyed_calls, 0);
+    server.entity_create_with(|| Box::new(TestEntity::new(true, stats.clone()))).unwrap();
 
-    // None -> SendCreateToClient
-    assert_server_send!(server, connection_one, vec![], vec![0, 0, 3, 1, 255, 128, 255]).expect("Server sents SendCreateToClient packet to Client.");
+    let (mut client, client_stats) = create_client(3);
+    client.set_config(Config {
+        length_codec: Box::new(TwoByte),
+        ..Config::default()
+    });
 
-    // ConfirmCreateToServer -> ConfirmClientCreate
-    assert_server_send!(server, connection_one, vec![1, 0], vec![1, 0]).expect("Server accepts ConfirmCreateToServer from Client and responds with ConfirmClientCreate.");
+    for packet in server.connection_send(&connection_one, 512).unwrap() {
+        client.receive(packet).expect("Client accepts SendCreateToClient packet.");
+    }
+    for packet in client.send(512).unwrap() {
+        server.connection_receive(&connection_one, packet).expect("Server accepts ConfirmCreateToServer from Client.");
+    }
+    for packet in server.connection_send(&connection_one, 512).unwrap() {
+        client.receive(packet).expect("Client accepts ConfirmClientCreate packet.");
+    }
+    for packet in client.send(512).unwrap() {
+        server.connection_receive(&connection_one, packet).expect("Server accepts AcceptServerUpdate from Client.");
+    }
 
-    // AcceptServerUpdate -> None
-    assert_server_send_empty!(server, connection_one, vec![2, 0]).expect("Server accepts AcceptServerUpdate from Client and sends no packet if part_calls returns None.");
-    assert_stats!(stats, part_calls, 1);
-    assert_stats!(stats, merge_calls, 0);
+    // A 600 byte entity state is split across two packets
+    let update_bytes: Vec<u8> = (0..600).map(|i| i as u8).collect();
+    stats.lock().unwrap().part_bytes_value = Some(update_bytes.clone());
 
-    // None -> SendUpdateToClient
-    stats.lock().unwrap().part_bytes_value = Some(vec![]);
-    assert_server_send!(server, connection_one, vec![], vec![3, 0, 0]).expect("Server send SendUpdateToClient packet without data bytes.");
-    assert_stats!(stats, part_calls, 2);
-    assert_stats!(stats, merge_calls, 0);
+    let packets = server.connection_send(&connection_one, 512).unwrap();
+    assert_eq!(packets.len(), 2);
+    assert!(packets.iter().all(|packet| packet.len() <= 512));
 
-    // None -> SendUpdateToClient
-    stats.lock().unwrap().part_bytes_value = Some(vec![255, 192, 96]);
-    assert_server_send!(server, connection_one, vec![], vec![3, 0, 3, 255, 192, 96]).expect("Server send SendUpdateToClient packet with data bytes.");
-    assert_stats!(stats, part_calls, 3);
-    assert_stats!(stats, merge_calls, 0);
+    client_stats.lock().unwrap().merge_bytes_value = update_bytes.clone();
+    for packet in packets {
+        client.receive(packet).expect("Client accepts fragmented SendUpdateToClient packet.");
+    }
+    assert_stats!(client_stats, merge_calls, 1);
 
-    // SendUpdateToServer -> None
-    stats.lock().unwrap().merge_bytes_value = vec![];
-    assert_server_send_empty!(server, connection_one, vec![3, 0, 0]).expect("Server accepts SendUpdateToServer packet without data bytes.");
-    assert_stats!(stats, merge_calls, 0);
+    // Without a wide enough length codec the entity state cannot be send
+    server.set_config(config(3));
+    stats.lock().unwrap().part_bytes_value = Some(update_bytes);
+    assert_eq!(server.connection_send(&connection_one, 512), Err(ServerError::PacketSizeTooSmall(512)));
 
-    // SendUpdateToServer -> None
-    stats.lock().unwrap().merge_bytes_value = vec![64, 56, 244];
-    assert_server_send_empty!(server, connection_one, vec![3, 0, 3, 64, 56, 244]).expect("Server accepts SendUpdateToServer packet with data bytes.");
-    assert_stats!(stats, merge_calls, 1);
-    assert_stats!(stats, part_calls, 5);
+}
 
-    // Destroy Entity
-    server.entity_destroy(entity).unwrap();
-    assert_stats!(stats, created_calls, 1);
-    assert_stats!(stats, destroyed_calls, 1);
-    assert_stats!(stats, drop_calls, 1);
+#[test]
+fn test_packet_size_too_small() {
 
-    // SendUpdateToServer -> None
-    stats.lock().unwrap().merge_bytes_value = vec![];
-    assert_server_send!(server, connection_one, vec![3, 0, 3, 64, 56, 244], vec![4, 0]).expect("Server ignores SendUpdateToServer packet for destroyed entity.");
-    assert_stats!(stats, merge_calls, 1);
-    assert_stats!(stats, part_calls, 5);
+    let mut server = Server::<ServerEntity, TestUserData>::new(config(3));
+    let stats: Rc<Mutex<TestStat>> = Rc::new(Mutex::new(TestStat::default()));
 
-    // None -> SendDestroyToClient
-    stats.lock().unwrap().merge_bytes_value = vec![];
-    assert_server_send!(server, connection_one, vec![], vec![4, 0]).expect("Server sends SendDestroyToClient packet for destroyed entity.");
-    assert_stats!(stats, part_calls, 5);
+    let connection_one = server.connection_add_with(||TestUserData::new(255)).unwrap();
+    server.entity_create_with(|| Box::new(TestEntity::new(true, stats.clone()))).unwrap();
 
-    // ConfirmCreateToServer -> SendUpdateToServer
-    assert_server_send!(server, connection_one, vec![1, 0], vec![4, 0]).expect("Server ignores ConfirmCreateToServer packet for destroyed entity.");
+    // Packets without room for a fragment header are rejected
+    assert_eq!(server.connection_send(&connection_one, 3), Err(ServerError::PacketSizeTooSmall(3)));
 
-    // AcceptServerUpdate -> SendUpdateToServer
-    assert_server_send!(server, connection_one, vec![2, 0], vec![4, 0]).expect("Server ignores AcceptServerUpdate packet for destroyed entity.");
+    // Entity bytes which need more than 255 fragments are rejected
+    let (mut client, client_stats) = create_client(3);
+    for packet in server.connection_send(&connection_one, 4).unwrap() {
+        assert!(packet.len() <= 4);
+        client.receive(packet).expect("Client accepts fragmented SendCreateToClient packet.");
+    }
+    client.receive(vec![1, 0]).unwrap();
+    client.receive(vec![3, 0, 0]).unwrap();
 
-    // ConfirmDestroyToServer -> None
-    assert_server_send_empty!(server, connection_one, vec![4, 0]).expect("Server accepts ConfirmDestroyToServer packet for destroyed entity and drops handle.");
+    server.connection_receive(&connection_one, vec![1, 0]).unwrap();
+    server.connection_send(&connection_one, 256).unwrap();
+    server.connection_receive(&connection_one, vec![2, 0]).unwrap();
 
-    // TODO test 0 timeout value
-    // TODO test multiple connections
+    stats.lock().unwrap().part_bytes_value = Some(vec![0; 255]);
+    assert_eq!(server.connection_send(&connection_one, 4), Err(ServerError::PacketSizeTooSmall(4)));
 
-}
+    client_stats.lock().unwrap().part_bytes_value = Some(vec![0; 255]);
+    assert_eq!(client.send(4), Err(ClientError::PacketSizeTooSmall(4)));
 
-#[test]
-fn test_server_entity_periodic_empty_update() {
+    // Larger packets still work
+    stats.lock().unwrap().part_bytes_value = Some(vec![0; 255]);
+    assert_eq!(server.connection_send(&connection_one, 4096).unwrap().len(), 1);
+    client_stats.lock().unwrap().part_bytes_value = Some(vec![0; 255]);
+    assert_eq!(client.send(4096).unwrap().len(), 1);
 
-    // TODO create client test
+    client.receive(vec![4, 0]).unwrap();
+    assert_eq!(client.disconnect_flush(1), Err(ClientError::PacketSizeTooSmall(1)));
+    assert_eq!(client.disconnect_flush(2), Ok(vec![vec![4, 0]]));
 
-    let mut server = Server::<ServerEntity, TestUserData>::new(config(5));
+}
 
-    server.set_config(Config {
-        handle_timeout_ticks: 5,
-        minimum_update_interval: Some(10)
-    });
+#[test]
+fn test_entity_bytes_exceed_length_codec() {
 
+    let mut server = Server::<ServerEntity, TestUserData>::new(config(3));
     let stats: Rc<Mutex<TestStat>> = Rc::new(Mutex::new(TestStat::default()));
 
     let connection_one = server.connection_add_with(||TestUserData::new(255)).unwrap();
-    let _ = server.entity_create_with(|| Box::new(TestEntityTwo::new(true, stats.clone()))).unwrap();
-    assert_stats!(stats, created_calls, 1);
-    assert_stats!(stats, destroyed_calls, 0);
+    server.entity_create_with(|| Box::new(TestEntity::new(true, stats.clone()))).unwrap();
 
-    // None -> SendCreateToClient
-    assert_server_send!(server, connection_one, vec![], vec![0, 0, 3, 2, 255, 128, 255]).expect("Server sents SendCreateToClient packet to Client.");
+    // Creations which exceed the single byte length are rejected
+    stats.lock().unwrap().snapshot_bytes_value = Some(vec![0; 256]);
+    assert_eq!(server.connection_send(&connection_one, 4096), Err(ServerError::PacketSizeTooSmall(4096)));
 
-    // ConfirmCreateToServer -> ConfirmClientCreate
-    assert_server_send!(server, connection_one, vec![1, 0], vec![1, 0]).expect("Server accepts ConfirmCreateToServer from Client and responds with ConfirmClientCreate.");
+    stats.lock().unwrap().snapshot_bytes_value = None;
+    assert_server_send!(server, connection_one, vec![], vec![0, 0, 3, 0, 1, 255, 128, 255]).expect("Server sends SendCreateToClient packet.");
+    assert_server_send!(server, connection_one, vec![1, 0], vec![1, 0]).expect("Server accepts ConfirmCreateToServer from Client.");
+    assert_server_send_empty!(server, connection_one, vec![2, 0]).expect("Server accepts AcceptServerUpdate from Client.");
 
-    // AcceptServerUpdate -> None
-    assert_server_send_empty!(server, connection_one, vec![2, 0]).expect("Server accepts AcceptServerUpdate from Client and sends no packet if part_calls returns None.");
-    assert_stats!(stats, part_calls, 1);
-    assert_stats!(stats, merge_calls, 0);
+    // Updates which exceed the single byte length are rejected
+    stats.lock().unwrap().part_bytes_value = Some(vec![0; 256]);
+    assert_eq!(server.connection_send(&connection_one, 4096), Err(ServerError::PacketSizeTooSmall(4096)));
 
-    let mut packets = vec![];
-    for _ in 0..20 {
-        let p = server.connection_send(&connection_one, 256).unwrap();
-        if p.is_empty() {
-            packets.push(vec![]);
+    stats.lock().unwrap().part_bytes_value = Some(vec![0; 255]);
+    assert_eq!(server.connection_send(&connection_one, 4096).unwrap().len(), 1);
 
-        } else {
-            packets.extend_from_slice(&p);
-        }
-    }
+    // Client updates which exceed the single byte length are rejected
+    let (mut client, client_stats) = create_client(3);
+    client.receive(vec![0, 0, 3, 0, 1, 255, 128, 255]).unwrap();
+    client.receive(vec![1, 0]).unwrap();
+    client.receive(vec![3, 0, 0]).unwrap();
 
-    assert_eq!(
-        packets,
-        vec![
-            vec![], vec![], vec![], vec![], vec![], vec![], vec![], vec![],
-            vec![3, 0, 0],
-            vec![], vec![], vec![], vec![], vec![], vec![], vec![], vec![], vec![],
-            vec![3, 0, 0],
-            vec![]
-        ],
-        "Server should send an empty update at least every 10 ticks."
-    );
+    client_stats.lock().unwrap().part_bytes_value = Some(vec![0; 256]);
+    assert_eq!(client.send(4096), Err(ClientError::PacketSizeTooSmall(4096)));
 
-    // SendUpdateToServer > None
-    assert_server_send_empty!(server, connection_one, vec![3, 0, 0]).expect("Server accepts empty client update packet.");
-    assert_stats!(stats, merge_calls, 0);
+    client_stats.lock().unwrap().part_bytes_value = Some(vec![0; 255]);
+    assert_eq!(client.send(4096).unwrap().len(), 1);
 
 }
 
 #[test]
-fn test_server_multi_entity_flow() {
+fn test_fragments_hostile_headers() {
 
     let mut server = Server::<ServerEntity, TestUserData>::new(config(3));
-    let stats: Rc<Mutex<TestStat>> = Rc::new(Mutex::new(TestStat::default()));
+    let connection_one = server.connection_add_with(||TestUserData::new(255)).unwrap();
+    let (mut client, stats) = create_client(3);
 
-    let connection_one = server.connection_add_with(||TestUserData::new(16)).unwrap();
-    let entity_one = server.entity_create_with(|| Box::new(TestEntity::new(true, stats.clone()))).unwrap();
-    let entity_two = server.entity_create_with(|| Box::new(TestEntity::new(true, stats.clone()))).unwrap();
-    let entity_three = server.entity_create_with(|| Box::new(TestEntity::new(true, stats.clone()))).unwrap();
+    // Fragments without a valid index and count are dropped
+    for i in 0..300 {
+        client.receive(vec![7, i as u8, 0, 1]).expect("Client drops fragment with a zero count.");
+        client.receive(vec![7, 3, i as u8, 1]).ok();
+        server.connection_receive(&connection_one, vec![7, i as u8, 0, 1]).expect("Server drops fragment with a zero count.");
+        server.connection_receive(&connection_one, vec![7, 3, i as u8, 1]).ok();
+    }
 
-    // None -> SendCreateToClient
-    assert_server_send!(server, connection_one, vec![], vec![
-        0, 0, 3, 1, 255, 128, 16,
-        0, 1, 3, 1, 255, 128, 16,
-        0, 2, 3, 1, 255, 128, 16
+    // Chunks larger than any entity state are dropped
+    client.receive(vec![7, 0, 2, 0, 0, 3, 0, 255]).expect("Client buffers first fragment.");
+    let mut packet = vec![7, 1, 2];
+    packet.extend_from_slice(&[0; 300]);
+    client.receive(packet).expect("Client drops oversized chunk.");
+    assert_stats!(stats, registry_calls, 0);
 
-    ]).expect("Server sents multiple SendCreateToClient in one packet to Client.");
+    // Valid chunks are still reassembled afterwards
+    client.receive(vec![7, 0, 2, 0, 0, 3, 0]).expect("Client buffers first fragment.");
+    client.receive(vec![7, 1, 2, 1, 255, 128, 255]).expect("Client reassembles last fragment.");
+    assert_stats!(stats, registry_calls, 1);
 
-    // ConfirmCreateToServer -> ConfirmClientCreate
-    assert_server_send!(server, connection_one, vec![1, 2], vec![
-        0, 0, 3, 1, 255, 128, 16,
-        0, 1, 3, 1, 255, 128, 16,
-        1, 2
+}
 
-    ]).expect("Server accepts ConfirmCreateToServer from Client.");
+#[test]
+fn test_server_disconnected_entity() {
 
-    assert_server_send!(server, connection_one, vec![1, 0], vec![
-        1, 0,
-        0, 1, 3, 1, 255, 128, 16,
-        1, 2
+    let mut server = Server::<ServerEntity, TestUserData>::new(config(5));
 
-    ]).expect("Server accepts AcceptServerUpdate from Client.");
+    let stats: Rc<Mutex<TestStat>> = Rc::new(Mutex::new(TestStat::default()));
 
-    assert_server_send!(server, connection_one, vec![2, 1], vec![
-        1, 0,
-        0, 1, 3, 1, 255, 128, 16,
-        1, 2
+    let entity_one = server.entity_create_with(|| Box::new(TestEntity::new(true, stats.clone())));
+    assert!(entity_one.is_ok());
 
-    ]).expect("Server ignores AcceptServerUpdate from Client if ConfirmCreateToServer packet was not yet sent.");
+    let entity_two = server.entity_create_with(|| Box::new(TestEntity::new(true, stats.clone())));
+    assert!(entity_two.is_ok());
+
+    // Test entity updates
+    let mut count = 0;
+    server.update_entities_with(|_, entity| {
+        entity.server_update(&mut count);
+    });
+
+    assert_eq!(count, 2);
+    assert_stats!(stats, merge_calls, 0);
     assert_stats!(stats, part_calls, 0);
+    assert_stats!(stats, drop_calls, 0);
 
-    // AcceptServerUpdate -> None
-    assert_server_send!(server, connection_one, vec![2, 0, 2, 2], vec![
-        0, 1, 3, 1, 255, 128, 16
+    // Test that alive entities are not dropped if there are no connections
+    let mut count = 0;
+    server.update_entities_with(|_, entity| {
+        entity.server_update(&mut count);
+    });
 
-    ]).expect("Server accepts multiple AcceptServerUpdate from Client in one packet.");
+    assert_eq!(count, 2);
+    assert_stats!(stats, drop_calls, 0);
 
-    assert_stats!(stats, part_calls, 2);
-    assert_stats!(stats, merge_calls, 0);
+    // Test that destroyed entities are dropped if there are no connections
+    assert!(server.entity_destroy(entity_one.unwrap()).is_ok());
 
-    // Destroy second entity
-    server.entity_destroy(entity_two).unwrap();
+    // Entity should be dropped right away
     assert_stats!(stats, drop_calls, 1);
 
-    // None -> SendCreateToClient
-    assert_server_send!(server, connection_one, vec![], vec![4, 1]).expect("Server sends SendDestroyToClient for destroy entity.");
-
     let mut count = 0;
-    server.update_entities_with(|_, entity| { entity.server_update(&mut count); });
-    server.update_entities_with(|_, entity| { entity.server_update(&mut count); });
-    assert_server_send!(server, connection_one, vec![], vec![4, 1]).expect("Server keeps destroyed entity handle before 3 update calls.");
+    server.update_entities_with(|_, entity| {
+        entity.server_update(&mut count);
+    });
 
-    server.update_entities_with(|_, entity| { entity.server_update(&mut count); });
-    assert_server_send_empty!(server, connection_one, vec![]).expect("Server drops destroyed entity handle after 3 update calls.");
+    assert_eq!(count, 1);
 
-    // SendUpdateToServer -> None
-    stats.lock().unwrap().merge_bytes_value = vec![];
-    assert_server_send_empty!(server, connection_one, vec![
-        3, 0, 0,
-        3, 2, 0
+    // Test that destroyed entities are dropped if there are no connections
+    assert!(server.entity_destroy(entity_two.unwrap()).is_ok());
 
-    ]).expect("Server accepts multiple SendUpdateToServer packets without data bytes.");
+    // Entity should be dropped right away
+    assert_stats!(stats, drop_calls, 2);
 
-    assert_stats!(stats, merge_calls, 0);
+    let mut count = 0;
+    server.update_entities_with(|_, entity| {
+        entity.server_update(&mut count);
+    });
 
-    // SendUpdateToServer -> None
-    stats.lock().unwrap().merge_bytes_value = vec![64, 56, 244];
-    assert_server_send_empty!(server, connection_one, vec![
-        3, 0, 3, 64, 56, 244,
-        3, 2, 3, 64, 56, 244
+    assert_eq!(count, 0);
 
-    ]).expect("Server accepts multiple SendUpdateToServer packets with data bytes.");
+}
 
-    assert_stats!(stats, merge_calls, 2);
-    assert_stats!(stats, part_calls, 12);
+#[test]
+fn test_server_connection_add_existing_entity() {
 
-    // None -> SendUpdateToClient
-    stats.lock().unwrap().part_bytes_value = Some(vec![255, 192, 96]);
-    assert_server_send!(server, connection_one, vec![], vec![3, 0, 3, 255, 192, 96]).expect("Server send SendUpdateToClient packet with data bytes.");
-    assert_stats!(stats, merge_calls, 2);
-    assert_stats!(stats, part_calls, 14);
+    let mut server = Server::<ServerEntity, TestUserData>::new(config(5));
+    let stats: Rc<Mutex<TestStat>> = Rc::new(Mutex::new(TestStat::default()));
 
-    // Destroy remaining entities
-    server.entity_destroy(entity_one).unwrap();
-    server.entity_destroy(entity_three).unwrap();
+    let _ = server.entity_create_with(|| Box::new(TestEntity::new(true, stats.clone())));
 
-    assert_server_send!(server, connection_one, vec![], vec![4, 0, 4, 2]).expect("Server sends multiple SendDestroyToClient for destroyed entities.");
+    let connection_one = server.connection_add_with(||TestUserData::new(128)).unwrap();
+    let connection_two = server.connection_add_with(||TestUserData::new(255)).unwrap();
 
-    server.update_entities_with(|_, entity| { entity.server_update(&mut count); });
-    server.update_entities_with(|_, entity| { entity.server_update(&mut count); });
-    assert_server_send!(server, connection_one, vec![], vec![4, 0, 4 ,2]).expect("Server keeps destroyed entity handles before 3 update calls.");
+    assert_server_send!(server, connection_one, vec![], vec![0, 0, 3, 0, 1, 255, 128, 128]).expect("Server sents SendCreateToClient packet to Client directly before next update call.");
+    assert_server_send!(server, connection_two, vec![], vec![0, 0, 3, 0, 1, 255, 128, 255]).expect("Server sents SendCreateToClient packet to Client directly before next update call.");
 
-    server.update_entities_with(|_, entity| { entity.server_update(&mut count); });
-    assert_server_send_empty!(server, connection_one, vec![]).expect("Server drops destroyed entity handles after 3 update calls.");
+    let mut count = 0;
+    server.update_entities_with(|_, entity| {
+        entity.server_update(&mut count);
+    });
+
+    assert_eq!(count, 1);
+
+    assert_server_send!(server, connection_one, vec![], vec![0, 0, 3, 0, 1, 255, 128, 128]).expect("Server sents SendCreateToClient packet to Client after next update call.");
+    assert_server_send!(server, connection_two, vec![], vec![0, 0, 3, 0, 1, 255, 128, 255]).expect("Server sents SendCreateToClient packet to Client after next update call.");
 
 }
 
 #[test]
-fn test_server_entity_forget() {
+fn test_server_connection_create_entity() {
 
     let mut server = Server::<ServerEntity, TestUserData>::new(config(5));
     let stats: Rc<Mutex<TestStat>> = Rc::new(Mutex::new(TestStat::default()));
 
-    let connection_one = server.connection_add_with(||TestUserData::new(96)).unwrap();
-    let _ = server.entity_create_with(|| Box::new(TestEntity::new(true, stats.clone()))).unwrap();
+    let connection_one = server.connection_add_with(||TestUserData::new(128)).unwrap();
+    let connection_two = server.connection_add_with(||TestUserData::new(255)).unwrap();
 
-    assert_stats!(stats, created_calls, 1);
-    assert_stats!(stats, destroyed_calls, 0);
+    assert_server_send_empty!(server, connection_one, vec![]).expect("Server sents no packet to Client before entity is created.");
+    assert_server_send_empty!(server, connection_two, vec![]).expect("Server sents no packet to Client before entity is created.");
 
+    let _ = server.entity_create_with(|| Box::new(TestEntity::new(true, stats.clone())));
 
-    // None -> SendCreateToClient
-    assert_server_send!(server, connection_one, vec![], vec![0, 0, 3, 1, 255, 128, 96]).expect("Server sents SendCreateToClient packet to Client.");
+    assert_server_send!(server, connection_one, vec![], vec![0, 0, 3, 0, 1, 255, 128, 128]).expect("Server sents SendCreateToClient packet to Client after entity creation directly before next update call.");
+    assert_server_send!(server, connection_two, vec![], vec![0, 0, 3, 0, 1, 255, 128, 255]).expect("Server sents SendCreateToClient packet to Client after entity creation directly before next update call.");
 
-    stats.lock().unwrap().filter_for_connection = true;
+    let mut count = 0;
+    server.update_entities_with(|_, entity| {
+        entity.server_update(&mut count);
+    });
 
-    // None -> SendCreateToClient (even though filtered)
-    assert_server_send!(server, connection_one, vec![], vec![0, 0, 3, 1, 255, 128, 96]).expect("Server does not send SendForgetToClient packet to Client if entity has not yet been confirmed to be created.");
+    assert_eq!(count, 1);
+    assert_stats!(stats, drop_calls, 0);
 
-    // ConfirmCreateToServer -> ConfirmClientCreate
-    assert_server_send!(server, connection_one, vec![1, 0], vec![5, 0]).expect("Server accepts ConfirmCreateToServer from Client and responds with SendForgetToClient for filtered entity.");
+    assert_server_send!(server, connection_one, vec![], vec![0, 0, 3, 0, 1, 255, 128, 128]).expect("Server sents SendCreateToClient packet to Client after entity creation after next update call.");
+    assert_server_send!(server, connection_two, vec![], vec![0, 0, 3, 0, 1, 255, 128, 255]).expect("Server sents SendCreateToClient packet to Client after entity creation after next update call.");
 
-    // None -> SendForgetToClient
-    assert_server_send!(server, connection_one, vec![], vec![5, 0]).expect("Server does send SendForgetToClient packet to Client if entity has been confirmed to be created.");
+}
 
-    // ConfirmCreateToServer -> SendForgetToClient
-    assert_server_send!(server, connection_one, vec![1, 0], vec![5, 0]).expect("Server ignores ConfirmCreateToServer from Client for forgotten entity.");
+#[test]
+fn test_server_connection_remove_existing_alive_entity() {
 
-    // SendUpdateToServer -> SendForgetToClient
-    assert_server_send!(server, connection_one, vec![1, 0], vec![5, 0]).expect("Server ignores ConfirmCreateToServer from Client for forgotten entity.");
-    assert_stats!(stats, merge_calls, 0);
+    let mut server = Server::<ServerEntity, TestUserData>::new(config(5));
+    let stats: Rc<Mutex<TestStat>> = Rc::new(Mutex::new(TestStat::default()));
 
-    // ConfirmClientCreate -> SendForgetToClient
-    assert_server_send!(server, connection_one, vec![1, 0], vec![5, 0]).expect("Server ignores ConfirmCreateToServer from Client for forgotten entity.");
+    let _ = server.entity_create_with(|| Box::new(TestEntity::new(true, stats.clone())));
 
-    // ConfirmDestroyToServer -> None
-    assert_server_send_empty!(server, connection_one, vec![4, 0]).expect("Server accepts ConfirmDestroyToServer from Client for forgotten entity.");
+    let connection_one = server.connection_add_with(||TestUserData::new(0)).unwrap();
+    let connection_two = server.connection_add_with(||TestUserData::new(0)).unwrap();
 
-    // None -> None
-    assert_server_send_empty!(server, connection_one, vec![]).expect("Server does not send any packets for filtered entity which does not exist on the client.");
+    let mut count = 0;
+    server.update_entities_with(|_, entity| { entity.server_update(&mut count); });
+    assert_eq!(count, 1);
 
-    stats.lock().unwrap().filter_for_connection = false;
+    server.connection_remove(connection_one).unwrap();
+    server.connection_remove(connection_two).unwrap();
 
-    // None -> SendCreateToClient again
-    assert_server_send!(server, connection_one, vec![], vec![0, 0, 3, 1, 255, 128, 96]).expect("Server sents SendCreateToClient packet to Client once the entity is no longer filtered.");
+    let mut count = 0;
+    server.update_entities_with(|_, entity| { entity.server_update(&mut count); });
+    assert_eq!(count, 1);
 
+    assert_stats!(stats, drop_calls, 0);
 
 }
 
 #[test]
-fn test_server_entity_forget_destroy() {
+fn test_server_create_resend_interval() {
 
-    let mut server = Server::<ServerEntity, TestUserData>::new(config(5));
+    let mut server = Server::<ServerEntity, TestUserData>::new(Config {
+        create_resend_interval: Some(3),
+        ..Config::default()
+    });
     let stats: Rc<Mutex<TestStat>> = Rc::new(Mutex::new(TestStat::default()));
 
     let connection_one = server.connection_add_with(||TestUserData::new(255)).unwrap();
-    let entity = server.entity_create_with(|| Box::new(TestEntity::new(true, stats.clone()))).unwrap();
-
-    stats.lock().unwrap().filter_for_connection = true;
+    let connection_two = server.connection_add_with(||TestUserData::new(96)).unwrap();
+    server.entity_create_with(|| Box::new(TestEntity::new(true, stats.clone()))).unwrap();
 
-    // None -> SendCreateToClient (even though filtered)
-    assert_server_send!(server, connection_one, vec![], vec![0, 0, 3, 1, 255, 128, 255]).expect("Server does not send SendForgetToClient packet to Client if entity has not yet been confirmed to be created.");
+    // Unconfirmed creations are only re-send on every third call
+    assert_server_send!(server, connection_one, vec![], vec![0, 0, 3, 0, 1, 255, 128, 255]).expect("Server sents SendCreateToClient packet to Client.");
+    assert_server_send_empty!(server, connection_one, vec![]).expect("Server throttles re-sending SendCreateToClient.");
+    assert_server_send_empty!(server, connection_one, vec![]).expect("Server throttles re-sending SendCreateToClient.");
+    assert_server_send!(server, connection_one, vec![], vec![0, 0, 3, 0, 1, 255, 128, 255]).expect("Server eventually re-sends SendCreateToClient packet to Client.");
+    assert_server_send_empty!(server, connection_one, vec![]).expect("Server throttles re-sending SendCreateToClient.");
 
-    // ConfirmCreateToServer -> ConfirmClientCreate
-    assert_server_send!(server, connection_one, vec![1, 0], vec![5, 0]).expect("Server accepts ConfirmCreateToServer from Client and responds with SendForgetToClient for filtered entity.");
+    // Throttling is tracked per connection
+    assert_server_send!(server, connection_two, vec![], vec![0, 0, 3, 0, 1, 255, 128, 96]).expect("Server sents SendCreateToClient packet to second Client.");
 
-    server.entity_destroy(entity).ok();
+    // Confirmations are answered right away
+    assert_server_send!(server, connection_one, vec![1, 0], vec![1, 0]).expect("Server accepts ConfirmCreateToServer from Client.");
+    assert_server_send_empty!(server, connection_one, vec![2, 0]).expect("Server accepts AcceptServerUpdate from Client.");
 
-    // None -> SendDestroyToClient
-    assert_server_send!(server, connection_one, vec![], vec![4, 0]).expect("Server sends SendDestroyToClient for destroyed entities.");
+    // Creations after a resync start out unthrottled
+    assert_eq!(server.connection_resync(&connection_one), Ok(()));
+    assert_server_send!(server, connection_one, vec![], vec![0, 0, 3, 0, 1, 255, 128, 255]).expect("Server re-sends SendCreateToClient packet to Client after resync.");
+    assert_server_send_empty!(server, connection_one, vec![]).expect("Server throttles re-sending SendCreateToClient.");
 
 }
 
 #[test]
-fn test_server_entity_forgotten_destroy() {
+fn test_server_maximum_update_rate() {
 
-    let mut server = Server::<ServerEntity, TestUserData>::new(config(5));
+    let mut server = Server::<ServerEntity, TestUserData>::new(Config {
+        maximum_update_rate: Some(3),
+        ..Config::default()
+    });
     let stats: Rc<Mutex<TestStat>> = Rc::new(Mutex::new(TestStat::default()));
 
     let connection_one = server.connection_add_with(||TestUserData::new(255)).unwrap();
-    let entity = server.entity_create_with(|| Box::new(TestEntity::new(true, stats.clone()))).unwrap();
-
-    stats.lock().unwrap().filter_for_connection = true;
-
-    // None -> SendCreateToClient (even though filtered)
-    assert_server_send!(server, connection_one, vec![], vec![0, 0, 3, 1, 255, 128, 255]).expect("Server does not send SendForgetToClient packet to Client if entity has not yet been confirmed to be created.");
+    let entity_one = server.entity_create_with(|| Box::new(TestEntity::new(true, stats.clone()))).unwrap();
+    assert_server_send!(server, connection_one, vec![], vec![0, 0, 3, 0, 1, 255, 128, 255]).expect("Server sents SendCreateToClient packet to Client.");
+    assert_server_send!(server, connection_one, vec![1, 0], vec![1, 0]).expect("Server accepts ConfirmCreateToServer from Client.");
+    assert_server_send_empty!(server, connection_one, vec![2, 0]).expect("Server accepts AcceptServerUpdate from Client.");
+    assert_stats!(stats, part_calls, 1);
 
-    // ConfirmCreateToServer -> ConfirmClientCreate
-    assert_server_send!(server, connection_one, vec![1, 0], vec![5, 0]).expect("Server accepts ConfirmCreateToServer from Client and responds with SendForgetToClient for filtered entity.");
+    // Always dirty entities are capped to one update every three ticks
+    let mut packets = vec![];
+    for _ in 0..7 {
+        stats.lock().unwrap().part_bytes_value = Some(vec![1]);
+        let p = server.connection_send(&connection_one, 256).unwrap();
+        if p.is_empty() {
+            packets.push(vec![]);
 
-    // ConfirmDestroyToServer -> None
-    assert_server_send_empty!(server, connection_one, vec![4, 0]).expect("Server accepts ConfirmDestroyToServer from Client for forgotten entity.");
+        } else {
+            packets.extend_from_slice(&p);
+        }
+    }
 
-    server.entity_destroy(entity).ok();
+    assert_eq!(packets, vec![
+        vec![3, 0, 1, 1], vec![], vec![],
+        vec![3, 0, 1, 1], vec![], vec![],
+        vec![3, 0, 1, 1]
+    ]);
+    assert_stats!(stats, part_calls, 4);
 
-    // None -> SendDestroyToClient
-    assert_server_send!(server, connection_one, vec![], vec![4, 0]).expect("Server still sends SendDestroyToClient for destroyed which the client already confirmed to be forgotten.");
+    // Creations and destructions are not capped
+    server.entity_create_with(|| Box::new(TestEntity::new(true, stats.clone()))).unwrap();
+    assert_server_send!(server, connection_one, vec![], vec![0, 1, 3, 0, 1, 255, 128, 255]).expect("Server sents SendCreateToClient packet while the update is capped.");
 
-    // Test destroy timeout for forgotten entities
-    let mut count = 0;
-    server.update_entities_with(|_, entity| { entity.server_update(&mut count); });
-    server.update_entities_with(|_, entity| { entity.server_update(&mut count); });
-    server.update_entities_with(|_, entity| { entity.server_update(&mut count); });
-    server.update_entities_with(|_, entity| { entity.server_update(&mut count); });
-    assert_server_send!(server, connection_one, vec![], vec![4, 0]).expect("Server still sends SendDestroyToClient to Client after next 4th update call.");
+    server.entity_destroy(entity_one).unwrap();
+    assert_server_send!(server, connection_one, vec![], vec![
+        4, 0,
+        0, 1, 3, 0, 1, 255, 128, 255
 
-    server.update_entities_with(|_, entity| { entity.server_update(&mut count); });
-    assert_server_send_empty!(server, connection_one, vec![]).expect("Server sends no further packets after handle was dropped after 5th update call.");
+    ]).expect("Server sends SendDestroyToClient packet while the update is capped.");
+    assert_stats!(stats, part_calls, 4);
 
 }
 
 #[test]
-fn test_server_connection_slot_reuse() {
+fn test_server_updates_paused() {
 
-    let mut server = Server::<ServerEntity, TestUserData>::new(config(5));
+    let mut server = Server::<ServerEntity, TestUserData>::new(Config::default());
     let stats: Rc<Mutex<TestStat>> = Rc::new(Mutex::new(TestStat::default()));
 
-    let connection_one = server.connection_add_with(||TestUserData::new(0)).unwrap();
-    let _ = server.entity_create_with(|| Box::new(TestEntity::new(true, stats.clone()))).unwrap();
+    let connection_one = server.connection_add_with(||TestUserData::new(255)).unwrap();
+    let entity_one = server.entity_create_with(|| Box::new(TestEntity::new(true, stats.clone()))).unwrap();
+    assert_server_send!(server, connection_one, vec![], vec![0, 0, 3, 0, 1, 255, 128, 255]).expect("Server sents SendCreateToClient packet to Client.");
+    assert_server_send!(server, connection_one, vec![1, 0], vec![1, 0]).expect("Server accepts ConfirmCreateToServer from Client.");
+    assert_server_send_empty!(server, connection_one, vec![2, 0]).expect("Server accepts AcceptServerUpdate from Client.");
+    assert_stats!(stats, part_calls, 1);
 
-    // Check for default state
-    assert_server_send!(server, connection_one, vec![], vec![0, 0, 3, 1, 255, 128, 0]).expect("Server sents SendCreateToClient packet to Client.");
+    // Updates are no longer send while paused
+    assert!(!server.updates_paused());
+    server.set_updates_paused(true);
+    assert!(server.updates_paused());
 
-    // Change Entity state
-    assert_server_send!(server, connection_one, vec![1, 0], vec![1, 0]).expect("Server accepts ConfirmCreateToServer from Client and responds with ConfirmClientCreate.");
+    stats.lock().unwrap().part_bytes_value = Some(vec![1]);
+    assert_server_send_empty!(server, connection_one, vec![]).expect("Server does not send updates while paused.");
+    assert_stats!(stats, part_calls, 1);
 
-    server.connection_remove(connection_one).expect("Connection removed.");
+    // Creations and destructions still progress
+    server.entity_create_with(|| Box::new(TestEntity::new(true, stats.clone()))).unwrap();
+    assert_server_send!(server, connection_one, vec![], vec![0, 1, 3, 0, 1, 255, 128, 255]).expect("Server sents SendCreateToClient packet while paused.");
+    assert_server_send!(server, connection_one, vec![1, 1], vec![1, 1]).expect("Server accepts ConfirmCreateToServer while paused.");
+    assert_server_send_empty!(server, connection_one, vec![2, 1]).expect("Server accepts AcceptServerUpdate while paused.");
 
-    // New connection should have
-    let connection_one = server.connection_add_with(||TestUserData::new(0)).unwrap();
+    server.entity_destroy(entity_one).unwrap();
+    assert_server_send!(server, connection_one, vec![], vec![4, 0]).expect("Server sends SendDestroyToClient packet while paused.");
+    assert_server_send_empty!(server, connection_one, vec![4, 0]).expect("Server accepts ConfirmDestroyToServer while paused.");
+    assert_stats!(stats, part_calls, 1);
 
-    // Check for reset default state on new connection
-    assert_server_send!(server, connection_one, vec![], vec![0, 0, 3, 1, 255, 128, 0]).expect("Server sents SendCreateToClient packet to Client.");
+    // Resumed updates are send again
+    server.set_updates_paused(false);
+    stats.lock().unwrap().part_bytes_value = Some(vec![1]);
+    assert_server_send!(server, connection_one, vec![], vec![3, 1, 1, 1]).expect("Server sends updates once resumed.");
 
 }
 
 #[test]
-fn test_server_connection_add_remove() {
-    let mut server = Server::<ServerEntity, TestUserData>::new(config(5));
-    let connection_one = server.connection_add_with(||TestUserData::new(0));
-    assert!(connection_one.is_ok());
-    assert!(server.connection_remove(connection_one.unwrap()).is_ok());
-}
+fn test_server_connection_tracked_entities() {
 
-#[test]
-fn test_server_ignore_invalid_packets() {
+    let mut server = Server::<ServerEntity, TestUserData>::new(config(2));
+    let stats: Rc<Mutex<TestStat>> = Rc::new(Mutex::new(TestStat::default()));
 
-    let mut server = Server::<ServerEntity, TestUserData>::new(config(5));
-    let connection_one = server.connection_add_with(||TestUserData::new(0)).expect("Failed to add client connection to server");;
+    let connection_one = server.connection_add_with(||TestUserData::new(255)).unwrap();
+    assert_eq!(server.connection_tracked_entities(&connection_one), Some(0));
+
+    // Unsent creations are not tracked
+    let entities = server.entity_create_batch(200, |_| Box::new(TestEntity::new(true, stats.clone()))).unwrap();
+    assert_eq!(server.connection_tracked_entities(&connection_one), Some(0));
+
+    // Send creations are
+    assert_eq!(server.connection_send(&connection_one, 4096).unwrap().len(), 1);
+    assert_eq!(server.connection_tracked_entities(&connection_one), Some(200));
+
+    // Confirmed entities are
+    let confirm: Vec<u8> = (0..200).flat_map(|i| vec![1, i as u8]).collect();
+    let accept: Vec<u8> = (0..200).flat_map(|i| vec![2, i as u8]).collect();
+    server.connection_receive(&connection_one, confirm).unwrap();
+    server.connection_send(&connection_one, 4096).unwrap();
+    server.connection_receive(&connection_one, accept).unwrap();
+    assert_eq!(server.connection_send(&connection_one, 4096).unwrap(), Vec::<Vec<u8>>::new());
+    assert_eq!(server.connection_tracked_entities(&connection_one), Some(200));
+    assert_eq!(server.connection_entity_state(&connection_one, &entities[199]), Some(RemoteState::Update));
+
+    // Dropped entities are no longer tracked
+    let mut entities = entities.into_iter();
+    for entity in entities.by_ref().take(190) {
+        server.entity_destroy(entity).unwrap();
+    }
+    server.connection_send(&connection_one, 4096).unwrap();
+    let destroy: Vec<u8> = (0..190).flat_map(|i| vec![4, i as u8]).collect();
+    server.connection_receive(&connection_one, destroy).unwrap();
+    server.connection_send(&connection_one, 4096).unwrap();
+    server.update_entities_with(|_, _| {});
+    assert_eq!(server.connection_tracked_entities(&connection_one), Some(10));
+    assert_eq!(server.pending_handle_count(), 0);
+
+    let remaining: Vec<Option<RemoteState>> = entities.map(|entity| server.connection_entity_state(&connection_one, &entity)).collect();
+    assert_eq!(remaining, vec![Some(RemoteState::Update); 10]);
+
+    // Foreign tokens are rejected
+    let mut server_two = Server::<ServerEntity, TestUserData>::new(config(2));
+    let connection_two = server_two.connection_add_with(||TestUserData::new(0)).unwrap();
+    assert_eq!(server.connection_tracked_entities(&connection_two), None);
 
-    assert_server_send_empty!(server, connection_one, vec![]).expect("Server ignores empty client packets");
-    assert_server_send_empty!(server, connection_one, vec![1]).expect("Server ignores client packets with len 1");
-    assert_server_send_empty!(server, connection_one, vec![1, 0]).expect("Server ignores ConfirmCreateToServer for non existent entity");
-    assert_server_send_empty!(server, connection_one, vec![2, 0]).expect("Server ignores AcceptServerUpdate for non existent entity");
-    assert_server_send_empty!(server, connection_one, vec![4, 0]).expect("Server ignores ConfirmDestroyToServer for non existent entity");
+}
 
-    assert_server_send_empty!(server, connection_one, vec![3, 2]).expect("Server ignores SendUpdateToServer packet for non existent entity");
-    assert_server_send_empty!(server, connection_one, vec![3, 2, 1]).expect("Server ignores SendUpdateToServer packet for non existent entity");
-    assert_server_send_empty!(server, connection_one, vec![3, 2, 1, 2]).expect("Server ignores SendUpdateToServer packet for non existent entity");
-    assert_server_send_empty!(server, connection_one, vec![3, 2, 1, 2, 5]).expect("Server ignores SendUpdateToServer packet for non existent entity");
+#[test]
+fn test_server_connection_inactive_slots() {
 
-    assert_eq!(assert_server_send!(server, connection_one, vec![3, 2, 10, 2, 5], vec![]), Err(ServerError::RemainingPacketData(vec![5])));
-    assert_eq!(assert_server_send!(server, connection_one, vec![5, 2], vec![]), Err(ServerError::InvalidPacketData(vec![5, 2])));
-    assert_eq!(assert_server_send!(server, connection_one, vec![255, 2], vec![]), Err(ServerError::InvalidPacketData(vec![255, 2])));
+    let mut server = Server::<ServerEntity, TestUserData>::new(config(2));
+    let stats: Rc<Mutex<TestStat>> = Rc::new(Mutex::new(TestStat::default()));
 
-}
-
-
-// Client Tests ---------------------------------------------------------------
-#[derive(Debug)]
-struct ClientRegistry {
-    stats: Rc<Mutex<TestStat>>
-}
+    let connection_one = server.connection_add_with(||TestUserData::new(255)).unwrap();
+    let connection_two = server.connection_add_with(||TestUserData::new(255)).unwrap();
+    let entity = server.entity_create_with(|| Box::new(TestEntity::new(true, stats.clone()))).unwrap();
 
-impl EntityRegistry<ClientEntity, TestUserData> for ClientRegistry {
-    fn entity_from_kind_and_bytes(&self, kind: u8, bytes: &[u8]) -> Option<Box<ClientEntity>> {
-        match kind {
-            1 => {
-                let mut entity = TestEntity::from_bytes(bytes).unwrap();
-                entity.set_stats(self.stats.clone());
-                self.stats.lock().unwrap().registry_calls += 1;
-                Some(Box::new(entity))
-            },
-            2 => {
-                let mut entity = TestEntityTwo::from_bytes(bytes).unwrap();
-                entity.set_stats(self.stats.clone());
-                self.stats.lock().unwrap().registry_calls += 1;
-                Some(Box::new(entity))
-            },
-            _ => None
-        }
+    // Only the slot of the single entity is tracked
+    assert_eq!(server.connection_send(&connection_one, 4096).unwrap().len(), 1);
+    assert_eq!(server.connection_tracked_entities(&connection_one), Some(1));
+    assert_eq!(server.connection_tracked_entities(&connection_two), Some(0));
+
+    // States received for untracked or empty slots are parsed but ignored
+    // without allocating slots for them
+    for state in 1..6 {
+        let bytes: Vec<u8> = (1..256).flat_map(|i| match state {
+            3 | 5 => vec![state, i as u8, 0],
+            _ => vec![state, i as u8]
+
+        }).collect();
+        assert_eq!(server.connection_receive(&connection_one, bytes.clone()), Ok(()));
+        assert_eq!(server.connection_receive(&connection_two, bytes), Ok(()));
+        assert_eq!(server.connection_tracked_entities(&connection_one), Some(1));
+        assert_eq!(server.connection_tracked_entities(&connection_two), Some(0));
     }
-}
 
-fn create_client(send_timeout: usize) -> (Client<ClientEntity, TestUserData, ClientRegistry>, Rc<Mutex<TestStat>>) {
-    let stats: Rc<Mutex<TestStat>> = Rc::new(Mutex::new(TestStat::default()));
-    let client = Client::<ClientEntity, TestUserData, ClientRegistry>::new(Config {
-        handle_timeout_ticks: send_timeout,
-        minimum_update_interval: None
+    // Confirmations for the entity are unaffected on the connection that
+    // knows it and ignored on the one which does not
+    server.connection_receive(&connection_one, vec![1, 0]).unwrap();
+    server.connection_receive(&connection_two, vec![1, 0]).unwrap();
+    assert_eq!(server.connection_entity_state(&connection_one, &entity), Some(RemoteState::Create));
+    assert_eq!(server.connection_entity_state(&connection_two, &entity), Some(RemoteState::Unknown));
+    assert_eq!(server.connection_tracked_entities(&connection_two), Some(0));
+    assert_eq!(server.check_invariants(), Ok(()));
 
-    }, ClientRegistry {
-        stats: stats.clone()
-    });
-    (client, stats)
 }
 
 #[test]
-fn test_client_create() {
-    create_client(5);
-}
+fn test_server_connection_remove_mixed_states() {
 
-#[test]
-fn test_client_debug() {
+    let mut server = Server::<ServerEntity, TestUserData>::new(config(3));
+    let stats: Rc<Mutex<TestStat>> = Rc::new(Mutex::new(TestStat::default()));
+    let hidden_stats: Rc<Mutex<TestStat>> = Rc::new(Mutex::new(TestStat::default()));
+    hidden_stats.lock().unwrap().forget_mode = Some(ForgetMode::Hide);
 
-    let (mut client, _) = create_client(5);
-    assert_eq!(format!("{:?}", client), "EntityClient (0 entity(s))");
-    assert_client_send!(client, vec![0, 0, 3, 1, 255, 128, 255], vec![1, 0]).expect("Client accepts SendCreateToClient packet.");
-    assert_eq!(format!("{:?}", client), "EntityClient (1 entity(s))");
+    let connection_one = server.connection_add_with(||TestUserData::new(255)).unwrap();
+    let connection_two = server.connection_add_with(||TestUserData::new(255)).unwrap();
+    server.entity_create_with(|| Box::new(TestEntity::new(true, stats.clone()))).unwrap();
+    server.entity_create_with(|| Box::new(TestEntity::new(true, stats.clone()))).unwrap();
+    server.entity_create_with(|| Box::new(TestEntity::new(true, stats.clone()))).unwrap();
+    let entity_three = server.entity_create_with(|| Box::new(TestEntity::new(true, stats.clone()))).unwrap();
+    let entity_four = server.entity_create_with(|| Box::new(TestEntity::new(true, hidden_stats.clone()))).unwrap();
+    assert_eq!(server.connection_send(&connection_one, 4096).unwrap().len(), 1);
+    assert_eq!(server.connection_send(&connection_two, 4096).unwrap().len(), 1);
+
+    // Connection one: 0 Unknown, 1 Create, 2 Update, 3 Destroy, 4 Hidden
+    server.connection_receive(&connection_one, vec![1, 1, 1, 2, 1, 3, 1, 4]).unwrap();
+    server.connection_receive(&connection_one, vec![2, 2, 2, 3, 2, 4]).unwrap();
+    server.connection_receive(&connection_two, vec![1, 3, 2, 3]).unwrap();
+    server.entity_destroy(entity_three).unwrap();
+    hidden_stats.lock().unwrap().filter_for_connection = true;
+    assert_eq!(server.connection_send(&connection_one, 4096), Ok(vec![vec![0, 0, 3, 0, 1, 255, 128, 255, 1, 1, 4, 3, 2, 4]]));
+    server.connection_receive(&connection_one, vec![4, 4]).unwrap();
+    assert_eq!(server.connection_entity_state(&connection_one, &entity_four), Some(RemoteState::Hidden));
+
+    // Connection two confirms the destruction, connection one never does
+    assert_eq!(server.connection_send(&connection_two, 4096).unwrap().len(), 1);
+    server.connection_receive(&connection_two, vec![4, 3]).unwrap();
+    assert_eq!(server.connection_send(&connection_two, 4096), Ok(vec![vec![
+        0, 0, 3, 0, 1, 255, 128, 255,
+        0, 1, 3, 0, 1, 255, 128, 255,
+        0, 2, 3, 0, 1, 255, 128, 255,
+        4, 3,
+        0, 4, 3, 0, 1, 255, 128, 255
+    ]]));
+    assert_eq!(server.update_entities_with(|_, _| {}).handles_dropped, 0);
+
+    // Removing connection one releases the destroyed entity right away and
+    // tells its client about all entities it knows, including hidden ones
+    let (_, packets) = server.connection_remove_graceful(connection_one, 4096).unwrap();
+    assert_eq!(packets, vec![vec![4, 0, 4, 1, 4, 2, 4, 3, 4, 4]]);
+    assert_eq!(server.check_invariants(), Ok(()));
+    assert_eq!(server.update_entities_with(|_, _| {}).handles_dropped, 1);
+    assert_eq!(server.check_invariants(), Ok(()));
+
+    // The remaining connection still tells its client about all alive entities
+    let (_, packets) = server.connection_remove_graceful(connection_two, 4096).unwrap();
+    assert_eq!(packets, vec![vec![4, 0, 4, 1, 4, 2, 4, 4]]);
+    assert_eq!(server.check_invariants(), Ok(()));
 
 }
 
 #[test]
-fn test_client_with_entities() {
+fn test_server_connection_remove_graceful() {
 
-    let (mut client, _) = create_client(3);
-
-    assert_client_send!(client, vec![0, 0, 3, 1, 255, 128, 255], vec![1, 0]).expect("Client accepts SendCreateToClient packet.");
-    assert_client_send!(client, vec![0, 1, 3, 1, 255, 128, 255], vec![1, 0, 1, 1]).expect("Client accepts SendCreateToClient packet.");
-    assert_client_send!(client, vec![0, 2, 3, 1, 255, 128, 255], vec![1, 0, 1, 1, 1, 2]).expect("Client accepts SendCreateToClient packet.");
-
-    let mut count = 0;
-    client.with_entities(|_, entity| { entity.client_update(&mut count); });
-    assert_eq!(count, 3);
+    let mut server = Server::<ServerEntity, TestUserData>::new(config(5));
+    let stats: Rc<Mutex<TestStat>> = Rc::new(Mutex::new(TestStat::default()));
 
-    assert_client_send!(client, vec![4, 1], vec![1, 0, 4, 1, 1, 2]).expect("Client accepts SendDestroyToClient packet.");
+    let connection_one = server.connection_add_with(||TestUserData::new(96)).unwrap();
+    let _ = server.entity_create_with(|| Box::new(TestEntity::new(true, stats.clone()))).unwrap();
+    let _ = server.entity_create_with(|| Box::new(TestEntity::new(true, stats.clone()))).unwrap();
 
-    let mut count = 0;
-    client.with_entities(|_, entity| { entity.client_update(&mut count); });
-    assert_eq!(count, 2);
+    // Entity 0 is confirmed, entity 1 was only send
+    assert_server_send!(server, connection_one, vec![], vec![0, 0, 3, 0, 1, 255, 128, 96, 0, 1, 3, 0, 1, 255, 128, 96]).expect("Server sents SendCreateToClient packets to Client.");
+    assert_server_send!(server, connection_one, vec![1, 0], vec![1, 0, 0, 1, 3, 0, 1, 255, 128, 96]).expect("Server accepts ConfirmCreateToServer from Client.");
 
-}
+    // Connections which never received the entities get no destroy packets
+    let connection_two = server.connection_add_with(||TestUserData::new(128)).unwrap();
+    let (user_data, packets) = server.connection_remove_graceful(connection_two, 256).unwrap();
+    assert_eq!(user_data, TestUserData::new(128));
+    assert!(packets.is_empty());
 
-#[test]
-fn test_client_map_entities() {
+    // Entities which were created after the last send are unknown to the client
+    let _ = server.entity_create_with(|| Box::new(TestEntity::new(true, stats.clone()))).unwrap();
 
-    let (mut client, _) = create_client(3);
+    let (user_data, packets) = server.connection_remove_graceful(connection_one, 256).unwrap();
+    assert_eq!(user_data, TestUserData::new(96));
+    assert_eq!(packets, vec![vec![4, 0, 4, 1]]);
 
-    assert_client_send!(client, vec![0, 0, 3, 1, 255, 128, 255], vec![1, 0]).expect("Client accepts SendCreateToClient packet.");
-    assert_client_send!(client, vec![0, 1, 3, 1, 255, 128, 255], vec![1, 0, 1, 1]).expect("Client accepts SendCreateToClient packet.");
-    assert_client_send!(client, vec![0, 2, 3, 1, 255, 128, 255], vec![1, 0, 1, 1, 1, 2]).expect("Client accepts SendCreateToClient packet.");
+    // Foreign tokens are rejected
+    let mut server_two = Server::<ServerEntity, TestUserData>::new(config(5));
+    let connection_three = server_two.connection_add_with(||TestUserData::new(0)).unwrap();
+    assert!(server.connection_remove_graceful(connection_three, 256).is_err());
 
-    let mut count = 0;
-    let results = client.map_entities::<usize, _>(|_, entity| { entity.client_update(&mut count); count });
-    assert_eq!(results, [1, 2, 3]);
-    assert_eq!(count, 3);
+}
 
-    assert_client_send!(client, vec![4, 1], vec![1, 0, 4, 1, 1, 2]).expect("Client accepts SendDestroyToClient packet.");
+#[test]
+fn test_server_connection_resync() {
 
-    let mut count = 0;
-    let results = client.map_entities::<usize, _>(|_, entity| { entity.client_update(&mut count); count });
-    assert_eq!(results, [1, 2]);
-    assert_eq!(count, 2);
+    let mut server = Server::<ServerEntity, TestUserData>::new(config(3));
+    let mut server_two = Server::<ServerEntity, TestUserData>::new(config(3));
+    let stats: Rc<Mutex<TestStat>> = Rc::new(Mutex::new(TestStat::default()));
 
-}
+    let connection_one = server.connection_add_with(||TestUserData::new(255)).unwrap();
+    let entity_one = server.entity_create_with(|| Box::new(TestEntity::new(true, stats.clone()))).unwrap();
+    server.entity_create_with(|| Box::new(TestEntity::new(true, stats.clone()))).unwrap();
 
-#[test]
-fn test_client_reset() {
+    assert_server_send!(server, connection_one, vec![], vec![
+        0, 0, 3, 0, 1, 255, 128, 255,
+        0, 1, 3, 0, 1, 255, 128, 255
 
-    let (mut client, stats) = create_client(3);
+    ]).expect("Server sents SendCreateToClient packets to Client.");
 
-    assert_client_send!(client, vec![0, 0, 3, 1, 255, 128, 255], vec![1, 0]).expect("Client accepts SendCreateToClient packet.");
-    assert_client_send!(client, vec![0, 1, 3, 1, 255, 128, 255], vec![1, 0, 1, 1]).expect("Client accepts SendCreateToClient packet.");
-    assert_client_send!(client, vec![0, 2, 3, 1, 255, 128, 255], vec![1, 0, 1, 1, 1, 2]).expect("Client accepts SendCreateToClient packet.");
+    assert_server_send!(server, connection_one, vec![1, 0, 1, 1], vec![1, 0, 1, 1]).expect("Server accepts ConfirmCreateToServer from Client.");
+    assert_server_send_empty!(server, connection_one, vec![2, 0, 2, 1]).expect("Server accepts AcceptServerUpdate from Client.");
+    assert_eq!(server.connection_entity_state(&connection_one, &entity_one), Some(RemoteState::Update));
 
-    let mut count = 0;
-    client.update_entities_with(|_, entity| { entity.client_update(&mut count); });
-    assert_eq!(count, 3);
+    // All entities are created from scratch after a resync
+    assert_eq!(server.connection_resync(&connection_one), Ok(()));
+    assert_eq!(server.connection_entity_state(&connection_one, &entity_one), Some(RemoteState::Accept));
+    assert_server_send!(server, connection_one, vec![], vec![
+        0, 0, 3, 0, 1, 255, 128, 255,
+        0, 1, 3, 0, 1, 255, 128, 255
 
-    assert_stats!(stats, drop_calls, 0);
-    assert_stats!(stats, destroyed_calls, 0);
-    client.reset();
-    assert_stats!(stats, drop_calls, 3);
-    assert_stats!(stats, destroyed_calls, 0);
+    ]).expect("Server re-sends SendCreateToClient packets to Client after resync.");
 
-    let mut count = 0;
-    client.update_entities_with(|_, entity| { entity.client_update(&mut count); });
-    assert_eq!(count, 0);
+    // Foreign tokens
+    let connection_two = server_two.connection_add_with(||TestUserData::new(0)).unwrap();
+    assert_eq!(server.connection_resync(&connection_two), Err(ServerError::UnknownSenderToken));
 
 }
 
 #[test]
-fn test_client_exhaustive_create_entity() {
-
-    let (mut client, _) = create_client(3);
-    let mut response = Vec::new();
+fn test_server_entity_set_owner() {
 
-    for e in 0..256 {
-        let i = e as u8;
-        response.push(1);
-        response.push(i);
-        assert_client_send!(client, vec![0, i, 3, 1, 255, 128, 255], response).expect("Client accepts SendCreateToClient packet.");
-    }
+    let mut server = Server::<ServerEntity, TestUserData>::new(config(3));
+    let stats: Rc<Mutex<TestStat>> = Rc::new(Mutex::new(TestStat::default()));
 
-    let mut count = 0;
-    client.update_entities_with(|_, entity| { entity.client_update(&mut count); });
-    assert_eq!(count, 256);
+    let connection_one = server.connection_add_with(||TestUserData::new(1)).unwrap();
+    let connection_two = server.connection_add_with(||TestUserData::new(2)).unwrap();
+    let entity = server.entity_create_with(|| Box::new(TestEntity::new(true, stats.clone()))).unwrap();
+    assert_eq!(server.entity_owner(&entity), None);
+
+    // Only the owner receives the local flag
+    server.entity_set_owner(&entity, Some(&connection_one));
+    assert_eq!(server.entity_owner(&entity), Some(connection_one.index()));
+    assert_stats!(stats, owner_changes, 1);
+    assert_server_send!(server, connection_one, vec![], vec![0, 0, 3, 0, 1, 255, 129, 1]).expect("Server sends local flag to owning Client.");
+    assert_server_send!(server, connection_two, vec![], vec![0, 0, 3, 0, 1, 255, 128, 2]).expect("Server sends no local flag to other Client.");
+
+    // Transferring ownership moves the flag to the other connection
+    server.entity_set_owner(&entity, Some(&connection_two));
+    assert_eq!(server.entity_owner(&entity), Some(connection_two.index()));
+    assert_stats!(stats, owner_changes, 2);
+    assert_server_send!(server, connection_one, vec![], vec![0, 0, 3, 0, 1, 255, 128, 1]).expect("Server no longer sends local flag to previous owner.");
+    assert_server_send!(server, connection_two, vec![], vec![0, 0, 3, 0, 1, 255, 129, 2]).expect("Server sends local flag to new owner.");
+
+    // Setting the same owner again is not a change
+    server.entity_set_owner(&entity, Some(&connection_two));
+    assert_stats!(stats, owner_changes, 2);
+
+    // Foreign connections are ignored
+    let mut server_two = Server::<ServerEntity, TestUserData>::new(config(3));
+    let connection_foreign = server_two.connection_add_with(||TestUserData::new(0)).unwrap();
+    server.entity_set_owner(&entity, Some(&connection_foreign));
+    assert_eq!(server.entity_owner(&entity), Some(connection_two.index()));
+
+    // Removing the owning connection clears the owner
+    assert!(server.connection_remove(connection_two).is_ok());
+    assert_eq!(server.entity_owner(&entity), None);
+    assert_stats!(stats, owner_changes, 3);
+    assert_server_send!(server, connection_one, vec![], vec![0, 0, 3, 0, 1, 255, 128, 1]).expect("Server sends no local flag without owner.");
 
 }
 
 #[test]
-fn test_client_connection_send_packet_split() {
+fn test_server_entities_owned_where() {
 
-    let (mut client, _) = create_client(3);
+    let mut server = Server::<ServerEntity, TestUserData>::new(config(3));
+    let stats: Rc<Mutex<TestStat>> = Rc::new(Mutex::new(TestStat::default()));
 
-    assert_client_send!(client, vec![0, 0, 3, 1, 255, 128, 255], vec![1, 0]).expect("Client accepts SendCreateToClient packet.");
-    assert_client_send!(client, vec![0, 1, 3, 1, 255, 128, 255], vec![1, 0, 1, 1]).expect("Client accepts SendCreateToClient packet.");
-    assert_client_send!(client, vec![0, 2, 3, 1, 255, 128, 255], vec![1, 0, 1, 1, 1, 2]).expect("Client accepts SendCreateToClient packet.");
+    // Two teams with one connection each
+    let red = server.connection_add_with(||TestUserData::new(1)).unwrap();
+    let blue = server.connection_add_with(||TestUserData::new(2)).unwrap();
 
-    assert_client_send_packets!(client, 6, vec![], vec![
-        vec![1, 0, 1, 1, 1, 2]
+    let entity_one = server.entity_create_with(|| Box::new(TestEntity::new(true, stats.clone()))).unwrap();
+    let entity_two = server.entity_create_with(|| Box::new(TestEntity::new(true, stats.clone()))).unwrap();
+    let entity_three = server.entity_create_with(|| Box::new(TestEntity::new(true, stats.clone()))).unwrap();
+    let entity_four = server.entity_create_with(|| Box::new(TestEntity::new(true, stats.clone()))).unwrap();
 
-    ]).expect("Client does split packets across entity state boundaries.");
+    server.entity_set_owner(&entity_one, Some(&red));
+    server.entity_set_owner(&entity_two, Some(&blue));
+    server.entity_set_owner(&entity_three, Some(&red));
 
-    assert_client_send_packets!(client, 4, vec![], vec![
-        vec![1, 0, 1, 1],
-        vec![1, 2]
+    // Only entities of the matching team are selected
+    assert_eq!(server.entities_owned_where(vec![&red, &blue], |team| *team == TestUserData::new(1)), vec![&entity_one, &entity_three]);
+    assert_eq!(server.entities_owned_where(vec![&red, &blue], |team| *team == TestUserData::new(2)), vec![&entity_two]);
+    assert!(server.entities_owned_where(vec![&red, &blue], |team| *team == TestUserData::new(3)).is_empty());
 
-    ]).expect("Client does split packets across entity state boundaries.");
+    // Connections which are not passed in are not considered
+    assert!(server.entities_owned_where(vec![&blue], |team| *team == TestUserData::new(1)).is_empty());
 
-    assert_client_send_packets!(client, 1, vec![], vec![
-        vec![1, 0],
-        vec![1, 1],
-        vec![1, 2]
+    // Foreign connections are ignored
+    let mut server_two = Server::<ServerEntity, TestUserData>::new(config(3));
+    let foreign = server_two.connection_add_with(||TestUserData::new(1)).unwrap();
+    assert!(server.entities_owned_where(vec![&foreign], |team| *team == TestUserData::new(1)).is_empty());
 
-    ]).expect("Client does split packets across entity state boundaries.");
+    // Destroyed entities are no longer selected
+    server.entity_destroy(entity_one).unwrap();
+    assert_eq!(server.entities_owned_where(vec![&red, &blue], |team| *team == TestUserData::new(1)), vec![&entity_three]);
+    assert_eq!(server.entity_owner(&entity_four), None);
 
 }
 
 #[test]
-fn test_client_ignore_invalid_packets() {
-
-    let (mut client, stats) = create_client(5);
+fn test_server_entity_snapshot_bytes() {
 
-    assert_client_send_empty!(client, vec![]).expect("Client ignores empty server packets");
-    assert_client_send_empty!(client, vec![0]).expect("Client ignores server packets with length 1");
-    assert_client_send_empty!(client, vec![0, 0]).expect("Client ignores incomplete SendCreateToClient packet");
-    assert_client_send_empty!(client, vec![0, 0, 1]).expect("Client ignores incomplete SendCreateToClient packet");
-    assert_client_send_empty!(client, vec![0, 0, 1, 2]).expect("Client ignores incomplete SendCreateToClient packet");
-    assert_eq!(assert_client_send_empty!(client, vec![0, 0, 10, 2, 5]), Err(ClientError::RemainingPacketData(vec![5])));
+    let mut server = Server::<ServerEntity, TestUserData>::new(config(3));
+    let stats: Rc<Mutex<TestStat>> = Rc::new(Mutex::new(TestStat::default()));
+    stats.lock().unwrap().snapshot_bytes_value = Some(vec![1, 2, 3, 4]);
 
-    assert_client_send_empty!(client, vec![3, 2]).expect("Client ignores SendUpdateToClient packet for non existent entity");
-    assert_client_send_empty!(client, vec![3, 2, 1]).expect("Client ignores SendUpdateToClient packet for non existent entity");
-    assert_client_send_empty!(client, vec![3, 2, 1, 2]).expect("Client ignores SendUpdateToClient packet for non existent entity");
-    assert_eq!(assert_client_send_empty!(client, vec![3, 2, 10, 2, 5]), Err(ClientError::RemainingPacketData(vec![5])));
+    let connection_one = server.connection_add_with(||TestUserData::new(255)).unwrap();
+    server.entity_create_with(|| Box::new(TestEntity::new(true, stats.clone()))).unwrap();
 
-    assert_client_send_empty!(client, vec![4, 2]).expect("Client ignores SendDestroyToClient packet for non existent entity");
-    assert_client_send_empty!(client, vec![5, 2]).expect("Client ignores SendForgetToClient packet for non existent entity");
-    assert_client_send_empty!(client, vec![1, 2]).expect("Client ignores ConfirmClientCreate packet for non existent entity");
-    assert_client_send_empty!(client, vec![1, 2, 0]).expect("Client ignores incomplete secondary packets");
+    // Creations carry the snapshot instead of to_bytes()
+    assert_server_send!(server, connection_one, vec![], vec![0, 0, 4, 0, 1, 1, 2, 3, 4]).expect("Server sents snapshot in SendCreateToClient packet to Client.");
+    assert_server_send!(server, connection_one, vec![1, 0], vec![1, 0]).expect("Server accepts ConfirmCreateToServer from Client.");
 
-    assert_eq!(assert_client_send_empty!(client, vec![6, 2]), Err(ClientError::InvalidPacketData(vec![6, 2])));
-    assert_eq!(assert_client_send_empty!(client, vec![255, 2]), Err(ClientError::InvalidPacketData(vec![255, 2])));
+    // Updates are still incremental
+    stats.lock().unwrap().part_bytes_value = Some(vec![5]);
+    assert_server_send!(server, connection_one, vec![2, 0], vec![3, 0, 1, 5]).expect("Server sends part_bytes() in SendUpdateToClient packet to Client.");
 
-    assert_stats!(stats, part_calls, 0);
-    assert_stats!(stats, merge_calls, 0);
-    assert_stats!(stats, drop_calls, 0);
-    assert_stats!(stats, registry_calls, 0);
+    // Resyncs re-create the entity from a fresh snapshot
+    stats.lock().unwrap().snapshot_bytes_value = Some(vec![1, 2, 3, 4, 5]);
+    assert_eq!(server.connection_resync(&connection_one), Ok(()));
+    assert_server_send!(server, connection_one, vec![], vec![0, 0, 5, 0, 1, 1, 2, 3, 4, 5]).expect("Server re-sends snapshot in SendCreateToClient packet to Client after resync.");
 
 }
 
 #[test]
-fn test_client_entity_flow() {
+fn test_server_connection_suspend_resume() {
 
-    let (mut client, stats) = create_client(3);
+    let mut server = Server::<ServerEntity, TestUserData>::new(config(3));
+    let stats: Rc<Mutex<TestStat>> = Rc::new(Mutex::new(TestStat::default()));
 
-    // SendCreateToClient -> ConfirmCreateToServer
-    assert_client_send!(client, vec![0, 0, 3, 1, 255, 128, 255], vec![1, 0]).expect("Client accepts SendCreateToClient packet.");
-    assert_stats!(stats, registry_calls, 1);
-    assert_stats!(stats, created_calls, 0);
-    assert_stats!(stats, destroyed_calls, 0);
+    let connection_one = server.connection_add_with(||TestUserData::new(255)).unwrap();
+    let entity = server.entity_create_with(|| Box::new(TestEntity::new(true, stats.clone()))).unwrap();
 
-    let mut count = 0;
-    client.update_entities_with(|_, entity| {
-        entity.client_update(&mut count);
-    });
-    assert_eq!(count, 1);
+    assert_server_send!(server, connection_one, vec![], vec![0, 0, 3, 0, 1, 255, 128, 255]).expect("Server sents SendCreateToClient packet to Client.");
+    assert_server_send!(server, connection_one, vec![1, 0], vec![1, 0]).expect("Server accepts ConfirmCreateToServer from Client.");
+    assert_server_send_empty!(server, connection_one, vec![2, 0]).expect("Server accepts AcceptServerUpdate from Client.");
 
-    // ConfirmClientCreate -> AcceptServerUpdate
-    assert_client_send!(client, vec![1, 0], vec![2, 0]).expect("Client accepts ConfirmClientCreate packet.");
-    assert_stats!(stats, created_calls, 1);
-    assert_stats!(stats, destroyed_calls, 0);
+    // Suspended connections can neither send nor receive
+    server.connection_suspend(&connection_one).unwrap();
+    assert_eq!(server.connection_send(&connection_one, 256), Err(ServerError::ConnectionSuspended));
+    assert_eq!(server.connection_receive(&connection_one, vec![]), Err(ServerError::ConnectionSuspended));
+    assert_eq!(server.active_connection_indices().count(), 0);
+    assert_eq!(server.connection_tokens_len(), 1);
 
-    // SendUpdateToClient -> None
-    assert_client_send_empty!(client, vec![3, 0, 0]).expect("Client accepts SendUpdateToClient packet without data bytes.");
-    assert_stats!(stats, part_calls, 1);
-    assert_stats!(stats, merge_calls, 0);
-    assert_stats_clone!(stats, merge_bytes_value, vec![]);
-    assert_stats!(stats, created_calls, 1);
-    assert_stats!(stats, destroyed_calls, 0);
+    // Entities created in the meantime are send after resuming
+    server.entity_create_with(|| Box::new(TestEntity::new(true, stats.clone()))).unwrap();
+    server.update_entities_with(|_, _| {});
+    server.connection_resume(&connection_one).unwrap();
+    assert_eq!(server.active_connection_indices().collect::<Vec<usize>>(), vec![0]);
 
-    stats.lock().unwrap().merge_bytes_value = vec![255, 192, 96];
-    assert_client_send_empty!(client, vec![3, 0, 3, 255, 192, 96]).expect("Client accepts SendUpdateToClient packet with data bytes.");
-    assert_stats!(stats, part_calls, 2);
-    assert_stats!(stats, merge_calls, 1);
-    assert_stats_clone!(stats, merge_bytes_value, vec![255, 192, 96]);
-    assert_stats!(stats, created_calls, 1);
-    assert_stats!(stats, destroyed_calls, 0);
+    // Already known entities are not created again
+    assert_server_send!(server, connection_one, vec![], vec![0, 1, 3, 0, 1, 255, 128, 255]).expect("Server only sends SendCreateToClient for the new entity.");
+    assert_eq!(server.connection_entity_state(&connection_one, &entity), Some(RemoteState::Update));
 
-    // None -> SendUpdateToServer
-    stats.lock().unwrap().part_bytes_value = Some(vec![]);
-    assert_stats!(stats, part_calls, 2);
-    assert_client_send!(client, vec![], vec![3, 0, 0]).expect("Client sends SendUpdateToServer packet without data bytes.");
-    assert_stats!(stats, created_calls, 1);
-    assert_stats!(stats, destroyed_calls, 0);
+    stats.lock().unwrap().part_bytes_value = Some(vec![1]);
+    assert_server_send!(server, connection_one, vec![1, 1], vec![3, 0, 1, 1, 1, 1]).expect("Server resumes SendUpdateToClient for the known entity.");
 
-    stats.lock().unwrap().part_bytes_value = Some(vec![96, 255, 192]);
-    assert_stats!(stats, part_calls, 3);
-    assert_client_send!(client, vec![], vec![3, 0, 3, 96, 255, 192]).expect("Client sends SendUpdateToServer packet with data bytes.");
-    assert_stats!(stats, created_calls, 1);
-    assert_stats!(stats, destroyed_calls, 0);
+}
 
-    // SendDestroyToClient -> ConfirmDestroyToServer
-    assert_client_send!(client, vec![4, 0], vec![4, 0]).expect("Client accepts SendDestroyToClient packet for existing entity.");
-    assert_stats!(stats, drop_calls, 1); // Entity is dropped directly
-    assert_stats!(stats, created_calls, 1);
-    assert_stats!(stats, destroyed_calls, 1);
+#[test]
+fn test_server_connection_suspend_drop_destroyed() {
 
-    let mut count = 0;
-    client.update_entities_with(|_, entity| {
-        entity.client_update(&mut count);
-    });
-    assert_eq!(count, 0);
+    let mut server = Server::<ServerEntity, TestUserData>::new(config(3));
+    let stats: Rc<Mutex<TestStat>> = Rc::new(Mutex::new(TestStat::default()));
 
-    // None -> ConfirmDestroyToServer
-    assert_client_send!(client, vec![], vec![4, 0]).expect("Client sent ConfirmDestroyToServer packet for destroy entity with pending handle.");
-    assert_stats!(stats, drop_calls, 1);
+    let connection_one = server.connection_add_with(||TestUserData::new(255)).unwrap();
+    let entity = server.entity_create_with(|| Box::new(TestEntity::new(true, stats.clone()))).unwrap();
 
-    // ConfirmClientCreate -> ConfirmDestroyToServer
-    assert_client_send!(client, vec![1, 0], vec![4, 0]).expect("Client ignores ConfirmClientCreate packet for destroyed entity.");
-    assert_stats!(stats, created_calls, 1);
-    assert_stats!(stats, destroyed_calls, 1);
+    assert_server_send!(server, connection_one, vec![], vec![0, 0, 3, 0, 1, 255, 128, 255]).expect("Server sents SendCreateToClient packet to Client.");
+    assert_server_send!(server, connection_one, vec![1, 0], vec![1, 0]).expect("Server accepts ConfirmCreateToServer from Client.");
 
-    // SendUpdateToClient -> ConfirmCreateToServer
-    assert_client_send!(client, vec![3, 0, 0, 1], vec![4, 0]).expect("Client ignores SendUpdateToClient packet for destroyed entity.");
+    // Destroyed entities do not wait for suspended connections
+    server.connection_suspend(&connection_one).unwrap();
+    server.entity_destroy(entity).unwrap();
+    assert_eq!(server.update_entities_with(|_, _| {}).handles_dropped, 1);
 
-    // After a total of 3 update calls since the entity destruction the handle should be removed by
-    // the client
-    client.update_entities_with(|_, _| {});
-    assert_client_send!(client, vec![], vec![4, 0]).expect("Client keeps handle when send timeout expires after 3 further update calls.");
+    // The client is told to destroy the entity once resumed
+    server.connection_resume(&connection_one).unwrap();
+    assert_server_send!(server, connection_one, vec![], vec![4, 0]).expect("Server sends SendDestroyToClient for the dropped entity.");
+    assert_server_send_empty!(server, connection_one, vec![]).expect("Server sends SendDestroyToClient only once.");
 
-    client.update_entities_with(|_, _| {});
-    assert_client_send_empty!(client, vec![]).expect("Client drops handle when send timeout expires after 3 further update calls.");
+    // Removing a suspended connection keeps the counts intact
+    let connection_two = server.connection_add_with(||TestUserData::new(128)).unwrap();
+    server.entity_create_with(|| Box::new(TestEntity::new(true, stats.clone()))).unwrap();
+    server.connection_suspend(&connection_two).unwrap();
+    assert!(server.connection_remove(connection_two).is_ok());
+    assert_eq!(server.check_invariants(), Ok(()));
 
-    // SendCreateToClient -> ConfirmCreateToServer
-    assert_client_send!(client, vec![0, 0, 3, 1, 255, 128, 255], vec![1, 0]).expect("Client accepts SendCreateToClient packet for dropped entity handle index.");
-    assert_stats!(stats, registry_calls, 2);
-    assert_stats!(stats, created_calls, 1);
-    assert_stats!(stats, destroyed_calls, 1);
+}
 
-    let mut count = 0;
-    client.update_entities_with(|_, entity| { entity.client_update(&mut count); });
-    assert_eq!(count, 1);
+#[test]
+fn test_server_connection_force_keepalive() {
 
-    // TODO test 0 value timeouts
+    let mut server = Server::<ServerEntity, TestUserData>::new(config(3));
+    let stats: Rc<Mutex<TestStat>> = Rc::new(Mutex::new(TestStat::default()));
+    let connection_one = server.connection_add_with(||TestUserData::new(255)).unwrap();
 
-}
+    // Without confirmed entities an empty packet is send
+    server.connection_force_keepalive(&connection_one).unwrap();
+    assert_eq!(server.connection_send(&connection_one, 256).unwrap(), vec![vec![]]);
+    assert_server_send_empty!(server, connection_one, vec![]).expect("Server only sends a single keepalive.");
 
-#[test]
-fn test_client_entity_ignore_unknown_kind_create() {
+    server.entity_create_with(|| Box::new(TestEntity::new(true, stats.clone()))).unwrap();
+    assert_server_send!(server, connection_one, vec![], vec![0, 0, 3, 0, 1, 255, 128, 255]).expect("Server sents SendCreateToClient packet to Client.");
+    assert_server_send!(server, connection_one, vec![1, 0], vec![1, 0]).expect("Server accepts ConfirmCreateToServer from Client.");
 
-    let (mut client, stats) = create_client(3);
+    assert_server_send_empty!(server, connection_one, vec![2, 0]).expect("Server accepts AcceptServerUpdate from Client.");
 
-    // SendCreateToClient -> None
-    assert_client_send_empty!(client, vec![0, 0, 3, 3, 255, 128, 255]).expect("Client ignores SendCreateToClient with unknown entity kind.");
-    assert_stats!(stats, registry_calls, 0);
+    // Other packets already serve as a keepalive
+    server.connection_force_keepalive(&connection_one).unwrap();
+    stats.lock().unwrap().part_bytes_value = Some(vec![1]);
+    assert_server_send!(server, connection_one, vec![], vec![3, 0, 1, 1]).expect("Server sends SendUpdateToClient instead of keepalive.");
 
-    let mut count = 0;
-    client.update_entities_with(|_, entity| { entity.client_update(&mut count); });
-    assert_eq!(count, 0);
+    // Confirmed entities receive an empty update
+    server.connection_force_keepalive(&connection_one).unwrap();
+    assert_server_send!(server, connection_one, vec![], vec![3, 0, 0]).expect("Server sends empty SendUpdateToClient as keepalive.");
+    assert_server_send_empty!(server, connection_one, vec![]).expect("Server only sends a single keepalive.");
 
 }
 
 #[test]
-fn test_client_entity_create_replace() {
+fn test_server_connect_remove_drop_destroy_entity() {
 
-    let (mut client, stats) = create_client(3);
+    let mut server = Server::<ServerEntity, TestUserData>::new(config(5));
+    let stats: Rc<Mutex<TestStat>> = Rc::new(Mutex::new(TestStat::default()));
 
-    // SendCreateToClient -> ConfirmCreateToServer
-    assert_client_send!(client, vec![0, 0, 3, 1, 255, 128, 255], vec![1, 0]).expect("Client accepts SendCreateToClient packet.");
+    let entity = server.entity_create_with(|| Box::new(TestEntity::new(true, stats.clone()))).unwrap();
+
+    let connection_one = server.connection_add_with(||TestUserData::new(128)).unwrap();
+    let connection_two = server.connection_add_with(||TestUserData::new(255)).unwrap();
+
+    assert_server_send!(server, connection_one, vec![], vec![0, 0, 3, 0, 1, 255, 128, 128]).expect("Server sents SendCreateToClient packet to Client.");
+    assert_server_send!(server, connection_two, vec![], vec![0, 0, 3, 0, 1, 255, 128, 255]).expect("Server sents SendCreateToClient packet to Client.");
+
+    let mut count = 0;
+    server.update_entities_with(|_, entity| { entity.server_update(&mut count); });
+    assert_eq!(count, 1);
+
+    server.entity_destroy(entity).unwrap();
+    assert_stats!(stats, drop_calls, 0);
+
+    assert_server_send!(server, connection_one, vec![], vec![4, 0]).expect("Server sents SendDestroyToClient packet to Client for destroyed entity.");
+    assert_server_send!(server, connection_two, vec![], vec![4, 0]).expect("Server sents SendDestroyToClient packet to Client for destroyed entity.");
+
+    let mut count = 0;
+    server.update_entities_with(|_, entity| { entity.server_update(&mut count); });
+    assert_eq!(count, 0);
+
+    assert_server_send!(server, connection_one, vec![], vec![4, 0]).expect("Server still sends SendDestroyToClient to Client after next update call.");
+    assert_server_send!(server, connection_two, vec![], vec![4, 0]).expect("Server still sends SendDestroyToClient to Client after next update call.");
+
+    assert_server_send!(server, connection_one, vec![4, 0], vec![4, 0]).expect("Server ignores ConfirmDestroyToServer and still send SendDestroyToClient to the client.");
+    assert_server_send!(server, connection_two, vec![], vec![4, 0]).expect("Server still sends SendDestroyToClient for other clients.");
+
+    // ConfirmDestroyToServer should have been ignored until now
+    assert_server_send!(server, connection_one, vec![4, 0], vec![4, 0]).expect("Server ignores ConfirmDestroyToServer until client sends ConfirmCreateToServer.");
+    assert_server_send!(server, connection_two, vec![4, 0], vec![4, 0]).expect("Server ignores ConfirmDestroyToServer until client sends ConfirmCreateToServer.");
+
+    // Send ConfirmCreateToServer
+    assert_server_send!(server, connection_one, vec![1, 0], vec![4, 0]).expect("Server accepts ConfirmCreateToServer for destroyed entity.");
+    assert_server_send!(server, connection_two, vec![1, 0], vec![4, 0]).expect("Server accepts ConfirmCreateToServer for destroyed entity.");
+
+    // ConfirmDestroyToServer should not be accepted
+    assert_server_send!(server, connection_one, vec![4, 0], vec![4, 0]).expect("Server accept ConfirmDestroyToServer and still send SendDestroyToClient to the client.");
+    assert_server_send!(server, connection_two, vec![], vec![4, 0]).expect("Server still sends SendDestroyToClient for other clients.");
+    assert_server_send_empty!(server, connection_two, vec![4, 0]).expect("Server accepts ConfirmDestroyToServer and sends so further SendDestroyToClient to the client.");
+
+    assert_server_send_empty!(server, connection_one, vec![]).expect("Server sends no further packets after handle was dropped.");
+    assert_server_send_empty!(server, connection_two, vec![]).expect("Server sends no further packets after handle was dropped.");
+
+    // Entity should be dropped along with its handle
+    assert_stats!(stats, drop_calls, 0);
+    server.update_entities_with(|_, entity| { entity.server_update(&mut count); });
+    assert_stats!(stats, drop_calls, 1);
+
+}
+
+#[test]
+fn test_server_timeout_destroyed_entities() {
+
+    let mut server = Server::<ServerEntity, TestUserData>::new(config(5));
+    let stats: Rc<Mutex<TestStat>> = Rc::new(Mutex::new(TestStat::default()));
+
+    let entity = server.entity_create_with(|| Box::new(TestEntity::new(true, stats.clone()))).unwrap();
+
+    let connection_one = server.connection_add_with(||TestUserData::new(128)).unwrap();
+    let connection_two = server.connection_add_with(||TestUserData::new(255)).unwrap();
+
+    assert_server_send!(server, connection_one, vec![], vec![0, 0, 3, 0, 1, 255, 128, 128]).expect("Server sents SendCreateToClient packet to Client.");
+    assert_server_send!(server, connection_two, vec![], vec![0, 0, 3, 0, 1, 255, 128, 255]).expect("Server sents SendCreateToClient packet to Client.");
+
+    let mut count = 0;
+    server.update_entities_with(|_, entity| { entity.server_update(&mut count); });
+    assert_eq!(count, 1);
+
+    server.entity_destroy(entity).unwrap();
+    assert_stats!(stats, drop_calls, 0);
+
+    assert_server_send!(server, connection_one, vec![], vec![4, 0]).expect("Server sents SendDestroyToClient packet to Client for destroyed entity.");
+    assert_server_send!(server, connection_two, vec![], vec![4, 0]).expect("Server sents SendDestroyToClient packet to Client for destroyed entity.");
+
+    let mut count = 0;
+    server.update_entities_with(|_, entity| { entity.server_update(&mut count); });
+    assert_eq!(count, 0);
+
+    assert_server_send!(server, connection_one, vec![], vec![4, 0]).expect("Server still sends SendCreateToClient to Client after next update call.");
+    assert_server_send!(server, connection_two, vec![], vec![4, 0]).expect("Server still sends SendCreateToClient to Client after next update call.");
+
+    server.update_entities_with(|_, entity| { entity.server_update(&mut count); });
+    server.update_entities_with(|_, entity| { entity.server_update(&mut count); });
+    server.update_entities_with(|_, entity| { entity.server_update(&mut count); });
+
+    assert_server_send!(server, connection_one, vec![], vec![4, 0]).expect("Server still sends SendCreateToClient to Client after next 4th update call.");
+    assert_server_send!(server, connection_two, vec![], vec![4, 0]).expect("Server still sends SendCreateToClient to Client after next 4th update call.");
+
+    assert_stats!(stats, drop_calls, 0);
+
+    server.update_entities_with(|_, entity| { entity.server_update(&mut count); });
+    assert_stats!(stats, drop_calls, 1);
+
+    assert_server_send_empty!(server, connection_one, vec![]).expect("Server sends no further packets after handle was dropped after 5th update call.");
+    assert_server_send_empty!(server, connection_two, vec![]).expect("Server sends no further packets after handle was dropped after 5th update call.");
+
+}
+
+#[test]
+fn test_server_timeout_destroyed_entities_override() {
+
+    let mut server = Server::<ServerEntity, TestUserData>::new(config(5));
+    let stats_short: Rc<Mutex<TestStat>> = Rc::new(Mutex::new(TestStat::default()));
+    let stats_long: Rc<Mutex<TestStat>> = Rc::new(Mutex::new(TestStat::default()));
+
+    stats_short.lock().unwrap().handle_timeout_ticks = Some(2);
+    stats_long.lock().unwrap().handle_timeout_ticks = Some(4);
+
+    let entity_short = server.entity_create_with(|| Box::new(TestEntity::new(true, stats_short.clone()))).unwrap();
+    let entity_long = server.entity_create_with(|| Box::new(TestEntity::new(true, stats_long.clone()))).unwrap();
+
+    let connection_one = server.connection_add_with(||TestUserData::new(128)).unwrap();
+    assert_server_send!(server, connection_one, vec![], vec![
+        0, 0, 3, 0, 1, 255, 128, 128,
+        0, 1, 3, 0, 1, 255, 128, 128
+
+    ]).expect("Server sents SendCreateToClient packets to Client.");
+
+    server.entity_destroy(entity_short).unwrap();
+    server.entity_destroy(entity_long).unwrap();
+    assert_server_send!(server, connection_one, vec![], vec![4, 0, 4, 1]).expect("Server sents SendDestroyToClient packets to Client for destroyed entities.");
+
+    server.update_entities_with(|_, _| {});
+    assert_server_send!(server, connection_one, vec![], vec![4, 0, 4, 1]).expect("Server keeps both handles after 1st update call.");
+
+    server.update_entities_with(|_, _| {});
+    assert_server_send!(server, connection_one, vec![], vec![4, 1]).expect("Server drops the short timeout handle after 2nd update call.");
+
+    server.update_entities_with(|_, _| {});
+    assert_server_send!(server, connection_one, vec![], vec![4, 1]).expect("Server keeps the long timeout handle after 3rd update call.");
+
+    server.update_entities_with(|_, _| {});
+    assert_server_send_empty!(server, connection_one, vec![]).expect("Server drops the long timeout handle after 4th update call.");
+
+}
+
+#[test]
+fn test_server_timeout_destroyed_entities_extended() {
+
+    let mut server = Server::<ServerEntity, TestUserData>::new(config(3));
+    let stats: Rc<Mutex<TestStat>> = Rc::new(Mutex::new(TestStat::default()));
+
+    // Entity extends its timeout by 2 ticks, twice
+    stats.lock().unwrap().destroy_timeout_extensions = 2;
+
+    let entity = server.entity_create_with(|| Box::new(TestEntity::new(true, stats.clone()))).unwrap();
+    let connection_one = server.connection_add_with(||TestUserData::new(128)).unwrap();
+    assert_server_send!(server, connection_one, vec![], vec![0, 0, 3, 0, 1, 255, 128, 128]).expect("Server sents SendCreateToClient packet to Client.");
+
+    server.entity_destroy(entity).unwrap();
+    assert_server_send!(server, connection_one, vec![], vec![4, 0]).expect("Server sents SendDestroyToClient packet to Client for destroyed entity.");
+
+    for _ in 0..6 {
+        server.update_entities_with(|_, _| {});
+        assert_server_send!(server, connection_one, vec![], vec![4, 0]).expect("Server keeps the extended handle for 6 update calls.");
+    }
+
+    assert_stats!(stats, destroy_timeout_extensions, 0);
+    assert_stats!(stats, drop_calls, 0);
+
+    server.update_entities_with(|_, _| {});
+    assert_server_send_empty!(server, connection_one, vec![]).expect("Server drops the extended handle after 7th update call.");
+    assert_stats!(stats, drop_calls, 1);
+
+}
+
+#[test]
+fn test_server_entity_flow() {
+
+    let mut server = Server::<ServerEntity, TestUserData>::new(config(5));
+    let stats: Rc<Mutex<TestStat>> = Rc::new(Mutex::new(TestStat::default()));
+
+    let connection_one = server.connection_add_with(||TestUserData::new(255)).unwrap();
+    let entity = server.entity_create_with(|| Box::new(TestEntity::new(true, stats.clone()))).unwrap();
+    assert_stats!(stats, created_calls, 1);
+    assert_stats!(stats, destroyed_calls, 0);
+
+    // None -> SendCreateToClient
+    assert_server_send!(server, connection_one, vec![], vec![0, 0, 3, 0, 1, 255, 128, 255]).expect("Server sents SendCreateToClient packet to Client.");
+
+    // ConfirmCreateToServer -> ConfirmClientCreate
+    assert_server_send!(server, connection_one, vec![1, 0], vec![1, 0]).expect("Server accepts ConfirmCreateToServer from Client and responds with ConfirmClientCreate.");
+
+    // AcceptServerUpdate -> None
+    assert_server_send_empty!(server, connection_one, vec![2, 0]).expect("Server accepts AcceptServerUpdate from Client and sends no packet if part_calls returns None.");
+    assert_stats!(stats, part_calls, 1);
+    assert_stats!(stats, merge_calls, 0);
+
+    // None -> SendUpdateToClient
+    stats.lock().unwrap().part_bytes_value = Some(vec![]);
+    assert_server_send!(server, connection_one, vec![], vec![3, 0, 0]).expect("Server send SendUpdateToClient packet without data bytes.");
+    assert_stats!(stats, part_calls, 2);
+    assert_stats!(stats, merge_calls, 0);
+
+    // None -> SendUpdateToClient
+    stats.lock().unwrap().part_bytes_value = Some(vec![255, 192, 96]);
+    assert_server_send!(server, connection_one, vec![], vec![3, 0, 3, 255, 192, 96]).expect("Server send SendUpdateToClient packet with data bytes.");
+    assert_stats!(stats, part_calls, 3);
+    assert_stats!(stats, merge_calls, 0);
+
+    // SendUpdateToServer -> None
+    stats.lock().unwrap().merge_bytes_value = vec![];
+    assert_server_send_empty!(server, connection_one, vec![3, 0, 0]).expect("Server accepts SendUpdateToServer packet without data bytes.");
+    assert_stats!(stats, merge_calls, 0);
+
+    // SendUpdateToServer -> None
+    stats.lock().unwrap().merge_bytes_value = vec![64, 56, 244];
+    assert_server_send_empty!(server, connection_one, vec![3, 0, 3, 64, 56, 244]).expect("Server accepts SendUpdateToServer packet with data bytes.");
+    assert_stats!(stats, merge_calls, 1);
+    assert_stats!(stats, part_calls, 5);
+
+    // Destroy Entity
+    server.entity_destroy(entity).unwrap();
+    assert_stats!(stats, created_calls, 1);
+    assert_stats!(stats, destroyed_calls, 1);
+    assert_stats!(stats, drop_calls, 0);
+
+    // SendUpdateToServer -> None
+    stats.lock().unwrap().merge_bytes_value = vec![];
+    assert_server_send!(server, connection_one, vec![3, 0, 3, 64, 56, 244], vec![4, 0]).expect("Server ignores SendUpdateToServer packet for destroyed entity.");
+    assert_stats!(stats, merge_calls, 1);
+    assert_stats!(stats, part_calls, 5);
+
+    // None -> SendDestroyToClient
+    stats.lock().unwrap().merge_bytes_value = vec![];
+    assert_server_send!(server, connection_one, vec![], vec![4, 0]).expect("Server sends SendDestroyToClient packet for destroyed entity.");
+    assert_stats!(stats, part_calls, 5);
+
+    // ConfirmCreateToServer -> SendUpdateToServer
+    assert_server_send!(server, connection_one, vec![1, 0], vec![4, 0]).expect("Server ignores ConfirmCreateToServer packet for destroyed entity.");
+
+    // AcceptServerUpdate -> SendUpdateToServer
+    assert_server_send!(server, connection_one, vec![2, 0], vec![4, 0]).expect("Server ignores AcceptServerUpdate packet for destroyed entity.");
+
+    // ConfirmDestroyToServer -> None
+    assert_server_send_empty!(server, connection_one, vec![4, 0]).expect("Server accepts ConfirmDestroyToServer packet for destroyed entity and drops handle.");
+
+    server.update_entities_with(|_, _| {});
+    assert_stats!(stats, drop_calls, 1);
+
+    // TODO test 0 timeout value
+    // TODO test multiple connections
+
+}
+
+#[test]
+fn test_server_entity_create_update_coalesced() {
+
+    let mut server = Server::<ServerEntity, TestUserData>::new(config(5));
+    let stats: Rc<Mutex<TestStat>> = Rc::new(Mutex::new(TestStat::default()));
+
+    let connection_one = server.connection_add_with(||TestUserData::new(96)).unwrap();
+    let _ = server.entity_create_with(|| Box::new(TestEntity::new(true, stats.clone()))).unwrap();
+
+    // Entity is created and updated within the same tick
+    stats.lock().unwrap().part_bytes_value = Some(vec![255, 192, 96]);
+
+    // None -> SendCreateToClient (update is folded into the create frame)
+    assert_server_send!(server, connection_one, vec![], vec![0, 0, 3, 0, 1, 255, 128, 96]).expect("Server sends a single SendCreateToClient frame for a created and updated entity.");
+    assert_stats!(stats, part_calls, 0);
+
+    // ConfirmCreateToServer -> ConfirmClientCreate
+    assert_server_send!(server, connection_one, vec![1, 0], vec![1, 0]).expect("Server accepts ConfirmCreateToServer from Client and responds with ConfirmClientCreate.");
+    assert_stats!(stats, part_calls, 0);
+
+    // AcceptServerUpdate -> SendUpdateToClient
+    assert_server_send!(server, connection_one, vec![2, 0], vec![3, 0, 3, 255, 192, 96]).expect("Server only sends updates once the client accepted the creation.");
+    assert_stats!(stats, part_calls, 1);
+
+}
+
+#[test]
+fn test_server_entity_relevance_low_frequency() {
+
+    let mut server = Server::<ServerEntity, TestUserData>::new(config(5));
+    let stats: Rc<Mutex<TestStat>> = Rc::new(Mutex::new(TestStat::default()));
+
+    let connection_one = server.connection_add_with(||TestUserData::new(96)).unwrap();
+    let _ = server.entity_create_with(|| Box::new(TestEntity::new(true, stats.clone()))).unwrap();
+    stats.lock().unwrap().relevance = Some(Relevance::LowFrequency(3));
+
+    // Creation is not affected by the relevance
+    assert_server_send!(server, connection_one, vec![], vec![0, 0, 3, 0, 1, 255, 128, 96]).expect("Server sents SendCreateToClient packet to Client.");
+    assert_server_send!(server, connection_one, vec![1, 0], vec![1, 0]).expect("Server accepts ConfirmCreateToServer from Client and responds with ConfirmClientCreate.");
+    assert_server_send_empty!(server, connection_one, vec![2, 0]).expect("Server accepts AcceptServerUpdate from Client and sends no packet if part_calls returns None.");
+    assert_stats!(stats, part_calls, 1);
+
+    // Updates are only serialized on every third send
+    let mut packets = vec![];
+    for _ in 0..6 {
+        stats.lock().unwrap().part_bytes_value = Some(vec![1]);
+        packets.push(server.connection_send(&connection_one, 256).unwrap());
+    }
+
+    assert_eq!(packets, vec![
+        vec![], vec![],
+        vec![vec![3, 0, 1, 1]],
+        vec![], vec![],
+        vec![vec![3, 0, 1, 1]]
+    ]);
+    assert_stats!(stats, part_calls, 3);
+
+    // Full relevance updates on every send again
+    stats.lock().unwrap().relevance = Some(Relevance::Full);
+    stats.lock().unwrap().part_bytes_value = Some(vec![2]);
+    assert_server_send!(server, connection_one, vec![], vec![3, 0, 1, 2]).expect("Server sends update for fully relevant entity.");
+    assert_stats!(stats, part_calls, 4);
+
+    // Forgotten relevance behaves like a filtered entity
+    stats.lock().unwrap().relevance = Some(Relevance::Forgotten);
+    assert_server_send!(server, connection_one, vec![], vec![5, 0]).expect("Server sends SendForgetToClient for forgotten entity.");
+
+}
+
+#[test]
+fn test_server_entity_length_codec() {
+
+    let mut server = Server::<ServerEntity, TestUserData>::new(Config {
+        length_codec: Box::new(Varint),
+        ..Config::default()
+    });
+    let stats: Rc<Mutex<TestStat>> = Rc::new(Mutex::new(TestStat::default()));
+
+    let connection_one = server.connection_add_with(||TestUserData::new(255)).unwrap();
+    server.entity_create_with(|| Box::new(TestEntity::new(true, stats.clone()))).unwrap();
+
+    assert_server_send!(server, connection_one, vec![], vec![0, 0, 3, 0, 1, 255, 128, 255]).expect("Server sends SendCreateToClient packet with single byte varint length.");
+    assert_server_send!(server, connection_one, vec![1, 0], vec![1, 0]).expect("Server accepts ConfirmCreateToServer from Client.");
+    assert_server_send_empty!(server, connection_one, vec![2, 0]).expect("Server accepts AcceptServerUpdate from Client.");
+
+    // Updates above 255 bytes use a two byte varint length
+    let update_bytes: Vec<u8> = (0..256).map(|i| i as u8).collect();
+    stats.lock().unwrap().part_bytes_value = Some(update_bytes.clone());
+
+    let mut packet = vec![3, 0, 128, 2];
+    packet.extend_from_slice(&update_bytes);
+    assert_server_send!(server, connection_one, vec![], packet.clone()).expect("Server sends SendUpdateToClient packet with two byte varint length.");
+
+    stats.lock().unwrap().merge_bytes_value = update_bytes;
+    assert_server_send_empty!(server, connection_one, packet).expect("Server accepts SendUpdateToServer packet with two byte varint length.");
+    assert_stats!(stats, merge_calls, 1);
+
+}
+
+#[test]
+fn test_server_entity_periodic_empty_update() {
+
+    // TODO create client test
+
+    let mut server = Server::<ServerEntity, TestUserData>::new(config(5));
+
+    server.set_config(Config {
+        handle_timeout_ticks: 5,
+        minimum_update_interval: Some(10),
+        ..Config::default()
+    });
+
+    let stats: Rc<Mutex<TestStat>> = Rc::new(Mutex::new(TestStat::default()));
+
+    let connection_one = server.connection_add_with(||TestUserData::new(255)).unwrap();
+    let _ = server.entity_create_with(|| Box::new(TestEntityTwo::new(true, stats.clone()))).unwrap();
+    assert_stats!(stats, created_calls, 1);
+    assert_stats!(stats, destroyed_calls, 0);
+
+    // None -> SendCreateToClient
+    assert_server_send!(server, connection_one, vec![], vec![0, 0, 3, 0, 2, 255, 128, 255]).expect("Server sents SendCreateToClient packet to Client.");
+
+    // ConfirmCreateToServer -> ConfirmClientCreate
+    assert_server_send!(server, connection_one, vec![1, 0], vec![1, 0]).expect("Server accepts ConfirmCreateToServer from Client and responds with ConfirmClientCreate.");
+
+    // AcceptServerUpdate -> None
+    assert_server_send_empty!(server, connection_one, vec![2, 0]).expect("Server accepts AcceptServerUpdate from Client and sends no packet if part_calls returns None.");
+    assert_stats!(stats, part_calls, 1);
+    assert_stats!(stats, merge_calls, 0);
+
+    let mut packets = vec![];
+    for _ in 0..20 {
+        let p = server.connection_send(&connection_one, 256).unwrap();
+        if p.is_empty() {
+            packets.push(vec![]);
+
+        } else {
+            packets.extend_from_slice(&p);
+        }
+    }
+
+    assert_eq!(
+        packets,
+        vec![
+            vec![], vec![], vec![], vec![], vec![], vec![], vec![], vec![],
+            vec![3, 0, 0],
+            vec![], vec![], vec![], vec![], vec![], vec![], vec![], vec![], vec![],
+            vec![3, 0, 0],
+            vec![]
+        ],
+        "Server should send an empty update at least every 10 ticks."
+    );
+
+    // SendUpdateToServer > None
+    assert_server_send_empty!(server, connection_one, vec![3, 0, 0]).expect("Server accepts empty client update packet.");
+    assert_stats!(stats, merge_calls, 0);
+
+}
+
+#[test]
+fn test_server_entity_should_send_update() {
+
+    let mut server = Server::<ServerEntity, TestUserData>::new(Config {
+        minimum_update_interval: Some(3),
+        ..Config::default()
+    });
+
+    let stats: Rc<Mutex<TestStat>> = Rc::new(Mutex::new(TestStat::default()));
+    stats.lock().unwrap().skip_updates = true;
+
+    let connection_one = server.connection_add_with(||TestUserData::new(255)).unwrap();
+    server.entity_create_with(|| Box::new(TestEntity::new(true, stats.clone()))).unwrap();
+
+    assert_server_send!(server, connection_one, vec![], vec![0, 0, 3, 0, 1, 255, 128, 255]).expect("Server sents SendCreateToClient packet to Client.");
+    assert_server_send!(server, connection_one, vec![1, 0], vec![1, 0]).expect("Server accepts ConfirmCreateToServer from Client.");
+    assert_server_send_empty!(server, connection_one, vec![2, 0]).expect("Server accepts AcceptServerUpdate from Client.");
+
+    // Static entities are never serialized but still send empty updates
+    let mut packets = vec![];
+    for _ in 0..6 {
+        packets.push(server.connection_send(&connection_one, 256).unwrap());
+    }
+    assert_eq!(packets, vec![
+        vec![], vec![vec![3, 0, 0]], vec![], vec![], vec![vec![3, 0, 0]], vec![]
+    ]);
+    assert_stats!(stats, part_calls, 0);
+
+    stats.lock().unwrap().skip_updates = false;
+    stats.lock().unwrap().part_bytes_value = Some(vec![1, 2]);
+    assert_server_send!(server, connection_one, vec![], vec![3, 0, 2, 1, 2]).expect("Server sends SendUpdateToClient once the entity has changes.");
+    assert_stats!(stats, part_calls, 1);
+
+}
+
+#[test]
+fn test_server_entity_update_dirty() {
+
+    let mut server = Server::<ServerEntity, TestUserData>::new(config(3));
+    let stats_one: Rc<Mutex<TestStat>> = Rc::new(Mutex::new(TestStat::default()));
+    let stats_two: Rc<Mutex<TestStat>> = Rc::new(Mutex::new(TestStat::default()));
+
+    let connection_one = server.connection_add_with(||TestUserData::new(255)).unwrap();
+    let entity_one = server.entity_create_with(|| Box::new(TestEntity::new(true, stats_one.clone()))).unwrap();
+    server.entity_create_with(|| Box::new(TestEntity::new(true, stats_two.clone()))).unwrap();
+
+    assert_server_send!(server, connection_one, vec![], vec![
+        0, 0, 3, 0, 1, 255, 128, 255,
+        0, 1, 3, 0, 1, 255, 128, 255
+
+    ]).expect("Server sents SendCreateToClient packets to Client.");
+    assert_server_send!(server, connection_one, vec![1, 0, 1, 1], vec![1, 0, 1, 1]).expect("Server accepts ConfirmCreateToServer from Client.");
+    assert_server_send_empty!(server, connection_one, vec![2, 0, 2, 1]).expect("Server accepts AcceptServerUpdate from Client.");
+
+    // Only the dirty entity is serialized
+    stats_one.lock().unwrap().part_bytes_value = Some(vec![1]);
+    stats_two.lock().unwrap().part_bytes_value = Some(vec![2]);
+    let update_stats = server.update_entities_with_dirty(|token, _| *token == entity_one);
+    assert_eq!(update_stats.alive, 2);
+
+    assert_server_send!(server, connection_one, vec![], vec![3, 0, 1, 1]).expect("Server sends SendUpdateToClient for the dirty entity only.");
+    assert_stats!(stats_one, part_calls, 2);
+    assert_stats!(stats_two, part_calls, 1);
+
+    // Plain updates mark all entities as dirty again
+    server.update_entities_with(|_, _| {});
+    assert_server_send!(server, connection_one, vec![], vec![3, 1, 1, 2]).expect("Server sends SendUpdateToClient for all entities after a plain update.");
+    assert_stats!(stats_two, part_calls, 2);
+
+}
+
+#[test]
+fn test_server_entity_mark_dirty() {
+
+    let mut server = Server::<ServerEntity, TestUserData>::new(config(3));
+    let stats_one: Rc<Mutex<TestStat>> = Rc::new(Mutex::new(TestStat::default()));
+    let stats_two: Rc<Mutex<TestStat>> = Rc::new(Mutex::new(TestStat::default()));
+
+    let connection_one = server.connection_add_with(||TestUserData::new(255)).unwrap();
+    let entity_one = server.entity_create_with(|| Box::new(TestEntity::new(true, stats_one.clone()))).unwrap();
+    let entity_two = server.entity_create_with(|| Box::new(TestEntity::new(true, stats_two.clone()))).unwrap();
+
+    assert_server_send!(server, connection_one, vec![], vec![
+        0, 0, 3, 0, 1, 255, 128, 255,
+        0, 1, 3, 0, 1, 255, 128, 255
+
+    ]).expect("Server sents SendCreateToClient packets to Client.");
+    assert_server_send!(server, connection_one, vec![1, 0, 1, 1], vec![1, 0, 1, 1]).expect("Server accepts ConfirmCreateToServer from Client.");
+    assert_server_send_empty!(server, connection_one, vec![2, 0, 2, 1]).expect("Server accepts AcceptServerUpdate from Client.");
+
+    // Clean entities are not serialized at all
+    stats_one.lock().unwrap().part_bytes_value = Some(vec![1]);
+    server.update_entities_with_dirty(|_, _| false);
+    assert_server_send_empty!(server, connection_one, vec![]).expect("Server does not send SendUpdateToClient for clean entities.");
+    assert_stats!(stats_one, part_calls, 1);
+    assert_stats!(stats_two, part_calls, 1);
+
+    // Marked entities are serialized until the next update
+    server.entity_mark_dirty(&entity_one);
+    assert_server_send!(server, connection_one, vec![], vec![3, 0, 1, 1]).expect("Server sends SendUpdateToClient for the marked entity.");
+    assert_stats!(stats_one, part_calls, 2);
+    assert_stats!(stats_two, part_calls, 1);
+
+    server.update_entities_with_dirty(|_, _| false);
+    assert_server_send_empty!(server, connection_one, vec![]).expect("Server does not send SendUpdateToClient once the mark was replaced.");
+    assert_stats!(stats_one, part_calls, 2);
+
+    // Creations and destructions still flow for clean entities
+    let stats_three: Rc<Mutex<TestStat>> = Rc::new(Mutex::new(TestStat::default()));
+    server.entity_create_with(|| Box::new(TestEntity::new(true, stats_three.clone()))).unwrap();
+    server.update_entities_with_dirty(|_, _| false);
+    server.entity_destroy(entity_two).unwrap();
+    assert_server_send!(server, connection_one, vec![], vec![
+        4, 1,
+        0, 2, 3, 0, 1, 255, 128, 255
+
+    ]).expect("Server sends SendDestroyToClient and SendCreateToClient for clean entities.");
+
+}
+
+#[test]
+fn test_server_entity_mark_dirty_periodic_empty_update() {
+
+    let mut server = Server::<ServerEntity, TestUserData>::new(Config {
+        handle_timeout_ticks: 3,
+        minimum_update_interval: Some(2),
+        ..Config::default()
+    });
+    let stats: Rc<Mutex<TestStat>> = Rc::new(Mutex::new(TestStat::default()));
+
+    let connection_one = server.connection_add_with(||TestUserData::new(255)).unwrap();
+    server.entity_create_with(|| Box::new(TestEntity::new(true, stats.clone()))).unwrap();
+
+    assert_server_send!(server, connection_one, vec![], vec![0, 0, 3, 0, 1, 255, 128, 255]).expect("Server sents SendCreateToClient packet to Client.");
+    assert_server_send!(server, connection_one, vec![1, 0], vec![1, 0]).expect("Server accepts ConfirmCreateToServer from Client.");
+    assert_server_send_empty!(server, connection_one, vec![2, 0]).expect("Server accepts AcceptServerUpdate from Client.");
+
+    // Clean entities still send their periodic empty updates
+    server.update_entities_with_dirty(|_, _| false);
+    assert_server_send!(server, connection_one, vec![], vec![3, 0, 0]).expect("Server sends periodic empty SendUpdateToClient for clean entity.");
+    assert_server_send_empty!(server, connection_one, vec![]).expect("Server waits for the next periodic update.");
+    assert_server_send!(server, connection_one, vec![], vec![3, 0, 0]).expect("Server sends periodic empty SendUpdateToClient for clean entity.");
+    assert_stats!(stats, part_calls, 1);
+
+}
+
+#[test]
+fn test_server_entity_periodic_empty_update_large_interval() {
+
+    let mut server = Server::<ServerEntity, TestUserData>::new(Config {
+        handle_timeout_ticks: 5,
+        minimum_update_interval: Some(300),
+        ..Config::default()
+    });
+
+    let stats: Rc<Mutex<TestStat>> = Rc::new(Mutex::new(TestStat::default()));
+
+    let connection_one = server.connection_add_with(||TestUserData::new(255)).unwrap();
+    let _ = server.entity_create_with(|| Box::new(TestEntityTwo::new(true, stats.clone()))).unwrap();
+
+    assert_server_send!(server, connection_one, vec![], vec![0, 0, 3, 0, 2, 255, 128, 255]).expect("Server sents SendCreateToClient packet to Client.");
+    assert_server_send!(server, connection_one, vec![1, 0], vec![1, 0]).expect("Server accepts ConfirmCreateToServer from Client and responds with ConfirmClientCreate.");
+
+    // First tick of the interval
+    assert_server_send_empty!(server, connection_one, vec![2, 0]).expect("Server accepts AcceptServerUpdate from Client and sends no packet if part_calls returns None.");
+
+    for tick in 2..300 {
+        assert_eq!(server.connection_send(&connection_one, 256).unwrap(), Vec::<Vec<u8>>::new(), "Server should not send an empty update on tick {}.", tick);
+    }
+
+    assert_eq!(server.connection_send(&connection_one, 256).unwrap(), vec![vec![3, 0, 0]], "Server should send an empty update on the 300th tick.");
+    assert_eq!(server.connection_send(&connection_one, 256).unwrap(), Vec::<Vec<u8>>::new(), "Server should restart the interval after an empty update.");
+
+}
+
+#[test]
+fn test_server_entity_delta_baseline() {
+
+    let mut server = Server::<ServerEntity, TestUserData>::new(Config {
+        acknowledge_updates: true,
+        ..Config::default()
+    });
+
+    let stats: Rc<Mutex<TestStat>> = Rc::new(Mutex::new(TestStat::default()));
+    let connection_one = server.connection_add_with(||TestUserData::new(255)).unwrap();
+    server.entity_create_with(|| Box::new(TestEntity::new(true, stats.clone()))).unwrap();
+
+    assert_server_send!(server, connection_one, vec![], vec![0, 0, 3, 0, 1, 255, 128, 255]).expect("Server sents SendCreateToClient packet to Client.");
+    assert_server_send!(server, connection_one, vec![1, 0], vec![1, 0]).expect("Server accepts ConfirmCreateToServer from Client.");
+    assert_server_send_empty!(server, connection_one, vec![2, 0]).expect("Server accepts AcceptServerUpdate from Client.");
+    assert_stats_clone!(stats, part_bytes_baseline, None);
+
+    // Updates are prefixed with their sequence
+    stats.lock().unwrap().part_bytes_value = Some(vec![1, 2]);
+    assert_server_send!(server, connection_one, vec![], vec![3, 0, 1, 2, 1, 2]).expect("Server sends sequenced SendUpdateToClient packet.");
+    assert_stats_clone!(stats, part_bytes_baseline, None);
+
+    stats.lock().unwrap().part_bytes_value = Some(vec![3]);
+    assert_server_send!(server, connection_one, vec![], vec![3, 0, 2, 1, 3]).expect("Server sends sequenced SendUpdateToClient packet.");
+    assert_stats_clone!(stats, part_bytes_baseline, None);
+
+    // Acknowledged updates become the baseline
+    assert_server_send_empty!(server, connection_one, vec![5, 0, 1]).expect("Server accepts AcknowledgeServerUpdate from Client.");
+    assert_stats_clone!(stats, part_bytes_baseline, Some(vec![1, 2]));
+
+    assert_server_send_empty!(server, connection_one, vec![5, 0, 2]).expect("Server accepts AcknowledgeServerUpdate from Client.");
+    assert_stats_clone!(stats, part_bytes_baseline, Some(vec![3]));
+
+    // Outdated acknowledgements are ignored
+    assert_server_send_empty!(server, connection_one, vec![5, 0, 1]).expect("Server ignores outdated AcknowledgeServerUpdate from Client.");
+    assert_stats_clone!(stats, part_bytes_baseline, Some(vec![3]));
+
+}
+
+#[test]
+fn test_server_entity_max_baselines() {
+
+    let mut server = Server::<ServerEntity, TestUserData>::new(Config {
+        acknowledge_updates: true,
+        max_baselines_per_connection: Some(1),
+        ..Config::default()
+    });
+
+    let stats_one: Rc<Mutex<TestStat>> = Rc::new(Mutex::new(TestStat::default()));
+    let stats_two: Rc<Mutex<TestStat>> = Rc::new(Mutex::new(TestStat::default()));
+    let connection_one = server.connection_add_with(||TestUserData::new(255)).unwrap();
+    server.entity_create_with(|| Box::new(TestEntity::new(true, stats_one.clone()))).unwrap();
+    server.entity_create_with(|| Box::new(TestEntity::new(true, stats_two.clone()))).unwrap();
+
+    assert_server_send!(server, connection_one, vec![], vec![
+        0, 0, 3, 0, 1, 255, 128, 255,
+        0, 1, 3, 0, 1, 255, 128, 255
+
+    ]).expect("Server sents SendCreateToClient packets to Client.");
+    assert_server_send!(server, connection_one, vec![1, 0, 1, 1], vec![1, 0, 1, 1]).expect("Server accepts ConfirmCreateToServer from Client.");
+    assert_server_send_empty!(server, connection_one, vec![2, 0, 2, 1]).expect("Server accepts AcceptServerUpdate from Client.");
+
+    // Acknowledged update of the first entity becomes its baseline
+    stats_one.lock().unwrap().part_bytes_value = Some(vec![1]);
+    assert_server_send!(server, connection_one, vec![], vec![3, 0, 1, 1, 1]).expect("Server sends sequenced SendUpdateToClient packet.");
+    assert_server_send_empty!(server, connection_one, vec![5, 0, 1]).expect("Server accepts AcknowledgeServerUpdate from Client.");
+    assert_stats_clone!(stats_one, part_bytes_baseline, Some(vec![1]));
+
+    // A more recent update of the second entity exceeds the cap
+    server.update_entities_with(|_, _| {});
+    stats_two.lock().unwrap().part_bytes_value = Some(vec![2]);
+    assert_server_send!(server, connection_one, vec![], vec![3, 1, 1, 1, 2]).expect("Server sends sequenced SendUpdateToClient packet.");
+
+    // The evicted baseline results in a full resend of the first entity
+    stats_one.lock().unwrap().part_bytes_value = Some(vec![3]);
+    assert_server_send!(server, connection_one, vec![], vec![3, 0, 2, 1, 3]).expect("Server sends sequenced SendUpdateToClient packet.");
+    assert_stats_clone!(stats_one, part_bytes_baseline, None);
+
+    // Acknowledging the evicted update is ignored while the second entity keeps its baseline
+    assert_server_send_empty!(server, connection_one, vec![5, 0, 1, 5, 1, 1]).expect("Server accepts AcknowledgeServerUpdate from Client.");
+    assert_stats_clone!(stats_one, part_bytes_baseline, None);
+    assert_stats_clone!(stats_two, part_bytes_baseline, Some(vec![2]));
+
+}
+
+#[test]
+fn test_server_entity_segments() {
+
+    let mut server = Server::<ServerEntity, TestUserData>::new(Config::default());
+    let (mut client, client_stats) = create_client(3);
+
+    let stats: Rc<Mutex<TestStat>> = Rc::new(Mutex::new(TestStat::default()));
+    let connection_one = server.connection_add_with(||TestUserData::new(255)).unwrap();
+    server.entity_create_with(|| Box::new(TestSegmentEntity::new(stats.clone()))).unwrap();
+
+    assert_server_send!(server, connection_one, vec![], vec![0, 0, 0, 0, 4]).expect("Server sents SendCreateToClient packet to Client.");
+    assert_server_send!(server, connection_one, vec![1, 0], vec![1, 0]).expect("Server accepts ConfirmCreateToServer from Client.");
+    assert_server_send_empty!(server, connection_one, vec![2, 0]).expect("Server accepts AcceptServerUpdate from Client.");
+    client.receive(vec![0, 0, 0, 0, 4]).unwrap();
+    client.receive(vec![1, 0]).unwrap();
+    client.receive(vec![3, 0, 0]).unwrap();
+
+    // Each segment is send with its ID and length and marked as segmented
+    stats.lock().unwrap().part_segments_value = vec![(0, vec![1, 2]), (1, vec![3])];
+    let packets = server.connection_send(&connection_one, 256).unwrap();
+    assert_eq!(packets, vec![vec![6, 0, 7, 0, 2, 1, 2, 1, 1, 3]]);
+    client.receive(packets[0].clone()).unwrap();
+    assert_stats_clone!(client_stats, merged_segments, vec![(0, vec![1, 2]), (1, vec![3])]);
+
+    // Only the changed segment is send
+    stats.lock().unwrap().part_segments_value = vec![(1, vec![4])];
+    let packets = server.connection_send(&connection_one, 256).unwrap();
+    assert_eq!(packets, vec![vec![6, 0, 3, 1, 1, 4]]);
+    client.receive(packets[0].clone()).unwrap();
+    assert_stats_clone!(client_stats, merged_segments, vec![(0, vec![1, 2]), (1, vec![3]), (1, vec![4])]);
+    assert_eq!(client.stats().updates_merged, 2);
+
+    // Malformed segments are rejected
+    client.receive(vec![6, 0, 3, 1, 5, 4]).unwrap();
+    assert_eq!(client.stats().updates_merged, 2);
+
+    // Unsegmented updates are not decoded as segments
+    client.receive(vec![3, 0, 3, 1, 5, 4]).unwrap();
+    assert_eq!(client.stats().updates_merged, 3);
+    assert_stats_clone!(client_stats, merged_segments, vec![(0, vec![1, 2]), (1, vec![3]), (1, vec![4])]);
+
+    // Without any changed segments no update is send
+    assert_server_send_empty!(server, connection_one, vec![]).expect("Server sends no update without changed segments.");
+
+    // Segments which exceed the varint length are rejected
+    stats.lock().unwrap().part_segments_value = vec![(0, vec![0; 1 << 28])];
+    assert_eq!(server.connection_send(&connection_one, 256), Err(ServerError::PacketSizeTooSmall(256)));
+
+    client.send(256).unwrap();
+    client_stats.lock().unwrap().part_segments_value = vec![(0, vec![0; 1 << 28])];
+    assert_eq!(client.send(256), Err(ClientError::PacketSizeTooSmall(256)));
+
+}
+
+#[test]
+fn test_entity_merge_bytes_default() {
+
+    // Entities which do not override merge_bytes accept any update
+    let mut entity = TestSendEntity::new(Arc::new(Mutex::new(TestStat::default())));
+    assert!(entity.merge_bytes(None, &[1, 2, 3]).is_ok());
+
+    // Segments are only decoded when explicitly requested
+    assert!(entity.merge_segments(None, &[1, 1, 3]).is_ok());
+    assert!(entity.merge_segments(None, &[1, 5, 4]).is_err());
+
+}
+
+#[test]
+fn test_server_entity_acked_tick() {
+
+    let mut server = Server::<ServerEntity, TestUserData>::new(Config {
+        acknowledge_updates: true,
+        ..Config::default()
+    });
+
+    let stats: Rc<Mutex<TestStat>> = Rc::new(Mutex::new(TestStat::default()));
+    let connection_one = server.connection_add_with(||TestUserData::new(255)).unwrap();
+    let entity = server.entity_create_with(|| Box::new(TestEntity::new(true, stats.clone()))).unwrap();
+
+    assert_server_send!(server, connection_one, vec![], vec![0, 0, 3, 0, 1, 255, 128, 255]).expect("Server sents SendCreateToClient packet to Client.");
+    assert_server_send!(server, connection_one, vec![1, 0], vec![1, 0]).expect("Server accepts ConfirmCreateToServer from Client.");
+    assert_server_send_empty!(server, connection_one, vec![2, 0]).expect("Server accepts AcceptServerUpdate from Client.");
+    assert_eq!(server.connection_entity_acked_tick(&connection_one, &entity), None);
+
+    // Send updates during the 2nd and 3rd tick
+    server.update_entities_with(|_, _| {});
+    server.update_entities_with(|_, _| {});
+    stats.lock().unwrap().part_bytes_value = Some(vec![1]);
+    assert_server_send!(server, connection_one, vec![], vec![3, 0, 1, 1, 1]).expect("Server sends sequenced SendUpdateToClient packet.");
+
+    server.update_entities_with(|_, _| {});
+    stats.lock().unwrap().part_bytes_value = Some(vec![2]);
+    assert_server_send!(server, connection_one, vec![], vec![3, 0, 2, 1, 2]).expect("Server sends sequenced SendUpdateToClient packet.");
+
+    // Acknowledgements map back to the tick the update was send at
+    server.connection_receive(&connection_one, vec![5, 0, 1]).unwrap();
+    assert_eq!(server.connection_entity_acked_tick(&connection_one, &entity), Some(2));
+
+    server.connection_receive(&connection_one, vec![5, 0, 2]).unwrap();
+    assert_eq!(server.connection_entity_acked_tick(&connection_one, &entity), Some(3));
+
+    // Stale tokens do not report the tick of their slot's new entity
+    server.entity_destroy_all();
+    assert_server_send!(server, connection_one, vec![], vec![4, 0]).expect("Server sents SendDestroyToClient packet to Client.");
+    assert_server_send_empty!(server, connection_one, vec![4, 0]).expect("Server accepts ConfirmDestroyToServer from Client.");
+    server.update_entities_with(|_, _| {});
+
+    let reused = server.entity_create_with(|| Box::new(TestEntity::new(true, stats.clone()))).unwrap();
+    assert_server_send!(server, connection_one, vec![], vec![0, 0, 3, 0, 1, 255, 128, 255]).expect("Server sents SendCreateToClient packet to Client.");
+    assert_server_send!(server, connection_one, vec![1, 0], vec![1, 0]).expect("Server accepts ConfirmCreateToServer from Client.");
+    assert_server_send_empty!(server, connection_one, vec![2, 0]).expect("Server accepts AcceptServerUpdate from Client.");
+
+    stats.lock().unwrap().part_bytes_value = Some(vec![3]);
+    assert_server_send!(server, connection_one, vec![], vec![3, 0, 1, 1, 3]).expect("Server sends sequenced SendUpdateToClient packet.");
+    server.connection_receive(&connection_one, vec![5, 0, 1]).unwrap();
+    assert_eq!(server.connection_entity_acked_tick(&connection_one, &reused), Some(4));
+    assert_eq!(server.connection_entity_acked_tick(&connection_one, &entity), None);
+
+}
+
+#[test]
+fn test_server_entity_confirmed_by() {
+
+    let mut server = Server::<ServerEntity, TestUserData>::new(config(5));
+    let stats: Rc<Mutex<TestStat>> = Rc::new(Mutex::new(TestStat::default()));
+
+    let connection_one = server.connection_add_with(||TestUserData::new(1)).unwrap();
+    let connection_two = server.connection_add_with(||TestUserData::new(2)).unwrap();
+    server.entity_create_with(|| Box::new(TestEntity::new(true, stats.clone()))).unwrap();
+
+    assert_server_send!(server, connection_one, vec![], vec![0, 0, 3, 0, 1, 255, 128, 1]).expect("Server sents SendCreateToClient packet to Client one.");
+    assert_server_send!(server, connection_two, vec![], vec![0, 0, 3, 0, 1, 255, 128, 2]).expect("Server sents SendCreateToClient packet to Client two.");
+
+    // ConfirmCreateToServer does not yet confirm the entity
+    assert_server_send!(server, connection_one, vec![1, 0], vec![1, 0]).expect("Server accepts ConfirmCreateToServer from Client one.");
+    assert_stats!(stats, confirmed_calls, 0);
+    assert_stats!(stats, observed_calls, 0);
+
+    // AcceptServerUpdate confirms the entity for the first connection
+    assert_server_send_empty!(server, connection_one, vec![2, 0]).expect("Server accepts AcceptServerUpdate from Client one.");
+    assert_stats!(stats, confirmed_calls, 1);
+    assert_stats!(stats, observed_calls, 1);
+
+    // Repeated AcceptServerUpdate packets are ignored
+    assert_server_send_empty!(server, connection_one, vec![2, 0]).expect("Server ignores repeated AcceptServerUpdate from Client one.");
+    assert_stats!(stats, confirmed_calls, 1);
+
+    // AcceptServerUpdate confirms the entity for the second connection
+    assert_server_send!(server, connection_two, vec![1, 0], vec![1, 0]).expect("Server accepts ConfirmCreateToServer from Client two.");
+    assert_server_send_empty!(server, connection_two, vec![2, 0, 2, 0]).expect("Server accepts AcceptServerUpdate from Client two.");
+    assert_stats!(stats, confirmed_calls, 2);
+
+    // The entity is only observed once
+    assert_stats!(stats, observed_calls, 1);
+
+}
+
+#[test]
+fn test_server_multi_entity_flow() {
+
+    let mut server = Server::<ServerEntity, TestUserData>::new(config(3));
+    let stats: Rc<Mutex<TestStat>> = Rc::new(Mutex::new(TestStat::default()));
+
+    let connection_one = server.connection_add_with(||TestUserData::new(16)).unwrap();
+    let entity_one = server.entity_create_with(|| Box::new(TestEntity::new(true, stats.clone()))).unwrap();
+    let entity_two = server.entity_create_with(|| Box::new(TestEntity::new(true, stats.clone()))).unwrap();
+    let entity_three = server.entity_create_with(|| Box::new(TestEntity::new(true, stats.clone()))).unwrap();
+
+    // None -> SendCreateToClient
+    assert_server_send!(server, connection_one, vec![], vec![
+        0, 0, 3, 0, 1, 255, 128, 16,
+        0, 1, 3, 0, 1, 255, 128, 16,
+        0, 2, 3, 0, 1, 255, 128, 16
+
+    ]).expect("Server sents multiple SendCreateToClient in one packet to Client.");
+
+    // ConfirmCreateToServer -> ConfirmClientCreate
+    assert_server_send!(server, connection_one, vec![1, 2], vec![
+        0, 0, 3, 0, 1, 255, 128, 16,
+        0, 1, 3, 0, 1, 255, 128, 16,
+        1, 2
+
+    ]).expect("Server accepts ConfirmCreateToServer from Client.");
+
+    assert_server_send!(server, connection_one, vec![1, 0], vec![
+        1, 0,
+        0, 1, 3, 0, 1, 255, 128, 16,
+        1, 2
+
+    ]).expect("Server accepts AcceptServerUpdate from Client.");
+
+    assert_server_send!(server, connection_one, vec![2, 1], vec![
+        1, 0,
+        0, 1, 3, 0, 1, 255, 128, 16,
+        1, 2
+
+    ]).expect("Server ignores AcceptServerUpdate from Client if ConfirmCreateToServer packet was not yet sent.");
+    assert_stats!(stats, part_calls, 0);
+
+    // AcceptServerUpdate -> None
+    assert_server_send!(server, connection_one, vec![2, 0, 2, 2], vec![
+        0, 1, 3, 0, 1, 255, 128, 16
+
+    ]).expect("Server accepts multiple AcceptServerUpdate from Client in one packet.");
+
+    assert_stats!(stats, part_calls, 2);
+    assert_stats!(stats, merge_calls, 0);
+
+    // Destroy second entity
+    server.entity_destroy(entity_two).unwrap();
+    assert_stats!(stats, drop_calls, 0);
+
+    // None -> SendCreateToClient
+    assert_server_send!(server, connection_one, vec![], vec![4, 1]).expect("Server sends SendDestroyToClient for destroy entity.");
+
+    let mut count = 0;
+    server.update_entities_with(|_, entity| { entity.server_update(&mut count); });
+    server.update_entities_with(|_, entity| { entity.server_update(&mut count); });
+    assert_server_send!(server, connection_one, vec![], vec![4, 1]).expect("Server keeps destroyed entity handle before 3 update calls.");
+
+    server.update_entities_with(|_, entity| { entity.server_update(&mut count); });
+    assert_server_send_empty!(server, connection_one, vec![]).expect("Server drops destroyed entity handle after 3 update calls.");
+    assert_stats!(stats, drop_calls, 1);
+
+    // SendUpdateToServer -> None
+    stats.lock().unwrap().merge_bytes_value = vec![];
+    assert_server_send_empty!(server, connection_one, vec![
+        3, 0, 0,
+        3, 2, 0
+
+    ]).expect("Server accepts multiple SendUpdateToServer packets without data bytes.");
+
+    assert_stats!(stats, merge_calls, 0);
+
+    // SendUpdateToServer -> None
+    stats.lock().unwrap().merge_bytes_value = vec![64, 56, 244];
+    assert_server_send_empty!(server, connection_one, vec![
+        3, 0, 3, 64, 56, 244,
+        3, 2, 3, 64, 56, 244
+
+    ]).expect("Server accepts multiple SendUpdateToServer packets with data bytes.");
+
+    assert_stats!(stats, merge_calls, 2);
+    assert_stats!(stats, part_calls, 12);
+
+    // None -> SendUpdateToClient
+    stats.lock().unwrap().part_bytes_value = Some(vec![255, 192, 96]);
+    assert_server_send!(server, connection_one, vec![], vec![3, 0, 3, 255, 192, 96]).expect("Server send SendUpdateToClient packet with data bytes.");
+    assert_stats!(stats, merge_calls, 2);
+    assert_stats!(stats, part_calls, 14);
+
+    // Destroy remaining entities
+    server.entity_destroy(entity_one).unwrap();
+    server.entity_destroy(entity_three).unwrap();
+
+    assert_server_send!(server, connection_one, vec![], vec![4, 0, 4, 2]).expect("Server sends multiple SendDestroyToClient for destroyed entities.");
+
+    server.update_entities_with(|_, entity| { entity.server_update(&mut count); });
+    server.update_entities_with(|_, entity| { entity.server_update(&mut count); });
+    assert_server_send!(server, connection_one, vec![], vec![4, 0, 4 ,2]).expect("Server keeps destroyed entity handles before 3 update calls.");
+
+    server.update_entities_with(|_, entity| { entity.server_update(&mut count); });
+    assert_server_send_empty!(server, connection_one, vec![]).expect("Server drops destroyed entity handles after 3 update calls.");
+
+}
+
+#[test]
+fn test_server_entity_destroy_before_send() {
+
+    let mut server = Server::<ServerEntity, TestUserData>::new(config(3));
+    let stats: Rc<Mutex<TestStat>> = Rc::new(Mutex::new(TestStat::default()));
+
+    let connection_one = server.connection_add_with(||TestUserData::new(255)).unwrap();
+    let connection_two = server.connection_add_with(||TestUserData::new(255)).unwrap();
+
+    // Entities destroyed before any send are not announced at all
+    let entity_one = server.entity_create_with(|| Box::new(TestEntity::new(true, stats.clone()))).unwrap();
+    server.entity_destroy(entity_one).unwrap();
+    assert_server_send_empty!(server, connection_one, vec![]).expect("Server sends no SendDestroyToClient for unknown entity.");
+    assert_server_send_empty!(server, connection_two, vec![]).expect("Server sends no SendDestroyToClient for unknown entity.");
+    assert_eq!(server.update_entities_with(|_, _| {}).handles_dropped, 1);
+
+    // Only the connection which received the creation is told about the destruction
+    let entity_two = server.entity_create_with(|| Box::new(TestEntity::new(true, stats.clone()))).unwrap();
+    assert_server_send!(server, connection_one, vec![], vec![0, 0, 3, 0, 1, 255, 128, 255]).expect("Server sents SendCreateToClient packet to Client.");
+    assert_server_send!(server, connection_one, vec![1, 0], vec![1, 0]).expect("Server accepts ConfirmCreateToServer from Client.");
+    server.entity_destroy(entity_two).unwrap();
+
+    assert_server_send_empty!(server, connection_two, vec![]).expect("Server sends no SendDestroyToClient for unknown entity.");
+    assert_server_send!(server, connection_one, vec![], vec![4, 0]).expect("Server sends SendDestroyToClient for created entity.");
+    assert_eq!(server.update_entities_with(|_, _| {}).handles_dropped, 0);
+
+    assert_server_send_empty!(server, connection_one, vec![4, 0]).expect("Server accepts ConfirmDestroyToServer from Client.");
+    assert_eq!(server.update_entities_with(|_, _| {}), ServerUpdateStats {
+        handles_dropped: 1,
+        handles_timed_out: 0,
+        alive: 0,
+        sleeping: 0
+    });
+
+}
+
+#[test]
+fn test_server_entity_destroy_all() {
+
+    let mut server = Server::<ServerEntity, TestUserData>::new(config(5));
+    let stats: Rc<Mutex<TestStat>> = Rc::new(Mutex::new(TestStat::default()));
+
+    let connection_one = server.connection_add_with(||TestUserData::new(255)).unwrap();
+    for _ in 0..3 {
+        server.entity_create_with(|| Box::new(TestEntity::new(true, stats.clone()))).unwrap();
+    }
+
+    assert_server_send!(server, connection_one, vec![], vec![
+        0, 0, 3, 0, 1, 255, 128, 255,
+        0, 1, 3, 0, 1, 255, 128, 255,
+        0, 2, 3, 0, 1, 255, 128, 255
+
+    ]).expect("Server sents SendCreateToClient packets to Client.");
+
+    assert_server_send!(server, connection_one, vec![1, 0, 1, 1, 1, 2], vec![1, 0, 1, 1, 1, 2]).expect("Server accepts ConfirmCreateToServer packets from Client.");
+
+    server.entity_destroy_all();
+    assert_stats!(stats, destroyed_calls, 3);
+    assert_eq!(server.stats().entities_destroyed, 3);
+    assert_eq!(server.map_entities(|_, _| ()).len(), 0);
+
+    assert_server_send!(server, connection_one, vec![], vec![4, 0, 4, 1, 4, 2]).expect("Server sents SendDestroyToClient packets to Client for all entities.");
+
+    // Destroying again has no effect
+    server.entity_destroy_all();
+    assert_stats!(stats, destroyed_calls, 3);
+
+    assert_server_send_empty!(server, connection_one, vec![4, 0, 4, 1, 4, 2]).expect("Server accepts ConfirmDestroyToServer packets for all entities.");
+    server.update_entities_with(|_, _| {});
+    assert_stats!(stats, drop_calls, 3);
+
+}
+
+#[test]
+fn test_server_entity_destroy_all_stale_token() {
+
+    let mut server = Server::<ServerEntity, TestUserData>::new(config(5));
+    let stats: Rc<Mutex<TestStat>> = Rc::new(Mutex::new(TestStat::default()));
+
+    let entity = server.entity_create_with(|| Box::new(TestEntity::new(true, stats.clone()))).unwrap();
+    server.entity_destroy_all();
+    for _ in 0..3 {
+        server.update_entities_with(|_, _| {});
+    }
+
+    // Tokens of entities in re-used slots no longer destroy anything
+    let reused = server.entity_create_with(|| Box::new(TestEntity::new(true, stats.clone()))).unwrap();
+    assert!(!server.entity_is_alive(&entity));
+    assert!(server.entity_is_alive(&reused));
+    let entity = server.entity_destroy(entity).unwrap_err();
+    assert!(server.entity_take(entity).is_err());
+    assert!(server.entity_get(&reused).is_some());
+    assert_stats!(stats, destroyed_calls, 1);
+
+}
+
+#[test]
+fn test_server_entity_take() {
+
+    let mut server = Server::<ServerEntity, TestUserData>::new(config(5));
+    let stats: Rc<Mutex<TestStat>> = Rc::new(Mutex::new(TestStat::default()));
+
+    let connection_one = server.connection_add_with(||TestUserData::new(255)).unwrap();
+    let entity = server.entity_create_with(|| Box::new(TestEntity::new(true, stats.clone()))).unwrap();
+
+    assert_server_send!(server, connection_one, vec![], vec![0, 0, 3, 0, 1, 255, 128, 255]).expect("Server sents SendCreateToClient packet to Client.");
+    assert_server_send!(server, connection_one, vec![1, 0], vec![1, 0]).expect("Server accepts ConfirmCreateToServer from Client.");
+
+    // Entity is destroyed and handed out
+    let taken = server.entity_take(entity).expect("Server hands out the destroyed entity.");
+    assert_stats!(stats, destroyed_calls, 1);
+    assert_stats!(stats, drop_calls, 0);
+    assert_eq!(server.stats().entities_destroyed, 1);
+    assert_eq!(server.map_entities(|_, _| ()).len(), 0);
+
+    // Handle is removed right away
+    assert_eq!(server.entity_count(), 0);
+    assert_eq!(server.pending_handle_count(), 0);
+    assert_eq!(server.check_invariants(), Ok(()));
+
+    // Client is still told to destroy the entity in front of the slot's new entity
+    server.entity_create_with(|| Box::new(TestEntity::new(true, stats.clone()))).unwrap();
+    assert_server_send!(server, connection_one, vec![], vec![4, 0, 0, 0, 3, 0, 1, 255, 128, 255]).expect("Server sents SendDestroyToClient packet to Client.");
+    assert_server_send!(server, connection_one, vec![4, 0], vec![0, 0, 3, 0, 1, 255, 128, 255]).expect("Server ignores ConfirmDestroyToServer for the taken entity.");
+    server.update_entities_with(|_, _| {});
+
+    // Dropping the handle does not drop the taken entity
+    assert_stats!(stats, drop_calls, 0);
+    drop(taken);
+    assert_stats!(stats, drop_calls, 1);
+
+    // Foreign tokens are rejected
+    let mut server_two = Server::<ServerEntity, TestUserData>::new(config(5));
+    let foreign = server_two.entity_create_with(|| Box::new(TestEntity::new(true, stats.clone()))).unwrap();
+    assert!(server.entity_take(foreign).is_err());
+
+}
+
+#[test]
+fn test_server_entity_take_unsent() {
+
+    let mut server = Server::<ServerEntity, TestUserData>::new(config(5));
+    let stats: Rc<Mutex<TestStat>> = Rc::new(Mutex::new(TestStat::default()));
+
+    let connection_one = server.connection_add_with(||TestUserData::new(255)).unwrap();
+    let entity = server.entity_create_with(|| Box::new(TestEntity::new(true, stats.clone()))).unwrap();
+
+    // Entities taken before their creation was send are never announced
+    server.entity_take(entity).expect("Server hands out the destroyed entity.");
+    assert_server_send_empty!(server, connection_one, vec![]).expect("Server sends no SendDestroyToClient packet for unsent entity.");
+    assert_eq!(server.check_invariants(), Ok(()));
+
+}
+
+#[test]
+fn test_server_entity_transient() {
+
+    let mut server = Server::<ServerEntity, TestUserData>::new(config(5));
+    let stats: Rc<Mutex<TestStat>> = Rc::new(Mutex::new(TestStat::default()));
+    stats.lock().unwrap().transient = true;
+
+    let connection_one = server.connection_add_with(||TestUserData::new(255)).unwrap();
+    let entity = server.entity_create_with(|| Box::new(TestEntity::new(true, stats.clone()))).unwrap();
+
+    assert_server_send!(server, connection_one, vec![], vec![0, 0, 3, 0, 1, 255, 128, 255]).expect("Server sents SendCreateToClient packet to Client.");
+    assert_server_send!(server, connection_one, vec![1, 0], vec![1, 0]).expect("Server accepts ConfirmCreateToServer from Client.");
+
+    // Handle is dropped right away
+    let observer = server.entity_observer(&entity);
+    server.entity_destroy(entity).unwrap();
+    assert_stats!(stats, destroyed_calls, 1);
+    assert_stats!(stats, drop_calls, 1);
+    assert_eq!(format!("{:?}", server), "EntityServer (1 connection(s), 0 entity(s))");
+
+    // Slot is free for re-use
+    stats.lock().unwrap().transient = false;
+    let reused = server.entity_create_with(|| Box::new(TestEntity::new(true, stats.clone()))).unwrap();
+
+    // Single destroy is send in front of the new entity and names the destroyed one
+    assert_eq!(server.connection_send_with_events(&connection_one, 4096), Ok((vec![vec![4, 0, 0, 0, 3, 0, 1, 255, 128, 255]], vec![
+        ConnectionSendEvent::Destroyed(observer.clone()),
+        ConnectionSendEvent::Created(server.entity_observer(&reused))
+    ])));
+    assert!(server.entity_observe(&observer).is_none());
+    assert_server_send!(server, connection_one, vec![], vec![0, 0, 3, 0, 1, 255, 128, 255]).expect("Server does not retry SendDestroyToClient.");
+
+    // Late confirmation of the destruction is ignored
+    assert_server_send!(server, connection_one, vec![4, 0], vec![0, 0, 3, 0, 1, 255, 128, 255]).expect("Server ignores ConfirmDestroyToServer for dropped transient entity.");
+
+}
+
+#[test]
+fn test_server_entity_forget() {
+
+    let mut server = Server::<ServerEntity, TestUserData>::new(config(5));
+    let stats: Rc<Mutex<TestStat>> = Rc::new(Mutex::new(TestStat::default()));
+
+    let connection_one = server.connection_add_with(||TestUserData::new(96)).unwrap();
+    let _ = server.entity_create_with(|| Box::new(TestEntity::new(true, stats.clone()))).unwrap();
+
+    assert_stats!(stats, created_calls, 1);
+    assert_stats!(stats, destroyed_calls, 0);
+
+
+    // None -> SendCreateToClient
+    assert_server_send!(server, connection_one, vec![], vec![0, 0, 3, 0, 1, 255, 128, 96]).expect("Server sents SendCreateToClient packet to Client.");
+
+    stats.lock().unwrap().filter_for_connection = true;
+
+    // None -> SendCreateToClient (even though filtered)
+    assert_server_send!(server, connection_one, vec![], vec![0, 0, 3, 0, 1, 255, 128, 96]).expect("Server does not send SendForgetToClient packet to Client if entity has not yet been confirmed to be created.");
+
+    // ConfirmCreateToServer -> ConfirmClientCreate
+    assert_server_send!(server, connection_one, vec![1, 0], vec![5, 0]).expect("Server accepts ConfirmCreateToServer from Client and responds with SendForgetToClient for filtered entity.");
+
+    // None -> SendForgetToClient
+    assert_server_send!(server, connection_one, vec![], vec![5, 0]).expect("Server does send SendForgetToClient packet to Client if entity has been confirmed to be created.");
+
+    // ConfirmCreateToServer -> SendForgetToClient
+    assert_server_send!(server, connection_one, vec![1, 0], vec![5, 0]).expect("Server ignores ConfirmCreateToServer from Client for forgotten entity.");
+
+    // SendUpdateToServer -> SendForgetToClient
+    assert_server_send!(server, connection_one, vec![1, 0], vec![5, 0]).expect("Server ignores ConfirmCreateToServer from Client for forgotten entity.");
+    assert_stats!(stats, merge_calls, 0);
+
+    // ConfirmClientCreate -> SendForgetToClient
+    assert_server_send!(server, connection_one, vec![1, 0], vec![5, 0]).expect("Server ignores ConfirmCreateToServer from Client for forgotten entity.");
+
+    // ConfirmDestroyToServer -> None
+    assert_server_send_empty!(server, connection_one, vec![4, 0]).expect("Server accepts ConfirmDestroyToServer from Client for forgotten entity.");
+
+    // None -> None
+    assert_server_send_empty!(server, connection_one, vec![]).expect("Server does not send any packets for filtered entity which does not exist on the client.");
+
+    stats.lock().unwrap().filter_for_connection = false;
+
+    // None -> SendCreateToClient again
+    assert_server_send!(server, connection_one, vec![], vec![0, 0, 3, 0, 1, 255, 128, 96]).expect("Server sents SendCreateToClient packet to Client once the entity is no longer filtered.");
+
+
+}
+
+#[test]
+fn test_server_entity_hide() {
+
+    let mut server = Server::<ServerEntity, TestUserData>::new(config(5));
+    let stats: Rc<Mutex<TestStat>> = Rc::new(Mutex::new(TestStat::default()));
+    stats.lock().unwrap().forget_mode = Some(ForgetMode::Hide);
+
+    let connection_one = server.connection_add_with(||TestUserData::new(96)).unwrap();
+    let entity = server.entity_create_with(|| Box::new(TestEntity::new(true, stats.clone()))).unwrap();
+
+    assert_server_send!(server, connection_one, vec![], vec![0, 0, 3, 0, 1, 255, 128, 96]).expect("Server sents SendCreateToClient packet to Client.");
+    assert_server_send!(server, connection_one, vec![1, 0], vec![1, 0]).expect("Server accepts ConfirmCreateToServer from Client.");
+    assert_server_send_empty!(server, connection_one, vec![2, 0]).expect("Server accepts AcceptServerUpdate from Client.");
+    assert_stats!(stats, confirmed_calls, 1);
+
+    stats.lock().unwrap().filter_for_connection = true;
+
+    // None -> SendHideToClient
+    assert_eq!(server.connection_send_with_events(&connection_one, 4096).unwrap().1, vec![ConnectionSendEvent::Hidden(server.entity_observer(&entity))]);
+    assert_server_send!(server, connection_one, vec![], vec![2, 0]).expect("Server repeats SendHideToClient until the Client confirms it.");
+    assert_eq!(server.unobserved_entities(), vec![&entity]);
+
+    // Un-filtering has no effect until the hide was confirmed
+    stats.lock().unwrap().filter_for_connection = false;
+    assert_server_send!(server, connection_one, vec![], vec![2, 0]).expect("Server repeats SendHideToClient until the Client confirms it.");
+    stats.lock().unwrap().filter_for_connection = true;
+
+    // ConfirmDestroyToServer -> None
+    stats.lock().unwrap().part_bytes_value = Some(vec![1]);
+    assert_server_send_empty!(server, connection_one, vec![4, 0]).expect("Server accepts ConfirmDestroyToServer from Client for hidden entity.");
+    assert_server_send_empty!(server, connection_one, vec![]).expect("Server does not send updates for hidden entities.");
+    assert!(!server.connection_has_visible_entities(&connection_one));
+    assert_eq!(server.unobserved_entities(), vec![&entity]);
+
+    // Hidden entities are revealed without being re-created
+    stats.lock().unwrap().filter_for_connection = false;
+    assert_server_send!(server, connection_one, vec![], vec![1, 0]).expect("Server sends ConfirmClientCreate to reveal hidden entity.");
+    assert!(server.unobserved_entities().is_empty());
+    assert_server_send!(server, connection_one, vec![], vec![1, 0]).expect("Server repeats ConfirmClientCreate until the Client accepts it.");
+    assert_server_send!(server, connection_one, vec![2, 0], vec![3, 0, 1, 1]).expect("Server resumes updates once the Client accepted them.");
+    assert_stats!(stats, confirmed_calls, 1);
+    assert_stats!(stats, new, 1);
+
+    // Hidden entities are still destroyed on the client
+    stats.lock().unwrap().filter_for_connection = true;
+    assert_server_send!(server, connection_one, vec![], vec![2, 0]).expect("Server sends SendHideToClient for filtered entity.");
+    assert_server_send_empty!(server, connection_one, vec![4, 0]).expect("Server accepts ConfirmDestroyToServer from Client for hidden entity.");
+    server.entity_destroy(entity).unwrap();
+    assert_server_send!(server, connection_one, vec![], vec![4, 0]).expect("Server sends SendDestroyToClient for hidden entity.");
+    assert_server_send_empty!(server, connection_one, vec![4, 0]).expect("Server accepts ConfirmDestroyToServer from Client for destroyed entity.");
+
+}
+
+#[test]
+fn test_server_connection_has_visible_entities() {
+
+    let mut server = Server::<ServerEntity, TestUserData>::new(config(5));
+    let stats: Rc<Mutex<TestStat>> = Rc::new(Mutex::new(TestStat::default()));
+    let hidden: Arc<Mutex<Vec<usize>>> = Arc::new(Mutex::new(Vec::new()));
+
+    let hidden_callback = hidden.clone();
+    server.set_connection_hidden_callback(move |connection| {
+        hidden_callback.lock().unwrap().push(connection.index());
+    });
+
+    let connection_one = server.connection_add_with(||TestUserData::new(96)).unwrap();
+    let _ = server.entity_create_with(|| Box::new(TestEntity::new(true, stats.clone()))).unwrap();
+    assert!(!server.connection_has_visible_entities(&connection_one));
+
+    // Entities become visible once the client confirmed their creation
+    assert_server_send!(server, connection_one, vec![], vec![0, 0, 3, 0, 1, 255, 128, 96]).expect("Server sents SendCreateToClient packet to Client.");
+    assert!(!server.connection_has_visible_entities(&connection_one));
+    assert_server_send!(server, connection_one, vec![1, 0], vec![1, 0]).expect("Server accepts ConfirmCreateToServer from Client.");
+    assert!(server.connection_has_visible_entities(&connection_one));
+    assert!(hidden.lock().unwrap().is_empty());
+
+    // Forgetting the only entity hides it from the connection
+    stats.lock().unwrap().filter_for_connection = true;
+    assert_server_send!(server, connection_one, vec![], vec![5, 0]).expect("Server sends SendForgetToClient for filtered entity.");
+    assert!(!server.connection_has_visible_entities(&connection_one));
+    assert_eq!(*hidden.lock().unwrap(), vec![0]);
+
+    // The callback only fires on the transition
+    assert_server_send_empty!(server, connection_one, vec![4, 0]).expect("Server accepts ConfirmDestroyToServer from Client for forgotten entity.");
+    assert!(!server.connection_has_visible_entities(&connection_one));
+    assert_eq!(*hidden.lock().unwrap(), vec![0]);
+
+}
+
+#[test]
+fn test_server_unobserved_entities() {
+
+    let mut server = Server::<ServerEntity, TestUserData>::new(config(5));
+    let stats: Rc<Mutex<TestStat>> = Rc::new(Mutex::new(TestStat::default()));
+
+    let connection_one = server.connection_add_with(||TestUserData::new(96)).unwrap();
+    let entity = server.entity_create_with(|| Box::new(TestEntity::new(true, stats.clone()))).unwrap();
+    assert!(server.unobserved_entities().is_empty());
+
+    // Create and confirm the entity on the client
+    assert_server_send!(server, connection_one, vec![], vec![0, 0, 3, 0, 1, 255, 128, 96]).expect("Server sents SendCreateToClient packet to Client.");
+    assert!(server.unobserved_entities().is_empty());
+
+    // Filter the entity for all connections
+    stats.lock().unwrap().filter_for_connection = true;
+    assert_server_send!(server, connection_one, vec![1, 0], vec![5, 0]).expect("Server responds with SendForgetToClient for filtered entity.");
+    assert_eq!(server.unobserved_entities(), vec![&entity]);
+
+    assert_server_send_empty!(server, connection_one, vec![4, 0]).expect("Server accepts ConfirmDestroyToServer from Client for forgotten entity.");
+    assert_eq!(server.unobserved_entities(), vec![&entity]);
+
+    // Entity becomes visible again
+    stats.lock().unwrap().filter_for_connection = false;
+    assert_server_send!(server, connection_one, vec![], vec![0, 0, 3, 0, 1, 255, 128, 96]).expect("Server sents SendCreateToClient packet to Client once the entity is no longer filtered.");
+    assert!(server.unobserved_entities().is_empty());
+
+    // Destroyed entities are never reported
+    stats.lock().unwrap().filter_for_connection = true;
+    server.entity_destroy(entity).unwrap();
+    assert!(server.unobserved_entities().is_empty());
+
+}
+
+#[test]
+fn test_server_entity_forget_destroy() {
+
+    let mut server = Server::<ServerEntity, TestUserData>::new(config(5));
+    let stats: Rc<Mutex<TestStat>> = Rc::new(Mutex::new(TestStat::default()));
+
+    let connection_one = server.connection_add_with(||TestUserData::new(255)).unwrap();
+    let entity = server.entity_create_with(|| Box::new(TestEntity::new(true, stats.clone()))).unwrap();
+
+    stats.lock().unwrap().filter_for_connection = true;
+
+    // None -> SendCreateToClient (even though filtered)
+    assert_server_send!(server, connection_one, vec![], vec![0, 0, 3, 0, 1, 255, 128, 255]).expect("Server does not send SendForgetToClient packet to Client if entity has not yet been confirmed to be created.");
+
+    // ConfirmCreateToServer -> ConfirmClientCreate
+    assert_server_send!(server, connection_one, vec![1, 0], vec![5, 0]).expect("Server accepts ConfirmCreateToServer from Client and responds with SendForgetToClient for filtered entity.");
+
+    server.entity_destroy(entity).ok();
+
+    // None -> SendDestroyToClient
+    assert_server_send!(server, connection_one, vec![], vec![4, 0]).expect("Server sends SendDestroyToClient for destroyed entities.");
+
+}
+
+#[test]
+fn test_server_entity_forgotten_destroy() {
+
+    let mut server = Server::<ServerEntity, TestUserData>::new(config(5));
+    let stats: Rc<Mutex<TestStat>> = Rc::new(Mutex::new(TestStat::default()));
+
+    let connection_one = server.connection_add_with(||TestUserData::new(255)).unwrap();
+    let entity = server.entity_create_with(|| Box::new(TestEntity::new(true, stats.clone()))).unwrap();
+
+    stats.lock().unwrap().filter_for_connection = true;
+
+    // None -> SendCreateToClient (even though filtered)
+    assert_server_send!(server, connection_one, vec![], vec![0, 0, 3, 0, 1, 255, 128, 255]).expect("Server does not send SendForgetToClient packet to Client if entity has not yet been confirmed to be created.");
+
+    // ConfirmCreateToServer -> ConfirmClientCreate
+    assert_server_send!(server, connection_one, vec![1, 0], vec![5, 0]).expect("Server accepts ConfirmCreateToServer from Client and responds with SendForgetToClient for filtered entity.");
+
+    // ConfirmDestroyToServer -> None
+    assert_server_send_empty!(server, connection_one, vec![4, 0]).expect("Server accepts ConfirmDestroyToServer from Client for forgotten entity.");
+
+    server.entity_destroy(entity).ok();
+
+    // None -> SendDestroyToClient
+    assert_server_send!(server, connection_one, vec![], vec![4, 0]).expect("Server still sends SendDestroyToClient for destroyed which the client already confirmed to be forgotten.");
+
+    // Test destroy timeout for forgotten entities
+    let mut count = 0;
+    server.update_entities_with(|_, entity| { entity.server_update(&mut count); });
+    server.update_entities_with(|_, entity| { entity.server_update(&mut count); });
+    server.update_entities_with(|_, entity| { entity.server_update(&mut count); });
+    server.update_entities_with(|_, entity| { entity.server_update(&mut count); });
+    assert_server_send!(server, connection_one, vec![], vec![4, 0]).expect("Server still sends SendDestroyToClient to Client after next 4th update call.");
+
+    server.update_entities_with(|_, entity| { entity.server_update(&mut count); });
+    assert_server_send_empty!(server, connection_one, vec![]).expect("Server sends no further packets after handle was dropped after 5th update call.");
+
+}
+
+#[test]
+fn test_server_connection_entity_state() {
+
+    let mut server = Server::<ServerEntity, TestUserData>::new(config(5));
+    let mut server_two = Server::<ServerEntity, TestUserData>::new(config(5));
+    let stats: Rc<Mutex<TestStat>> = Rc::new(Mutex::new(TestStat::default()));
+
+    let connection_one = server.connection_add_with(||TestUserData::new(255)).unwrap();
+    let connection_two = server_two.connection_add_with(||TestUserData::new(255)).unwrap();
+    let entity = server.entity_create_with(|| Box::new(TestEntity::new(true, stats.clone()))).unwrap();
+
+    assert_eq!(server.connection_entity_state(&connection_one, &entity), Some(RemoteState::Unknown));
+    assert_eq!(server.connection_entity_state(&connection_two, &entity), None);
+    assert_eq!(server_two.connection_entity_state(&connection_two, &entity), None);
+
+    assert_server_send!(server, connection_one, vec![], vec![0, 0, 3, 0, 1, 255, 128, 255]).expect("Server sents SendCreateToClient packet to Client.");
+    assert_eq!(server.connection_entity_state(&connection_one, &entity), Some(RemoteState::Unknown));
+
+    assert_server_send!(server, connection_one, vec![1, 0], vec![1, 0]).expect("Server accepts ConfirmCreateToServer from Client and responds with ConfirmClientCreate.");
+    assert_eq!(server.connection_entity_state(&connection_one, &entity), Some(RemoteState::Create));
+
+    assert_server_send_empty!(server, connection_one, vec![2, 0]).expect("Server accepts AcceptServerUpdate from Client.");
+    assert_eq!(server.connection_entity_state(&connection_one, &entity), Some(RemoteState::Update));
+
+    stats.lock().unwrap().filter_for_connection = true;
+    assert_server_send!(server, connection_one, vec![], vec![5, 0]).expect("Server sends SendForgetToClient packet to Client for filtered entity.");
+    assert_eq!(server.connection_entity_state(&connection_one, &entity), Some(RemoteState::Forget));
+
+    assert_server_send_empty!(server, connection_one, vec![4, 0]).expect("Server accepts ConfirmDestroyToServer from Client for forgotten entity.");
+    assert_eq!(server.connection_entity_state(&connection_one, &entity), Some(RemoteState::Forgotten));
+
+}
+
+#[test]
+fn test_server_connection_slot_reuse() {
+
+    let mut server = Server::<ServerEntity, TestUserData>::new(config(5));
+    let stats: Rc<Mutex<TestStat>> = Rc::new(Mutex::new(TestStat::default()));
+
+    let connection_one = server.connection_add_with(||TestUserData::new(0)).unwrap();
+    let _ = server.entity_create_with(|| Box::new(TestEntity::new(true, stats.clone()))).unwrap();
+
+    // Check for default state
+    assert_server_send!(server, connection_one, vec![], vec![0, 0, 3, 0, 1, 255, 128, 0]).expect("Server sents SendCreateToClient packet to Client.");
+
+    // Change Entity state
+    assert_server_send!(server, connection_one, vec![1, 0], vec![1, 0]).expect("Server accepts ConfirmCreateToServer from Client and responds with ConfirmClientCreate.");
+
+    server.connection_remove(connection_one).expect("Connection removed.");
+
+    // New connection should have
+    let connection_one = server.connection_add_with(||TestUserData::new(0)).unwrap();
+
+    // Check for reset default state on new connection
+    assert_server_send!(server, connection_one, vec![], vec![0, 0, 3, 0, 1, 255, 128, 0]).expect("Server sents SendCreateToClient packet to Client.");
+
+}
+
+#[test]
+fn test_server_connection_add_remove() {
+    let mut server = Server::<ServerEntity, TestUserData>::new(config(5));
+    let connection_one = server.connection_add_with(||TestUserData::new(0));
+    assert!(connection_one.is_ok());
+    assert!(server.connection_remove(connection_one.unwrap()).is_ok());
+}
+
+#[test]
+fn test_server_active_connection_indices() {
+
+    let mut server = Server::<ServerEntity, TestUserData>::new(config(5));
+    assert_eq!(server.connection_tokens_len(), 0);
+    assert_eq!(server.active_connection_indices().collect::<Vec<usize>>(), Vec::<usize>::new());
+
+    let connection_one = server.connection_add_with(||TestUserData::new(0)).unwrap();
+    let connection_two = server.connection_add_with(||TestUserData::new(0)).unwrap();
+    let connection_three = server.connection_add_with(||TestUserData::new(0)).unwrap();
+    assert_eq!(server.connection_tokens_len(), 3);
+    assert_eq!(server.active_connection_indices().collect::<Vec<usize>>(), vec![
+        connection_one.index(),
+        connection_two.index(),
+        connection_three.index()
+    ]);
+
+    let index_two = connection_two.index();
+    server.connection_remove(connection_two).unwrap();
+    assert_eq!(server.connection_tokens_len(), 2);
+    assert_eq!(server.active_connection_indices().collect::<Vec<usize>>(), vec![
+        connection_one.index(),
+        connection_three.index()
+    ]);
+
+    // Freed slots are re-used
+    let connection_four = server.connection_add_with(||TestUserData::new(0)).unwrap();
+    assert_eq!(connection_four.index(), index_two);
+    assert_eq!(server.active_connection_indices().collect::<Vec<usize>>(), vec![
+        connection_one.index(),
+        connection_three.index(),
+        index_two
+    ]);
+
+}
+
+#[test]
+fn test_server_ignore_invalid_packets() {
+
+    let mut server = Server::<ServerEntity, TestUserData>::new(config(5));
+    let connection_one = server.connection_add_with(||TestUserData::new(0)).expect("Failed to add client connection to server");;
+
+    assert_server_send_empty!(server, connection_one, vec![]).expect("Server ignores empty client packets");
+    assert_server_send_empty!(server, connection_one, vec![1]).expect("Server ignores client packets with len 1");
+    assert_server_send_empty!(server, connection_one, vec![1, 0]).expect("Server ignores ConfirmCreateToServer for non existent entity");
+    assert_server_send_empty!(server, connection_one, vec![2, 0]).expect("Server ignores AcceptServerUpdate for non existent entity");
+    assert_server_send_empty!(server, connection_one, vec![4, 0]).expect("Server ignores ConfirmDestroyToServer for non existent entity");
+
+    assert_eq!(server.connection_receive(&connection_one, vec![3, 2]), Err(ServerError::TruncatedEntityData(2)));
+    assert_eq!(server.connection_receive(&connection_one, vec![3, 2, 1]), Err(ServerError::TruncatedEntityData(2)));
+    assert_server_send_empty!(server, connection_one, vec![3, 2, 1, 2]).expect("Server ignores SendUpdateToServer packet for non existent entity");
+    assert_server_send_empty!(server, connection_one, vec![3, 2, 1, 2, 5]).expect("Server ignores SendUpdateToServer packet for non existent entity");
+
+    assert_eq!(assert_server_send!(server, connection_one, vec![3, 2, 10, 2, 5], vec![]), Err(ServerError::TruncatedEntityData(2)));
+    assert_eq!(assert_server_send!(server, connection_one, vec![4, 2, 10, 2, 5], vec![]), Err(ServerError::RemainingPacketData(vec![5])));
+    assert_server_send_empty!(server, connection_one, vec![5, 2, 1]).expect("Server ignores AcknowledgeServerUpdate for non existent entity");
+    assert_eq!(server.connection_receive(&connection_one, vec![5, 2]), Err(ServerError::TruncatedEntityData(2)));
+    assert_eq!(server.connection_receive(&connection_one, vec![5, 2, 1, 5, 3]), Err(ServerError::TruncatedEntityData(3)));
+    assert_eq!(assert_server_send!(server, connection_one, vec![8, 2], vec![]), Err(ServerError::InvalidPacketData(vec![8, 2])));
+    assert_eq!(assert_server_send!(server, connection_one, vec![255, 2], vec![]), Err(ServerError::InvalidPacketData(vec![255, 2])));
+
+}
+
+#[test]
+fn test_server_receive_with_custom_packets() {
+
+    let mut server = Server::<ServerEntity, TestUserData>::new(config(5));
+    let connection_one = server.connection_add_with(||TestUserData::new(0)).unwrap();
+    let mut custom = Vec::new();
+
+    // Whole custom packets
+    assert!(server.connection_receive_with(&connection_one, vec![255, 2], |bytes| custom.push(bytes.to_vec())).is_ok());
+
+    // Custom data trailing entity data
+    assert!(server.connection_receive_with(&connection_one, vec![1, 0, 9, 1, 2], |bytes| custom.push(bytes.to_vec())).is_ok());
+    assert!(server.connection_receive_with(&connection_one, vec![2, 0, 9], |bytes| custom.push(bytes.to_vec())).is_ok());
+
+    assert_eq!(custom, vec![vec![255, 2], vec![9, 1, 2], vec![9]]);
+
+    // Data within the reserved range is still reported as an error
+    assert_eq!(server.connection_receive_with(&connection_one, vec![8, 2], |_| unreachable!()), Err(ServerError::InvalidPacketData(vec![8, 2])));
+    assert_eq!(server.connection_receive_with(&connection_one, vec![1, 0, 7, 1, 2], |_| unreachable!()), Err(ServerError::RemainingPacketData(vec![2])));
+
+}
+
+#[test]
+fn test_server_send_inspector() {
+
+    let mut server = Server::<ServerEntity, TestUserData>::new(config(3));
+    let stats: Rc<Mutex<TestStat>> = Rc::new(Mutex::new(TestStat::default()));
+    let inspected: Arc<Mutex<Vec<(usize, Vec<u8>)>>> = Arc::new(Mutex::new(Vec::new()));
+
+    let server_inspected = inspected.clone();
+    server.set_send_inspector(move |connection, bytes| {
+        server_inspected.lock().unwrap().push((connection.index(), bytes.to_vec()));
+    });
+
+    let connection_one = server.connection_add_with(||TestUserData::new(255)).unwrap();
+    let connection_two = server.connection_add_with(||TestUserData::new(96)).unwrap();
+    server.entity_create_with(|| Box::new(TestEntity::new(true, stats.clone()))).unwrap();
+    server.entity_create_with(|| Box::new(TestEntity::new(true, stats.clone()))).unwrap();
+
+    let packets_one = server.connection_send(&connection_one, 8).unwrap();
+    let packets_two = server.connection_send(&connection_two, 4096).unwrap();
+    assert_eq!(packets_one.len(), 2);
+    assert_eq!(packets_two.len(), 1);
+
+    let mut expected: Vec<(usize, Vec<u8>)> = packets_one.iter().map(|p| (connection_one.index(), p.clone())).collect();
+    expected.extend(packets_two.iter().map(|p| (connection_two.index(), p.clone())));
+    assert_eq!(*inspected.lock().unwrap(), expected);
+
+    // Empty sends are not inspected
+    inspected.lock().unwrap().clear();
+    let mut server_empty = Server::<ServerEntity, TestUserData>::new(config(3));
+    let connection_empty = server_empty.connection_add_with(||TestUserData::new(0)).unwrap();
+    let empty_inspected = inspected.clone();
+    server_empty.set_send_inspector(move |connection, bytes| {
+        empty_inspected.lock().unwrap().push((connection.index(), bytes.to_vec()));
+    });
+    assert_eq!(server_empty.connection_send(&connection_empty, 4096), Ok(vec![]));
+    assert!(inspected.lock().unwrap().is_empty());
+
+}
+
+#[test]
+fn test_server_connection_receive_many() {
+
+    let mut server = Server::<ServerEntity, TestUserData>::new(config(5));
+    let stats: Rc<Mutex<TestStat>> = Rc::new(Mutex::new(TestStat::default()));
+
+    let connection_one = server.connection_add_with(||TestUserData::new(96)).unwrap();
+    let _ = server.entity_create_with(|| Box::new(TestEntity::new(true, stats.clone()))).unwrap();
+    let _ = server.entity_create_with(|| Box::new(TestEntity::new(true, stats.clone()))).unwrap();
+    assert_eq!(server.connection_send(&connection_one, 4096), Ok(vec![vec![
+        0, 0, 3, 0, 1, 255, 128, 96,
+        0, 1, 3, 0, 1, 255, 128, 96
+    ]]));
+
+    // Processing stops at the malformed third packet
+    assert_eq!(server.connection_receive_many(&connection_one, vec![
+        vec![1, 0],
+        vec![2, 0],
+        vec![8, 2],
+        vec![1, 1]
+
+    ]), Err((2, ServerError::InvalidPacketData(vec![8, 2]))));
+
+    // Packets in front of it were applied, the ones after it were not
+    assert_stats!(stats, confirmed_calls, 1);
+    assert_eq!(server.connection_send(&connection_one, 4096), Ok(vec![vec![0, 1, 3, 0, 1, 255, 128, 96]]));
+
+    assert_eq!(server.connection_receive_many(&connection_one, vec![vec![1, 1], vec![2, 1]]), Ok(()));
+    assert_stats!(stats, confirmed_calls, 2);
+    assert_eq!(server.connection_receive_many(&connection_one, vec![]), Ok(()));
+
+    // Token errors are reported for the first packet
+    let mut server_two = Server::<ServerEntity, TestUserData>::new(config(5));
+    let connection_two = server_two.connection_add_with(||TestUserData::new(0)).unwrap();
+    assert_eq!(server.connection_receive_many(&connection_two, vec![vec![1, 0], vec![2, 0]]), Err((0, ServerError::UnknownReceiverToken(vec![1, 0]))));
+
+}
+
+#[test]
+fn test_server_connection_receive_slice() {
+
+    let server_config = || Config {
+        packet_header: Some(PacketHeader::new(1, |bytes| bytes[0] = 7)),
+        ..Config::default()
+    };
+
+    let mut server = Server::<ServerEntity, TestUserData>::new(server_config());
+    let mut server_slice = Server::<ServerEntity, TestUserData>::new(server_config());
+    let stats: Rc<Mutex<TestStat>> = Rc::new(Mutex::new(TestStat::default()));
+    let stats_slice: Rc<Mutex<TestStat>> = Rc::new(Mutex::new(TestStat::default()));
+
+    let connection_one = server.connection_add_with(||TestUserData::new(255)).unwrap();
+    let connection_slice = server_slice.connection_add_with(||TestUserData::new(255)).unwrap();
+    for _ in 0..2 {
+        server.entity_create_with(|| Box::new(TestEntity::new(true, stats.clone()))).unwrap();
+        server_slice.entity_create_with(|| Box::new(TestEntity::new(true, stats_slice.clone()))).unwrap();
+    }
+
+    let mut send = |bytes: Vec<u8>| {
+        let result = server.connection_receive(&connection_one, bytes.clone());
+        assert_eq!(server_slice.connection_receive_slice(&connection_slice, &bytes), result);
+        let packets = server.connection_send(&connection_one, 4096).unwrap();
+        assert_eq!(server_slice.connection_send(&connection_slice, 4096).unwrap(), packets);
+        (result, packets)
+    };
+
+    assert_eq!(send(vec![]), (Ok(()), vec![vec![
+        7,
+        0, 0, 3, 0, 1, 255, 128, 255,
+        0, 1, 3, 0, 1, 255, 128, 255
+    ]]));
+    assert_eq!(send(vec![7, 1, 0, 1, 1]), (Ok(()), vec![vec![7, 1, 0, 1, 1]]));
+    assert_eq!(send(vec![7, 2, 0, 2, 1]), (Ok(()), vec![]));
+
+    // Errors hand back the same bytes
+    assert_eq!(send(vec![7, 8, 2]).0, Err(ServerError::InvalidPacketData(vec![8, 2])));
+
+    // Foreign tokens are rejected
+    let mut server_two = Server::<ServerEntity, TestUserData>::new(server_config());
+    let connection_two = server_two.connection_add_with(||TestUserData::new(0)).unwrap();
+    assert_eq!(server.connection_receive_slice(&connection_two, &[7, 1, 0]), Err(ServerError::UnknownReceiverToken(vec![7, 1, 0])));
+    assert_stats!(stats, confirmed_calls, 2);
+    assert_stats!(stats_slice, confirmed_calls, 2);
+
+}
+
+#[test]
+fn test_server_connection_receive_partial() {
+
+    let mut server = Server::<ServerEntity, TestUserData>::new(config(1));
+    let stats: Rc<Mutex<TestStat>> = Rc::new(Mutex::new(TestStat::default()));
+
+    let connection_one = server.connection_add_with(||TestUserData::new(255)).unwrap();
+    let entity = server.entity_create_with(|| Box::new(TestEntity::new(true, stats.clone()))).unwrap();
+
+    assert_server_send!(server, connection_one, vec![], vec![0, 0, 3, 0, 1, 255, 128, 255]).expect("Server sents SendCreateToClient packet to Client.");
+    assert_eq!(server.connection_receive_partial(&connection_one, &[1, 0, 2]), Ok(2));
+    assert_eq!(server.connection_receive_partial(&connection_one, &[2, 0]), Ok(2));
+    assert_eq!(server.connection_entity_state(&connection_one, &entity), Some(RemoteState::Update));
+
+    // Truncated SendUpdateToServer states are not consumed
+    stats.lock().unwrap().merge_bytes_value = vec![1, 2];
+    assert_eq!(server.connection_receive_partial(&connection_one, &[]), Ok(0));
+    assert_eq!(server.connection_receive_partial(&connection_one, &[3]), Ok(0));
+    assert_eq!(server.connection_receive_partial(&connection_one, &[3, 0]), Ok(0));
+    assert_eq!(server.connection_receive_partial(&connection_one, &[3, 0, 2, 1]), Ok(0));
+    assert_stats!(stats, merge_calls, 0);
+
+    // Complete states in front of a truncated one are consumed
+    assert_eq!(server.connection_receive_partial(&connection_one, &[3, 0, 2, 1, 2, 3, 0, 2]), Ok(5));
+    assert_stats!(stats, merge_calls, 1);
+    assert_eq!(server.connection_receive_partial(&connection_one, &[3, 0, 2, 1, 2, 3, 0, 2, 1, 2]), Ok(10));
+    assert_stats!(stats, merge_calls, 3);
+
+    // Truncated acknowledgements are not consumed either
+    assert_eq!(server.connection_receive_partial(&connection_one, &[5, 0]), Ok(0));
+
+    // Invalid data is still reported as an error
+    assert_eq!(server.connection_receive_partial(&connection_one, &[255, 2]), Err(ServerError::InvalidPacketData(vec![255, 2])));
+    assert_eq!(server.connection_receive_partial(&connection_one, &[1, 0, 7, 1, 2]), Err(ServerError::RemainingPacketData(vec![2])));
+
+}
+
+#[test]
+fn test_server_rejected_entity_update() {
+
+    let mut server = Server::<ServerEntity, TestUserData>::new(config(1));
+    let stats: Rc<Mutex<TestStat>> = Rc::new(Mutex::new(TestStat::default()));
+
+    let connection_one = server.connection_add_with(||TestUserData::new(255)).unwrap();
+    server.entity_create_with(|| Box::new(TestEntity::new(true, stats.clone()))).unwrap();
+
+    assert_server_send!(server, connection_one, vec![], vec![0, 0, 3, 0, 1, 255, 128, 255]).expect("Server sents SendCreateToClient packet to Client.");
+    assert_server_send!(server, connection_one, vec![1, 0], vec![1, 0]).expect("Server accepts ConfirmCreateToServer from Client.");
+    assert_server_send_empty!(server, connection_one, vec![2, 0]).expect("Server accepts AcceptServerUpdate from Client.");
+
+    stats.lock().unwrap().reject_merge = true;
+    assert_eq!(server.connection_receive(&connection_one, vec![3, 0, 2, 13, 37]), Err(ServerError::RejectedEntityUpdate(0, 0)));
+    assert_stats!(stats, merge_calls, 1);
+    assert_eq!(server.stats().updates_merged, 0);
+
+    stats.lock().unwrap().reject_merge = false;
+    stats.lock().unwrap().merge_bytes_value = vec![13, 37];
+    assert_eq!(server.connection_receive(&connection_one, vec![3, 0, 2, 13, 37]), Ok(()));
+    assert_eq!(server.stats().updates_merged, 1);
+
+}
+
+#[test]
+fn test_server_stats() {
+
+    let mut server = Server::<ServerEntity, TestUserData>::new(config(1));
+    let stats: Rc<Mutex<TestStat>> = Rc::new(Mutex::new(TestStat::default()));
+
+    let connection_one = server.connection_add_with(||TestUserData::new(255)).unwrap();
+    let entity_one = server.entity_create_with(|| Box::new(TestEntity::new(true, stats.clone()))).unwrap();
+    let _ = server.entity_create_with(|| Box::new(TestEntity::new(true, stats.clone()))).unwrap();
+
+    assert_eq!(server.stats(), &ServerStats {
+        entities_created: 2,
+        entities_destroyed: 0,
+        bytes_sent: 0,
+        updates_merged: 0
+    });
+
+    assert_server_send!(server, connection_one, vec![], vec![
+        0, 0, 3, 0, 1, 255, 128, 255,
+        0, 1, 3, 0, 1, 255, 128, 255
+
+    ]).expect("Server sents SendCreateToClient packets to Client.");
+
+    assert_server_send!(server, connection_one, vec![1, 0, 1, 1], vec![1, 0, 1, 1]).expect("Server accepts ConfirmCreateToServer from Client.");
+    assert_server_send_empty!(server, connection_one, vec![2, 0, 2, 1]).expect("Server accepts AcceptServerUpdate from Client.");
+
+    stats.lock().unwrap().merge_bytes_value = vec![64, 56, 244];
+    assert_server_send_empty!(server, connection_one, vec![
+        3, 0, 3, 64, 56, 244,
+        3, 1, 3, 64, 56, 244
+
+    ]).expect("Server accepts SendUpdateToServer packets with data bytes.");
+
+    server.entity_destroy(entity_one).unwrap();
+    assert_server_send!(server, connection_one, vec![], vec![4, 0]).expect("Server sends SendDestroyToClient for destroyed entity.");
+
+    assert_eq!(server.stats(), &ServerStats {
+        entities_created: 2,
+        entities_destroyed: 1,
+        bytes_sent: 22,
+        updates_merged: 2
+    });
+
+    server.reset_stats();
+    assert_eq!(server.stats(), &ServerStats::default());
+
+}
+
+#[test]
+fn test_server_update_stats() {
+
+    let mut server = Server::<ServerEntity, TestUserData>::new(config(2));
+    let stats: Rc<Mutex<TestStat>> = Rc::new(Mutex::new(TestStat::default()));
+
+    let entity_one = server.entity_create_with(|| Box::new(TestEntity::new(true, stats.clone()))).unwrap();
+    let _ = server.entity_create_with(|| Box::new(TestEntity::new(true, stats.clone()))).unwrap();
+
+    assert_eq!(server.update_entities_with(|_, _| {}), ServerUpdateStats {
+        handles_dropped: 0,
+        handles_timed_out: 0,
+        alive: 2,
+        sleeping: 0
+    });
+
+    // Without any connections the handle is dropped right away
+    server.entity_destroy(entity_one).unwrap();
+    assert_eq!(server.update_entities_with(|_, _| {}), ServerUpdateStats {
+        handles_dropped: 1,
+        handles_timed_out: 0,
+        alive: 1,
+        sleeping: 0
+    });
+
+    // With an unresponsive connection the handle times out
+    let connection_one = server.connection_add_with(||TestUserData::new(255)).unwrap();
+    let entity_three = server.entity_create_with(|| Box::new(TestEntity::new(true, stats.clone()))).unwrap();
+    assert_server_send!(server, connection_one, vec![], vec![
+        0, 0, 3, 0, 1, 255, 128, 255,
+        0, 1, 3, 0, 1, 255, 128, 255
+
+    ]).expect("Server sents SendCreateToClient packets to Client.");
+
+    server.entity_destroy(entity_three).unwrap();
+    assert_eq!(server.update_entities_with(|_, _| {}), ServerUpdateStats {
+        handles_dropped: 0,
+        handles_timed_out: 0,
+        alive: 1,
+        sleeping: 0
+    });
+
+    assert_eq!(server.update_entities_with(|_, _| {}), ServerUpdateStats {
+        handles_dropped: 1,
+        handles_timed_out: 1,
+        alive: 1,
+        sleeping: 0
+    });
+
+}
+
+#[test]
+fn test_server_entity_dropped_reason() {
+
+    let mut server = Server::<ServerEntity, TestUserData>::new(config(2));
+    let stats_one: Rc<Mutex<TestStat>> = Rc::new(Mutex::new(TestStat::default()));
+    let stats_two: Rc<Mutex<TestStat>> = Rc::new(Mutex::new(TestStat::default()));
+
+    // Without any connections the destruction is confirmed right away
+    let entity_one = server.entity_create_with(|| Box::new(TestEntity::new(true, stats_one.clone()))).unwrap();
+    server.entity_destroy(entity_one).unwrap();
+    assert_stats_clone!(stats_one, drop_reasons, vec![DropReason::Confirmed]);
+    server.update_entities_with(|_, _| {});
+    assert_stats_clone!(stats_one, drop_reasons, vec![DropReason::Confirmed]);
+
+    // With an unresponsive connection the handle times out
+    let connection_one = server.connection_add_with(||TestUserData::new(255)).unwrap();
+    let entity_two = server.entity_create_with(|| Box::new(TestEntity::new(true, stats_two.clone()))).unwrap();
+    assert_server_send!(server, connection_one, vec![], vec![
+        0, 0, 3, 0, 1, 255, 128, 255
+
+    ]).expect("Server sents SendCreateToClient packet to Client.");
+
+    server.entity_destroy(entity_two).unwrap();
+    server.update_entities_with(|_, _| {});
+    assert_stats_clone!(stats_two, drop_reasons, Vec::<DropReason>::new());
+    server.update_entities_with(|_, _| {});
+    assert_stats_clone!(stats_two, drop_reasons, vec![DropReason::TimedOut]);
+
+    // Dropped handles are not notified again
+    server.update_entities_with(|_, _| {});
+    assert_stats_clone!(stats_one, drop_reasons, vec![DropReason::Confirmed]);
+    assert_stats_clone!(stats_two, drop_reasons, vec![DropReason::TimedOut]);
+
+}
+
+#[test]
+fn test_server_pending_handle_count() {
+
+    let mut server = Server::<ServerEntity, TestUserData>::new(config(3));
+    let stats: Rc<Mutex<TestStat>> = Rc::new(Mutex::new(TestStat::default()));
+
+    // Without any connections destroyed handles are reclaimed on the next update
+    let entity_one = server.entity_create_with(|| Box::new(TestEntity::new(true, stats.clone()))).unwrap();
+    let _ = server.entity_create_with(|| Box::new(TestEntity::new(true, stats.clone()))).unwrap();
+    assert_eq!(server.pending_handle_count(), 0);
+
+    server.entity_destroy(entity_one).unwrap();
+    assert_eq!(server.pending_handle_count(), 1);
+    server.update_entities_with(|_, _| {});
+    assert_eq!(server.pending_handle_count(), 0);
+
+    // With a silent connection destroyed handles linger until they time out
+    let connection_one = server.connection_add_with(||TestUserData::new(255)).unwrap();
+    let entity_three = server.entity_create_with(|| Box::new(TestEntity::new(true, stats.clone()))).unwrap();
+    assert_server_send!(server, connection_one, vec![], vec![
+        0, 0, 3, 0, 1, 255, 128, 255,
+        0, 1, 3, 0, 1, 255, 128, 255
+
+    ]).expect("Server sents SendCreateToClient packets to Client.");
+
+    server.entity_destroy(entity_three).unwrap();
+    assert_eq!(server.pending_handle_count(), 1);
+    server.update_entities_with(|_, _| {});
+    assert_eq!(server.pending_handle_count(), 1);
+    server.update_entities_with(|_, _| {});
+    assert_eq!(server.pending_handle_count(), 1);
+    server.update_entities_with(|_, _| {});
+    assert_eq!(server.pending_handle_count(), 0);
+
+}
+
+#[test]
+fn test_server_live_entity_count() {
+
+    let mut server = Server::<ServerEntity, TestUserData>::new(config(2));
+    let stats: Rc<Mutex<TestStat>> = Rc::new(Mutex::new(TestStat::default()));
+
+    let connection_one = server.connection_add_with(||TestUserData::new(255)).unwrap();
+    let entity_one = server.entity_create_with(|| Box::new(TestEntity::new(true, stats.clone()))).unwrap();
+    let _ = server.entity_create_with(|| Box::new(TestEntity::new(true, stats.clone()))).unwrap();
+    assert_eq!(server.entity_count(), 2);
+    assert_eq!(server.live_entity_count(), 2);
+
+    assert_server_send!(server, connection_one, vec![], vec![
+        0, 0, 3, 0, 1, 255, 128, 255,
+        0, 1, 3, 0, 1, 255, 128, 255
+
+    ]).expect("Server sents SendCreateToClient packets to Client.");
+
+    // Destroyed entities are no longer live while their handle lingers
+    server.entity_destroy(entity_one).unwrap();
+    assert_eq!(server.entity_count(), 2);
+    assert_eq!(server.live_entity_count(), 1);
+
+    server.update_entities_with(|_, _| {});
+    assert_eq!(server.entity_count(), 2);
+    server.update_entities_with(|_, _| {});
+    assert_eq!(server.entity_count(), 1);
+    assert_eq!(server.live_entity_count(), 1);
+
+}
+
+#[test]
+fn test_server_kind_bandwidth() {
+
+    let mut server = Server::<ServerEntity, TestUserData>::new(config(3));
+    let stats_one: Rc<Mutex<TestStat>> = Rc::new(Mutex::new(TestStat::default()));
+    let stats_two: Rc<Mutex<TestStat>> = Rc::new(Mutex::new(TestStat::default()));
+    stats_two.lock().unwrap().kind = Some(2);
+
+    let connection_one = server.connection_add_with(||TestUserData::new(255)).unwrap();
+    server.entity_create_with(|| Box::new(TestEntity::new(true, stats_one.clone()))).unwrap();
+    server.entity_create_with(|| Box::new(TestEntity::new(true, stats_two.clone()))).unwrap();
+
+    assert_server_send!(server, connection_one, vec![], vec![
+        0, 0, 3, 0, 1, 255, 128, 255,
+        0, 1, 3, 0, 2, 255, 128, 255
+
+    ]).expect("Server sents SendCreateToClient packets to Client.");
+    assert_server_send!(server, connection_one, vec![1, 0, 1, 1], vec![1, 0, 1, 1]).expect("Server accepts ConfirmCreateToServer from Client.");
+    assert_server_send_empty!(server, connection_one, vec![2, 0, 2, 1]).expect("Server accepts AcceptServerUpdate from Client.");
+    assert!(server.kind_bandwidth().is_empty());
+
+    // Updates of differing sizes are tallied per kind
+    stats_one.lock().unwrap().part_bytes_value = Some(vec![1, 2, 3, 4]);
+    stats_two.lock().unwrap().part_bytes_value = Some(vec![5]);
+    assert_server_send!(server, connection_one, vec![], vec![3, 0, 4, 1, 2, 3, 4, 3, 1, 1, 5]).expect("Server sends SendUpdateToClient packets.");
+
+    stats_one.lock().unwrap().part_bytes_value = Some(vec![6, 7]);
+    stats_one.lock().unwrap().merge_bytes_value = vec![8, 9, 10];
+    assert_server_send!(server, connection_one, vec![3, 0, 3, 8, 9, 10], vec![3, 0, 2, 6, 7]).expect("Server accepts SendUpdateToServer from Client.");
+
+    assert_eq!(server.kind_bandwidth().get(&1), Some(&KindBandwidth {
+        part_calls: 2,
+        part_bytes: 6,
+        merge_calls: 1,
+        merge_bytes: 3
+    }));
+
+    assert_eq!(server.kind_bandwidth().get(&2), Some(&KindBandwidth {
+        part_calls: 1,
+        part_bytes: 1,
+        merge_calls: 0,
+        merge_bytes: 0
+    }));
+
+    server.reset_stats();
+    assert!(server.kind_bandwidth().is_empty());
+
+}
+
+#[test]
+fn test_server_update_entities_with_drops() {
+
+    let mut server = Server::<ServerEntity, TestUserData>::new(config(2));
+    let stats: Rc<Mutex<TestStat>> = Rc::new(Mutex::new(TestStat::default()));
+
+    let connection_one = server.connection_add_with(||TestUserData::new(255)).unwrap();
+    let entity = server.entity_create_with(|| Box::new(TestEntity::new(true, stats.clone()))).unwrap();
+    let entity_ref = entity.as_ref();
+
+    assert_server_send!(server, connection_one, vec![], vec![0, 0, 3, 0, 1, 255, 128, 255]).expect("Server sents SendCreateToClient packet to Client.");
+    server.entity_destroy(entity).unwrap();
+    assert_server_send!(server, connection_one, vec![], vec![4, 0]).expect("Server sents SendDestroyToClient packet to Client for destroyed entity.");
+
+    // The handle is kept until the destruction times out
+    let mut dropped = Vec::new();
+    server.update_entities_with_drops(|_, _| {}, |token| dropped.push(token.as_ref()));
+    assert!(dropped.is_empty());
+
+    let update_stats = server.update_entities_with_drops(|_, _| {}, |token| dropped.push(token.as_ref()));
+    assert_eq!(update_stats.handles_dropped, 1);
+    assert_eq!(dropped, vec![entity_ref]);
+
+    // Dropped handles are only reported once
+    server.update_entities_with_drops(|_, _| {}, |token| dropped.push(token.as_ref()));
+    assert_eq!(dropped, vec![entity_ref]);
+
+}
+
+#[test]
+fn test_server_update_sleeping_entities() {
+
+    let mut server = Server::<ServerEntity, TestUserData>::new(config(3));
+    let stats: Rc<Mutex<TestStat>> = Rc::new(Mutex::new(TestStat::default()));
+    stats.lock().unwrap().can_sleep = true;
+
+    server.entity_create_with(|| Box::new(TestEntity::new(true, stats.clone()))).unwrap();
+
+    // Unobserved entities sleep but are kept alive
+    let mut count = 0;
+    assert_eq!(server.update_entities_with(|_, _| count += 1), ServerUpdateStats {
+        handles_dropped: 0,
+        handles_timed_out: 0,
+        alive: 0,
+        sleeping: 1
+    });
+    assert_eq!(count, 0);
+    assert_eq!(server.map_entities(|_, _| ()).len(), 1);
+
+    // Observed entities are updated again
+    let connection_one = server.connection_add_with(||TestUserData::new(255)).unwrap();
+    assert_server_send!(server, connection_one, vec![], vec![0, 0, 3, 0, 1, 255, 128, 255]).expect("Server sents SendCreateToClient packet to Client.");
+
+    assert_eq!(server.update_entities_with(|_, _| count += 1), ServerUpdateStats {
+        handles_dropped: 0,
+        handles_timed_out: 0,
+        alive: 1,
+        sleeping: 0
+    });
+    assert_eq!(count, 1);
+
+}
+
+#[test]
+fn test_server_fuzz_invariants() {
+
+    let mut random = TestRandom::new(0x2545_f491_4f6c_dd1d);
+    let mut server = Server::<ServerEntity, TestUserData>::new(config(5));
+    let stats: Rc<Mutex<TestStat>> = Rc::new(Mutex::new(TestStat::default()));
+
+    let mut entities = Vec::new();
+    let mut connections = Vec::new();
+
+    for step in 0..10000 {
+
+        match random.next(10) {
+            0 => if let Ok(entity) = server.entity_create_with(|| Box::new(TestEntityTwo::new(true, stats.clone()))) {
+                entities.push(entity);
+            },
+            1 => if !entities.is_empty() {
+                let index = random.next(entities.len());
+                server.entity_destroy(entities.remove(index)).ok();
+            },
+            2 => if let Ok(connection) = server.connection_add_with(|| TestUserData::new(0)) {
+                connections.push(connection);
+            },
+            3 => if !connections.is_empty() && random.next(4) == 0 {
+                let index = random.next(connections.len());
+                server.connection_remove(connections.remove(index)).ok();
+            },
+            4 => { server.update_entities_with(|_, _| {}); },
+            _ => if !connections.is_empty() {
+
+                // Mix of plausible and random client packets
+                let connection = &connections[random.next(connections.len())];
+                let mut packet = Vec::new();
+                for _ in 0..random.next(4) {
+                    packet.push(random.next(7) as u8);
+                    packet.push(random.next(8) as u8);
+                    if random.next(4) == 0 {
+                        packet.push(random.next(256) as u8);
+                    }
+                }
+
+                server.connection_receive(connection, packet).ok();
+                server.connection_send(connection, 4 + random.next(64)).unwrap();
+            }
+        }
+
+        if let Err(err) = server.check_invariants() {
+            panic!("Invariant violated at step {}: {}", step, err);
+        }
+
+    }
+
+}
+
+
+// Client Tests ---------------------------------------------------------------
+#[derive(Debug)]
+struct ClientRegistry {
+    stats: Rc<Mutex<TestStat>>
+}
+
+impl EntityRegistry<ClientEntity, TestUserData> for ClientRegistry {
+    fn entity_from_kind_and_bytes(&self, kind: u16, bytes: &[u8]) -> Result<Box<ClientEntity>, RegistryError> {
+        match kind {
+            1 | 300 => {
+                let mut entity = TestEntity::from_bytes(bytes).unwrap();
+                entity.set_stats(self.stats.clone());
+                self.stats.lock().unwrap().registry_calls += 1;
+                Ok(Box::new(entity))
+            },
+            2 => {
+                let mut entity = TestEntityTwo::from_bytes(bytes).unwrap();
+                entity.set_stats(self.stats.clone());
+                self.stats.lock().unwrap().registry_calls += 1;
+                Ok(Box::new(entity))
+            },
+            4 => match TestSegmentEntity::from_bytes(bytes) {
+                Some(_) => Ok(Box::new(TestSegmentEntity::new(self.stats.clone()))),
+                None => Err(RegistryError::InvalidBytes(kind))
+            },
+            _ => Err(RegistryError::UnknownKind(kind))
+        }
+    }
+}
+
+fn create_client(send_timeout: usize) -> (Client<ClientEntity, TestUserData, ClientRegistry>, Rc<Mutex<TestStat>>) {
+    let stats: Rc<Mutex<TestStat>> = Rc::new(Mutex::new(TestStat::default()));
+    let client = Client::<ClientEntity, TestUserData, ClientRegistry>::new(Config {
+        handle_timeout_ticks: send_timeout,
+        ..Config::default()
+
+    }, ClientRegistry {
+        stats: stats.clone()
+    });
+    (client, stats)
+}
+
+#[test]
+fn test_client_create() {
+    create_client(5);
+}
+
+#[test]
+fn test_client_debug() {
+
+    let (mut client, _) = create_client(5);
+    assert_eq!(format!("{:?}", client), "EntityClient (0 entity(s))");
+    assert_client_send!(client, vec![0, 0, 3, 0, 1, 255, 128, 255], vec![1, 0]).expect("Client accepts SendCreateToClient packet.");
+    assert_eq!(format!("{:?}", client), "EntityClient (1 entity(s))");
+
+}
+
+#[test]
+fn test_client_with_id() {
+
+    let stats: Rc<Mutex<TestStat>> = Rc::new(Mutex::new(TestStat::default()));
+    let mut client_one = Client::<ClientEntity, TestUserData, ClientRegistry>::with_id(Config::default(), ClientRegistry {
+        stats: stats.clone()
+
+    }, 3000);
+    let client_two = Client::<ClientEntity, TestUserData, ClientRegistry>::with_id(Config::default(), ClientRegistry {
+        stats: stats.clone()
+
+    }, 3001);
+    let mut client_three = Client::<ClientEntity, TestUserData, ClientRegistry>::with_id(Config::default(), ClientRegistry {
+        stats: stats.clone()
+
+    }, 3000);
+    assert_eq!(client_one.instance_index(), 3000);
+    assert_eq!(client_two.instance_index(), 3001);
+
+    assert_client_send!(client_one, vec![0, 0, 3, 0, 1, 255, 128, 255], vec![1, 0]).expect("Client accepts SendCreateToClient packet.");
+    assert_client_send!(client_three, vec![0, 0, 3, 0, 1, 255, 128, 255], vec![1, 0]).expect("Client accepts SendCreateToClient packet.");
+    let tokens = client_one.map_entities(|token, _| token.clone());
+
+    // Clients with different ids reject each others tokens
+    assert!(client_one.entity_local_state(&tokens[0]).is_some());
+    assert!(client_two.entity_local_state(&tokens[0]).is_none());
+
+    // Clients with the same id accept each others tokens
+    assert!(client_three.entity_local_state(&tokens[0]).is_some());
+
+}
+
+#[test]
+fn test_client_entity_get() {
+
+    let (mut client, _) = create_client(5);
+    let (mut client_two, _) = create_client(5);
+
+    assert_client_send!(client, vec![0, 0, 3, 0, 1, 255, 128, 255], vec![1, 0]).expect("Client accepts SendCreateToClient packet.");
+    assert_client_send!(client_two, vec![0, 0, 3, 0, 1, 255, 128, 255], vec![1, 0]).expect("Client accepts SendCreateToClient packet.");
+    let token = client.map_entities(|token, _| token.clone()).remove(0);
+
+    assert!(client.entity_get(&token).is_some());
+    let mut count = 0;
+    client.entity_get_mut(&token).unwrap().client_update(&mut count);
+    assert_eq!(count, 1);
+
+    assert!(client.entity_is_alive(&token));
+
+    // Foreign tokens are rejected
+    assert!(!client_two.entity_is_alive(&token));
+    assert!(client_two.entity_get(&token).is_none());
+    assert!(client_two.entity_get_mut(&token).is_none());
+
+    // Destroyed entities are no longer returned
+    assert_client_send!(client, vec![4, 0], vec![4, 0]).expect("Client accepts SendDestroyToClient packet.");
+    assert!(!client.entity_is_alive(&token));
+    assert!(client.entity_get(&token).is_none());
+    assert!(client.entity_get_mut(&token).is_none());
+
+}
+
+#[test]
+fn test_client_with_entities_of_kind() {
+
+    let (mut client, _) = create_client(3);
+
+    assert_client_send!(client, vec![
+        0, 0, 3, 0, 1, 255, 128, 255,
+        0, 1, 3, 0, 2, 255, 128, 255,
+        0, 2, 3, 0, 1, 255, 128, 255
+
+    ], vec![1, 0, 1, 1, 1, 2]).expect("Client accepts SendCreateToClient packets.");
+
+    let mut count = 0;
+    client.with_entities_of_kind(1, |_, entity| { entity.client_update(&mut count); });
+    assert_eq!(count, 2);
+
+    let mut count = 0;
+    client.with_entities_of_kind(2, |_, entity| { entity.client_update(&mut count); });
+    assert_eq!(count, 1);
+
+    let mut count = 0;
+    client.with_entities_of_kind(3, |_, entity| { entity.client_update(&mut count); });
+    assert_eq!(count, 0);
+
+    // Destroyed entities are skipped
+    assert_client_send!(client, vec![4, 0], vec![4, 0, 1, 1, 1, 2]).expect("Client accepts SendDestroyToClient packet.");
+
+    let mut count = 0;
+    client.with_entities_of_kind(1, |_, entity| { entity.client_update(&mut count); });
+    assert_eq!(count, 1);
+
+}
+
+#[test]
+fn test_client_pending_handle_count() {
+
+    let (mut client, _) = create_client(2);
+
+    assert_client_send!(client, vec![0, 0, 3, 0, 1, 255, 128, 255], vec![1, 0]).expect("Client accepts SendCreateToClient packet.");
+    assert_eq!(client.pending_handle_count(), 0);
+
+    // Destroyed handles linger until they time out
+    assert_client_send!(client, vec![4, 0], vec![4, 0]).expect("Client accepts SendDestroyToClient packet.");
+    assert_eq!(client.pending_handle_count(), 1);
+    client.update_entities_with(|_, _| {});
+    assert_eq!(client.pending_handle_count(), 1);
+    client.update_entities_with(|_, _| {});
+    assert_eq!(client.pending_handle_count(), 0);
+
+}
+
+#[test]
+fn test_client_disconnect_flush() {
+
+    let mut server = Server::<ServerEntity, TestUserData>::new(config(5));
+    let stats: Rc<Mutex<TestStat>> = Rc::new(Mutex::new(TestStat::default()));
+    let connection_one = server.connection_add_with(||TestUserData::new(255)).unwrap();
+    let entity_one = server.entity_create_with(|| Box::new(TestEntity::new(true, stats.clone()))).unwrap();
+    let _ = server.entity_create_with(|| Box::new(TestEntity::new(true, stats.clone()))).unwrap();
+
+    let (mut client, _) = create_client(5);
+    assert_client_send!(client, vec![0, 0, 3, 0, 1, 255, 128, 255, 0, 1, 3, 0, 1, 255, 128, 255], vec![1, 0, 1, 1]).expect("Client accepts SendCreateToClient packets.");
+    assert_eq!(client.disconnect_flush(256).unwrap(), Vec::<Vec<u8>>::new());
+
+    server.connection_send(&connection_one, 256).unwrap();
+    server.connection_receive(&connection_one, vec![1, 0, 1, 1]).unwrap();
+    server.entity_destroy(entity_one).unwrap();
+    assert_server_send!(server, connection_one, vec![], vec![4, 0, 1, 1]).expect("Server sends SendDestroyToClient packet.");
+    client.receive(vec![4, 0]).unwrap();
+
+    // Confirmations are produced for handles which are timing out
+    let packets = client.disconnect_flush(256).unwrap();
+    assert_eq!(packets, vec![vec![4, 0]]);
+    client.reset();
+    assert_eq!(client.disconnect_flush(256).unwrap(), Vec::<Vec<u8>>::new());
+
+    // The server reclaims the slot without waiting for the timeout
+    for packet in packets {
+        server.connection_receive(&connection_one, packet).unwrap();
+    }
+    server.connection_send(&connection_one, 256).unwrap();
+    server.update_entities_with(|_, _| {});
+    assert_eq!(server.pending_handle_count(), 0);
+    assert_eq!(server.entity_count(), 1);
+
+}
+
+#[test]
+fn test_client_live_entity_count() {
+
+    let (mut client, _) = create_client(2);
+
+    assert_client_send!(client, vec![0, 0, 3, 0, 1, 255, 128, 255, 0, 1, 3, 0, 1, 255, 128, 255], vec![1, 0, 1, 1]).expect("Client accepts SendCreateToClient packets.");
+    assert_eq!(client.entity_count(), 2);
+    assert_eq!(client.live_entity_count(), 2);
+
+    // Destroyed entities are no longer live while their handle lingers
+    assert_client_send!(client, vec![4, 0], vec![4, 0, 1, 1]).expect("Client accepts SendDestroyToClient packet.");
+    assert_eq!(client.entity_count(), 2);
+    assert_eq!(client.live_entity_count(), 1);
+
+    client.update_entities_with(|_, _| {});
+    assert_eq!(client.entity_count(), 2);
+    client.update_entities_with(|_, _| {});
+    assert_eq!(client.entity_count(), 1);
+    assert_eq!(client.live_entity_count(), 1);
+
+}
+
+#[test]
+fn test_client_with_entities() {
+
+    let (mut client, _) = create_client(3);
+
+    assert_client_send!(client, vec![0, 0, 3, 0, 1, 255, 128, 255], vec![1, 0]).expect("Client accepts SendCreateToClient packet.");
+    assert_client_send!(client, vec![0, 1, 3, 0, 1, 255, 128, 255], vec![1, 0, 1, 1]).expect("Client accepts SendCreateToClient packet.");
+    assert_client_send!(client, vec![0, 2, 3, 0, 1, 255, 128, 255], vec![1, 0, 1, 1, 1, 2]).expect("Client accepts SendCreateToClient packet.");
+
+    let mut count = 0;
+    client.with_entities(|_, entity| { entity.client_update(&mut count); });
+    assert_eq!(count, 3);
+
+    assert_client_send!(client, vec![4, 1], vec![1, 0, 4, 1, 1, 2]).expect("Client accepts SendDestroyToClient packet.");
+
+    let mut count = 0;
+    client.with_entities(|_, entity| { entity.client_update(&mut count); });
+    assert_eq!(count, 2);
+
+}
+
+#[test]
+fn test_client_map_entities() {
+
+    let (mut client, _) = create_client(3);
+
+    assert_client_send!(client, vec![0, 0, 3, 0, 1, 255, 128, 255], vec![1, 0]).expect("Client accepts SendCreateToClient packet.");
+    assert_client_send!(client, vec![0, 1, 3, 0, 1, 255, 128, 255], vec![1, 0, 1, 1]).expect("Client accepts SendCreateToClient packet.");
+    assert_client_send!(client, vec![0, 2, 3, 0, 1, 255, 128, 255], vec![1, 0, 1, 1, 1, 2]).expect("Client accepts SendCreateToClient packet.");
+
+    let mut count = 0;
+    let results = client.map_entities::<usize, _>(|_, entity| { entity.client_update(&mut count); count });
+    assert_eq!(results, [1, 2, 3]);
+    assert_eq!(count, 3);
+
+    assert_client_send!(client, vec![4, 1], vec![1, 0, 4, 1, 1, 2]).expect("Client accepts SendDestroyToClient packet.");
+
+    let mut count = 0;
+    let results = client.map_entities::<usize, _>(|_, entity| { entity.client_update(&mut count); count });
+    assert_eq!(results, [1, 2]);
+    assert_eq!(count, 2);
+
+}
+
+#[test]
+fn test_client_reset() {
+
+    let (mut client, stats) = create_client(3);
+
+    assert_client_send!(client, vec![0, 0, 3, 0, 1, 255, 128, 255], vec![1, 0]).expect("Client accepts SendCreateToClient packet.");
+    assert_client_send!(client, vec![0, 1, 3, 0, 1, 255, 128, 255], vec![1, 0, 1, 1]).expect("Client accepts SendCreateToClient packet.");
+    assert_client_send!(client, vec![0, 2, 3, 0, 1, 255, 128, 255], vec![1, 0, 1, 1, 1, 2]).expect("Client accepts SendCreateToClient packet.");
+
+    let mut count = 0;
+    client.update_entities_with(|_, entity| { entity.client_update(&mut count); });
+    assert_eq!(count, 3);
+
+    assert_stats!(stats, drop_calls, 0);
+    assert_stats!(stats, destroyed_calls, 0);
+    client.reset();
+    assert_stats!(stats, drop_calls, 3);
+    assert_stats!(stats, destroyed_calls, 0);
+
+    let mut count = 0;
+    client.update_entities_with(|_, entity| { entity.client_update(&mut count); });
+    assert_eq!(count, 0);
+
+}
+
+#[test]
+fn test_client_exhaustive_create_entity() {
+
+    let (mut client, _) = create_client(3);
+    let mut response = Vec::new();
+
+    for e in 0..256 {
+        let i = e as u8;
+        response.push(1);
+        response.push(i);
+        assert_client_send!(client, vec![0, i, 3, 0, 1, 255, 128, 255], response).expect("Client accepts SendCreateToClient packet.");
+    }
+
+    let mut count = 0;
+    client.update_entities_with(|_, entity| { entity.client_update(&mut count); });
+    assert_eq!(count, 256);
+
+}
+
+#[test]
+fn test_client_connection_send_packet_split() {
+
+    let (mut client, _) = create_client(3);
+
+    assert_client_send!(client, vec![0, 0, 3, 0, 1, 255, 128, 255], vec![1, 0]).expect("Client accepts SendCreateToClient packet.");
+    assert_client_send!(client, vec![0, 1, 3, 0, 1, 255, 128, 255], vec![1, 0, 1, 1]).expect("Client accepts SendCreateToClient packet.");
+    assert_client_send!(client, vec![0, 2, 3, 0, 1, 255, 128, 255], vec![1, 0, 1, 1, 1, 2]).expect("Client accepts SendCreateToClient packet.");
+
+    assert_client_send_packets!(client, 6, vec![], vec![
+        vec![1, 0, 1, 1, 1, 2]
+
+    ]).expect("Client does split packets across entity state boundaries.");
+
+    assert_client_send_packets!(client, 4, vec![], vec![
+        vec![1, 0, 1, 1],
+        vec![1, 2]
+
+    ]).expect("Client does split packets across entity state boundaries.");
+
+    // Packets which cannot hold any fragment data are rejected
+    assert_eq!(client.send(1), Err(ClientError::PacketSizeTooSmall(1)));
+
+}
+
+#[test]
+fn test_client_ignore_invalid_packets() {
+
+    let (mut client, stats) = create_client(5);
+
+    assert_client_send_empty!(client, vec![]).expect("Client ignores empty server packets");
+    assert_client_send_empty!(client, vec![0]).expect("Client ignores server packets with length 1");
+    assert_eq!(assert_client_send_empty!(client, vec![0, 0]), Err(ClientError::TruncatedEntityData(0)));
+    assert_eq!(assert_client_send_empty!(client, vec![0, 0, 1]), Err(ClientError::TruncatedEntityData(0)));
+    assert_eq!(assert_client_send_empty!(client, vec![0, 0, 1, 2]), Err(ClientError::TruncatedEntityData(0)));
+    assert_eq!(assert_client_send_empty!(client, vec![0, 0, 10, 2, 5]), Err(ClientError::TruncatedEntityData(0)));
+
+    assert_eq!(assert_client_send_empty!(client, vec![3, 2]), Err(ClientError::TruncatedEntityData(2)));
+    assert_eq!(assert_client_send_empty!(client, vec![3, 2, 1]), Err(ClientError::TruncatedEntityData(2)));
+    assert_client_send_empty!(client, vec![3, 2, 1, 2]).expect("Client ignores SendUpdateToClient packet for non existent entity");
+    assert_eq!(assert_client_send_empty!(client, vec![3, 2, 10, 2, 5]), Err(ClientError::TruncatedEntityData(2)));
+    assert_eq!(assert_client_send_empty!(client, vec![4, 2, 10, 2, 5]), Err(ClientError::RemainingPacketData(vec![5])));
+
+    assert_client_send_empty!(client, vec![4, 2]).expect("Client ignores SendDestroyToClient packet for non existent entity");
+    assert_client_send_empty!(client, vec![5, 2]).expect("Client ignores SendForgetToClient packet for non existent entity");
+    assert_client_send_empty!(client, vec![1, 2]).expect("Client ignores ConfirmClientCreate packet for non existent entity");
+    assert_client_send_empty!(client, vec![1, 2, 0]).expect("Client ignores incomplete secondary packets");
+
+    assert_eq!(assert_client_send_empty!(client, vec![8, 2]), Err(ClientError::InvalidPacketData(vec![8, 2])));
+    assert_eq!(assert_client_send_empty!(client, vec![255, 2]), Err(ClientError::InvalidPacketData(vec![255, 2])));
+
+    assert_stats!(stats, part_calls, 0);
+    assert_stats!(stats, merge_calls, 0);
+    assert_stats!(stats, drop_calls, 0);
+    assert_stats!(stats, registry_calls, 0);
+
+}
+
+#[test]
+fn test_client_truncated_entity_data() {
+
+    let (mut client, stats) = create_client(5);
+
+    // Entities in front of a truncated creation are still created
+    assert_eq!(client.receive(vec![
+        0, 0, 3, 0, 1, 255, 128, 255,
+        0, 1, 200, 0, 1, 255
+
+    ]), Err(ClientError::TruncatedEntityData(1)));
+    assert_stats!(stats, registry_calls, 1);
+    assert_client_send!(client, vec![1, 0], vec![2, 0]).expect("Client accepts ConfirmClientCreate packet.");
+    assert_client_send_empty!(client, vec![3, 0, 0]).expect("Client accepts SendUpdateToClient packet.");
+
+    // Truncated updates are not merged
+    assert_eq!(client.receive(vec![3, 0, 4, 1, 2]), Err(ClientError::TruncatedEntityData(0)));
+    assert_stats!(stats, merge_calls, 0);
+    assert_eq!(client.recent_errors(), &[
+        ClientError::TruncatedEntityData(1),
+        ClientError::TruncatedEntityData(0)
+    ]);
+
+}
+
+#[test]
+fn test_server_truncated_entity_data() {
+
+    let mut server = Server::<ServerEntity, TestUserData>::new(config(5));
+    let stats: Rc<Mutex<TestStat>> = Rc::new(Mutex::new(TestStat::default()));
+
+    let connection_one = server.connection_add_with(||TestUserData::new(255)).unwrap();
+    server.entity_create_with(|| Box::new(TestEntity::new(true, stats.clone()))).unwrap();
+    assert_server_send!(server, connection_one, vec![], vec![0, 0, 3, 0, 1, 255, 128, 255]).expect("Server sents SendCreateToClient packet to Client.");
+    assert_server_send!(server, connection_one, vec![1, 0], vec![1, 0]).expect("Server accepts ConfirmCreateToServer from Client.");
+    assert_server_send_empty!(server, connection_one, vec![2, 0]).expect("Server accepts AcceptServerUpdate from Client.");
+
+    // Truncated updates are rejected
+    assert_eq!(server.connection_receive(&connection_one, vec![3, 0, 4, 1, 2]), Err(ServerError::TruncatedEntityData(0)));
+    assert_stats!(stats, merge_calls, 0);
+
+    // Partial receives stop in front of them instead
+    assert_eq!(server.connection_receive_partial(&connection_one, &[3, 0, 4, 1, 2]), Ok(0));
+    stats.lock().unwrap().merge_bytes_value = vec![1, 2, 3, 4];
+    assert_eq!(server.connection_receive_partial(&connection_one, &[3, 0, 4, 1, 2, 3, 4]), Ok(7));
+    assert_stats!(stats, merge_calls, 1);
+
+}
+
+#[test]
+fn test_client_recent_errors() {
+
+    let (mut client, _) = create_client(5);
+    assert!(client.recent_errors().is_empty());
+
+    assert_eq!(client.receive(vec![8, 2]), Err(ClientError::InvalidPacketData(vec![8, 2])));
+    client.receive(vec![]).expect("Client ignores empty server packets");
+    assert_eq!(client.receive(vec![4, 0, 10, 2, 5]), Err(ClientError::RemainingPacketData(vec![5])));
+
+    assert_eq!(client.recent_errors(), &[
+        ClientError::InvalidPacketData(vec![8, 2]),
+        ClientError::RemainingPacketData(vec![5])
+    ]);
+
+    // Only the most recent errors are kept
+    for i in 0..20 {
+        client.receive(vec![8, i]).ok();
+    }
+
+    assert_eq!(client.recent_errors().len(), 16);
+    assert_eq!(client.recent_errors()[0], ClientError::InvalidPacketData(vec![8, 4]));
+    assert_eq!(client.recent_errors()[15], ClientError::InvalidPacketData(vec![8, 19]));
+
+}
+
+#[test]
+fn test_client_entity_flow() {
+
+    let (mut client, stats) = create_client(3);
+
+    // SendCreateToClient -> ConfirmCreateToServer
+    assert_client_send!(client, vec![0, 0, 3, 0, 1, 255, 128, 255], vec![1, 0]).expect("Client accepts SendCreateToClient packet.");
+    assert_stats!(stats, registry_calls, 1);
+    assert_stats!(stats, created_calls, 0);
+    assert_stats!(stats, destroyed_calls, 0);
+
+    let mut count = 0;
+    client.update_entities_with(|_, entity| {
+        entity.client_update(&mut count);
+    });
+    assert_eq!(count, 1);
+
+    // ConfirmClientCreate -> AcceptServerUpdate
+    assert_client_send!(client, vec![1, 0], vec![2, 0]).expect("Client accepts ConfirmClientCreate packet.");
+    assert_stats!(stats, created_calls, 1);
+    assert_stats!(stats, destroyed_calls, 0);
+
+    // SendUpdateToClient -> None
+    assert_client_send_empty!(client, vec![3, 0, 0]).expect("Client accepts SendUpdateToClient packet without data bytes.");
+    assert_stats!(stats, part_calls, 1);
+    assert_stats!(stats, merge_calls, 0);
+    assert_stats_clone!(stats, merge_bytes_value, vec![]);
+    assert_stats!(stats, created_calls, 1);
+    assert_stats!(stats, destroyed_calls, 0);
+
+    stats.lock().unwrap().merge_bytes_value = vec![255, 192, 96];
+    assert_client_send_empty!(client, vec![3, 0, 3, 255, 192, 96]).expect("Client accepts SendUpdateToClient packet with data bytes.");
+    assert_stats!(stats, part_calls, 2);
+    assert_stats!(stats, merge_calls, 1);
+    assert_stats_clone!(stats, merge_bytes_value, vec![255, 192, 96]);
+    assert_stats!(stats, created_calls, 1);
+    assert_stats!(stats, destroyed_calls, 0);
+
+    // None -> SendUpdateToServer
+    stats.lock().unwrap().part_bytes_value = Some(vec![]);
+    assert_stats!(stats, part_calls, 2);
+    assert_client_send!(client, vec![], vec![3, 0, 0]).expect("Client sends SendUpdateToServer packet without data bytes.");
+    assert_stats!(stats, created_calls, 1);
+    assert_stats!(stats, destroyed_calls, 0);
+
+    stats.lock().unwrap().part_bytes_value = Some(vec![96, 255, 192]);
+    assert_stats!(stats, part_calls, 3);
+    assert_client_send!(client, vec![], vec![3, 0, 3, 96, 255, 192]).expect("Client sends SendUpdateToServer packet with data bytes.");
+    assert_stats!(stats, created_calls, 1);
+    assert_stats!(stats, destroyed_calls, 0);
+
+    // SendDestroyToClient -> ConfirmDestroyToServer
+    assert_client_send!(client, vec![4, 0], vec![4, 0]).expect("Client accepts SendDestroyToClient packet for existing entity.");
+    assert_stats!(stats, drop_calls, 1); // Entity is dropped directly
+    assert_stats!(stats, created_calls, 1);
+    assert_stats!(stats, destroyed_calls, 1);
+
+    let mut count = 0;
+    client.update_entities_with(|_, entity| {
+        entity.client_update(&mut count);
+    });
+    assert_eq!(count, 0);
+
+    // None -> ConfirmDestroyToServer
+    assert_client_send!(client, vec![], vec![4, 0]).expect("Client sent ConfirmDestroyToServer packet for destroy entity with pending handle.");
+    assert_stats!(stats, drop_calls, 1);
+
+    // ConfirmClientCreate -> ConfirmDestroyToServer
+    assert_client_send!(client, vec![1, 0], vec![4, 0]).expect("Client ignores ConfirmClientCreate packet for destroyed entity.");
+    assert_stats!(stats, created_calls, 1);
+    assert_stats!(stats, destroyed_calls, 1);
+
+    // SendUpdateToClient -> ConfirmCreateToServer
+    assert_client_send!(client, vec![3, 0, 0, 1], vec![4, 0]).expect("Client ignores SendUpdateToClient packet for destroyed entity.");
+
+    // After a total of 3 update calls since the entity destruction the handle should be removed by
+    // the client
+    client.update_entities_with(|_, _| {});
+    assert_client_send!(client, vec![], vec![4, 0]).expect("Client keeps handle when send timeout expires after 3 further update calls.");
+
+    client.update_entities_with(|_, _| {});
+    assert_client_send_empty!(client, vec![]).expect("Client drops handle when send timeout expires after 3 further update calls.");
+
+    // SendCreateToClient -> ConfirmCreateToServer
+    assert_client_send!(client, vec![0, 0, 3, 0, 1, 255, 128, 255], vec![1, 0]).expect("Client accepts SendCreateToClient packet for dropped entity handle index.");
+    assert_stats!(stats, registry_calls, 2);
+    assert_stats!(stats, created_calls, 1);
+    assert_stats!(stats, destroyed_calls, 1);
+
+    let mut count = 0;
+    client.update_entities_with(|_, entity| { entity.client_update(&mut count); });
+    assert_eq!(count, 1);
+
+    // TODO test 0 value timeouts
+
+}
+
+#[test]
+fn test_client_entity_ignore_unknown_kind_create() {
+
+    let (mut client, stats) = create_client(3);
+
+    // SendCreateToClient -> None
+    assert_client_send_empty!(client, vec![0, 0, 3, 0, 3, 255, 128, 255]).expect("Client ignores SendCreateToClient with unknown entity kind.");
+    assert_stats!(stats, registry_calls, 0);
+
+    let mut count = 0;
+    client.update_entities_with(|_, entity| { entity.client_update(&mut count); });
+    assert_eq!(count, 0);
+
+}
+
+#[test]
+fn test_client_entity_create_wide_kind() {
+
+    let mut server = Server::<ServerEntity, TestUserData>::new(config(3));
+    let stats: Rc<Mutex<TestStat>> = Rc::new(Mutex::new(TestStat::default()));
+    stats.lock().unwrap().kind = Some(300);
+
+    let connection_one = server.connection_add_with(||TestUserData::new(255)).unwrap();
+    server.entity_create_with(|| Box::new(TestEntity::new(true, stats.clone()))).unwrap();
+
+    let packets = server.connection_send(&connection_one, 4096).unwrap();
+    assert_eq!(packets, vec![vec![0, 0, 3, 1, 44, 255, 128, 255]]);
+
+    let (mut client, client_stats) = create_client(3);
+    assert_client_send!(client, packets[0].clone(), vec![1, 0]).expect("Client accepts SendCreateToClient packet with a kind above 255.");
+    assert_stats!(client_stats, registry_calls, 1);
+
+}
+
+#[test]
+fn test_client_entity_create_replace() {
+
+    let (mut client, stats) = create_client(3);
+
+    // SendCreateToClient -> ConfirmCreateToServer
+    assert_client_send!(client, vec![0, 0, 3, 0, 1, 255, 128, 255], vec![1, 0]).expect("Client accepts SendCreateToClient packet.");
+    assert_stats!(stats, registry_calls, 1);
+    assert_stats!(stats, created_calls, 0);
+
+    let mut count = 0;
+    client.update_entities_with(|_, entity| { entity.client_update(&mut count); });
+    assert_eq!(count, 1);
+
+    // DOES NOT REPLACE entity of same kind while still in CREATE state
+    assert_client_send!(client, vec![0, 0, 3, 0, 1, 255, 128, 255], vec![1, 0]).expect("Client does not replace existing entity of same kind in create state.");
+    assert_stats!(stats, registry_calls, 1);
+    assert_stats!(stats, created_calls, 0);
+    assert_stats!(stats, destroyed_calls, 0);
+
+    let mut count = 0;
+    client.update_entities_with(|_, entity| { entity.client_update(&mut count); });
+    assert_eq!(count, 1);
+
+    // ConfirmClientCreate -> AcceptServerUpdate
+    assert_client_send!(client, vec![1, 0], vec![2, 0]).expect("Client accepts ConfirmClientCreate packet.");
+    assert_stats!(stats, created_calls, 1);
+
+    // DOES REPLACE entity of the same kind when no longer in CREATE state
+    assert_client_send!(client, vec![0, 0, 3, 0, 1, 255, 128, 255], vec![1, 0]).expect("Client does replace existing entity of same kind outside of create state.");
+    assert_stats!(stats, registry_calls, 2);
+    assert_stats!(stats, created_calls, 1);
+    assert_stats!(stats, destroyed_calls, 0);
+
+    // DOES REPLACE entity of different kind in state other than CREATE
+    assert_client_send!(client, vec![0, 0, 3, 0, 2, 255, 128, 255], vec![1, 0]).expect("Client does replace existing entity of different kind.");
+    assert_stats!(stats, registry_calls, 3);
+    assert_stats!(stats, created_calls, 1);
+    assert_stats!(stats, destroyed_calls, 0);
+
+    // DOES REPLACE entity of different kind in CREATE state
+    assert_client_send!(client, vec![0, 0, 3, 0, 1, 255, 128, 255], vec![1, 0]).expect("Client does replace existing entity of different kind.");
+    assert_stats!(stats, registry_calls, 4);
+    assert_stats!(stats, created_calls, 1);
+    assert_stats!(stats, destroyed_calls, 0);
+
+    assert_client_send!(client, vec![1, 0], vec![2, 0]).expect("Client accepts ConfirmClientCreate packet.");
+    assert_stats!(stats, created_calls, 2);
+
+}
+
+#[test]
+fn test_client_entity_destroy_create_same_packet() {
+
+    let (mut client, stats) = create_client(3);
+
+    // Entity in UPDATE state
+    assert_client_send!(client, vec![0, 0, 3, 0, 1, 255, 128, 255], vec![1, 0]).expect("Client accepts SendCreateToClient packet.");
+    assert_client_send!(client, vec![1, 0], vec![2, 0]).expect("Client accepts ConfirmClientCreate packet.");
+    assert_client_send_empty!(client, vec![3, 0, 0]).expect("Client accepts SendUpdateToClient packet.");
+
+    // SendDestroyToClient followed by SendCreateToClient for the same slot
+    assert_client_send!(client, vec![4, 0, 0, 0, 3, 0, 1, 255, 128, 255], vec![1, 0]).expect("Client destroys and then re-creates entity in the same slot.");
+    assert_stats!(stats, registry_calls, 2);
+    assert_stats!(stats, destroyed_calls, 1);
+    let token = client.map_entities(|token, _| token.clone()).remove(0);
+    assert_eq!(client.entity_local_state(&token), Some(LocalState::Create));
+
+    // Entity still in CREATE state
+    assert_client_send!(client, vec![4, 0, 0, 0, 3, 0, 1, 255, 128, 255], vec![1, 0]).expect("Client destroys and then re-creates entity in create state in the same slot.");
+    assert_stats!(stats, registry_calls, 3);
+    assert_stats!(stats, destroyed_calls, 2);
+    assert_eq!(client.map_entities(|_, _| ()).len(), 1);
+
+    // The new entity is not affected by the destroy
+    let mut count = 0;
+    for _ in 0..5 {
+        client.update_entities_with(|_, entity| { entity.client_update(&mut count); });
+    }
+    assert_eq!(count, 5);
+
+    assert_client_send!(client, vec![1, 0], vec![2, 0]).expect("Client accepts ConfirmClientCreate packet for new entity.");
+    assert_stats!(stats, created_calls, 2);
+
+}
+
+#[test]
+fn test_client_maximum_update_rate() {
+
+    let (mut client, stats) = create_client(3);
+    client.set_config(Config {
+        maximum_update_rate: Some(3),
+        ..Config::default()
+    });
+
+    assert_client_send!(client, vec![0, 0, 3, 0, 1, 255, 128, 255], vec![1, 0]).expect("Client accepts SendCreateToClient packet.");
+    assert_client_send!(client, vec![1, 0], vec![2, 0]).expect("Client accepts ConfirmClientCreate packet.");
+    assert_client_send_empty!(client, vec![3, 0, 0]).expect("Client accepts SendUpdateToClient packet without data bytes.");
+
+    // Always dirty entities are capped to one update every three ticks
+    let mut packets = vec![];
+    for _ in 0..7 {
+        stats.lock().unwrap().part_bytes_value = Some(vec![1]);
+        let p = client.send(256).unwrap();
+        if p.is_empty() {
+            packets.push(vec![]);
+
+        } else {
+            packets.extend_from_slice(&p);
+        }
+    }
+
+    assert_eq!(packets, vec![
+        vec![3, 0, 1, 1], vec![], vec![],
+        vec![3, 0, 1, 1], vec![], vec![],
+        vec![3, 0, 1, 1]
+    ]);
+    assert_stats!(stats, part_calls, 4);
+
+    // Destructions and creations are not capped
+    assert_client_send!(client, vec![4, 0, 0, 1, 3, 0, 1, 255, 128, 255], vec![4, 0, 1, 1]).expect("Client confirms destruction and creation while the update is capped.");
+
+}
+
+#[test]
+fn test_client_updates_paused() {
+
+    let (mut client, stats) = create_client(3);
+    assert_client_send!(client, vec![0, 0, 3, 0, 1, 255, 128, 255], vec![1, 0]).expect("Client accepts SendCreateToClient packet.");
+    assert_client_send!(client, vec![1, 0], vec![2, 0]).expect("Client accepts ConfirmClientCreate packet.");
+    assert_client_send_empty!(client, vec![3, 0, 0]).expect("Client accepts SendUpdateToClient packet without data bytes.");
+
+    // Updates are no longer send while paused
+    assert!(!client.updates_paused());
+    client.set_updates_paused(true);
+    assert!(client.updates_paused());
+
+    stats.lock().unwrap().part_bytes_value = Some(vec![1]);
+    assert_client_send_empty!(client, vec![]).expect("Client does not send updates while paused.");
+
+    // Destructions and creations are still confirmed
+    assert_client_send!(client, vec![4, 0, 0, 1, 3, 0, 1, 255, 128, 255], vec![4, 0, 1, 1]).expect("Client confirms destruction and creation while paused.");
+    assert_client_send!(client, vec![1, 1], vec![4, 0, 2, 1]).expect("Client accepts ConfirmClientCreate packet while paused.");
+    assert_client_send!(client, vec![3, 1, 0], vec![4, 0]).expect("Client accepts SendUpdateToClient packet while paused.");
+
+    // Resumed updates are send again
+    client.set_updates_paused(false);
+    stats.lock().unwrap().part_bytes_value = Some(vec![1]);
+    assert_client_send!(client, vec![], vec![4, 0, 3, 1, 1, 1]).expect("Client sends updates once resumed.");
+
+}
+
+#[test]
+fn test_client_entity_periodic_empty_update() {
+
+    let (mut client, stats) = create_client(3);
+
+    client.set_config(Config {
+        handle_timeout_ticks: 5,
+        minimum_update_interval: Some(10),
+        ..Config::default()
+    });
+
+    // SendCreateToClient -> ConfirmCreateToServer
+    assert_client_send!(client, vec![0, 0, 3, 0, 2, 255, 128, 255], vec![1, 0]).expect("Client accepts SendCreateToClient packet.");
+    assert_stats!(stats, registry_calls, 1);
+    assert_stats!(stats, created_calls, 0);
+
+    let mut count = 0;
+    client.update_entities_with(|_, entity| { entity.client_update(&mut count); });
+    assert_eq!(count, 1);
+
+    // ConfirmClientCreate -> AcceptServerUpdate
+    assert_client_send!(client, vec![1, 0], vec![2, 0]).expect("Client accepts ConfirmClientCreate packet.");
+    assert_stats!(stats, created_calls, 1);
+
+    // SendUpdateToClient -> None
+    assert_client_send_empty!(client, vec![3, 0, 0]).expect("Client accepts SendUpdateToClient packet without data bytes.");
+    assert_stats!(stats, merge_calls, 0);
+
+    let mut packets = vec![];
+    for _ in 0..20 {
+        let p = client.send(256).unwrap();
+        if p.is_empty() {
+            packets.push(vec![]);
+
+        } else {
+            packets.extend_from_slice(&p);
+        }
+    }
+
+    assert_eq!(
+        packets,
+        vec![
+            vec![], vec![], vec![], vec![], vec![], vec![], vec![], vec![],
+            vec![3, 0, 0],
+            vec![], vec![], vec![], vec![], vec![], vec![], vec![], vec![], vec![],
+            vec![3, 0, 0],
+            vec![]
+        ],
+        "Client should send an empty update at least every 10 ticks."
+    );
+
+    // SendUpdateToClient > None
+    assert_client_send_empty!(client, vec![3, 0, 0]).expect("Client accepts empty server update packet.");
+    assert_stats!(stats, merge_calls, 0);
+
+}
+
+#[test]
+fn test_client_multi_entity_flow() {
+
+    let (mut client, stats) = create_client(3);
+
+    // SendCreateToClient -> ConfirmCreateToServer
+    assert_client_send!(client, vec![
+        0, 8, 3, 0, 1, 255, 128, 255,
+        0, 42, 3, 0, 1, 255, 128, 255,
+        0, 72, 3, 0, 1, 255, 128, 255
+
+    ], vec![1, 8, 1, 42, 1, 72]).expect("Client accepts multiple SendCreateToClient packets.");
+    assert_stats!(stats, registry_calls, 3);
+
+    let mut count = 0;
+    client.update_entities_with(|_, entity| {
+        entity.client_update(&mut count);
+    });
+    assert_eq!(count, 3);
+
+    // ConfirmClientCreate -> AcceptServerUpdate
+    assert_client_send!(client, vec![1, 8], vec![2, 8, 1, 42, 1, 72]).expect("Client accepts ConfirmClientCreate packet.");
+    assert_client_send!(client, vec![1, 54], vec![2, 8, 1, 42, 1, 72]).expect("Client ignores ConfirmClientCreate packet for unknown entity.");
+    assert_client_send!(client, vec![1, 72], vec![2, 8, 1, 42, 2, 72]).expect("Client accepts ConfirmClientCreate packet.");
+
+    // SendUpdateToClient -> None
+    assert_client_send!(client, vec![3, 72, 0], vec![2, 8, 1, 42]).expect("Client accepts SendUpdateToClient packet without data bytes.");
+    assert_stats!(stats, part_calls, 1);
+    assert_stats!(stats, merge_calls, 0);
+    assert_stats_clone!(stats, merge_bytes_value, vec![]);
+
+    stats.lock().unwrap().merge_bytes_value = vec![255, 192, 96];
+    assert_client_send!(client, vec![3, 8, 3, 255, 192, 96], vec![1, 42]).expect("Client accepts SendUpdateToClient packet with data bytes.");
+    assert_stats!(stats, part_calls, 3);
+    assert_stats!(stats, merge_calls, 1);
+
+    // None -> SendUpdateToServer
+    stats.lock().unwrap().part_bytes_value = Some(vec![]);
+    assert_client_send!(client, vec![], vec![3, 8, 0, 1, 42]).expect("Client sends SendUpdateToServer packet without data bytes.");
+    assert_stats!(stats, part_calls, 5);
+
+    stats.lock().unwrap().part_bytes_value = Some(vec![96, 255, 192]);
+    assert_client_send!(client, vec![], vec![3, 8, 3, 96, 255, 192, 1, 42]).expect("Client sends SendUpdateToServer packet with data bytes.");
+
+    // SendDestroyToClient -> ConfirmDestroyToServer
+    assert_client_send!(client, vec![4, 8], vec![4, 8, 1, 42]).expect("Client accepts SendDestroyToClient packet for existing entity.");
+    assert_stats!(stats, drop_calls, 1); // Entity is dropped directly
+
+    assert_client_send!(client, vec![4, 72], vec![4, 8, 1, 42, 4, 72]).expect("Client accepts SendDestroyToClient packet for existing entity.");
+    assert_stats!(stats, drop_calls, 2); // Entity is dropped directly
+
+    assert_client_send!(client, vec![4, 42], vec![4, 8, 4, 42, 4, 72]).expect("Client accepts SendDestroyToClient packet for existing entity.");
+    assert_stats!(stats, drop_calls, 3); // Entity is dropped directly
+
+    client.update_entities_with(|_, _| {});
+    client.update_entities_with(|_, _| {});
+    assert_client_send!(client, vec![], vec![4, 8, 4, 42, 4, 72]).expect("Client keeps all handles until send timeout expires after 3 further update calls.");
+
+    client.update_entities_with(|_, _| {});
+    assert_client_send_empty!(client, vec![]).expect("Client drops all handles when send timeout expires after 3 further update calls.");
+
+    // TODO test multiple entity replace
+
+}
+
+#[test]
+fn test_client_destroy_all() {
+
+    let (mut client, stats) = create_client(3);
+
+    assert_client_send!(client, vec![0, 0, 3, 0, 1, 255, 128, 255], vec![1, 0]).expect("Client accepts SendCreateToClient packet.");
+    assert_client_send!(client, vec![0, 1, 3, 0, 1, 255, 128, 255], vec![1, 0, 1, 1]).expect("Client accepts SendCreateToClient packet.");
+
+    client.destroy_all();
+    assert_stats!(stats, destroyed_calls, 2);
+    assert_eq!(client.stats().entities_destroyed, 2);
+    assert_eq!(client.map_entities(|_, _| ()).len(), 0);
+
+    // Handles confirm the destruction until they time out
+    assert_client_send!(client, vec![], vec![4, 0, 4, 1]).expect("Client sends ConfirmDestroyToServer packets for all entities.");
+
+    for _ in 0..3 {
+        client.update_entities_with(|_, _| {});
+    }
+
+    assert_client_send_empty!(client, vec![]).expect("Client drops handles after timeout.");
+
+}
+
+#[test]
+fn test_client_entity_forget() {
+
+    let (mut client, stats) = create_client(3);
+
+    // SendCreateToClient -> ConfirmCreateToServer
+    assert_client_send!(client, vec![0, 0, 3, 0, 1, 255, 128, 255], vec![1, 0]).expect("Client accepts SendCreateToClient packet.");
     assert_stats!(stats, registry_calls, 1);
     assert_stats!(stats, created_calls, 0);
 
-    let mut count = 0;
-    client.update_entities_with(|_, entity| { entity.client_update(&mut count); });
-    assert_eq!(count, 1);
+    let mut count = 0;
+    client.update_entities_with(|_, entity| { entity.client_update(&mut count); });
+    assert_eq!(count, 1);
+
+    // SendForgetToClient -> ConfirmDestroyToServer
+    assert_client_send!(client, vec![5, 0], vec![4, 0]).expect("Client accepts SendForgetToClient packet.");
+
+    // Client should NOT run destroyed() for forgotten entities
+    assert_stats!(stats, destroyed_calls, 0);
+
+    // Client should simply drop forgotten entities
+    assert_stats!(stats, drop_calls, 1);
+
+    let mut count = 0;
+    client.update_entities_with(|_, entity| { entity.client_update(&mut count); });
+    assert_eq!(count, 0);
+
+}
+
+#[test]
+fn test_client_entity_hide() {
+
+    let (mut client, stats) = create_client(3);
+
+    assert_client_send!(client, vec![0, 0, 3, 0, 1, 255, 128, 255], vec![1, 0]).expect("Client accepts SendCreateToClient packet.");
+    assert_client_send_empty!(client, vec![1, 0, 3, 0, 0]).expect("Client accepts ConfirmClientCreate and SendUpdateToClient packets.");
+    let tokens = client.map_entities(|token, _| token.clone());
+
+    // SendHideToClient -> ConfirmDestroyToServer (once)
+    assert_eq!(client.receive_with_events(vec![2, 0]), Ok(vec![ClientEntityEvent::Hidden(tokens[0].clone())]));
+    assert_eq!(client.send(4096).unwrap(), vec![vec![4, 0]]);
+    assert_eq!(client.send(4096).unwrap(), Vec::<Vec<u8>>::new());
+    assert_client_send!(client, vec![2, 0], vec![4, 0]).expect("Client confirms repeated SendHideToClient packet.");
+
+    // Hidden entities are kept, but ignore updates
+    stats.lock().unwrap().merge_bytes_value = vec![1];
+    assert_client_send_empty!(client, vec![3, 0, 1, 1]).expect("Client ignores SendUpdateToClient for hidden entity.");
+    assert_stats!(stats, merge_calls, 0);
+    assert_stats!(stats, destroyed_calls, 0);
+    assert_stats!(stats, drop_calls, 0);
+
+    let mut count = 0;
+    client.update_entities_with(|_, entity| { entity.client_update(&mut count); });
+    assert_eq!(count, 1);
+
+    // ConfirmClientCreate -> AcceptServerUpdate
+    assert_eq!(client.receive_with_events(vec![1, 0]), Ok(vec![ClientEntityEvent::Revealed(tokens[0].clone())]));
+    assert_eq!(client.send(4096).unwrap(), vec![vec![2, 0]]);
+    assert_client_send_empty!(client, vec![3, 0, 1, 1]).expect("Client accepts SendUpdateToClient for revealed entity.");
+    assert_stats!(stats, merge_calls, 1);
+
+    // The same entity instance is kept throughout
+    assert_stats!(stats, registry_calls, 1);
+    assert_stats!(stats, created_calls, 1);
+    assert_stats!(stats, drop_calls, 0);
+    assert_eq!(client.map_entities(|token, _| token.clone()), tokens);
+
+    // Hidden entities can still be destroyed
+    assert_client_send!(client, vec![2, 0], vec![4, 0]).expect("Client accepts SendHideToClient packet.");
+    assert_client_send!(client, vec![4, 0], vec![4, 0]).expect("Client accepts SendDestroyToClient for hidden entity.");
+    assert_stats!(stats, destroyed_calls, 1);
+
+}
+
+#[test]
+fn test_client_receive_inspector() {
+
+    let (mut client, _) = create_client(3);
+    let inspected: Arc<Mutex<Vec<Vec<u8>>>> = Arc::new(Mutex::new(Vec::new()));
+
+    let client_inspected = inspected.clone();
+    client.set_receive_inspector(move |bytes| {
+        client_inspected.lock().unwrap().push(bytes.to_vec());
+    });
+
+    let packets = vec![
+        vec![0, 0, 3, 0, 1, 255, 128, 255],
+        vec![1, 0],
+        vec![255, 1]
+    ];
+
+    // Packets are inspected before they are parsed, including invalid ones
+    assert!(client.receive(packets[0].clone()).is_ok());
+    assert!(client.receive_with_events(packets[1].clone()).is_ok());
+    assert!(client.receive(packets[2].clone()).is_err());
+    assert_eq!(*inspected.lock().unwrap(), packets);
+
+}
+
+#[test]
+fn test_client_receive_with_custom_packets() {
+
+    let (mut client, _) = create_client(5);
+    let mut custom = Vec::new();
+
+    // Whole custom packets
+    assert!(client.receive_with(vec![255, 2], |bytes| custom.push(bytes.to_vec())).is_ok());
+
+    // Custom data trailing entity data
+    assert!(client.receive_with(vec![4, 2, 9, 1, 2], |bytes| custom.push(bytes.to_vec())).is_ok());
+    assert!(client.receive_with(vec![5, 2, 9], |bytes| custom.push(bytes.to_vec())).is_ok());
+
+    assert_eq!(custom, vec![vec![255, 2], vec![9, 1, 2], vec![9]]);
+
+    // Data within the reserved range is still reported as an error
+    assert_eq!(client.receive_with(vec![8, 2], |_| unreachable!()), Err(ClientError::InvalidPacketData(vec![8, 2])));
+    assert_eq!(client.receive_with(vec![4, 2, 7, 1, 2], |_| unreachable!()), Err(ClientError::RemainingPacketData(vec![2])));
+
+}
+
+#[test]
+fn test_client_receive_with_events() {
+
+    let (mut client, _) = create_client(3);
+
+    let events = client.receive_with_events(vec![
+        0, 0, 3, 0, 1, 255, 128, 255,
+        0, 1, 3, 0, 1, 255, 128, 255
+    ]);
+    let tokens = client.map_entities(|token, _| token.clone());
+    assert_eq!(events, Ok(vec![
+        ClientEntityEvent::Created(tokens[0].clone()),
+        ClientEntityEvent::Created(tokens[1].clone())
+    ]));
+
+    // Destroyed entities are reported once
+    assert_eq!(client.receive_with_events(vec![4, 0]), Ok(vec![ClientEntityEvent::Destroyed(tokens[0].clone())]));
+    assert_eq!(client.receive_with_events(vec![4, 0]), Ok(vec![]));
+
+    // Forgotten entities are reported separately
+    assert_eq!(client.receive_with_events(vec![5, 1]), Ok(vec![ClientEntityEvent::Forgotten(tokens[1].clone())]));
+    assert_eq!(client.receive_with_events(vec![5, 1]), Ok(vec![]));
+
+    // Replacing a destroyed entity only reports its creation
+    assert_eq!(client.receive_with_events(vec![0, 0, 3, 0, 1, 255, 128, 255]), Ok(vec![ClientEntityEvent::Created(tokens[0].clone())]));
+
+    // Errors are still reported
+    assert_eq!(client.receive_with_events(vec![8, 2]), Err(ClientError::InvalidPacketData(vec![8, 2])));
+
+}
+
+#[test]
+fn test_client_receive_with_events_rejected() {
+
+    let (mut client, _) = create_client(3);
+
+    // Unknown kinds and malformed bytes are distinguishable
+    assert_eq!(client.receive_with_events(vec![0, 0, 3, 0, 3, 255, 128, 255]), Ok(vec![
+        ClientEntityEvent::Rejected(RegistryError::UnknownKind(3))
+    ]));
+
+    assert_eq!(client.receive_with_events(vec![0, 0, 1, 0, 4, 255]), Ok(vec![
+        ClientEntityEvent::Rejected(RegistryError::InvalidBytes(4))
+    ]));
+
+    // Rejected creations are ignored
+    assert_client_send_empty!(client, vec![0, 0, 1, 0, 4, 255]).expect("Client ignores rejected SendCreateToClient packet.");
+    assert_eq!(client.receive_with_events(vec![0, 0, 0, 0, 4]).unwrap().len(), 1);
+
+}
+
+#[test]
+fn test_client_handshake() {
+
+    let mut server = Server::<ServerEntity, TestUserData>::new(Config {
+        require_handshake: true,
+        ..Config::default()
+    });
+
+    let stats: Rc<Mutex<TestStat>> = Rc::new(Mutex::new(TestStat::default()));
+    let connection_one = server.connection_add_with(||TestUserData::new(255)).unwrap();
+    server.entity_create_with(|| Box::new(TestEntity::new(true, stats.clone()))).unwrap();
+
+    // No entities are synchronised before the handshake
+    assert_eq!(server.connection_send(&connection_one, 4096), Ok(vec![]));
+
+    let (client, _) = create_client(3);
+    let handshake = client.handshake_packet();
+
+    // Mismatched versions are rejected
+    let mut mismatched = handshake.clone();
+    let version = mismatched.len() - 1;
+    mismatched[version] += 1;
+    assert_eq!(server.accept_handshake(&connection_one, &mismatched), Err(ServerError::ProtocolMismatch));
+    assert_eq!(server.accept_handshake(&connection_one, &[1, 0]), Err(ServerError::ProtocolMismatch));
+    assert_eq!(server.connection_send(&connection_one, 4096), Ok(vec![]));
+
+    assert_eq!(server.accept_handshake(&connection_one, &handshake), Ok(()));
+    assert_eq!(server.connection_send(&connection_one, 4096), Ok(vec![vec![0, 0, 3, 0, 1, 255, 128, 255]]));
+
+    // Foreign tokens
+    let mut server_two = Server::<ServerEntity, TestUserData>::new(Config::default());
+    let connection_two = server_two.connection_add_with(||TestUserData::new(0)).unwrap();
+    assert_eq!(server.accept_handshake(&connection_two, &handshake), Err(ServerError::UnknownReceiverToken(handshake.clone())));
+
+}
+
+#[test]
+fn test_client_protocol_version() {
+
+    let mut server = Server::<ServerEntity, TestUserData>::new(Config {
+        announce_version: true,
+        ..Config::default()
+    });
+
+    let stats: Rc<Mutex<TestStat>> = Rc::new(Mutex::new(TestStat::default()));
+    let connection = server.connection_add_with(||TestUserData::new(255)).unwrap();
+    server.entity_create_with(|| Box::new(TestEntity::new(true, stats.clone()))).unwrap();
+
+    // The version is announced ahead of the first packet only
+    let packets = server.connection_send(&connection, 4096).unwrap();
+    assert_eq!(packets, vec![
+        vec![6, 0x43, 0x45, PROTOCOL_VERSION],
+        vec![0, 0, 3, 0, 1, 255, 128, 255]
+    ]);
+    assert_eq!(server.connection_send(&connection, 4096).unwrap()[0][0], 0);
+
+    let (mut client, _) = create_client(3);
+    for packet in packets {
+        client.receive(packet).expect("Client accepts packets of a matching protocol version.");
+    }
+
+    // A server with a different version is rejected
+    let (mut client, _) = create_client(3);
+    assert_eq!(client.receive(vec![6, 0x43, 0x45, PROTOCOL_VERSION + 1]), Err(ClientError::ProtocolVersionMismatch {
+        expected: PROTOCOL_VERSION,
+        got: PROTOCOL_VERSION + 1
+    }));
+
+}
+
+#[test]
+fn test_client_protocol_version_checksum() {
+
+    let mut server = Server::<ServerEntity, TestUserData>::new(Config {
+        announce_version: true,
+        packet_checksum: true,
+        ..Config::default()
+    });
+    let stats: Rc<Mutex<TestStat>> = Rc::new(Mutex::new(TestStat::default()));
+    let connection = server.connection_add_with(||TestUserData::new(255)).unwrap();
+    server.entity_create_with(|| Box::new(TestEntity::new(true, stats.clone()))).unwrap();
+
+    let (mut client, client_stats) = create_client(3);
+    client.set_config(Config {
+        packet_checksum: true,
+        ..Config::default()
+    });
+
+    // Client handshakes are send without a checksum
+    assert_eq!(client.handshake_packet(), vec![6, 0x43, 0x45, PROTOCOL_VERSION]);
+    server.accept_handshake(&connection, &client.handshake_packet()).expect("Server accepts unchecksummed handshake.");
+
+    // The version announcement is checksummed like any other packet
+    let packets = server.connection_send(&connection, 4096).unwrap();
+    assert_eq!(packets.len(), 2);
+    assert_eq!(packets[0][..5].to_vec(), vec![6, 6, 0x43, 0x45, PROTOCOL_VERSION]);
+    assert_eq!(packets[0].len(), 7);
+    assert_eq!(packets[1][0], 6);
+
+    for packet in packets {
+        client.receive(packet).expect("Client accepts checksummed packets of a matching protocol version.");
+    }
+    assert_stats!(client_stats, registry_calls, 1);
+
+    // Unchecksummed announcements are rejected
+    let (mut client, _) = create_client(3);
+    client.set_config(Config {
+        packet_checksum: true,
+        ..Config::default()
+    });
+    assert_eq!(client.receive(vec![6, 0x43, 0x45, PROTOCOL_VERSION]), Err(ClientError::ChecksumMismatch(vec![6, 0x43, 0x45, PROTOCOL_VERSION])));
+
+}
+
+#[test]
+fn test_client_packet_header() {
+
+    let mut server = Server::<ServerEntity, TestUserData>::new(Config {
+        packet_header: Some(PacketHeader::new(1, |bytes| bytes[0] = 7)),
+        ..Config::default()
+    });
+
+    let stats: Rc<Mutex<TestStat>> = Rc::new(Mutex::new(TestStat::default()));
+    let connection_one = server.connection_add_with(||TestUserData::new(255)).unwrap();
+    server.entity_create_with(|| Box::new(TestEntity::new(true, stats.clone()))).unwrap();
+    server.entity_create_with(|| Box::new(TestEntity::new(true, stats.clone()))).unwrap();
+
+    // Header is prepended and counts against the packet size
+    let packets = server.connection_send(&connection_one, 9).unwrap();
+    assert_eq!(packets, vec![
+        vec![7, 0, 0, 3, 0, 1, 255, 128, 255],
+        vec![7, 0, 1, 3, 0, 1, 255, 128, 255]
+    ]);
+
+    let (mut client, _) = create_client(5);
+    client.set_config(Config {
+        packet_header: Some(PacketHeader::new(1, |bytes| bytes[0] = 7)),
+        ..Config::default()
+    });
+
+    // Header is stripped on receive
+    for packet in packets {
+        client.receive(packet).expect("Client strips packet header.");
+    }
+
+    let packets = client.send(4096).unwrap();
+    assert_eq!(packets, vec![vec![7, 1, 0, 1, 1]]);
+
+    for packet in packets {
+        server.connection_receive(&connection_one, packet).expect("Server strips packet header.");
+    }
+
+    assert_eq!(server.connection_send(&connection_one, 4096).unwrap(), vec![vec![7, 1, 0, 1, 1]]);
+
+    // Packets shorter than the header are invalid
+    let (mut client, _) = create_client(5);
+    client.set_config(Config {
+        packet_header: Some(PacketHeader::new(2, |bytes| bytes.copy_from_slice(&[7, 7]))),
+        ..Config::default()
+    });
+    assert_eq!(client.receive(vec![7]), Err(ClientError::InvalidPacketData(vec![7])));
+
+}
+
+#[test]
+fn test_client_server_stream() {
+
+    let mut server = Server::<ServerEntity, TestUserData>::new(Config {
+        announce_version: true,
+        ..Config::default()
+    });
+    let stats: Rc<Mutex<TestStat>> = Rc::new(Mutex::new(TestStat::default()));
+    let connection_one = server.connection_add_with(||TestUserData::new(255)).unwrap();
+    let entity_one = server.entity_create_with(|| Box::new(TestEntity::new(true, stats.clone()))).unwrap();
+    let entity_two = server.entity_create_with(|| Box::new(TestEntity::new(true, stats.clone()))).unwrap();
+
+    let (mut client, client_stats) = create_client(3);
+
+    // Packets are prefixed with their length
+    let stream = server.connection_send_stream(&connection_one).unwrap();
+    assert_eq!(stream, vec![
+        4, 6, 0x43, 0x45, PROTOCOL_VERSION,
+        16, 0, 0, 3, 0, 1, 255, 128, 255, 0, 1, 3, 0, 1, 255, 128, 255
+    ]);
+
+    // Incomplete packets are left for the next call
+    assert_eq!(client.receive_stream(&stream[..10]), Ok(5));
+    assert_stats!(client_stats, registry_calls, 0);
+    assert_eq!(client.receive_stream(&stream[5..]), Ok(17));
+    assert_stats!(client_stats, registry_calls, 2);
+
+    let stream = client.send_stream().unwrap();
+    assert_eq!(stream, vec![4, 1, 0, 1, 1]);
+    assert_eq!(server.connection_receive_stream(&connection_one, &stream), Ok(5));
+
+    let stream = server.connection_send_stream(&connection_one).unwrap();
+    assert_eq!(stream, vec![4, 1, 0, 1, 1]);
+    assert_eq!(client.receive_stream(&stream), Ok(5));
+
+    let stream = client.send_stream().unwrap();
+    assert_eq!(stream, vec![4, 2, 0, 2, 1]);
+    assert_eq!(server.connection_receive_stream(&connection_one, &stream), Ok(5));
+
+    // Nothing is left to be send once both sides are synchronised
+    assert_eq!(server.connection_send_stream(&connection_one).unwrap(), Vec::<u8>::new());
+    assert_eq!(server.connection_entity_state(&connection_one, &entity_one), Some(RemoteState::Update));
+    assert_eq!(server.connection_entity_state(&connection_one, &entity_two), Some(RemoteState::Update));
+
+    // Invalid packets stop the processing of the stream
+    assert_eq!(server.connection_receive_stream(&connection_one, &[2, 9, 9, 0]), Err((0, ServerError::InvalidPacketData(vec![9, 9]))));
+    assert_eq!(client.receive_stream(&[1, 9]), Err((0, ClientError::InvalidPacketData(vec![9]))));
+
+    // Bytes consumed in front of a failing packet are reported
+    assert_eq!(server.connection_receive_stream(&connection_one, &[0, 0, 2, 9, 9]), Err((2, ServerError::InvalidPacketData(vec![9, 9]))));
+    assert_eq!(client.receive_stream(&[0, 1, 9]), Err((1, ClientError::InvalidPacketData(vec![9]))));
+
+    // Malformed or oversized length prefixes are errors rather than incomplete packets
+    assert_eq!(server.connection_receive_stream(&connection_one, &[0, 0xFF, 0xFF]), Ok(1));
+    assert_eq!(server.connection_receive_stream(&connection_one, &[0, 0xFF, 0xFF, 0xFF]), Err((1, ServerError::InvalidStreamFrame)));
+    assert_eq!(server.connection_receive_stream(&connection_one, &[0x80, 0x80, 0x04]), Err((0, ServerError::InvalidStreamFrame)));
+    assert_eq!(client.receive_stream(&[0xFF, 0xFF, 0xFF, 0xFF, 0x7F]), Err((0, ClientError::InvalidStreamFrame)));
+    assert_eq!(client.receive_stream(&[0x80, 0x80, 0x04, 1]), Err((0, ClientError::InvalidStreamFrame)));
+
+}
+
+#[test]
+fn test_client_receive_slice() {
+
+    let (mut client, stats) = create_client(5);
+    let (mut client_slice, stats_slice) = create_client(5);
+
+    let mut receive = |bytes: Vec<u8>| {
+        let result = client.receive(bytes.clone());
+        assert_eq!(client_slice.receive_slice(&bytes), result);
+        let packets = client.send(4096).unwrap();
+        assert_eq!(client_slice.send(4096).unwrap(), packets);
+        (result, packets)
+    };
+
+    assert_eq!(receive(vec![0, 0, 3, 0, 1, 255, 128, 255, 0, 1, 3, 0, 1, 255, 128, 255]), (Ok(()), vec![vec![1, 0, 1, 1]]));
+    assert_eq!(receive(vec![1, 0, 1, 1]), (Ok(()), vec![vec![2, 0, 2, 1]]));
+    assert_eq!(receive(vec![4, 1]), (Ok(()), vec![vec![2, 0, 4, 1]]));
+
+    // Errors hand back the same bytes
+    assert_eq!(receive(vec![8, 2]).0, Err(ClientError::InvalidPacketData(vec![8, 2])));
+
+    assert_stats!(stats, created_calls, 2);
+    assert_stats!(stats_slice, created_calls, 2);
+    assert_stats!(stats, destroyed_calls, 1);
+    assert_stats!(stats_slice, destroyed_calls, 1);
+    assert_eq!(client.recent_errors(), client_slice.recent_errors());
+
+}
+
+#[test]
+fn test_client_update_ticks() {
+
+    let mut server = Server::<ServerEntity, TestUserData>::new(Config {
+        acknowledge_updates: true,
+        send_update_ticks: true,
+        ..Config::default()
+    });
+    let server_stats: Rc<Mutex<TestStat>> = Rc::new(Mutex::new(TestStat::default()));
+
+    let (mut client, client_stats) = create_client(5);
+    client.set_config(Config {
+        acknowledge_updates: true,
+        send_update_ticks: true,
+        ..Config::default()
+    });
+
+    let connection_one = server.connection_add_with(||TestUserData::new(255)).unwrap();
+    server.entity_create_with(|| Box::new(TestEntity::new(true, server_stats.clone()))).unwrap();
+
+    let mut exchange = |server: &mut Server<ServerEntity, TestUserData>| {
+        for packet in server.connection_send(&connection_one, 4096).unwrap() {
+            client.receive(packet).expect("Client accepts server packet.");
+        }
+        for packet in client.send(4096).unwrap() {
+            server.connection_receive(&connection_one, packet).expect("Server accepts client packet.");
+        }
+    };
+
+    exchange(&mut server);
+    exchange(&mut server);
+    assert!(client_stats.lock().unwrap().merge_ticks.is_empty());
 
-    // DOES NOT REPLACE entity of same kind while still in CREATE state
-    assert_client_send!(client, vec![0, 0, 3, 1, 255, 128, 255], vec![1, 0]).expect("Client does not replace existing entity of same kind in create state.");
+    // Updates carry the server tick at which they were serialized
+    for _ in 0..5 {
+        server.update_entities_with(|_, _| {});
+    }
+    server_stats.lock().unwrap().part_bytes_value = Some(vec![1, 2]);
+    client_stats.lock().unwrap().merge_bytes_value = vec![1, 2];
+    assert_eq!(server.connection_send(&connection_one, 4096).unwrap(), vec![vec![3, 0, 1, 0, 0, 0, 5, 2, 1, 2]]);
+
+    server_stats.lock().unwrap().part_bytes_value = Some(vec![1, 2]);
+    exchange(&mut server);
+    assert_stats_clone!(client_stats, merge_ticks, vec![5]);
+    assert_stats!(client_stats, merge_calls, 1);
+
+    // Ticks beyond a single byte arrive intact
+    for _ in 0..300 {
+        server.update_entities_with(|_, _| {});
+    }
+    server_stats.lock().unwrap().part_bytes_value = Some(vec![1, 2]);
+    exchange(&mut server);
+    assert_stats_clone!(client_stats, merge_ticks, vec![5, 305]);
+
+}
+
+#[test]
+fn test_client_compression() {
+
+    let (mut client, _) = create_client(5);
+    client.set_config(Config {
+        compression: Some(Compressor::new(rle_compress, rle_decompress)),
+        ..Config::default()
+    });
+
+    // Compressed packets are decompressed on receive
+    let mut create = vec![8];
+    create.extend(rle_compress(&[0, 0, 3, 0, 1, 255, 128, 255, 0, 1, 3, 0, 1, 255, 128, 255]));
+    client.receive(create).expect("Client accepts compressed SendCreateToClient packet.");
+
+    // Packets which do not shrink are send uncompressed
+    assert_eq!(client.send(4096).unwrap(), vec![vec![1, 0, 1, 1]]);
+
+    // Invalid compressed data is rejected
+    assert_eq!(client.receive(vec![8, 1]), Err(ClientError::InvalidPacketData(vec![8, 1])));
+
+}
+
+#[test]
+fn test_client_packet_checksum() {
+
+    let (mut client, stats) = create_client(5);
+    client.set_config(Config {
+        packet_checksum: true,
+        ..Config::default()
+    });
+
+    // Corrupted packets are rejected before being parsed
+    let mut create = vec![6, 0, 0, 3, 0, 1, 255, 128, 255, 23, 132];
+    create[6] ^= 1;
+    assert_eq!(client.receive(create.clone()), Err(ClientError::ChecksumMismatch(create)));
+    assert_stats!(stats, registry_calls, 0);
+
+    // Packets without a checksum are rejected
+    assert_eq!(client.receive(vec![0, 0, 3, 0, 1, 255, 128, 255]), Err(ClientError::ChecksumMismatch(vec![0, 0, 3, 0, 1, 255, 128, 255])));
+    assert_stats!(stats, registry_calls, 0);
+
+    // Valid packets are accepted and replies are checksummed
+    client.receive(vec![6, 0, 0, 3, 0, 1, 255, 128, 255, 23, 132]).expect("Client accepts checksummed SendCreateToClient packet.");
     assert_stats!(stats, registry_calls, 1);
-    assert_stats!(stats, created_calls, 0);
-    assert_stats!(stats, destroyed_calls, 0);
+    assert_eq!(client.send(4096).unwrap(), vec![vec![6, 1, 0, 2, 1]]);
 
-    let mut count = 0;
-    client.update_entities_with(|_, entity| { entity.client_update(&mut count); });
-    assert_eq!(count, 1);
+    // Custom packets are passed through without a checksum
+    let mut custom = Vec::new();
+    assert!(client.receive_with(vec![255, 2], |bytes| custom.push(bytes.to_vec())).is_ok());
+    assert_eq!(custom, vec![vec![255, 2]]);
+    assert_eq!(client.receive_with(vec![8, 2], |_| unreachable!()), Err(ClientError::ChecksumMismatch(vec![8, 2])));
 
-    // ConfirmClientCreate -> AcceptServerUpdate
-    assert_client_send!(client, vec![1, 0], vec![2, 0]).expect("Client accepts ConfirmClientCreate packet.");
-    assert_stats!(stats, created_calls, 1);
+    // Peers without checksums do not accept checksummed packets
+    client.set_config(Config::default());
+    assert_eq!(client.receive(vec![6, 1, 200, 202, 201]), Err(ClientError::TruncatedEntityData(1)));
 
-    // DOES REPLACE entity of the same kind when no longer in CREATE state
-    assert_client_send!(client, vec![0, 0, 3, 1, 255, 128, 255], vec![1, 0]).expect("Client does replace existing entity of same kind outside of create state.");
+}
+
+#[test]
+fn test_client_create_payload_mismatch() {
+
+    let (mut client, stats) = create_client(5);
+
+    // Creations are accepted when the entity consumed all advertised bytes
+    stats.lock().unwrap().create_bytes_consumed = Some(3);
+    assert_client_send!(client, vec![0, 0, 3, 0, 1, 255, 128, 255], vec![1, 0]).expect("Client accepts SendCreateToClient packet.");
+
+    // Bodies which are longer than the entity expects are rejected
+    stats.lock().unwrap().create_bytes_consumed = Some(2);
+    assert_eq!(client.receive(vec![0, 1, 3, 0, 1, 255, 128, 255, 0, 2, 3, 0, 1, 255, 128, 255]), Err(ClientError::CreatePayloadMismatch(1)));
     assert_stats!(stats, registry_calls, 2);
-    assert_stats!(stats, created_calls, 1);
-    assert_stats!(stats, destroyed_calls, 0);
+    assert_eq!(client.live_entity_count(), 1);
+    assert_eq!(client.send(256).unwrap(), vec![vec![1, 0]]);
 
-    // DOES REPLACE entity of different kind in state other than CREATE
-    assert_client_send!(client, vec![0, 0, 3, 2, 255, 128, 255], vec![1, 0]).expect("Client does replace existing entity of different kind.");
-    assert_stats!(stats, registry_calls, 3);
-    assert_stats!(stats, created_calls, 1);
-    assert_stats!(stats, destroyed_calls, 0);
+}
 
-    // DOES REPLACE entity of different kind in CREATE state
-    assert_client_send!(client, vec![0, 0, 3, 1, 255, 128, 255], vec![1, 0]).expect("Client does replace existing entity of different kind.");
-    assert_stats!(stats, registry_calls, 4);
-    assert_stats!(stats, created_calls, 1);
-    assert_stats!(stats, destroyed_calls, 0);
+#[test]
+fn test_client_length_codec() {
+
+    let (mut client, stats) = create_client(5);
+    client.set_config(Config {
+        length_codec: Box::new(TwoByte),
+        ..Config::default()
+    });
 
+    assert_client_send!(client, vec![0, 0, 0, 3, 0, 1, 255, 128, 255], vec![1, 0]).expect("Client accepts SendCreateToClient packet with two byte length.");
+    assert_stats!(stats, registry_calls, 1);
     assert_client_send!(client, vec![1, 0], vec![2, 0]).expect("Client accepts ConfirmClientCreate packet.");
-    assert_stats!(stats, created_calls, 2);
+
+    // Empty updates still carry the full length prefix
+    assert_client_send_empty!(client, vec![3, 0, 0, 0]).expect("Client accepts SendUpdateToClient packet without data bytes.");
+
+    let update_bytes: Vec<u8> = (0..256).map(|i| i as u8).collect();
+    let mut packet = vec![3, 0, 1, 0];
+    packet.extend_from_slice(&update_bytes);
+
+    stats.lock().unwrap().merge_bytes_value = update_bytes.clone();
+    assert_client_send_empty!(client, packet.clone()).expect("Client accepts SendUpdateToClient packet with 256 data bytes.");
+    assert_stats!(stats, merge_calls, 1);
+
+    stats.lock().unwrap().part_bytes_value = Some(update_bytes);
+    assert_client_send!(client, vec![], packet).expect("Client sends SendUpdateToServer packet with 256 data bytes.");
+
+    // Truncated lengths are rejected
+    assert_eq!(client.receive(vec![3, 0, 1]), Err(ClientError::TruncatedEntityData(0)));
+    assert_stats!(stats, merge_calls, 1);
 
 }
 
 #[test]
-fn test_client_entity_periodic_empty_update() {
+fn test_client_acknowledge_updates_timed_out_handle() {
 
     let (mut client, stats) = create_client(3);
-
     client.set_config(Config {
-        handle_timeout_ticks: 5,
-        minimum_update_interval: Some(10)
+        acknowledge_updates: true,
+        handle_timeout_ticks: 3,
+        ..Config::default()
     });
 
-    // SendCreateToClient -> ConfirmCreateToServer
-    assert_client_send!(client, vec![0, 0, 3, 2, 255, 128, 255], vec![1, 0]).expect("Client accepts SendCreateToClient packet.");
-    assert_stats!(stats, registry_calls, 1);
-    assert_stats!(stats, created_calls, 0);
+    assert_client_send!(client, vec![0, 0, 3, 0, 1, 255, 128, 255], vec![1, 0]).expect("Client accepts SendCreateToClient packet.");
+    assert_client_send!(client, vec![1, 0], vec![2, 0]).expect("Client accepts ConfirmClientCreate packet.");
 
-    let mut count = 0;
-    client.update_entities_with(|_, entity| { entity.client_update(&mut count); });
-    assert_eq!(count, 1);
+    // Pending acknowledgements are dropped along with timed out handles
+    stats.lock().unwrap().merge_bytes_value = vec![255, 192, 96];
+    client.receive(vec![3, 0, 5, 3, 255, 192, 96, 4, 0]).unwrap();
+    for _ in 0..5 {
+        client.update_entities_with(|_, _| {});
+    }
+    assert_eq!(client.check_invariants(), Ok(()));
+    assert_eq!(client.send(256).unwrap(), Vec::<Vec<u8>>::new());
 
-    // ConfirmClientCreate -> AcceptServerUpdate
+}
+
+#[test]
+fn test_client_acknowledge_updates_replaced_entity() {
+
+    let (mut client, stats) = create_client(3);
+    client.set_config(Config {
+        acknowledge_updates: true,
+        ..Config::default()
+    });
+
+    assert_client_send!(client, vec![0, 0, 3, 0, 1, 255, 128, 255], vec![1, 0]).expect("Client accepts SendCreateToClient packet.");
     assert_client_send!(client, vec![1, 0], vec![2, 0]).expect("Client accepts ConfirmClientCreate packet.");
-    assert_stats!(stats, created_calls, 1);
 
-    // SendUpdateToClient -> None
-    assert_client_send_empty!(client, vec![3, 0, 0]).expect("Client accepts SendUpdateToClient packet without data bytes.");
-    assert_stats!(stats, merge_calls, 0);
+    // Pending acknowledgements are not send for the entity replacing the slot
+    stats.lock().unwrap().merge_bytes_value = vec![255, 192, 96];
+    client.receive(vec![3, 0, 5, 3, 255, 192, 96]).unwrap();
+    assert_client_send!(client, vec![0, 0, 3, 0, 1, 255, 128, 255], vec![1, 0]).expect("Client replaces entity without acknowledging its previous update.");
+    assert_eq!(client.check_invariants(), Ok(()));
 
-    let mut packets = vec![];
-    for _ in 0..20 {
-        let p = client.send(256);
-        if p.is_empty() {
-            packets.push(vec![]);
+}
 
-        } else {
-            packets.extend_from_slice(&p);
-        }
-    }
+#[test]
+fn test_client_acknowledge_updates() {
 
-    assert_eq!(
-        packets,
-        vec![
-            vec![], vec![], vec![], vec![], vec![], vec![], vec![], vec![],
-            vec![3, 0, 0],
-            vec![], vec![], vec![], vec![], vec![], vec![], vec![], vec![], vec![],
-            vec![3, 0, 0],
-            vec![]
-        ],
-        "Client should send an empty update at least every 10 ticks."
-    );
+    let (mut client, stats) = create_client(3);
+    client.set_config(Config {
+        acknowledge_updates: true,
+        ..Config::default()
+    });
 
-    // SendUpdateToClient > None
-    assert_client_send_empty!(client, vec![3, 0, 0]).expect("Client accepts empty server update packet.");
-    assert_stats!(stats, merge_calls, 0);
+    assert_client_send!(client, vec![0, 0, 3, 0, 1, 255, 128, 255], vec![1, 0]).expect("Client accepts SendCreateToClient packet.");
+    assert_client_send!(client, vec![1, 0], vec![2, 0]).expect("Client accepts ConfirmClientCreate packet.");
+
+    // Empty updates are not acknowledged
+    assert_client_send_empty!(client, vec![3, 0, 4, 0]).expect("Client accepts sequenced SendUpdateToClient packet without data bytes.");
+
+    stats.lock().unwrap().merge_bytes_value = vec![255, 192, 96];
+    assert_client_send!(client, vec![3, 0, 5, 3, 255, 192, 96], vec![5, 0, 5]).expect("Client acknowledges sequenced SendUpdateToClient packet.");
+    assert_stats!(stats, merge_calls, 1);
+
+    // Acknowledgements are only sent once
+    assert_client_send_empty!(client, vec![]).expect("Client does not repeat AcknowledgeServerUpdate.");
 
 }
 
 #[test]
-fn test_client_multi_entity_flow() {
+fn test_client_stats() {
 
     let (mut client, stats) = create_client(3);
 
-    // SendCreateToClient -> ConfirmCreateToServer
     assert_client_send!(client, vec![
-        0, 8, 3, 1, 255, 128, 255,
-        0, 42, 3, 1, 255, 128, 255,
-        0, 72, 3, 1, 255, 128, 255
+        0, 0, 3, 0, 1, 255, 128, 255,
+        0, 1, 3, 0, 1, 255, 128, 255,
+        0, 2, 3, 0, 1, 255, 128, 255
 
-    ], vec![1, 8, 1, 42, 1, 72]).expect("Client accepts multiple SendCreateToClient packets.");
-    assert_stats!(stats, registry_calls, 3);
+    ], vec![1, 0, 1, 1, 1, 2]).expect("Client accepts multiple SendCreateToClient packets.");
 
-    let mut count = 0;
-    client.update_entities_with(|_, entity| {
-        entity.client_update(&mut count);
+    assert_client_send!(client, vec![1, 0], vec![2, 0, 1, 1, 1, 2]).expect("Client accepts ConfirmClientCreate packet.");
+
+    stats.lock().unwrap().merge_bytes_value = vec![255, 192, 96];
+    assert_client_send!(client, vec![3, 0, 3, 255, 192, 96], vec![1, 1, 1, 2]).expect("Client accepts SendUpdateToClient packet with data bytes.");
+
+    assert_client_send!(client, vec![4, 1], vec![4, 1, 1, 2]).expect("Client accepts SendDestroyToClient packet.");
+    assert_client_send!(client, vec![5, 2], vec![4, 1, 4, 2]).expect("Client accepts SendForgetToClient packet.");
+    assert_client_send!(client, vec![4, 1], vec![4, 1, 4, 2]).expect("Client ignores SendDestroyToClient packet for already destroyed entity.");
+
+    assert_eq!(client.stats(), &ClientStats {
+        entities_created: 3,
+        entities_destroyed: 1,
+        entities_forgotten: 1,
+        bytes_received: 38,
+        bytes_sent: 28,
+        updates_merged: 1
     });
-    assert_eq!(count, 3);
 
-    // ConfirmClientCreate -> AcceptServerUpdate
-    assert_client_send!(client, vec![1, 8], vec![2, 8, 1, 42, 1, 72]).expect("Client accepts ConfirmClientCreate packet.");
-    assert_client_send!(client, vec![1, 54], vec![2, 8, 1, 42, 1, 72]).expect("Client ignores ConfirmClientCreate packet for unknown entity.");
-    assert_client_send!(client, vec![1, 72], vec![2, 8, 1, 42, 2, 72]).expect("Client accepts ConfirmClientCreate packet.");
+    client.reset_stats();
+    assert_eq!(client.stats(), &ClientStats::default());
 
-    // SendUpdateToClient -> None
-    assert_client_send!(client, vec![3, 72, 0], vec![2, 8, 1, 42]).expect("Client accepts SendUpdateToClient packet without data bytes.");
-    assert_stats!(stats, part_calls, 1);
-    assert_stats!(stats, merge_calls, 0);
-    assert_stats_clone!(stats, merge_bytes_value, vec![]);
+}
+
+#[test]
+fn test_client_kind_bandwidth() {
+
+    let (mut client, stats) = create_client(3);
+
+    assert_client_send!(client, vec![0, 0, 3, 0, 1, 255, 128, 255], vec![1, 0]).expect("Client accepts SendCreateToClient packet.");
+    assert_client_send!(client, vec![1, 0], vec![2, 0]).expect("Client accepts ConfirmClientCreate packet.");
 
     stats.lock().unwrap().merge_bytes_value = vec![255, 192, 96];
-    assert_client_send!(client, vec![3, 8, 3, 255, 192, 96], vec![1, 42]).expect("Client accepts SendUpdateToClient packet with data bytes.");
+    stats.lock().unwrap().part_bytes_value = Some(vec![1, 2]);
+    assert_client_send!(client, vec![3, 0, 3, 255, 192, 96], vec![3, 0, 2, 1, 2]).expect("Client accepts SendUpdateToClient packet with data bytes.");
+
+    assert_eq!(client.kind_bandwidth().get(&1), Some(&KindBandwidth {
+        part_calls: 1,
+        part_bytes: 2,
+        merge_calls: 1,
+        merge_bytes: 3
+    }));
+
+    client.reset_stats();
+    assert!(client.kind_bandwidth().is_empty());
+
+}
+
+#[test]
+fn test_client_send_with_budget() {
+
+    let stats: Rc<Mutex<TestStat>> = Rc::new(Mutex::new(TestStat::default()));
+    let mut client = Client::<ClientEntity, TestUserData, ClientRegistry>::new(Config {
+        acknowledge_updates: true,
+        ..Config::default()
+
+    }, ClientRegistry {
+        stats: stats.clone()
+    });
+
+    let mut buffer = PacketBuffer::new();
+    client.receive(vec![0, 0, 3, 0, 2, 255, 128, 255, 0, 1, 3, 0, 1, 255, 128, 255]).unwrap();
+    assert_eq!(client.send_into(256, &mut buffer).unwrap(), &[vec![1, 0, 1, 1]][..]);
+    client.receive(vec![1, 0, 1, 1]).unwrap();
+    assert_eq!(client.send_with_budget(256, 0).unwrap(), vec![vec![2, 0, 2, 1]]);
+    client.receive(vec![3, 0, 0, 0, 3, 1, 0, 0]).unwrap();
+    assert_eq!(client.send(256).unwrap(), Vec::<Vec<u8>>::new());
+    assert_stats!(stats, part_calls, 2);
+
+    // Higher priority updates are serialized first and the rest is deferred
+    stats.lock().unwrap().priority = 1;
+    stats.lock().unwrap().part_bytes_value = Some(vec![1, 2]);
+    stats.lock().unwrap().merge_bytes_value = vec![9];
+    client.receive(vec![3, 0, 1, 1, 9, 3, 1, 1, 1, 9]).unwrap();
+
+    // Acknowledgements are never deferred
+    assert_eq!(client.send_with_budget(256, 1).unwrap(), vec![vec![3, 1, 2, 1, 2, 5, 1, 1, 5, 0, 1]]);
     assert_stats!(stats, part_calls, 3);
-    assert_stats!(stats, merge_calls, 1);
 
-    // None -> SendUpdateToServer
-    stats.lock().unwrap().part_bytes_value = Some(vec![]);
-    assert_client_send!(client, vec![], vec![3, 8, 0, 1, 42]).expect("Client sends SendUpdateToServer packet without data bytes.");
+    // Deferred updates are serialized by the next send
+    assert_eq!(client.send(256).unwrap(), Vec::<Vec<u8>>::new());
     assert_stats!(stats, part_calls, 5);
 
-    stats.lock().unwrap().part_bytes_value = Some(vec![96, 255, 192]);
-    assert_client_send!(client, vec![], vec![3, 8, 3, 96, 255, 192, 1, 42]).expect("Client sends SendUpdateToServer packet with data bytes.");
+    // Destruction confirmations are never deferred
+    client.receive(vec![4, 0]).unwrap();
+    assert_eq!(client.send_with_budget(256, 0).unwrap(), vec![vec![4, 0]]);
+    assert_stats!(stats, part_calls, 5);
 
-    // SendDestroyToClient -> ConfirmDestroyToServer
-    assert_client_send!(client, vec![4, 8], vec![4, 8, 1, 42]).expect("Client accepts SendDestroyToClient packet for existing entity.");
-    assert_stats!(stats, drop_calls, 1); // Entity is dropped directly
+}
 
-    assert_client_send!(client, vec![4, 72], vec![4, 8, 1, 42, 4, 72]).expect("Client accepts SendDestroyToClient packet for existing entity.");
-    assert_stats!(stats, drop_calls, 2); // Entity is dropped directly
+#[test]
+fn test_client_entity_local_state() {
 
-    assert_client_send!(client, vec![4, 42], vec![4, 8, 4, 42, 4, 72]).expect("Client accepts SendDestroyToClient packet for existing entity.");
-    assert_stats!(stats, drop_calls, 3); // Entity is dropped directly
+    let (mut client, _) = create_client(3);
+    let (mut client_two, _) = create_client(3);
+
+    assert_client_send!(client, vec![0, 0, 3, 0, 1, 255, 128, 255], vec![1, 0]).expect("Client accepts SendCreateToClient packet.");
+    assert_client_send!(client_two, vec![0, 0, 3, 0, 1, 255, 128, 255], vec![1, 0]).expect("Client accepts SendCreateToClient packet.");
+
+    let entity = client.map_entities(|token, _| token.clone()).remove(0);
+    assert_eq!(client.entity_local_state(&entity), Some(LocalState::Create));
+    assert_eq!(client_two.entity_local_state(&entity), None);
+
+    assert_client_send!(client, vec![1, 0], vec![2, 0]).expect("Client accepts ConfirmClientCreate packet.");
+    assert_eq!(client.entity_local_state(&entity), Some(LocalState::Accept));
+
+    assert_client_send_empty!(client, vec![3, 0, 0]).expect("Client accepts SendUpdateToClient packet without data bytes.");
+    assert_eq!(client.entity_local_state(&entity), Some(LocalState::Update));
+
+    assert_client_send!(client, vec![4, 0], vec![4, 0]).expect("Client accepts SendDestroyToClient packet.");
+    assert_eq!(client.entity_local_state(&entity), Some(LocalState::Update));
 
     client.update_entities_with(|_, _| {});
     client.update_entities_with(|_, _| {});
-    assert_client_send!(client, vec![], vec![4, 8, 4, 42, 4, 72]).expect("Client keeps all handles until send timeout expires after 3 further update calls.");
-
     client.update_entities_with(|_, _| {});
-    assert_client_send_empty!(client, vec![]).expect("Client drops all handles when send timeout expires after 3 further update calls.");
-
-    // TODO test multiple entity replace
+    assert_eq!(client.entity_local_state(&entity), None);
 
 }
 
+
 #[test]
-fn test_client_entity_forget() {
+fn test_client_fuzz_invariants() {
 
-    let (mut client, stats) = create_client(3);
+    let mut random = TestRandom::new(0x9e37_79b9_7f4a_7c15);
+    let (mut client, _) = create_client(3);
 
-    // SendCreateToClient -> ConfirmCreateToServer
-    assert_client_send!(client, vec![0, 0, 3, 1, 255, 128, 255], vec![1, 0]).expect("Client accepts SendCreateToClient packet.");
-    assert_stats!(stats, registry_calls, 1);
-    assert_stats!(stats, created_calls, 0);
+    for step in 0..10000 {
 
-    let mut count = 0;
-    client.update_entities_with(|_, entity| { entity.client_update(&mut count); });
-    assert_eq!(count, 1);
+        match random.next(12) {
+            0 => client.update_entities_with(|_, _| {}),
+            1 => {
+                client.send(4 + random.next(64)).unwrap();
+            },
+            2 => if random.next(16) == 0 {
+                client.reset();
+            },
+            3 => if random.next(16) == 0 {
+                client.destroy_all();
+            },
+            _ => {
+
+                // Mix of well formed frames and trailing garbage
+                let mut packet = Vec::new();
+                for _ in 0..random.next(4) {
+                    let index = random.next(8) as u8;
+                    match random.next(6) {
+                        0 => packet.extend_from_slice(&[0, index, 3, 0, 2 + random.next(2) as u8, 255, 128, 255]),
+                        1 => packet.extend_from_slice(&[1, index]),
+                        2 => {
+                            let len = random.next(3) as u8;
+                            packet.extend_from_slice(&[3, index, len]);
+                            for _ in 0..len {
+                                packet.push(random.next(256) as u8);
+                            }
+                        },
+                        3 => packet.extend_from_slice(&[4, index]),
+                        4 => packet.extend_from_slice(&[5, index]),
+                        _ => packet.push(6 + random.next(250) as u8)
+                    }
+                }
+
+                client.receive(packet).ok();
+            }
+        }
 
-    // SendForgetToClient -> ConfirmDestroyToServer
-    assert_client_send!(client, vec![5, 0], vec![4, 0]).expect("Client accepts SendForgetToClient packet.");
+        if let Err(err) = client.check_invariants() {
+            panic!("Invariant violated at step {}: {}", step, err);
+        }
 
-    // Client should NOT run destroyed() for forgotten entities
-    assert_stats!(stats, destroyed_calls, 0);
+    }
 
-    // Client should simply drop forgotten entities
-    assert_stats!(stats, drop_calls, 1);
+}
 
-    let mut count = 0;
-    client.update_entities_with(|_, entity| { entity.client_update(&mut count); });
-    assert_eq!(count, 0);
 
+// Config Tests ---------------------------------------------------------------
+#[test]
+fn test_config_builder() {
+
+    let config = Config::builder()
+        .handle_timeout_ticks(5)
+        .minimum_update_interval(Some(10))
+        .packet_overhead(2)
+        .acknowledge_updates(true)
+        .length_codec(TwoByte)
+        .require_handshake(true)
+        .max_baselines_per_connection(Some(64))
+        .max_entities_per_packet(Some(8))
+        .announce_version(true)
+        .create_resend_interval(Some(4))
+        .maximum_update_rate(Some(3))
+        .send_update_ticks(true)
+        .packet_checksum(true)
+        .build()
+        .expect("Builder accepts valid configuration.");
+
+    assert_eq!(config.handle_timeout_ticks, 5);
+    assert_eq!(config.minimum_update_interval, Some(10));
+    assert_eq!(config.slot_strategy, SlotStrategy::Lowest);
+    assert_eq!(config.packet_overhead, 2);
+    assert!(config.acknowledge_updates);
+    assert_eq!(config.length_codec.max_length(), 65535);
+    assert!(config.require_handshake);
+    assert_eq!(config.max_baselines_per_connection, Some(64));
+    assert_eq!(config.max_entities_per_packet, Some(8));
+    assert!(config.announce_version);
+    assert_eq!(config.create_resend_interval, Some(4));
+    assert_eq!(config.maximum_update_rate, Some(3));
+    assert!(config.send_update_ticks);
+    assert!(config.packet_checksum);
+
+}
+
+#[test]
+fn test_config_builder_packet_size_too_small() {
+    assert_eq!(
+        Config::builder().packet_overhead(2).packet_checksum(true).max_bytes_per_packet(8).build().unwrap_err(),
+        ConfigError::PacketSizeTooSmall(8)
+    );
+    assert!(Config::builder().packet_overhead(2).packet_checksum(true).max_bytes_per_packet(9).build().is_ok());
+}
+
+#[test]
+fn test_config_builder_zero_update_interval() {
+    assert_eq!(
+        Config::builder().minimum_update_interval(Some(0)).build().unwrap_err(),
+        ConfigError::ZeroUpdateInterval
+    );
+    assert_eq!(
+        Config::builder().max_entities_per_packet(Some(0)).build().unwrap_err(),
+        ConfigError::ZeroEntitiesPerPacket
+    );
+    assert_eq!(
+        Config::builder().create_resend_interval(Some(0)).build().unwrap_err(),
+        ConfigError::ZeroCreateResendInterval
+    );
+    assert_eq!(
+        Config::builder().maximum_update_rate(Some(0)).build().unwrap_err(),
+        ConfigError::ZeroUpdateRate
+    );
 }
 
+#[test]
+fn test_config_length_codec_round_trip() {
+
+    fn round_trip(codec: &LengthCodec, length: usize, expected: Vec<u8>) {
+        let mut bytes = Vec::new();
+        codec.encode(length, &mut bytes);
+        assert_eq!(bytes, expected);
+        assert_eq!(codec.decode(&bytes), Some((length, bytes.len())));
+        assert_eq!(codec.decode(&bytes[..bytes.len() - 1]), None);
+    }
+
+    assert_eq!(SingleByte.max_length(), 255);
+    round_trip(&SingleByte, 0, vec![0]);
+    round_trip(&SingleByte, 255, vec![255]);
+
+    assert_eq!(TwoByte.max_length(), 65535);
+    round_trip(&TwoByte, 0, vec![0, 0]);
+    round_trip(&TwoByte, 255, vec![0, 255]);
+    round_trip(&TwoByte, 256, vec![1, 0]);
+    round_trip(&TwoByte, 65535, vec![255, 255]);
+
+    assert_eq!(Varint.max_length(), 268_435_455);
+    round_trip(&Varint, 0, vec![0]);
+    round_trip(&Varint, 127, vec![127]);
+    round_trip(&Varint, 128, vec![128, 1]);
+    round_trip(&Varint, 255, vec![255, 1]);
+    round_trip(&Varint, 256, vec![128, 2]);
+    round_trip(&Varint, 268_435_455, vec![255, 255, 255, 127]);
+
+    // Varints longer than the maximum length are rejected
+    assert_eq!(Varint.decode(&[255, 255, 255, 255, 1]), None);
+
+}
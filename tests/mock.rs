@@ -1,11 +1,11 @@
 // STD Dependencies -----------------------------------------------------------
 use std::rc::Rc;
-use std::sync::Mutex;
+use std::sync::{Arc, Mutex};
 
 
 // Library Dependencies -------------------------------------------------------
 extern crate cobalt_entity;
-use cobalt_entity::{Entity, ConnectionToken};
+use cobalt_entity::{Entity, ConnectionToken, DropReason, ForgetMode, MergeError, Relevance};
 
 
 // Mocks ----------------------------------------------------------------------
@@ -26,14 +26,37 @@ impl TestUserData {
 pub struct TestStat {
     pub new: usize,
     pub created_calls: usize,
+    pub confirmed_calls: usize,
+    pub observed_calls: usize,
     pub destroyed_calls: usize,
     pub part_calls: usize,
     pub merge_calls: usize,
     pub drop_calls: usize,
     pub registry_calls: usize,
     pub part_bytes_value: Option<Vec<u8>>,
+    pub snapshot_bytes_value: Option<Vec<u8>>,
+    pub part_bytes_baseline: Option<Vec<u8>>,
     pub merge_bytes_value: Vec<u8>,
-    pub filter_for_connection: bool
+    pub filter_for_connection: bool,
+    pub filter_user_data: Option<u8>,
+    pub create_bytes_consumed: Option<usize>,
+    pub relevance: Option<Relevance>,
+    pub forget_mode: Option<ForgetMode>,
+    pub category: u32,
+    pub transient: bool,
+    pub kind: Option<u16>,
+    pub reject_merge: bool,
+    pub skip_updates: bool,
+    pub can_sleep: bool,
+    pub priority: u8,
+    pub owner: Option<usize>,
+    pub owner_changes: usize,
+    pub part_segments_value: Vec<(u8, Vec<u8>)>,
+    pub merged_segments: Vec<(u8, Vec<u8>)>,
+    pub handle_timeout_ticks: Option<usize>,
+    pub destroy_timeout_extensions: usize,
+    pub drop_reasons: Vec<DropReason>,
+    pub merge_ticks: Vec<u32>
 }
 
 pub trait ServerEntity: Entity<TestUserData> {
@@ -67,6 +90,11 @@ impl TestEntity {
     pub fn set_stats(&mut self, stats: Rc<Mutex<TestStat>>) {
         self.stats = stats;
     }
+
+    // Compares against the owner index cached by owner_changed
+    fn is_owned_by(&self, connection_slot: &ConnectionToken<TestUserData>) -> bool {
+        self.stats.lock().unwrap().owner == Some(connection_slot.index())
+    }
 }
 
 impl Drop for TestEntity {
@@ -81,32 +109,130 @@ impl Entity<TestUserData> for TestEntity {
         self.stats.lock().unwrap().created_calls += 1;
     }
 
-    fn filter(&self, _: &ConnectionToken<TestUserData>) -> bool {
-        !self.stats.lock().unwrap().filter_for_connection
+    fn observed(&mut self, _: &ConnectionToken<TestUserData>) {
+        self.stats.lock().unwrap().observed_calls += 1;
+    }
+
+    fn confirmed_by(&mut self, _: &ConnectionToken<TestUserData>) {
+        self.stats.lock().unwrap().confirmed_calls += 1;
+    }
+
+    fn filter(&self, connection_slot: &ConnectionToken<TestUserData>) -> bool {
+        let stats = self.stats.lock().unwrap();
+        !stats.filter_for_connection && stats.filter_user_data != Some(connection_slot.user_data.value)
+    }
+
+    fn relevance(&self, connection_slot: &ConnectionToken<TestUserData>) -> Relevance {
+        let relevance = self.stats.lock().unwrap().relevance;
+        relevance.unwrap_or_else(|| {
+            if self.filter(connection_slot) {
+                Relevance::Full
+
+            } else {
+                Relevance::Forgotten
+            }
+        })
+    }
+
+    fn forget_mode(&self) -> ForgetMode {
+        self.stats.lock().unwrap().forget_mode.unwrap_or(ForgetMode::Destroy)
     }
 
     fn destroyed(&mut self) {
         self.stats.lock().unwrap().destroyed_calls += 1;
     }
 
+    fn handle_timeout_ticks(&self) -> Option<usize> {
+        self.stats.lock().unwrap().handle_timeout_ticks
+    }
+
+    fn extend_destroy_timeout(&self, remaining: usize) -> Option<usize> {
+        let mut stats = self.stats.lock().unwrap();
+        if remaining == 0 && stats.destroy_timeout_extensions > 0 {
+            stats.destroy_timeout_extensions -= 1;
+            Some(2)
+
+        } else {
+            None
+        }
+    }
+
+    fn dropped(&mut self, reason: DropReason) {
+        self.stats.lock().unwrap().drop_reasons.push(reason);
+    }
+
     fn part_bytes(&mut self, connection_slot: Option<&ConnectionToken<TestUserData>>) -> Option<Vec<u8>> {
         assert_eq!(connection_slot.is_some(), self.server_entity);
         self.stats.lock().unwrap().part_calls += 1;
         self.stats.lock().unwrap().part_bytes_value.take()
     }
 
-    fn merge_bytes(&mut self, connection_slot: Option<&ConnectionToken<TestUserData>>, bytes: &[u8]) {
+    fn priority(&self) -> u8 {
+        self.stats.lock().unwrap().priority
+    }
+
+    fn part_bytes_delta(&mut self, connection_slot: Option<&ConnectionToken<TestUserData>>, baseline: Option<&[u8]>) -> Option<Vec<u8>> {
+        self.stats.lock().unwrap().part_bytes_baseline = baseline.map(|bytes| bytes.to_vec());
+        self.part_bytes(connection_slot)
+    }
+
+    fn merge_bytes(&mut self, connection_slot: Option<&ConnectionToken<TestUserData>>, bytes: &[u8]) -> Result<(), MergeError> {
         assert_eq!(connection_slot.is_some(), self.server_entity);
-        assert_eq!(self.stats.lock().unwrap().merge_bytes_value, bytes);
-        self.stats.lock().unwrap().merge_calls += 1;
+        let mut stats = self.stats.lock().unwrap();
+        stats.merge_calls += 1;
+        if stats.reject_merge {
+            Err(MergeError)
+
+        } else {
+            assert_eq!(stats.merge_bytes_value, bytes);
+            Ok(())
+        }
+    }
+
+    fn merge_bytes_at(&mut self, connection_slot: Option<&ConnectionToken<TestUserData>>, tick: u32, bytes: &[u8]) -> Result<(), MergeError> {
+        self.stats.lock().unwrap().merge_ticks.push(tick);
+        self.merge_bytes(connection_slot, bytes)
+    }
+
+    fn kind(&self) -> u16 {
+        self.stats.lock().unwrap().kind.unwrap_or(1)
+    }
+
+    fn category(&self) -> u32 {
+        self.stats.lock().unwrap().category
+    }
+
+    fn should_send_update(&self) -> bool {
+        !self.stats.lock().unwrap().skip_updates
     }
 
-    fn kind(&self) -> u8 {
-        1
+    fn can_sleep(&self) -> bool {
+        self.stats.lock().unwrap().can_sleep
+    }
+
+    fn transient(&self) -> bool {
+        self.stats.lock().unwrap().transient
+    }
+
+    fn owner_changed(&mut self, owner: Option<usize>) {
+        let mut stats = self.stats.lock().unwrap();
+        stats.owner = owner;
+        stats.owner_changes += 1;
     }
 
     fn to_bytes(&self, connection_slot: &ConnectionToken<TestUserData>) -> Vec<u8> {
-        vec![255, 128, connection_slot.user_data.value]
+        // Owners receive a local flag
+        if self.is_owned_by(connection_slot) {
+            vec![255, 129, connection_slot.user_data.value]
+
+        } else {
+            vec![255, 128, connection_slot.user_data.value]
+        }
+    }
+
+    fn snapshot_bytes(&self, connection_slot: &ConnectionToken<TestUserData>) -> Vec<u8> {
+        let snapshot = self.stats.lock().unwrap().snapshot_bytes_value.clone();
+        snapshot.unwrap_or_else(|| self.to_bytes(connection_slot))
     }
 
     fn from_bytes(bytes: &[u8]) -> Option<TestEntity> {
@@ -114,6 +240,10 @@ impl Entity<TestUserData> for TestEntity {
         Some(TestEntity::new(false, Rc::new(Mutex::new(TestStat::default()))))
     }
 
+    fn validate_create_bytes(&self, _: &[u8]) -> Option<usize> {
+        self.stats.lock().unwrap().create_bytes_consumed
+    }
+
 }
 
 impl ServerEntity for TestEntity {
@@ -162,11 +292,12 @@ impl Entity<TestUserData> for TestEntityTwo {
         None
     }
 
-    fn merge_bytes(&mut self, _: Option<&ConnectionToken<TestUserData>>, _: &[u8]) {
+    fn merge_bytes(&mut self, _: Option<&ConnectionToken<TestUserData>>, _: &[u8]) -> Result<(), MergeError> {
         self.stats.lock().unwrap().merge_calls += 1;
+        Ok(())
     }
 
-    fn kind(&self) -> u8 {
+    fn kind(&self) -> u16 {
         2
     }
 
@@ -189,6 +320,109 @@ impl ClientEntity for TestEntityTwo {
 
 }
 
+#[derive(Debug)]
+pub struct TestSegmentEntity {
+    stats: Rc<Mutex<TestStat>>
+}
+
+impl TestSegmentEntity {
+    pub fn new(stats: Rc<Mutex<TestStat>>) -> TestSegmentEntity {
+        TestSegmentEntity {
+            stats: stats
+        }
+    }
+}
+
+impl Entity<TestUserData> for TestSegmentEntity {
+
+    fn part_bytes(&mut self, _: Option<&ConnectionToken<TestUserData>>) -> Option<Vec<u8>> {
+        None
+    }
+
+    fn part_segments(&mut self, _: Option<&ConnectionToken<TestUserData>>) -> Vec<(u8, Vec<u8>)> {
+        self.stats.lock().unwrap().part_segments_value.drain(0..).collect()
+    }
+
+    fn merge_segment(&mut self, _: Option<&ConnectionToken<TestUserData>>, id: u8, bytes: &[u8]) -> Result<(), MergeError> {
+        self.stats.lock().unwrap().merged_segments.push((id, bytes.to_vec()));
+        Ok(())
+    }
+
+    fn kind(&self) -> u16 {
+        4
+    }
+
+    fn to_bytes(&self, _: &ConnectionToken<TestUserData>) -> Vec<u8> {
+        vec![]
+    }
+
+    fn from_bytes(bytes: &[u8]) -> Option<TestSegmentEntity> {
+        if bytes.is_empty() {
+            Some(TestSegmentEntity::new(Rc::new(Mutex::new(TestStat::default()))))
+
+        } else {
+            None
+        }
+    }
+
+}
+
+impl ServerEntity for TestSegmentEntity {
+
+}
+
+impl ClientEntity for TestSegmentEntity {
+
+}
+
+#[derive(Debug)]
+pub struct TestSendEntity {
+    stats: Arc<Mutex<TestStat>>
+}
+
+impl TestSendEntity {
+    pub fn new(stats: Arc<Mutex<TestStat>>) -> TestSendEntity {
+        TestSendEntity {
+            stats: stats
+        }
+    }
+}
+
+impl Entity<TestUserData> for TestSendEntity {
+
+    fn part_bytes(&mut self, _: Option<&ConnectionToken<TestUserData>>) -> Option<Vec<u8>> {
+        let mut stats = self.stats.lock().unwrap();
+        stats.part_calls += 1;
+        stats.part_bytes_value.take()
+    }
+
+    fn kind(&self) -> u16 {
+        5
+    }
+
+}
+
+pub struct TestRandom {
+    state: u64
+}
+
+impl TestRandom {
+
+    pub fn new(seed: u64) -> TestRandom {
+        TestRandom {
+            state: seed
+        }
+    }
+
+    pub fn next(&mut self, max: usize) -> usize {
+        self.state ^= self.state << 13;
+        self.state ^= self.state >> 7;
+        self.state ^= self.state << 17;
+        (self.state % max as u64) as usize
+    }
+
+}
+
 
 // Macros ---------------------------------------------------------------------
 #[macro_export]
@@ -196,7 +430,7 @@ macro_rules! assert_client_send {
     ($name:ident, $input:expr, $output:expr) => {
         match $name.receive($input) {
             Ok(_) => {
-                assert_eq!($name.send(4096), vec![$output.clone()]);
+                assert_eq!($name.send(4096).unwrap(), vec![$output.clone()]);
                 Ok(())
             },
             Err(err) => Err(err)
@@ -209,7 +443,7 @@ macro_rules! assert_client_send_empty {
     ($name:ident, $input:expr) => {
         match $name.receive($input) {
             Ok(_) => {
-                assert_eq!($name.send(4096), Vec::<Vec<u8>>::new());
+                assert_eq!($name.send(4096).unwrap(), Vec::<Vec<u8>>::new());
                 Ok(())
             },
             Err(err) => Err(err)
@@ -223,7 +457,7 @@ macro_rules! assert_client_send_packets {
         match $name.receive($input) {
             Ok(_) => {
                 assert_eq!(
-                    $name.send($packet_size),
+                    $name.send($packet_size).unwrap(),
                     $output
                 );
                 Ok(())